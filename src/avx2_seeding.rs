@@ -13,6 +13,8 @@ pub const BYTE_TO_SEQ: [u8; 256] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
+/// # Safety
+/// 调用方必须确认CPU支持AVX2
 #[inline]
 #[target_feature(enable = "avx2")]
 pub unsafe fn mm_hash256(kmer: __m256i) -> __m256i {
@@ -37,9 +39,11 @@ pub unsafe fn mm_hash256(kmer: __m256i) -> __m256i {
     let s6 = _mm256_slli_epi64(key, 31);
     key = _mm256_add_epi64(key, s6);
 
-    return key;
+    key
 }
 
+/// # Safety
+/// 调用方必须确认CPU支持AVX2
 #[target_feature(enable = "avx2")]
 pub unsafe fn extract_markers_avx2(string: &[u8], kmer_vec: &mut Vec<Hash>, c: usize, k: usize) {
     if string.len() < k {
@@ -79,17 +83,16 @@ pub unsafe fn extract_markers_avx2(string: &[u8], kmer_vec: &mut Vec<Hash>, c: u
 
         rolling_kmer_r_marker = _mm256_srli_epi64(rolling_kmer_r_marker, 2);
 
-        let shift_nuc_r;
-        if use_40 {
-            shift_nuc_r = _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_40);
+        let shift_nuc_r = if use_40 {
+            _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_40)
         } else {
-            shift_nuc_r = _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_60);
-        }
+            _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_60)
+        };
         rolling_kmer_r_marker = _mm256_or_si256(rolling_kmer_r_marker, shift_nuc_r);
     }
 
     let marker_mask = (Hash::MAX >> (std::mem::size_of::<Hash>() * 8 - 2 * k)) as i64;
-    let rev_marker_mask: i64 = !(0 | (3 << 2 * k - 2));
+    let rev_marker_mask: i64 = !(3 << (2 * k - 2));
     let threshold_marker = Hash::MAX / c as Hash;
 
     let mm256_marker_mask = _mm256_set_epi64x(marker_mask, marker_mask, marker_mask, marker_mask);
@@ -113,12 +116,11 @@ pub unsafe fn extract_markers_avx2(string: &[u8], kmer_vec: &mut Vec<Hash>, c: u
         rolling_kmer_f_marker = _mm256_and_si256(rolling_kmer_f_marker, mm256_marker_mask);
 
         rolling_kmer_r_marker = _mm256_srli_epi64(rolling_kmer_r_marker, 2);
-        let shift_nuc_r;
-        if use_40 {
-            shift_nuc_r = _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_40);
+        let shift_nuc_r = if use_40 {
+            _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_40)
         } else {
-            shift_nuc_r = _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_60);
-        }
+            _mm256_slli_epi64(r_nucs, TWO_K_MINUS_2_60)
+        };
         rolling_kmer_r_marker = _mm256_and_si256(rolling_kmer_r_marker, mm256_rev_marker_mask);
         rolling_kmer_r_marker = _mm256_or_si256(rolling_kmer_r_marker, shift_nuc_r);
 
@@ -9,6 +9,7 @@ pub struct Cli {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum Mode {
     /// extract sequences into samples (reads) and databases (genomes). Each sample.fq -> sample.sylsp. All *.fa -> *.syldb. 
     #[clap(display_order = 1)]
@@ -31,6 +32,27 @@ pub enum Mode {
     ///Mark unique (taxa-specific) tags in .syldb files.
     #[clap(arg_required_else_help = true, display_order = 7)]
     Mark(MarkArgs),
+    ///Generate a self-contained HTML report from a profile abundance matrix.
+    #[clap(arg_required_else_help = true, display_order = 8)]
+    Report(ReportArgs),
+    ///Check .syldb and .sylsp files for corruption and structural problems.
+    #[clap(arg_required_else_help = true, display_order = 9)]
+    Validate(ValidateArgs),
+    ///Run a batch of extract/sketch/profile/... jobs listed in a YAML or TSV manifest.
+    #[clap(arg_required_else_help = true, display_order = 10)]
+    Run(RunArgs),
+    ///Summarize tag sharing between the samples of a combined .sylsp file.
+    #[clap(arg_required_else_help = true, display_order = 11)]
+    Summary(SummaryArgs),
+    ///Export k-mer sketches as sourmash signature JSON.
+    #[clap(arg_required_else_help = true, display_order = 12)]
+    Export(ExportArgs),
+    ///Write the tag sequences of an extracted .syldb or .sylsp as FASTA.
+    #[clap(arg_required_else_help = true, display_order = 13)]
+    ExportFasta(ExportFastaArgs),
+    ///Compare two abundance matrices from profile, e.g. runs with different thresholds.
+    #[clap(arg_required_else_help = true, display_order = 14)]
+    CompareProfiles(CompareProfilesArgs),
 }
 
 
@@ -86,6 +108,102 @@ pub struct ExtractArgs {
 
     #[clap(long="max-ram", help_heading = "MEMORY", help = "Maximum RAM usage in GB (default: 16)")]
     pub max_ram: Option<usize>,
+
+    #[clap(long="long-read", help_heading = "ALGORITHM", help = "Error-tolerant recognition site matching for long reads (Nanopore/PacBio). Only applies to read inputs")]
+    pub long_read: bool,
+
+    #[clap(long="max-site-mismatches", default_value_t = 1, help_heading = "ALGORITHM", help = "Maximum edits (substitutions, insertions or deletions) allowed in the recognition site with --long-read (1-2 recommended; 0 matches the site exactly)")]
+    pub max_site_mismatches: usize,
+
+    #[clap(long="genome-archive", help_heading = "GENOME INPUT", help = "A .tar or .tar.gz of genome FASTAs, read without unpacking. Each FASTA member is one genome, named by its path in the archive (--keep-individual does not apply)")]
+    pub genome_archive: Option<String>,
+
+    #[clap(long="tag-fasta", num_args=1.., help_heading = "TAG INPUT", help = "Pre-extracted 2bRAD tag FASTA files (one tag per record, e.g. from the original 2bRAD Perl scripts), imported without enzyme matching into <out-name>.syldb or .sylsp (default name: tags). Tags must have the -e enzyme's tag length")]
+    pub tag_fasta: Option<Vec<String>>,
+
+    #[clap(long="tag-fasta-type", default_value = "genome", value_parser = ["genome", "sample"], help_heading = "TAG INPUT", help = "Import each --tag-fasta file as one database genome (.syldb) or as one sample whose records are tag observations (.sylsp)")]
+    pub tag_fasta_type: String,
+
+    #[clap(long="tag-orientation", default_value = "canonical", value_parser = ["canonical", "forward"], help_heading = "TAG INPUT", help = "How --tag-fasta tags are hashed: canonical collapses each tag with its reverse complement like extract does by default; forward hashes tags as written, for lists that were not canonicalized (compare against data extracted with --strand-specific)")]
+    pub tag_orientation: String,
+
+    #[clap(long="keep-going", help_heading = "GENOME INPUT", help = "Build the database from the genome files that could be read even if some failed. Without it, any failed genome file makes extract exit with an error after printing the batch summary")]
+    pub keep_going: bool,
+
+    #[clap(long="append", help_heading = "GENOME INPUT", help = "Existing .syldb to extend: its entries are combined with the newly extracted genomes in the output database. Uniqueness marks from mark are cleared")]
+    pub append: Option<String>,
+
+    #[clap(long="merge-pairs", help_heading = "ALGORITHM", help = "Merge overlapping paired-end mates into one fragment before tag extraction, so sites spanning the read junction are recovered. Mates without a confident overlap are processed independently")]
+    pub merge_pairs: bool,
+
+    #[clap(long="strand-specific", help_heading = "ALGORITHM", help = "Keep tags in the observed orientation instead of collapsing them with their reverse complement (strand-specific protocols). Roughly doubles the tag space and database size; databases and samples must be extracted with the same setting")]
+    pub strand_specific: bool,
+
+    #[clap(long="min-complexity", default_value_t = 0.0, help_heading = "ALGORITHM", help = "Drop low-complexity tags whose base composition entropy (0-2 bits) is below this value, e.g. 1.0 removes homopolymer and dinucleotide-repeat tags. 0 disables the filter")]
+    pub min_complexity: f64,
+
+    #[clap(long="adapter", num_args=1.., help_heading = "ALGORITHM", help = "Remove tags containing adapter/linker sequence: a DNA sequence, or a built-in name (truseq, nextera, smallrna, all). A tag matches if it shares a 12 bp stretch with the adapter on either strand")]
+    pub adapter: Vec<String>,
+
+    #[clap(long="flag-adapters", requires = "adapter", help_heading = "ALGORITHM", help = "Only count and report adapter-containing tags instead of removing them")]
+    pub flag_adapters: bool,
+
+    #[clap(long="tag-offset", help_heading = "ALGORITHM", help = "When a recognition-site match is longer than the enzyme's tag length, start the tag this many bases into the match instead of centering it (for asymmetric Type IIB sites). Changes tag hashes, so databases and samples must use the same value")]
+    pub tag_offset: Option<usize>,
+
+    #[clap(long="subsample-tags", help_heading = "ALGORITHM", help = "Keep at most N tags per genome (the N with the smallest hash, reproducible). Lowers per-genome tag totals, which are the containment denominators in query/profile")]
+    pub subsample_tags: Option<usize>,
+
+    #[clap(long="subsample-rate", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), help_heading = "ALGORITHM", help = "Keep about 1/c of all tags (those with mm_hash64(tag hash) < u64::MAX/c), the same hash sampling `sketch` applies to k-mers with -c. Databases and samples must use the same rate; it is recorded in the .syldb and .sylsp headers and profile/query reject a mismatch. 1 keeps every tag")]
+    pub subsample_rate: u64,
+
+    #[clap(long="min-read-length", default_value_t = 0, help_heading = "ALGORITHM", help = "Skip reads shorter than this many bases before tag extraction (adapter dimers and fragments rarely hold a full recognition site). Applies to read inputs only, not genomes; for paired reads each mate is checked. 0 keeps every read")]
+    pub min_read_length: usize,
+
+    #[clap(long="strict-length", alias="tag-length-filter", help_heading = "ALGORITHM", help = "Drop tags whose length differs from the enzyme tag length (e.g. a site match shorter than the tag). Without this flag such tags are kept and only counted in a warning")]
+    pub strict_length: bool,
+
+    #[clap(long="error-correct", help_heading = "ALGORITHM", help = "Two-pass read error correction: after a sample's tags are extracted, rewrite each tag that has a single-substitution neighbor at least --error-correct-ratio times as abundant into that neighbor before hashing. Recovers tags carrying a sequencing error; applies to read inputs only")]
+    pub error_correct: bool,
+
+    #[clap(long="error-correct-ratio", default_value_t = 10.0, help_heading = "ALGORITHM", help = "With --error-correct, how many times more abundant than a tag its single-substitution neighbor must be to absorb it (must be > 1)")]
+    pub error_correct_ratio: f64,
+
+    #[clap(long="prefilter-db", help_heading = "ALGORITHM", help = "Reference panel (.syldb from extract) for targeted studies: keep a read's tags only if at least one of them is in the panel, discarding off-target reads early. For paired reads the whole fragment is kept or dropped. Applies to read inputs only")]
+    pub prefilter_db: Option<String>,
+
+    #[clap(long="store-seq", help_heading = "OUTPUT", help = "Store tag sequences in .syldb/.sylsp files (needed by `inspect --validate`; increases file size)")]
+    pub store_seq: bool,
+
+    #[clap(long="fnv-tag-hash", help_heading = "ALGORITHM", help = "Hash tags with the legacy FNV-1a function instead of the default 2-bit packed mm_hash64, to stay compatible with .syldb/.sylsp files extracted by older versions")]
+    pub fnv_tag_hash: bool,
+
+    #[clap(long="index-sequences", help_heading = "OUTPUT", help = "Write a <output>.seqidx sidecar mapping tag hashes to tag sequences next to the combined .syldb/.sylsp, so `inspect` can show real sequences while the main file stays compact")]
+    pub index_sequences: bool,
+
+    #[clap(long="force", help_heading = "OUTPUT", help = "Overwrite existing output files")]
+    pub force: bool,
+
+    #[clap(long="temp-dir", help_heading = "OUTPUT", help = "Directory for temporary files while writing outputs; outputs are renamed into place only after a complete write [default: output directory]")]
+    pub temp_dir: Option<String>,
+
+    #[clap(long="progress-json", help_heading = "OUTPUT", help = "Periodically write a one-line JSON status {phase, samples_done, samples_total, elapsed} to this file for workflow managers; the file is replaced atomically and ends with phase \"done\"")]
+    pub progress_json: Option<String>,
+
+    #[clap(long="dry-run", help = "Resolve all inputs and print the planned inputs, sample names and output paths without processing anything")]
+    pub dry_run: bool,
+
+    #[clap(long="recognition-site-report", help_heading = "OUTPUT", help = "Write a TSV counting, per input file, the enzyme recognition sites found, how many yielded valid tags, and why the rest were lost (ambiguous bases such as N, site cut off at a sequence end, low complexity, adapter, subsampling, duplicate within a sequence). Re-reads the inputs after extraction; --genome-archive members are not included")]
+    pub recognition_site_report: Option<String>,
+
+    #[clap(long="keep-individual", help_heading = "OUTPUT", help = "Also write one .sylsp/.syldb per input file in addition to the combined output")]
+    pub keep_individual: bool,
+
+    #[clap(long="separate-samples", help_heading = "OUTPUT", help = "Write one .sylsp per input read file instead of a combined .sylsp")]
+    pub separate_samples: bool,
+
+    #[clap(long="depth-normalize", help_heading = "OUTPUT", help = "Record each sample's total tag count in the header of the combined .sylsp, so `profile --depth-normalize` can bring samples of very different sequencing depth to a common depth")]
+    pub depth_normalize: bool,
 }
 
 #[derive(Args, Default)]
@@ -141,6 +259,9 @@ pub struct SketchArgs {
     #[clap(long="fpr", default_value_t = 0.001, help_heading = "ALGORITHM", help = "False positive rate for deduplication")]
     pub fpr: f64,
 
+    #[clap(long="max-dedup-count", help_heading = "ALGORITHM", help = "Stop deduplicating a k-mer once its count reaches this value. Higher caps remove more PCR duplicates from high-coverage k-mers but keep more read pairs in memory [default: 10000 for single-end reads, uncapped for paired reads]")]
+    pub max_dedup_count: Option<u32>,
+
     #[clap(long="no-pseudotax", help_heading = "ALGORITHM", help = "Disable pseudotaxonomy tracking")]
     pub no_pseudotax: bool,
 
@@ -171,6 +292,18 @@ pub struct SketchArgs {
     #[clap(short='l', long="list-sequence", help_heading = "INPUT", help = "File containing list of input sequences")]
     pub list_sequence: Option<String>,
 
+    #[clap(long="force", help_heading = "OUTPUT", help = "Overwrite existing output files")]
+    pub force: bool,
+
+    #[clap(long="temp-dir", help_heading = "OUTPUT", help = "Directory for temporary files while writing outputs; outputs are renamed into place only after a complete write [default: output directory]")]
+    pub temp_dir: Option<String>,
+
+    #[clap(long="progress-json", help_heading = "OUTPUT", help = "Periodically write a one-line JSON status {phase, samples_done, samples_total, elapsed} to this file for workflow managers; the file is replaced atomically and ends with phase \"done\"")]
+    pub progress_json: Option<String>,
+
+    #[clap(long="dry-run", help = "Resolve all inputs and print the planned inputs, sample names and output paths without sketching anything")]
+    pub dry_run: bool,
+
     // 用于兼容性的字段
     pub files: Vec<String>,
 }
@@ -189,6 +322,10 @@ pub struct ContainArgs {
     pub min_number_kmers: f64,
     #[clap(short, long="minimum-ani", help_heading = "ALGORITHM", help = "Minimum adjusted ANI to consider (0-100). Default is 90 for query and 95 for profile. Smaller than 95 for profile will give inaccurate results." )]
     pub minimum_ani: Option<f64>,
+    #[clap(long="min-shared-tags", default_value_t = 20, help_heading = "ALGORITHM", help = "Minimum number of tags shared between sample and reference to report a hit. Lower it for shallow samples or small genomes")]
+    pub min_shared_tags: usize,
+    #[clap(long="min-coverage", help_heading = "ALGORITHM", help = "Minimum fraction of reference tags observed in the sample (0-1) [default: 0.01]")]
+    pub min_coverage: Option<f64>,
     #[clap(short, default_value_t = 3, help = "Number of threads")]
     pub threads: usize,
     #[clap(short='s', long="sample-threads", help = "Number of samples to be processed concurrently. Default: (# of total threads / 3) + 1 for profile, 1 for query")]
@@ -220,8 +357,8 @@ pub struct ContainArgs {
     #[clap(short='2', long="second-pairs", num_args=1.., help = "Second pairs for raw paired-end reads (fastx/gzip)", help_heading = "extracting")]
     pub second_pair: Vec<String>,
 
-    #[clap(short, default_value_t = 200, help_heading = "extracting", help = "Subsampling rate. Does nothing for pre-extracted files")]
-    pub c: usize,
+    #[clap(short, help_heading = "extracting", help = "Subsampling rate. Pre-sketched files with different c values are compared at max(database c, sample c); a larger -c downsamples both further")]
+    pub c: Option<usize>,
     #[clap(short,long="individual-records", help_heading = "extracting", help = "Use individual records (e.g. contigs) for database construction instead. Does nothing for pre-extracted files")]
     pub individual: bool,
     #[clap(long="min-spacing", default_value_t = 30, help_heading = "extracting", help = "Minimum spacing between selected 2bRAD tags on the database genomes. Does nothing for pre-extracted files")]
@@ -229,6 +366,16 @@ pub struct ContainArgs {
 
     #[clap(short='o',long="output-file", help = "Output to this file (TSV format). [default: stdout]", help_heading="INPUT/OUTPUT")]
     pub out_file_name: Option<String>,
+    #[clap(long="sort-by", value_parser = ["ani", "abundance", "coverage", "name", "shared_tags"], help_heading="INPUT/OUTPUT", help = "Order each sample's hits by this metric (descending; name ascending). [default: database order]")]
+    pub sort_by: Option<String>,
+    #[clap(long="genome-name-map", alias="name-map", help_heading="INPUT/OUTPUT", help = "Two-column TSV (genome ID, display name) used to label genomes in the output, e.g. organism names instead of accessions. Genome IDs are matched after stripping paths and extensions; unmapped genomes keep their ID")]
+    pub genome_name_map: Option<String>,
+    #[clap(long="report-absent", help_heading="INPUT/OUTPUT", help = "List every database genome for every sample: genomes that were not detected (no shared tags or rejected by a threshold) are reported with all statistics set to 0")]
+    pub report_absent: bool,
+    #[clap(long="unfiltered-dump", help_heading="INPUT/OUTPUT", help = "Write every candidate hit before filtering (ANI, coverage, shared tags) to this TSV, with the threshold that rejected it. Useful for tuning --minimum-ani and coverage thresholds")]
+    pub unfiltered_dump: Option<String>,
+    #[clap(long="precision", alias="output-precision", help_heading="INPUT/OUTPUT", help = "Decimal places for every numeric column of the output. [default: 2-6 depending on the column]")]
+    pub precision: Option<usize>,
     #[clap(long="log-reassignments", help = "Output information for how 2bRAD tags for genomes are reassigned during `profile`. Caution: can be verbose and slows down computation.")]
     pub log_reassignments: bool,
 
@@ -250,7 +397,10 @@ pub struct ContainArgs {
     pub no_adj: bool,
     #[clap(long="mean-coverage", help_heading = "ALGORITHM", help = "Use the robust mean coverage estimator instead of median estimator", hide=true )]
     pub mean_coverage: bool,
-
+    #[clap(long="force", help = "Overwrite existing output files")]
+    pub force: bool,
+    #[clap(long="temp-dir", help_heading="INPUT/OUTPUT", help = "Directory for temporary files while writing the output file; it is renamed into place only after a complete write [default: output directory]")]
+    pub temp_dir: Option<String>,
 }
 
 #[derive(Args)]
@@ -263,6 +413,18 @@ pub struct InspectArgs {
     pub log_path: Option<String>,
     #[clap(long="tsv-name", default_value = "tag_matrix.tsv", help = "Name of the TSV file for tag count matrix")]
     pub tsv_name: String,
+    #[clap(long="min-count", default_value_t = 1, help = "Exclude tags whose total count across all samples is below this value from the statistics and matrix")]
+    pub min_count: usize,
+    #[clap(short='e', long="enzyme", default_value = "BcgI", help = "Restriction enzyme used for extraction; used to estimate the expected tag count per genome")]
+    pub enzyme: String,
+    #[clap(long="validate", help = "Check that stored tag sequences are canonical and that no reverse-complement pairs are both present (requires extract --store-seq)")]
+    pub validate: bool,
+    #[clap(long="gc-report", alias="strandedness-report", help = "Report per-base composition, mean GC and the GC distribution of stored tag sequences for each genome or sample, to spot enzyme bias or contamination (requires extract --store-seq)")]
+    pub gc_report: bool,
+    #[clap(long="force", help = "Overwrite existing output files")]
+    pub force: bool,
+    #[clap(long="temp-dir", help = "Directory for temporary files while writing outputs [default: output directory]")]
+    pub temp_dir: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -275,9 +437,30 @@ pub struct ProfileArgs {
     
     #[arg(long, help_heading = "ALGORITHM", help = "Minimum adjusted ANI to consider (0-100). Default is 95 for profile. Smaller than 95 for profile will give inaccurate results.")]
     pub minimum_ani: Option<f64>,
+
+    #[arg(long, default_value_t = 20, help_heading = "ALGORITHM", help = "Minimum number of tags shared between sample and genome for the genome to be profiled")]
+    pub min_shared_tags: usize,
+
+    #[arg(long, alias = "coverage-depth-threshold", default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help_heading = "ALGORITHM", help = "Count a sample tag as present only if it was observed at least this many times in the sample. 2 or 3 discards singleton tags from sequencing errors and removes spurious low-abundance hits, at some cost in sensitivity for shallow samples")]
+    pub min_tag_depth: usize,
+
+    #[arg(long, help_heading = "ALGORITHM", help = "Minimum fraction of a genome's tags observed in the sample (0-1) [default: 0.01]")]
+    pub min_coverage: Option<f64>,
+
+    #[arg(long, default_value_t = 0.0, help_heading = "ALGORITHM", help = "Minimum fraction of the genome length (0-1) spanned by the shared tags, summed over contigs from the first to the last shared tag. Rejects genomes detected only through tags clustered in a repeat region. Needs a database with genome sizes; 0 disables the filter")]
+    pub min_breadth: f64,
+
+    #[arg(long, default_value_t = 50, help_heading = "ALGORITHM", help = "Exclude genomes with less than this number of extracted 2bRAD tags")]
+    pub min_number_kmers: usize,
     
     #[arg(long, default_value_t = 1)]
     pub threads: usize,
+
+    #[arg(long, default_value = "auto", value_parser = ["auto", "samples", "genomes"], help = "Level that is processed in parallel: samples (one sample file per thread, genomes compared sequentially) or genomes (one sample at a time, database genomes compared in parallel). Only one level runs in parallel to avoid oversubscribing threads. auto uses samples when there are at least as many sample files as threads")]
+    pub parallel_over: String,
+
+    #[arg(long, default_value = "genome", value_parser = ["genome", "contig"], help_heading = "ALGORITHM", help = "Unit that abundances are reported for. genome sums the entries of a multi-record genome (contigs, or per-contig sketches) into one genome; contig profiles each database entry on its own, without genome-size corrections")]
+    pub aggregate: String,
     
     #[arg(long)]
     pub out_file_name: Option<String>,
@@ -290,18 +473,144 @@ pub struct ProfileArgs {
     
     #[arg(long, help = "Taxonomy annotation file (e.g., taxonomy.txt) for species-level aggregation")]
     pub taxonomy_file: Option<String>,
+
+    #[arg(long, default_value = "gtdb", value_parser = ["gtdb", "ncbi", "custom"], help = "Taxonomy file format: gtdb (accession, d__;p__;...;s__ string, further columns), ncbi (accession, 'superkingdom;phylum;...;species' lineage, optionally as rank:name), custom (accession, then one column per rank from kingdom to species)")]
+    pub taxonomy_format: String,
+
+    #[arg(long, default_value = "auto", value_parser = ["auto", "yes", "no"], help = "Whether the first line of --taxonomy-file is a header. auto treats it as a header when its first column is a column name such as accession or user_genome, or (gtdb) when it holds no d__/p__/... ranks")]
+    pub taxonomy_has_header: String,
+
+    #[arg(long, default_value_t = crate::contain::DEFAULT_TAXONOMY_MAX_SKIPPED, help = "Fail when more than this fraction of --taxonomy-file data lines cannot be parsed (usually the wrong --taxonomy-format or delimiter). 1 only warns")]
+    pub taxonomy_max_skipped: f64,
     
     #[arg(long, default_value_t = 10.0, help_heading = "ALGORITHM", help = "Minimum G-score threshold for species filtering. G-score = sqrt(reads_count * tag_count). Default is 10.0")]
     pub gscore_threshold: f64,
-}
 
-#[derive(Debug)]
-pub struct QueryArgs {
-    pub query_file: String,
-    pub db_file: String,
-    pub out_file_name: Option<String>,
-    pub minimum_ani: f64,
-    pub threads: usize,
+    #[arg(long, default_value_t = 0.0, help_heading = "ALGORITHM", help = "Pseudocount added to both reads_count and tag_count before computing the G-score, i.e. sqrt((reads_count + p) * (tag_count + p)). With the default 0, a species with zero reads or zero tags has a G-score of 0 and is dropped by any positive --gscore-threshold")]
+    pub gscore_pseudocount: f64,
+
+    #[arg(long, default_value_t = 1, help_heading = "ALGORITHM", help = "Report a species in a sample only if at least this many of its reference genomes are detected there (requires --taxonomy-file)")]
+    pub min_genomes_per_species: usize,
+
+    #[arg(long, help_heading = "ALGORITHM", help = "Merge strain-level species into one row before species aggregation: species names are cut to genus + epithet and GTDB-style uppercase suffixes are removed, e.g. 'Prevotella copri_A' and 'Prevotella copri_B' both become 'Prevotella copri'. Names with 'sp.' are left unchanged (requires --taxonomy-file)")]
+    pub collapse_strains: bool,
+
+    #[arg(long, help = "Overwrite existing output files")]
+    pub force: bool,
+
+    #[arg(long, help = "Directory for temporary files while writing outputs [default: output directory]")]
+    pub temp_dir: Option<String>,
+
+    #[arg(long, help = "Periodically write a one-line JSON status {phase, samples_done, samples_total, elapsed} to this file for workflow managers; the file is replaced atomically and ends with phase \"done\"")]
+    pub progress_json: Option<String>,
+
+    #[arg(long, help = "Buffer size in bytes for the output file writers. Larger buffers mean fewer write calls for very large abundance matrices [default: 8192]")]
+    pub write_buffer_size: Option<usize>,
+
+    #[arg(long, help = "Only profile against the genome IDs listed in this file (one per line)")]
+    pub include_genomes: Option<String>,
+
+    #[arg(long, help = "Exclude the genome IDs listed in this file (one per line), e.g. host or contaminant genomes. Applied after --include-genomes")]
+    pub exclude_genomes: Option<String>,
+
+    #[arg(long, alias = "name-map", help = "Two-column TSV (genome ID, display name) used to label genomes in the genome-level results and abundance matrix, e.g. organism names instead of accessions. Unmapped genomes keep their ID")]
+    pub genome_name_map: Option<String>,
+
+    #[arg(long, help = "Host genome database (.syldb, e.g. human or plant). Sample tags found in it are removed before profiling and the host fraction of each sample is reported")]
+    pub host_db: Option<String>,
+
+    #[arg(long, requires = "host_db", help = "With --host-db, keep tags that occur in both the host and the target database instead of removing them")]
+    pub keep_shared_host_tags: bool,
+
+    #[arg(long, help = "Keep every database genome file even when two paths have the same GCF_/GCA_ accession or identical tags. By default only the first such path is profiled")]
+    pub no_dealias: bool,
+
+    #[arg(long, help = "Print elapsed time per profiling phase at the end of the run")]
+    pub timing: bool,
+
+    #[arg(long, help = "Write every candidate genome hit before filtering (ANI, coverage, shared tags) to this TSV, with the threshold that rejected it. Useful for tuning --minimum-ani and coverage thresholds")]
+    pub unfiltered_dump: Option<String>,
+
+    #[arg(long, help = "List every database genome for every sample in the results and matrices: genomes that were not detected are reported with zero abundance and zero shared tags, giving complete cross-sample tables")]
+    pub report_absent: bool,
+
+    #[arg(long, help = "Directory for one <sample>.shared_tags.tsv per sample listing, for every detected genome, the shared tag hashes behind the call (and tag sequences if the database was extracted with --store-seq), to verify surprising hits against the reference")]
+    pub shared_tags_dump: Option<String>,
+
+    #[arg(long, help = "Directory for one checkpoint per sample file, written after its initial query and winner-table reassignment, so an interrupted run can continue with --resume")]
+    pub checkpoint: Option<String>,
+
+    #[arg(long, requires = "checkpoint", conflicts_with_all = ["unfiltered_dump", "shared_tags_dump"], help = "Reuse the checkpoints in --checkpoint for sample files that were already processed against the same, unmodified database (same size and modification time) with the same filtering and sample preprocessing options, and only compute the rest. Cannot be combined with --unfiltered-dump or --shared-tags-dump, which would miss the resumed samples")]
+    pub resume: bool,
+
+    #[arg(long, alias = "output-precision", help = "Decimal places for every numeric column in the result table and abundance matrices. [default: 4 for genome and 6 for species abundances, 2-3 elsewhere]")]
+    pub precision: Option<usize>,
+
+    #[arg(long, help = "Log winner-table size and how many tags are shared between genomes during reassignment")]
+    pub log_reassignments: bool,
+
+    #[arg(long, default_value = "wide", value_parser = ["wide", "long"], help = "Abundance matrix layout: wide (taxa x samples) or long (one row per nonzero sample-taxon pair)")]
+    pub matrix_format: String,
+
+    #[arg(long, help = "Write abundance matrices only to their TSV files instead of also echoing them into the report on stdout/-o, which then keeps just the summary tables")]
+    pub matrix_only_tsv: bool,
+
+    #[arg(long, default_value = "relative", value_parser = ["relative", "cpm", "raw"], help = "Units of the abundance matrices: relative (percent taxonomic abundance), cpm (shared tags per million shared tags in the sample) or raw (shared tag counts)")]
+    pub abundance_units: String,
+
+    #[arg(long, help = "With --matrix-format long, prepend a Run column with this ID to every row, so the long tables of many profile runs can be concatenated into one table")]
+    pub run_id: Option<String>,
+
+    #[arg(long, default_value = "zero", value_parser = ["zero", "na"], help = "How the genome abundance matrix marks genomes that were never compared against a sample (e.g. below --min-number-kmers): zero, or NA to tell them apart from compared-but-undetected genomes (0)")]
+    pub missing_value: String,
+
+    #[arg(long, help = "Write a sample x sample distance matrix computed from the final abundance table to this file")]
+    pub distance_matrix: Option<String>,
+
+    #[arg(long, default_value = "bray-curtis", value_parser = ["bray-curtis", "cosine", "aitchison"], help = "Distance metric for --distance-matrix. aitchison is the Euclidean distance after a CLR transform")]
+    pub distance_metric: String,
+
+    #[arg(long, help = "Write the centered-log-ratio (CLR) transformed abundance matrix to this file, e.g. for PCA")]
+    pub clr_output: Option<String>,
+
+    #[arg(long, default_value_t = 0.01, help = "Pseudocount added to every abundance (percent) before the CLR transform, so zeros can be logged")]
+    pub clr_pseudocount: f64,
+
+    #[arg(long, help_heading = "ALGORITHM", help = "Rarefy every sample to this many tag observations before profiling; samples with fewer observations are dropped")]
+    pub rarefy: Option<usize>,
+
+    #[arg(long, default_value_t = 42, help_heading = "ALGORITHM", help = "Random seed for --rarefy and --preview")]
+    pub rarefy_seed: u64,
+
+    #[arg(long, conflicts_with = "rarefy", help_heading = "ALGORITHM", help = "Rarefy the samples of each combined .sylsp to the depth of its shallowest sample, using the per-sample tag totals recorded by `extract --depth-normalize`, so deep samples do not dominate statistics pooled across the file. Files without recorded totals use the tag counts they contain")]
+    pub depth_normalize: bool,
+
+    #[arg(long, help_heading = "ALGORITHM", help = "Fast preview: profile only this fraction (0-1] of each sample's tags, randomly chosen with --rarefy-seed. Results are approximate")]
+    pub preview: Option<f64>,
+
+    #[arg(long, help = "Also write a contig-level table (shared tags, total tags, coverage per contig and sample) to this file, e.g. to tell plasmid from chromosome hits")]
+    pub per_contig: Option<String>,
+
+    #[arg(long, alias = "output-stats-json", help = "Write a JSON run summary to this file: sample and database genome counts, total/shared tags and detected genomes per sample, species detected, G-score threshold and phase timings")]
+    pub run_summary: Option<String>,
+
+    #[arg(long, help = "With --taxonomy-file, list for each species and sample the reference genome with the most shared tags (ties: higher ANI), e.g. to pick a genome for downstream read mapping")]
+    pub species_representative_genome: bool,
+
+    #[arg(long, default_value = "winner", value_parser = ["winner", "em"], help_heading = "ALGORITHM", help = "Abundance estimation: winner assigns each shared tag to the best genome; em splits shared tags among candidate genomes in proportion to their estimated abundance")]
+    pub abundance_method: String,
+
+    #[arg(long, help_heading = "ALGORITHM", help = "Report taxonomic abundance as cell-number abundance: divide each genome's sequence abundance by its genome size (gn_size in the .syldb) before normalizing. Sequence abundance is unchanged")]
+    pub size_normalize: bool,
+
+    #[arg(long, help = "Also write a genome-level coverage_corrected_<tsv-name> matrix: each genome's effective coverage divided by its genome size (gn_size in the .syldb), renormalized per sample. The main matrix is unchanged")]
+    pub coverage_corrected_matrix: bool,
+
+    #[arg(long, help = "Skip samples with fewer than this many tag observations (e.g. failed libraries) and leave them out of the matrix")]
+    pub min_sample_tags: Option<usize>,
+
+    #[arg(long, value_parser = ["ani", "abundance", "coverage", "name", "shared_tags"], help = "Order genome-level results by this metric (descending; name ascending) [default: genome ID, then ANI]")]
+    pub sort_by: Option<String>,
 }
 
 #[derive(Args)]
@@ -314,6 +623,14 @@ pub struct ViewArgs {
     pub log_path: Option<String>,
     #[clap(long="tsv-name", default_value = "kmer_matrix.tsv", help = "Name of the TSV file for k-mer count matrix")]
     pub tsv_name: String,
+    #[clap(long="long-format", help = "Write the k-mer matrix as sparse (kmer_hash, sample, count) triples, skipping zeros")]
+    pub long_format: bool,
+    #[clap(long="min-count", default_value_t = 1, help = "Exclude k-mers whose total count across all samples is below this value from the statistics and matrix")]
+    pub min_count: usize,
+    #[clap(long="force", help = "Overwrite existing output files")]
+    pub force: bool,
+    #[clap(long="temp-dir", help = "Directory for temporary files while writing outputs [default: output directory]")]
+    pub temp_dir: Option<String>,
 }
 
 #[derive(Args)]
@@ -326,5 +643,100 @@ pub struct MarkArgs {
     
     #[clap(long="debug", help = "Enable debug output")]
     pub debug: bool,
+    
+    #[clap(long="force", help = "Overwrite an existing output file given with -o")]
+    pub force: bool,
+
+    #[clap(long="temp-dir", help = "Directory for temporary files while writing the output [default: output directory]")]
+    pub temp_dir: Option<String>,
+
+    #[clap(long="collapse-rc", help = "Treat a tag and its reverse complement as the same tag when deciding uniqueness. Only matters for databases extracted with --strand-specific; needs tag sequences stored with --store-seq")]
+    pub collapse_rc: bool,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    #[clap(help = "Abundance matrix TSV written by profile (genome-level or species-level)")]
+    pub matrix: String,
+
+    #[clap(short='o', long="output", default_value = "report.html", help = "Output HTML file")]
+    pub output: String,
+
+    #[clap(long="top", default_value_t = 10, help = "Number of most abundant taxa to show; the rest are grouped as 'Other'")]
+    pub top: usize,
+
+    #[clap(long="taxonomy-file", help = "Taxonomy annotation file used to show species names for a genome-level matrix")]
+    pub taxonomy_file: Option<String>,
+
+    #[clap(long="taxonomy-format", default_value = "gtdb", value_parser = ["gtdb", "ncbi", "custom"], help = "Format of --taxonomy-file, as in profile")]
+    pub taxonomy_format: String,
+
+    #[clap(long="taxonomy-has-header", default_value = "auto", value_parser = ["auto", "yes", "no"], help = "Whether the first line of --taxonomy-file is a header, as in profile")]
+    pub taxonomy_has_header: String,
+
+    #[clap(long="force", help = "Overwrite an existing output file")]
+    pub force: bool,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    #[clap(num_args=1.., help = "*.syldb/*.sylsp files to check")]
+    pub files: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[clap(long="manifest", alias="jobs-file", help = "Job manifest: YAML with a top-level `jobs` list of {name, command, args}, or TSV lines of name<TAB>command<TAB>arguments. Arguments are exactly those of the subcommand; relative paths are resolved against the current directory")]
+    pub manifest: String,
+
+    #[clap(long="fail-fast", help = "Stop at the first failed job instead of running the remaining ones")]
+    pub fail_fast: bool,
+}
+
+#[derive(Args)]
+pub struct SummaryArgs {
+    #[clap(help = "Combined .sylsp file with several samples (from extract)")]
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[clap(help = "Sketch database (.syldb) or sample (.sylsp) from `sketch`; one signature is written per genome or sample. Hashes are meta2bseek k-mer hashes, labelled with hash_function 0.meta2bseek_mm64 rather than sourmash's 0.murmur64, so they cannot be mixed with signatures sourmash computed itself")]
+    pub file: String,
+
+    #[clap(short='o', long="output", help_heading = "OUTPUT", help = "Write the signature JSON to this file. [default: stdout]")]
+    pub output: Option<String>,
+
+    #[clap(long="force", help_heading = "OUTPUT", help = "Overwrite existing output files")]
+    pub force: bool,
+}
+
+#[derive(Args)]
+pub struct ExportFastaArgs {
+    #[clap(help = "Database (.syldb) or sample (.sylsp) from `extract` with tag sequences, i.e. extracted with --store-seq or with a .seqidx sidecar from --index-sequences. Headers are >genome|contig|tagN for databases and >sample|tagN for samples")]
+    pub file: String,
+
+    #[clap(short='o', long="output", help_heading = "OUTPUT", help = "Write the FASTA to this file. [default: stdout]")]
+    pub output: Option<String>,
+
+    #[clap(long="force", help_heading = "OUTPUT", help = "Overwrite existing output files")]
+    pub force: bool,
+}
+
+#[derive(Args)]
+pub struct CompareProfilesArgs {
+    #[clap(help = "Abundance matrix TSV from the first (reference) profile run, genome-level or species-level in the default wide format")]
+    pub old: String,
+
+    #[clap(help = "Abundance matrix TSV from the second profile run")]
+    pub new: String,
+
+    #[clap(short='o', long="output", help_heading = "OUTPUT", help = "Write the per-taxon deltas (Taxon, Sample, Old, New, Delta, Status) to this TSV. [default: stdout, after the per-sample summary]")]
+    pub output: Option<String>,
+
+    #[clap(long="min-delta", default_value_t = 0.0, help_heading = "OUTPUT", help = "Only list taxa whose abundance changed by at least this much in a sample")]
+    pub min_delta: f64,
+
+    #[clap(long="force", help_heading = "OUTPUT", help = "Overwrite existing output files")]
+    pub force: bool,
 }
-    
\ No newline at end of file
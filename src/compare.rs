@@ -0,0 +1,204 @@
+use crate::cmdline::CompareProfilesArgs;
+use crate::constants::{natural_cmp, OutputOptions};
+use crate::distance::bray_curtis;
+use crate::report::{parse_abundance_matrix, AbundanceTable};
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+// 同一taxon在同一样本中新旧两次profile的丰度
+#[derive(Debug, Clone, PartialEq)]
+struct TaxonDelta {
+    taxon: String,
+    sample: String,
+    old: f64,
+    new: f64,
+}
+
+impl TaxonDelta {
+    fn delta(&self) -> f64 {
+        self.new - self.old
+    }
+
+    fn status(&self) -> &'static str {
+        match (self.old > 0.0, self.new > 0.0) {
+            (false, true) => "gained",
+            (true, false) => "lost",
+            _ if self.old == self.new => "unchanged",
+            _ => "changed",
+        }
+    }
+}
+
+// 每个样本的整体差异
+#[derive(Debug, Clone, PartialEq)]
+struct SampleComparison {
+    sample: String,
+    bray_curtis: f64,
+    // 两个丰度向量都为常数时相关系数没有定义
+    pearson: Option<f64>,
+    gained: usize,
+    lost: usize,
+    shared: usize,
+}
+
+#[derive(Debug, Default)]
+struct ProfileComparison {
+    samples: Vec<SampleComparison>,
+    // 至少一边丰度非零的(taxon, 样本)，按taxon、样本排序
+    deltas: Vec<TaxonDelta>,
+    only_old_samples: Vec<String>,
+    only_new_samples: Vec<String>,
+}
+
+pub fn compare_profiles(args: CompareProfilesArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, None);
+    let old = read_matrix(&args.old)?;
+    let new = read_matrix(&args.new)?;
+    let comparison = compare_tables(&old, &new)?;
+
+    for sample in &comparison.only_old_samples {
+        eprintln!("Warning: sample {} is only in {}; skipped", sample, args.old);
+    }
+    for sample in &comparison.only_new_samples {
+        eprintln!("Warning: sample {} is only in {}; skipped", sample, args.new);
+    }
+
+    println!("Sample\tBray_Curtis\tPearson_r\tGained\tLost\tShared");
+    for s in &comparison.samples {
+        let pearson = s.pearson.map_or_else(|| "NA".to_string(), |r| format!("{:.4}", r));
+        println!("{}\t{:.4}\t{}\t{}\t{}\t{}", s.sample, s.bray_curtis, pearson, s.gained, s.lost, s.shared);
+    }
+
+    let mut writer = match &args.output {
+        Some(path) => Box::new(BufWriter::new(output.create_file(path)?)) as Box<dyn Write>,
+        None => {
+            println!();
+            Box::new(BufWriter::new(std::io::stdout())) as Box<dyn Write>
+        }
+    };
+    writeln!(writer, "Taxon\tSample\tOld\tNew\tDelta\tStatus")?;
+    for d in comparison.deltas.iter().filter(|d| d.delta().abs() >= args.min_delta) {
+        writeln!(writer, "{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{}", d.taxon, d.sample, d.old, d.new, d.delta(), d.status())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_matrix(path: &str) -> Result<AbundanceTable> {
+    let file = File::open(path).with_context(|| format!("Failed to open abundance matrix: {}", path))?;
+    parse_abundance_matrix(BufReader::new(file)).with_context(|| format!("Failed to parse abundance matrix: {}", path))
+}
+
+fn abundance(table: &AbundanceTable, taxon: &str, sample_index: Option<usize>) -> f64 {
+    sample_index
+        .and_then(|i| table.taxa.iter().find(|(name, _)| name == taxon).map(|(_, values)| values[i]))
+        .unwrap_or(0.0)
+}
+
+// 两个矩阵中都有的样本逐一比较；某一边缺少的taxon按0计
+fn compare_tables(old: &AbundanceTable, new: &AbundanceTable) -> Result<ProfileComparison> {
+    let mut comparison = ProfileComparison {
+        only_old_samples: old.samples.iter().filter(|s| !new.samples.contains(s)).cloned().collect(),
+        only_new_samples: new.samples.iter().filter(|s| !old.samples.contains(s)).cloned().collect(),
+        ..Default::default()
+    };
+    let mut samples: Vec<&String> = old.samples.iter().filter(|s| new.samples.contains(s)).collect();
+    if samples.is_empty() {
+        return Err(anyhow!("The two abundance matrices have no samples in common"));
+    }
+    samples.sort_by(|a, b| natural_cmp(a, b));
+
+    let mut taxa: Vec<&str> = old.taxa.iter().chain(&new.taxa)
+        .map(|(name, _)| name.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    taxa.sort_by(|a, b| natural_cmp(a, b));
+
+    for sample in samples {
+        let old_index = old.samples.iter().position(|s| s == sample);
+        let new_index = new.samples.iter().position(|s| s == sample);
+        let old_values: Vec<f64> = taxa.iter().map(|taxon| abundance(old, taxon, old_index)).collect();
+        let new_values: Vec<f64> = taxa.iter().map(|taxon| abundance(new, taxon, new_index)).collect();
+
+        let mut summary = SampleComparison {
+            sample: sample.clone(),
+            bray_curtis: bray_curtis(&old_values, &new_values),
+            pearson: pearson(&old_values, &new_values),
+            gained: 0,
+            lost: 0,
+            shared: 0,
+        };
+        for ((taxon, &o), &n) in taxa.iter().zip(&old_values).zip(&new_values) {
+            if o <= 0.0 && n <= 0.0 {
+                continue;
+            }
+            let delta = TaxonDelta { taxon: taxon.to_string(), sample: sample.clone(), old: o, new: n };
+            match delta.status() {
+                "gained" => summary.gained += 1,
+                "lost" => summary.lost += 1,
+                _ => summary.shared += 1,
+            }
+            comparison.deltas.push(delta);
+        }
+        comparison.samples.push(summary);
+    }
+    comparison.deltas.sort_by(|a, b| natural_cmp(&a.taxon, &b.taxon).then_with(|| natural_cmp(&a.sample, &b.sample)));
+    Ok(comparison)
+}
+
+fn pearson(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 {
+        return None;
+    }
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let cov: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let var_a: f64 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+    let var_b: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+    if var_a == 0.0 || var_b == 0.0 {
+        return None;
+    }
+    Some(cov / (var_a * var_b).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_profiles_reports_deltas_of_perturbed_matrix() {
+        let old = "Genome\tS1\tS2\nG1\t50.0\t20.0\nG2\t30.0\t80.0\nG3\t20.0\t0.0\n";
+        // G1在S1中下降10，G3在S1中消失，G4在S2中新出现；S3只在新矩阵中
+        let new = "Genome\tS1\tS2\tS3\nG1\t40.0\t20.0\t5.0\nG2\t60.0\t70.0\t95.0\nG4\t0.0\t10.0\t0.0\n";
+        let old = parse_abundance_matrix(old.as_bytes()).unwrap();
+        let new = parse_abundance_matrix(new.as_bytes()).unwrap();
+        let comparison = compare_tables(&old, &new).unwrap();
+
+        assert_eq!(comparison.only_new_samples, vec!["S3".to_string()]);
+        assert!(comparison.only_old_samples.is_empty());
+
+        let find = |taxon: &str, sample: &str| comparison.deltas.iter().find(|d| d.taxon == taxon && d.sample == sample).unwrap();
+        assert!((find("G1", "S1").delta() + 10.0).abs() < 1e-12);
+        assert_eq!(find("G1", "S2").status(), "unchanged");
+        assert_eq!(find("G2", "S1").status(), "changed");
+        assert_eq!(find("G3", "S1").status(), "lost");
+        assert_eq!(find("G4", "S2").status(), "gained");
+        // 两边都为0的组合不列出
+        assert!(!comparison.deltas.iter().any(|d| d.taxon == "G3" && d.sample == "S2"));
+
+        let s1 = &comparison.samples[0];
+        assert_eq!((s1.sample.as_str(), s1.gained, s1.lost, s1.shared), ("S1", 0, 1, 2));
+        assert!((s1.bray_curtis - 60.0 / 200.0).abs() < 1e-12);
+        let s2 = &comparison.samples[1];
+        assert_eq!((s2.gained, s2.lost, s2.shared), (1, 0, 2));
+        assert!((s2.bray_curtis - 20.0 / 200.0).abs() < 1e-12);
+        assert!(s2.pearson.unwrap() > 0.9);
+
+        let identical = compare_tables(&old, &old).unwrap();
+        assert!(identical.samples.iter().all(|s| s.bray_curtis == 0.0 && (s.pearson.unwrap() - 1.0).abs() < 1e-12));
+    }
+}
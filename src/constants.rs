@@ -15,7 +15,6 @@ pub const DEREP_PROFILE_ANI: f64 = 0.975;
 pub const MAX_DEDUP_COUNT: u32 = 4;
 pub const MAX_DEDUP_LEN: usize = 10000000;
 */
-pub const DEFAULT_FPR: f64 = 0.0001;
 //pub const MED_KMER_FOR_ID_EST: f64 = 3.;
 
 // 定义Hash类型
@@ -40,3 +39,402 @@ pub fn hash_bytes(bytes: &[u8]) -> Hash {
     }
     hash
 }
+
+// 初始化rayon全局线程池，返回实际使用的线程数。全局池在进程内只能建一次，重复调用
+// （库方式使用或同一进程跑多个模式）复用已有的池；请求的线程数与已有的池不同时告警，
+// 而不是悄悄忽略后一次的-t。threads为0表示不指定
+pub fn init_thread_pool(threads: usize) -> usize {
+    if rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().is_err() {
+        let existing = rayon::current_num_threads();
+        if threads != 0 && threads != existing {
+            eprintln!(
+                "Warning: this process already runs a thread pool with {} threads; ignoring the requested {} threads",
+                existing, threads
+            );
+        }
+    }
+    rayon::current_num_threads()
+}
+
+// 输出文件BufWriter的默认容量，profile可用--write-buffer-size调大
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 8 * 1024;
+
+static TEMP_COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+// 一次运行的输出设置，由各模式入口根据命令行构造后向下传递：
+// force为false时拒绝覆盖已存在的输出（--force）；temp_dir为原子写入的临时文件目录（--temp-dir，
+// 默认为目标所在目录）；write_buffer_size为输出BufWriter的容量（--write-buffer-size）
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputOptions {
+    pub force: bool,
+    pub temp_dir: Option<std::path::PathBuf>,
+    pub write_buffer_size: usize,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            force: false,
+            temp_dir: None,
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+        }
+    }
+}
+
+impl OutputOptions {
+    pub fn new(force: bool, temp_dir: Option<&str>) -> Self {
+        OutputOptions {
+            force,
+            temp_dir: temp_dir.map(std::path::PathBuf::from),
+            ..OutputOptions::default()
+        }
+    }
+
+    pub fn with_write_buffer_size(mut self, bytes: Option<usize>) -> Self {
+        self.write_buffer_size = bytes.unwrap_or(DEFAULT_WRITE_BUFFER_SIZE);
+        self
+    }
+
+    // 进度文件等需要反复替换的输出
+    pub fn overwriting() -> Self {
+        OutputOptions { force: true, ..OutputOptions::default() }
+    }
+
+    pub fn buf_writer<W: std::io::Write>(&self, inner: W) -> std::io::BufWriter<W> {
+        std::io::BufWriter::with_capacity(self.write_buffer_size, inner)
+    }
+
+    fn refuse_existing(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        if !self.force && path.exists() {
+            return Err(anyhow::anyhow!(
+                "Output file {} already exists; use --force to overwrite",
+                path.display()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn create_file<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<std::fs::File> {
+        let path = path.as_ref();
+        self.refuse_existing(path)?;
+        std::fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create output file {}: {}", path.display(), e))
+    }
+
+    // 单个输出文件的预检：目录可写、--temp-dir可写，未--force时文件不能已存在
+    pub fn check_file<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        self.refuse_existing(path)?;
+        check_output_dir(path.parent().unwrap_or_else(|| std::path::Path::new("")))?;
+        if let Some(temp_dir) = &self.temp_dir {
+            check_output_dir(temp_dir)?;
+        }
+        Ok(())
+    }
+
+    // 原子写入：先写到临时文件，成功后再rename到最终路径；
+    // 中途失败则删除临时文件，最终路径上不会出现写了一半的文件
+    pub fn write_atomically<P, F>(&self, path: P, write: F) -> anyhow::Result<()>
+    where
+        P: AsRef<std::path::Path>,
+        F: FnOnce(&mut std::io::BufWriter<std::fs::File>) -> anyhow::Result<()>,
+    {
+        let path = path.as_ref();
+        self.refuse_existing(path)?;
+
+        let tmp_path = self.temp_path_for(path);
+        let result = (|| {
+            let file = std::fs::File::create(&tmp_path)
+                .map_err(|e| anyhow::anyhow!("Failed to create temporary file {}: {}", tmp_path.display(), e))?;
+            let mut writer = self.buf_writer(file);
+            write(&mut writer)?;
+            let file = writer
+                .into_inner()
+                .map_err(|e| anyhow::anyhow!("Failed to flush {}: {}", tmp_path.display(), e.error()))?;
+            file.sync_all()?;
+            move_into_place(&tmp_path, path)
+        })();
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    fn temp_path_for(&self, path: &std::path::Path) -> std::path::PathBuf {
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let tmp_name = format!(
+            ".{}.{}.{}.tmp",
+            file_name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        );
+        let dir = self.temp_dir.clone().unwrap_or_else(|| {
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => std::path::PathBuf::from("."),
+            }
+        });
+        dir.join(tmp_name)
+    }
+}
+
+// 输出预检：在耗时的计算开始前创建输出目录并试写一个临时文件，路径或权限问题立即报错，
+// 而不是算了很久才在最终写出时失败
+pub fn check_output_dir<P: AsRef<std::path::Path>>(dir: P) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+    let dir = if dir.as_os_str().is_empty() { std::path::Path::new(".") } else { dir };
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow::anyhow!("Cannot create output directory {}: {}", dir.display(), e))?;
+    let probe = dir.join(format!(
+        ".meta2bseek_write_test.{}.{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
+    std::fs::File::create(&probe)
+        .map_err(|e| anyhow::anyhow!("Output directory {} is not writable: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+fn move_into_place(tmp_path: &std::path::Path, path: &std::path::Path) -> anyhow::Result<()> {
+    if std::fs::rename(tmp_path, path).is_ok() {
+        return Ok(());
+    }
+    // 临时目录和目标不在同一文件系统时rename会失败：先复制到目标旁边，再在同一目录内rename
+    let staged = temp_path_for_sibling(tmp_path, path);
+    let result = std::fs::copy(tmp_path, &staged)
+        .and_then(|_| std::fs::rename(&staged, path))
+        .map_err(|e| anyhow::anyhow!("Failed to move output into place at {}: {}", path.display(), e));
+    let _ = std::fs::remove_file(tmp_path);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    result
+}
+
+fn temp_path_for_sibling(tmp_path: &std::path::Path, path: &std::path::Path) -> std::path::PathBuf {
+    let tmp_name = tmp_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(tmp_name)
+}
+
+// --progress-json：供Nextflow/Snakemake等流程管理器轮询的进度文件，与给人看的标准错误输出分开。
+// 每次整体原子替换，读取方不会看到写了一半的JSON；阶段切换和结束时必写，其余按PROGRESS_INTERVAL节流
+pub const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+pub const PROGRESS_DONE_PHASE: &str = "done";
+
+#[derive(serde::Serialize)]
+struct ProgressRecord<'a> {
+    phase: &'a str,
+    samples_done: usize,
+    samples_total: usize,
+    elapsed: f64,
+}
+
+pub struct ProgressReporter {
+    path: std::path::PathBuf,
+    start: std::time::Instant,
+    last_write: Option<std::time::Instant>,
+    phase: String,
+    samples_done: usize,
+    samples_total: usize,
+}
+
+impl ProgressReporter {
+    pub fn new<P: AsRef<std::path::Path>>(path: P) -> Self {
+        ProgressReporter {
+            path: path.as_ref().to_path_buf(),
+            start: std::time::Instant::now(),
+            last_write: None,
+            phase: String::new(),
+            samples_done: 0,
+            samples_total: 0,
+        }
+    }
+
+    pub fn phase(&mut self, phase: &str, samples_total: usize) {
+        self.phase = phase.to_string();
+        self.samples_done = 0;
+        self.samples_total = samples_total;
+        self.write();
+    }
+
+    pub fn sample_done(&mut self) {
+        self.samples_done += 1;
+        if self.last_write.is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL) {
+            self.write();
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.phase = PROGRESS_DONE_PHASE.to_string();
+        self.samples_done = self.samples_total;
+        self.write();
+    }
+
+    // 进度文件写失败不应中断计算，只告警
+    fn write(&mut self) {
+        let record = ProgressRecord {
+            phase: &self.phase,
+            samples_done: self.samples_done,
+            samples_total: self.samples_total,
+            elapsed: self.start.elapsed().as_secs_f64(),
+        };
+        let result = OutputOptions::overwriting().write_atomically(&self.path, |writer| {
+            serde_json::to_writer(&mut *writer, &record)?;
+            std::io::Write::write_all(writer, b"\n")?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: failed to update progress file {}: {}", self.path.display(), e);
+        }
+        self.last_write = Some(std::time::Instant::now());
+    }
+}
+
+// 一次运行的进度句柄，由各模式入口根据--progress-json构造后向下传递；
+// 未设置--progress-json时各方法什么都不做。并行处理的样本共用一个句柄，内部加锁
+#[derive(Default)]
+pub struct Progress {
+    reporter: Option<std::sync::Mutex<ProgressReporter>>,
+}
+
+impl Progress {
+    pub fn new(path: Option<&str>) -> Self {
+        Progress { reporter: path.map(|path| std::sync::Mutex::new(ProgressReporter::new(path))) }
+    }
+
+    pub fn phase(&self, phase: &str, samples_total: usize) {
+        if let Some(reporter) = &self.reporter {
+            reporter.lock().unwrap().phase(phase, samples_total);
+        }
+    }
+
+    pub fn sample_done(&self) {
+        if let Some(reporter) = &self.reporter {
+            reporter.lock().unwrap().sample_done();
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(reporter) = &self.reporter {
+            reporter.lock().unwrap().finish();
+        }
+    }
+}
+
+// 自然排序：连续数字按数值比较，sample2排在sample10之前；其余部分按字符比较。
+// 数值相同但写法不同（如01与1）时最后按原字符串区分，保证是全序
+pub fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (Some(xc), Some(yc)) = (x.chars().next(), y.chars().next()) else {
+            return x.len().cmp(&y.len()).then_with(|| a.cmp(b));
+        };
+        let (x_digit, y_digit) = (xc.is_ascii_digit(), yc.is_ascii_digit());
+        let x_end = x.find(|c: char| c.is_ascii_digit() != x_digit).unwrap_or(x.len());
+        let y_end = y.find(|c: char| c.is_ascii_digit() != y_digit).unwrap_or(y.len());
+        let (x_run, y_run) = (&x[..x_end], &y[..y_end]);
+        let ordering = if x_digit && y_digit {
+            let (xn, yn) = (x_run.trim_start_matches('0'), y_run.trim_start_matches('0'));
+            xn.len().cmp(&yn.len()).then_with(|| xn.cmp(yn))
+        } else {
+            x_run.cmp(y_run)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        x = &x[x_end..];
+        y = &y[y_end..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_init_thread_pool_twice_does_not_panic() {
+        let first = init_thread_pool(2);
+        // 第二次请求不同的线程数只告警，继续使用已有的池
+        assert_eq!(init_thread_pool(first + 1), first);
+        assert_eq!(rayon::current_num_threads(), first);
+    }
+
+    #[test]
+    fn test_existing_output_requires_force() {
+        let dir = TempDir::new("force");
+        let path = dir.join("out.tsv");
+        std::fs::write(&path, b"old").unwrap();
+
+        assert!(OutputOptions::new(false, None).create_file(&path).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert!(OutputOptions::new(true, None).create_file(&path).is_ok());
+        assert!(std::fs::read(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failed_write_leaves_no_file_at_target() {
+        let dir = TempDir::new("atomic");
+        let target = dir.join("out.syldb");
+
+        let output = OutputOptions::default();
+        let result = output.write_atomically(&target, |writer| {
+            use std::io::Write;
+            writer.write_all(b"partial")?;
+            Err(anyhow::anyhow!("simulated crash mid-write"))
+        });
+        assert!(result.is_err());
+        assert!(!target.exists());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        output.write_atomically(&target, |writer| {
+            use std::io::Write;
+            writer.write_all(b"complete")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"complete");
+    }
+
+    #[test]
+    fn test_natural_sort_orders_numbered_samples() {
+        let mut samples = vec!["sample10", "sample2", "sample1"];
+        samples.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(samples, vec!["sample1", "sample2", "sample10"]);
+
+        let mut mixed = vec!["s1_r10", "s1_r9", "s01_r1", "s1_r1", "b", "a10", "a2"];
+        mixed.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(mixed, vec!["a2", "a10", "b", "s01_r1", "s1_r1", "s1_r9", "s1_r10"]);
+        assert_eq!(natural_cmp("sample", "sample1"), std::cmp::Ordering::Less);
+    }
+
+    #[test]
+    fn test_progress_file_tracks_phases_and_completion() {
+        let dir = TempDir::new("progress");
+        let path = dir.join("progress.json");
+        let read = || -> serde_json::Value { serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap() };
+        let mut reporter = ProgressReporter::new(&path);
+
+        reporter.phase("profiling", 3);
+        let state = read();
+        assert_eq!(state["phase"], "profiling");
+        assert_eq!(state["samples_done"], 0);
+        assert_eq!(state["samples_total"], 3);
+        reporter.sample_done();
+        reporter.sample_done();
+
+        // 新阶段立即写出，不受节流影响
+        reporter.phase("writing", 3);
+        assert_eq!(read()["phase"], "writing");
+
+        reporter.finish();
+        let state = read();
+        assert_eq!(state["phase"], PROGRESS_DONE_PHASE);
+        assert_eq!(state["samples_done"], 3);
+        assert!(state["elapsed"].as_f64().unwrap() >= 0.0);
+    }
+}
@@ -1,23 +1,27 @@
 use crate::cmdline::{ContainArgs, ProfileArgs};
 use anyhow::{Result, anyhow, Context};
 use std::collections::HashMap;
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use rayon::prelude::*;
 use std::sync::Mutex;
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::path::PathBuf;
-use crate::constants::Hash;
-use std::thread;
-use std::time::Duration;
-use memory_stats::memory_stats;
+use crate::constants::{Hash, natural_cmp, init_thread_pool, OutputOptions, Progress};
+use std::time::{Duration, Instant};
 
 pub use crate::extract::{SyldbEntry, SylspEntry};
+use crate::mark::build_tag_to_genomes;
+use crate::distance::{distance_matrix, write_clr_matrix, write_distance_matrix};
+use crate::extract::{GenomeSketch, check_sample_enzyme, check_subsample_rates, database_enzyme, read_syldb, read_syldb_with_header, read_sylsp_entries, read_sylsp_header, read_sylsp_header_full, syldb_granularity, DbGranularity, SyldbHeader, SylspKind, SYLDB_FORMAT_VERSION, SYLDB_MAGIC};
+use crate::sketch::SequencesSketch;
+use crate::sylph::{read_sylph_genome_sketches, read_sylph_sequences_sketch};
 
 // 定义分类学信息结构体
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct TaxonomyInfo {
     pub kingdom: String,    // d__
     pub phylum: String,     // p__
@@ -34,26 +38,99 @@ impl TaxonomyInfo {
         
         for part in gtdb_str.split(';') {
             let part = part.trim();
-            if part.starts_with("d__") {
-                taxonomy.kingdom = part[3..].to_string();
-            } else if part.starts_with("p__") {
-                taxonomy.phylum = part[3..].to_string();
-            } else if part.starts_with("c__") {
-                taxonomy.class = part[3..].to_string();
-            } else if part.starts_with("o__") {
-                taxonomy.order = part[3..].to_string();
-            } else if part.starts_with("f__") {
-                taxonomy.family = part[3..].to_string();
-            } else if part.starts_with("g__") {
-                taxonomy.genus = part[3..].to_string();
-            } else if part.starts_with("s__") {
-                taxonomy.species = part[3..].to_string();
+            if let Some(name) = part.strip_prefix("d__") {
+                taxonomy.kingdom = name.to_string();
+            } else if let Some(name) = part.strip_prefix("p__") {
+                taxonomy.phylum = name.to_string();
+            } else if let Some(name) = part.strip_prefix("c__") {
+                taxonomy.class = name.to_string();
+            } else if let Some(name) = part.strip_prefix("o__") {
+                taxonomy.order = name.to_string();
+            } else if let Some(name) = part.strip_prefix("f__") {
+                taxonomy.family = name.to_string();
+            } else if let Some(name) = part.strip_prefix("g__") {
+                taxonomy.genus = name.to_string();
+            } else if let Some(name) = part.strip_prefix("s__") {
+                taxonomy.species = name.to_string();
             }
         }
         
         Ok(taxonomy)
     }
+
+    // NCBI谱系："superkingdom;phylum;...;species"按位置对应各级，
+    // 或带级别名的"superkingdom:Bacteria;phylum:..."（其他级别如strain忽略）。缺失的级别留空
+    pub fn from_ncbi_lineage(lineage: &str) -> Result<Self> {
+        let parts: Vec<&str> = lineage.split(';').map(str::trim).filter(|p| !p.is_empty()).collect();
+        if !parts.iter().any(|p| p.contains(':')) {
+            return Ok(Self::from_ranks(&parts));
+        }
+
+        let mut taxonomy = TaxonomyInfo::default();
+        for part in parts {
+            let Some((rank, name)) = part.split_once(':') else {
+                return Err(anyhow!("NCBI lineage mixes labelled and unlabelled ranks: {}", lineage));
+            };
+            let name = name.trim().to_string();
+            match rank.trim().to_ascii_lowercase().as_str() {
+                "superkingdom" | "kingdom" | "domain" => taxonomy.kingdom = name,
+                "phylum" => taxonomy.phylum = name,
+                "class" => taxonomy.class = name,
+                "order" => taxonomy.order = name,
+                "family" => taxonomy.family = name,
+                "genus" => taxonomy.genus = name,
+                "species" => taxonomy.species = name,
+                _ => {}
+            }
+        }
+        Ok(taxonomy)
+    }
+
+    // 按kingdom..species顺序给出的各级名称，不足7级的留空
+    pub fn from_ranks(ranks: &[&str]) -> Self {
+        let rank = |i: usize| ranks.get(i).map(|r| r.trim().to_string()).unwrap_or_default();
+        TaxonomyInfo {
+            kingdom: rank(0),
+            phylum: rank(1),
+            class: rank(2),
+            order: rank(3),
+            family: rank(4),
+            genus: rank(5),
+            species: rank(6),
+        }
+    }
+
+    // fields为taxonomy文件中accession之后的各列：gtdb/ncbi取第一列的谱系字符串，
+    // custom每一列是一个级别（kingdom..species）
+    pub fn parse(fields: &[&str], format: &str) -> Result<Self> {
+        let lineage = fields.first().map(|f| f.trim()).unwrap_or("");
+        match format {
+            "gtdb" => Self::from_gtdb_string(lineage),
+            "ncbi" => Self::from_ncbi_lineage(lineage),
+            "custom" => Ok(Self::from_ranks(fields)),
+            _ => Err(anyhow!("Unknown taxonomy format '{}'; expected gtdb, ncbi or custom", format)),
+        }
+    }
     
+    // --collapse-strains的规范化规则：种名只保留前两个词（属名+种加词），并去掉其后
+    // GTDB式的大写字母后缀（"Prevotella copri_A" -> "Prevotella copri"），菌株名等
+    // 其余部分丢弃。"sp."之类未定名的物种保持原样，以免不同的未定名物种被合并
+    pub fn collapse_strains(&self) -> Self {
+        let strip_suffix = |word: &str| -> String {
+            match word.rsplit_once('_') {
+                Some((base, suffix)) if !base.is_empty() && !suffix.is_empty()
+                    && suffix.chars().all(|c| c.is_ascii_uppercase()) => base.to_string(),
+                _ => word.to_string(),
+            }
+        };
+        let words: Vec<&str> = self.species.split_whitespace().collect();
+        let species = match words.as_slice() {
+            [genus, epithet, ..] if !epithet.starts_with("sp.") => format!("{} {}", strip_suffix(genus), strip_suffix(epithet)),
+            _ => self.species.clone(),
+        };
+        TaxonomyInfo { species, ..self.clone() }
+    }
+
     pub fn get_species_key(&self) -> String {
         format!("{}|{}|{}|{}|{}|{}|{}", 
                 self.kingdom, self.phylum, self.class, 
@@ -66,14 +143,35 @@ impl TaxonomyInfo {
 pub struct SpeciesAbundanceResult {
     pub taxonomy: Arc<TaxonomyInfo>,
     pub sample_abundances: FxHashMap<String, f64>,
+    // 每个样本中该物种各基因组的共享标签数之和，供--abundance-units cpm/raw使用
+    pub sample_shared_tags: FxHashMap<String, usize>,
     pub total_tags: usize,
     pub genome_count: usize,
     pub reads_count: usize,
     pub gscore: f64,
+    // 样本 -> 该物种中共享标签最多的基因组（--species-representative-genome）
+    pub sample_representatives: FxHashMap<String, SpeciesRepresentative>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesRepresentative {
+    pub genome_id: String,
+    pub shared_tags: usize,
+    pub adjusted_ani: f64,
+}
+
+impl SpeciesRepresentative {
+    // 共享标签更多者优先；相同则ANI更高者，再相同则genome_id字典序更小者，结果与样本内顺序无关
+    fn beats(&self, other: &SpeciesRepresentative) -> bool {
+        self.shared_tags.cmp(&other.shared_tags)
+            .then_with(|| self.adjusted_ani.partial_cmp(&other.adjusted_ani).unwrap_or(std::cmp::Ordering::Equal))
+            .then_with(|| other.genome_id.cmp(&self.genome_id))
+            == std::cmp::Ordering::Greater
+    }
 }
 
 // 定义比对结果结构
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResult {
     pub sample_file: String,
     pub genome_file: String,
@@ -85,6 +183,8 @@ pub struct QueryResult {
     pub median_cov: f64,
     pub mean_cov_geq1: f64,
     pub containment_ind: String,
+    pub containment: f64,
+    pub jaccard: f64,
     pub naive_ani: f64,
     pub contig_name: String,
     pub ref_tags: usize,
@@ -92,6 +192,10 @@ pub struct QueryResult {
     pub query_tags: usize,
     pub taxonomic_abundance: f64,
     pub sequence_abundance: f64,
+    // (含共享标签的窗口数, 含参考标签的窗口数)，见breadth_bins
+    pub breadth_bins: (usize, usize),
+    // 共享标签在contig上的跨度(bp)，见shared_span
+    pub shared_span: usize,
 }
 
 // 新增基因组级别的结果结构体
@@ -99,13 +203,27 @@ pub struct QueryResult {
 pub struct GenomeProfileResult {
     pub genome_id: String,
     pub sample_id: String,
-    pub file_path: String,
     pub adjusted_ani: f64,
     pub taxonomic_abundance: f64,
     pub sequence_abundance: f64,
     pub common_tags: usize,
     pub total_tags: usize,
     pub eff_cov: f64,
+    pub breadth_bins: (usize, usize),
+    // 各contig共享标签跨度之和(bp)，--min-breadth用它除以基因组大小
+    pub shared_span: usize,
+}
+
+// --per-contig输出：基因组内每条contig在每个样本中的匹配情况
+#[derive(Debug, Clone, PartialEq)]
+struct ContigProfileRow {
+    genome_id: String,
+    contig_name: String,
+    sample_id: String,
+    adjusted_ani: f64,
+    shared_tags: usize,
+    total_tags: usize,
+    eff_cov: f64,
 }
 
 // Winner table条目结构
@@ -114,14 +232,16 @@ struct WinnerTableEntry {
     pub ani: f64,
     pub genome_id: String,
     pub was_reassigned: bool,
+    // winner的参考标签数，用于ANI持平时比较
+    pub ref_tags: usize,
 }
 
-// 重新分配统计信息
-#[derive(Debug, Clone)]
-struct ReassignmentStats {
-    pub tags_lost: usize,
-    pub total_tags: usize,
-    pub reassignment_ratio: f64,
+// winner table的重叠统计，与winner分配在同一遍中累计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct WinnerTableStats {
+    pub tags: usize,
+    // 被多个基因组共享的标签数
+    pub contested: usize,
 }
 
 // ==================== 修复的常量定义 ====================
@@ -135,6 +255,52 @@ const MIN_TAGS_FOR_GENOME: usize = 50;    // 基因组最小标签数
 const PROFILE_MIN_ANI: f64 = 97.0;        // 95 -> 97 (profile模式更严格)
 const PROFILE_MIN_COVERAGE: f64 = 0.01;   // 0.005 -> 0.01
 
+// 过滤阈值：默认取上面的常量，可由命令行覆盖（--min-shared-tags / --min-coverage / -M）
+#[derive(Debug, Clone, Copy)]
+pub struct FilterThresholds {
+    pub min_shared_tags: usize,
+    pub min_coverage: f64,
+    pub min_tags_for_genome: usize,
+    // profile --min-tag-depth：样本中出现次数不足的tag视为不存在
+    pub min_tag_depth: usize,
+    // profile --min-breadth：共享标签跨度占基因组长度的最小比例，0表示不过滤
+    pub min_breadth: f64,
+}
+
+impl Default for FilterThresholds {
+    fn default() -> Self {
+        FilterThresholds {
+            min_shared_tags: MIN_SHARED_TAGS,
+            min_coverage: MIN_COVERAGE,
+            min_tags_for_genome: MIN_TAGS_FOR_GENOME,
+            min_tag_depth: 1,
+            min_breadth: 0.0,
+        }
+    }
+}
+
+impl FilterThresholds {
+    fn for_query(args: &ContainArgs) -> Self {
+        FilterThresholds {
+            min_shared_tags: args.min_shared_tags,
+            min_coverage: args.min_coverage.unwrap_or(MIN_COVERAGE),
+            min_tags_for_genome: args.min_number_kmers as usize,
+            min_tag_depth: 1,
+            min_breadth: 0.0,
+        }
+    }
+
+    fn for_profile(args: &ProfileArgs) -> Self {
+        FilterThresholds {
+            min_shared_tags: args.min_shared_tags,
+            min_coverage: args.min_coverage.unwrap_or(PROFILE_MIN_COVERAGE),
+            min_tags_for_genome: args.min_number_kmers,
+            min_tag_depth: args.min_tag_depth,
+            min_breadth: args.min_breadth,
+        }
+    }
+}
+
 struct MultiWriter {
     writers: Vec<Box<dyn Write + Send>>,
 }
@@ -164,6 +330,48 @@ impl Write for MultiWriter {
 }
 
 pub fn query(args: ContainArgs) -> Result<()> {
+    let output = query_output_options(&args);
+    check_query_outputs(&output, &args)?;
+    let names = args.genome_name_map.as_deref().map(read_genome_name_map).transpose()?;
+    let mut results = query_to_results(&args)?;
+    if let Some(names) = &names {
+        for result in &mut results {
+            result.genome_file = display_genome_name(&result.genome_file, names).to_string();
+        }
+    }
+    let write_table = |writer: &mut dyn Write| -> Result<()> {
+        print_header(writer)?;
+        for result in &results {
+            print_result(result, args.precision, writer)?;
+        }
+        Ok(())
+    };
+    let mut stdout = output.buf_writer(std::io::stdout());
+    write_table(&mut stdout)?;
+    stdout.flush()?;
+    // -o的文件经--temp-dir原子写入
+    if let Some(path) = &args.out_file_name {
+        output.write_atomically(path, |writer| write_table(writer))
+            .with_context(|| format!("Failed to write output file: {}", path))?;
+    }
+    Ok(())
+}
+
+fn query_output_options(args: &ContainArgs) -> OutputOptions {
+    OutputOptions::new(args.force, args.temp_dir.as_deref())
+}
+
+// 在读入任何数据前检查所有输出位置都可写
+fn check_query_outputs(output: &OutputOptions, args: &ContainArgs) -> Result<()> {
+    for path in [&args.out_file_name, &args.unfiltered_dump].into_iter().flatten() {
+        output.check_file(path).with_context(|| format!("Output check failed for {}", path))?;
+    }
+    Ok(())
+}
+
+// query的计算部分，供库调用：返回通过过滤的结果（按数据库、样本文件的顺序；--sort-by时每个样本内已排序），
+// 除--unfiltered-dump外不写任何输出
+pub fn query_to_results(args: &ContainArgs) -> Result<Vec<QueryResult>> {
     let db_files: Vec<_> = args.files.iter()
         .filter(|f| f.ends_with(".syldb"))
         .collect();
@@ -180,8 +388,13 @@ pub fn query(args: ContainArgs) -> Result<()> {
         return Err(anyhow!("No .sylsp files found in input files"));
     }
 
-    let writer = Arc::new(Mutex::new(create_multi_writer(&args.out_file_name)?));
-    print_header(&writer)?;
+    let thresholds = FilterThresholds::for_query(args);
+    let output = query_output_options(args);
+    let dump = args.unfiltered_dump.as_deref().map(|path| UnfilteredDump::create(&output, path)).transpose()?;
+    let mut all_results = Vec::new();
+    // 所有样本×数据库的共享标签总数，为0时给出诊断
+    let total_shared_tags = std::sync::atomic::AtomicUsize::new(0);
+    let mut compared_tag_dbs = 0;
 
     for db_path in db_files {
         eprintln!("Processing database file: {}", db_path);
@@ -189,34 +402,54 @@ pub fn query(args: ContainArgs) -> Result<()> {
         let db_file = File::open(db_path)
             .with_context(|| format!("Failed to open database file: {}", db_path))?;
         let db_reader = BufReader::new(db_file);
-        let db_entries: Vec<SyldbEntry> = bincode::deserialize_from(db_reader)
-            .with_context(|| format!("Failed to deserialize database file: {}", db_path))?;
+        let (db_header, db_entries) = match read_database(db_reader, db_path)? {
+            Database::Tags(header, entries) => (header, entries),
+            Database::Sketches(genome_sketches) => {
+                // sketch数据库在比较时协调c值
+                eprintln!("Found {} genome sketches in database", genome_sketches.len());
+                let sketched_files: FxHashSet<&str> = genome_sketches.iter().map(|sketch| sketch.file_name.as_str()).collect();
+                if sketched_files.len() < genome_sketches.len() {
+                    eprintln!("Warning: {} holds per-contig sketches (sketch --individual) of {} genome files; query reports each contig separately", db_path, sketched_files.len());
+                }
+                all_results.extend(query_sketch_db(db_path, &genome_sketches, &sample_files, args.c, args.minimum_ani, &thresholds, dump.as_ref(), args.report_absent)?);
+                continue;
+            }
+        };
 
         eprintln!("Found {} entries in database", db_entries.len());
+        let db_enzyme = database_enzyme(&db_entries).with_context(|| format!("Cannot query {}", db_path))?;
+        compared_tag_dbs += 1;
 
-        sample_files.par_iter().try_for_each(|sample_path| -> Result<()> {
+        let per_sample: Vec<Vec<QueryResult>> = sample_files.par_iter().map(|sample_path| -> Result<Vec<QueryResult>> {
             eprintln!("Processing sample file: {}", sample_path);
             
             let sample_file = File::open(sample_path)
                 .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
-            let sample_reader = BufReader::new(sample_file);
-            let sample_entries: Vec<SylspEntry> = bincode::deserialize_from(sample_reader)
+            let mut sample_reader = BufReader::new(sample_file);
+            let header = read_sylsp_header_full(&mut sample_reader)?;
+            check_subsample_rates(db_path, db_header.as_ref(), sample_path, header.as_ref())?;
+            check_sample_enzyme(db_path, db_enzyme.as_deref(), sample_path, header.as_ref())?;
+            let sample_entries = read_sylsp_entries(sample_reader, header.as_ref())
                 .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
 
             eprintln!("Found {} entries in sample", sample_entries.len());
 
             if sample_entries.is_empty() {
                 eprintln!("Warning: Sample {} has no tags", sample_path);
-                return Ok(());
+                if !args.report_absent {
+                    return Ok(Vec::new());
+                }
             }
 
             let sample_tags: HashMap<Hash, usize> = sample_entries.iter()
-                .map(|entry| (entry.tag.clone(), 1))
+                .map(|entry| (entry.tag, 1))
                 .collect();
 
             let total_sample_tags = sample_entries.len();
             eprintln!("Total unique tags in sample: {}", total_sample_tags);
 
+            // --sort-by时在本样本内排序
+            let mut passed_results = Vec::new();
             for db_entry in &db_entries {
                 let mut shared_tags = 0;
                 let mut coverages = Vec::new();
@@ -231,11 +464,13 @@ pub fn query(args: ContainArgs) -> Result<()> {
 
                 eprintln!("Found {} shared tags between sample and reference {}", 
                          shared_tags, db_entry.sequence_id);
+                total_shared_tags.fetch_add(shared_tags, std::sync::atomic::Ordering::Relaxed);
 
-                let mut result = calculate_statistics(
+                let mut result = calculate_statistics_with(
                     shared_tags,
                     total_sample_tags,
                     total_ref_tags,
+                    thresholds.min_shared_tags,
                 );
 
                 result.sample_file = sample_path.to_string();
@@ -244,6 +479,8 @@ pub fn query(args: ContainArgs) -> Result<()> {
                 result.shared_tags = shared_tags;
                 result.query_tags = total_sample_tags;
                 result.ref_tags = total_ref_tags;
+                result.breadth_bins = breadth_bins(db_entry, |tag| sample_tags.contains_key(tag));
+                result.shared_span = shared_span(db_entry, |tag| sample_tags.contains_key(tag));
 
                 if shared_tags > 0 {
                     result.mean_cov_geq1 = 1.0;
@@ -260,65 +497,493 @@ pub fn query(args: ContainArgs) -> Result<()> {
                 }
 
                 // FIX: 使用修复后的过滤函数
-                if filter_results(&result, args.minimum_ani) {
+                if filter_results(&result, args.minimum_ani, &thresholds, dump.as_ref()) {
                     eprintln!("Result passed filters: ANI={:.2}, Coverage={:.3}", 
                             result.adjusted_ani, result.eff_cov);
-                    print_result(&result, &writer)?;
+                    passed_results.push(result);
                 } else {
                     eprintln!("Result filtered out: ANI={:.2}, Coverage={:.3}", 
                             result.adjusted_ani, result.eff_cov);
+                    if args.report_absent {
+                        passed_results.push(absent_result(&result));
+                    }
                 }
             }
-            Ok(())
-        })?;
+
+            if let Some(key) = &args.sort_by {
+                passed_results.sort_by(|a, b| compare_by(a, b, key));
+            }
+            Ok(passed_results)
+        }).collect::<Result<_>>()?;
+        all_results.extend(per_sample.into_iter().flatten());
     }
 
-    Ok(())
+    if let Some(dump) = dump {
+        dump.finish()?;
+    }
+
+    if compared_tag_dbs > 0 && total_shared_tags.load(std::sync::atomic::Ordering::Relaxed) == 0 {
+        return Err(zero_shared_tags_error("query", sample_files.len()));
+    }
+
+    Ok(all_results)
+}
+
+// 样本中出现在数据库里的不同标签数
+fn count_shared_tags(db_tags: &FxHashSet<Hash>, sample_tags: &[SampleTag]) -> usize {
+    sample_tags.iter()
+        .map(|sample_tag| sample_tag.tag)
+        .filter(|tag| db_tags.contains(tag))
+        .collect::<FxHashSet<Hash>>()
+        .len()
+}
+
+// 所有样本与数据库都没有任何共享标签时，结果表必然为空：报错并列出常见原因，而不是静默输出空表
+fn zero_shared_tags_error(command: &str, num_samples: usize) -> anyhow::Error {
+    anyhow!(
+        "None of the {} sample file(s) shares a single tag with the database, so the {} output would be empty. Likely causes:\n  \
+         - database and samples were extracted with different enzymes (--enzyme)\n  \
+         - different extraction settings (--fnv-tag-hash, --subsample-rate, --strand-specific)\n  \
+         - the samples are empty or contain no recognition sites (check them with `meta2bseek inspect`)\n  \
+         - the samples come from organisms absent from the database",
+        num_samples, command
+    )
+}
+
+// ==================== --sort-by：query/profile结果的统一排序 ====================
+// 数值指标降序，name升序；主键相同时按名称排，保证输出稳定
+trait RankedResult {
+    fn ani(&self) -> f64;
+    fn abundance(&self) -> f64;
+    fn coverage(&self) -> f64;
+    fn name(&self) -> &str;
+    fn shared_tags(&self) -> usize;
+}
+
+impl RankedResult for QueryResult {
+    fn ani(&self) -> f64 { self.adjusted_ani }
+    fn abundance(&self) -> f64 { self.taxonomic_abundance }
+    fn coverage(&self) -> f64 { self.eff_cov }
+    fn name(&self) -> &str { &self.contig_name }
+    fn shared_tags(&self) -> usize { self.shared_tags }
+}
+
+impl RankedResult for GenomeProfileResult {
+    fn ani(&self) -> f64 { self.adjusted_ani }
+    fn abundance(&self) -> f64 { self.taxonomic_abundance }
+    fn coverage(&self) -> f64 { self.eff_cov }
+    fn name(&self) -> &str { &self.genome_id }
+    fn shared_tags(&self) -> usize { self.common_tags }
+}
+
+fn compare_by<T: RankedResult>(a: &T, b: &T, key: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let descending = |x: f64, y: f64| y.partial_cmp(&x).unwrap_or(Ordering::Equal);
+    let primary = match key {
+        "abundance" => descending(a.abundance(), b.abundance()),
+        "coverage" => descending(a.coverage(), b.coverage()),
+        "name" => Ordering::Equal,
+        "shared_tags" => b.shared_tags().cmp(&a.shared_tags()),
+        _ => descending(a.ani(), b.ani()),
+    };
+    primary.then_with(|| a.name().cmp(b.name()))
+}
+
+// ==================== sketch(k-mer)路径的c值协调 ====================
+// sketch 只保留 hash < u64::MAX / c 的k-mer，c不同的两个sketch不可直接比较；
+// 把更细的一方过滤到更粗的阈值后，两者就是同一个下采样集合。
+// 用户给出-c时只可能进一步提高c，不给时两者c相同就不再过滤
+fn reconciled_c(db_c: usize, sample_c: usize, requested_c: Option<usize>) -> usize {
+    db_c.max(sample_c).max(requested_c.unwrap_or(1)).max(1)
+}
+
+// 返回(共享k-mer数, 协调后的参考k-mer数, 协调后的样本k-mer数)
+fn reconcile_and_intersect(genome: &GenomeSketch, sample: &SequencesSketch, requested_c: Option<usize>) -> (usize, usize, usize) {
+    let c = reconciled_c(genome.c, sample.c, requested_c);
+    let threshold = u64::MAX / c as u64;
+
+    let ref_kmers: Vec<&Hash> = genome.genome_kmers.iter()
+        .filter(|hash| **hash < threshold)
+        .collect();
+    let query_kmers = sample.kmer_counts.keys()
+        .filter(|hash| **hash < threshold)
+        .count();
+    let shared_kmers = ref_kmers.iter()
+        .filter(|hash| sample.kmer_counts.contains_key(**hash))
+        .count();
+
+    (shared_kmers, ref_kmers.len(), query_kmers)
+}
+
+// query/profile的数据库：extract格式的标签库，或sketch/sylph的基因组sketch
+enum Database {
+    Tags(Option<SyldbHeader>, Vec<SyldbEntry>),
+    Sketches(Vec<GenomeSketch>),
+}
+
+// 以M2DB格式头开头的一定是extract数据库，读取出错（截断、版本过新等）直接报告真实原因；
+// 没有格式头时才需要区分旧版extract数据库和sketch数据库，两者都读不出时报告前者的错误
+fn read_database<R: BufRead>(mut reader: R, db_path: &str) -> Result<Database> {
+    let has_magic = reader.fill_buf()
+        .with_context(|| format!("Failed to read database file: {}", db_path))?
+        .starts_with(&SYLDB_MAGIC);
+    match read_syldb_with_header(reader) {
+        Ok((header, entries)) => Ok(Database::Tags(header, entries)),
+        Err(err) if has_magic => Err(err).with_context(|| format!("Failed to deserialize database file: {}", db_path)),
+        Err(err) => {
+            let genome_sketches = read_genome_sketches(db_path)
+                .map_err(|_| err)
+                .with_context(|| format!("Failed to deserialize database file: {}", db_path))?;
+            Ok(Database::Sketches(genome_sketches))
+        }
+    }
+}
+
+pub(crate) fn read_genome_sketches(db_path: &str) -> Result<Vec<GenomeSketch>> {
+    let db_file = File::open(db_path)
+        .with_context(|| format!("Failed to open database file: {}", db_path))?;
+    match bincode::deserialize_from::<_, Vec<GenomeSketch>>(BufReader::new(db_file)) {
+        Ok(genome_sketches) => Ok(genome_sketches),
+        Err(e) => {
+            // 再按sylph原生的.syldb尝试
+            let db_file = File::open(db_path)
+                .with_context(|| format!("Failed to open database file: {}", db_path))?;
+            let genome_sketches = read_sylph_genome_sketches(BufReader::new(db_file)).map_err(|_| e)?;
+            eprintln!("Read {} as a sylph database", db_path);
+            Ok(genome_sketches)
+        }
+    }
+}
+
+// 样本可能是单个SequencesSketch，也可能是合并后的Vec<SequencesSketch>
+pub(crate) fn read_sequences_sketches(sample_path: &str) -> Result<Vec<SequencesSketch>> {
+    let sample_file = File::open(sample_path)
+        .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
+    let mut sample_reader = BufReader::new(sample_file);
+    match read_sylsp_header(&mut sample_reader)? {
+        Some(SylspKind::SingleSketch) => {
+            let sketch: SequencesSketch = bincode::deserialize_from(sample_reader)
+                .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
+            return Ok(vec![sketch]);
+        }
+        Some(SylspKind::SketchList) => {
+            return bincode::deserialize_from(sample_reader)
+                .with_context(|| format!("Failed to deserialize sample file: {}", sample_path));
+        }
+        Some(SylspKind::TagEntries) => {
+            return Err(anyhow!("{} contains extracted 2bRAD tags and cannot be compared against a sketch database", sample_path));
+        }
+        None => {}
+    }
+
+    // 旧文件没有格式头，按单个/多个sketch依次尝试
+    if let Ok(sketch) = bincode::deserialize_from::<_, SequencesSketch>(sample_reader) {
+        return Ok(vec![sketch]);
+    }
+
+    let sample_file = File::open(sample_path)
+        .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
+    let sketches: Result<Vec<SequencesSketch>, _> = bincode::deserialize_from(BufReader::new(sample_file));
+    if let Ok(sketches) = sketches {
+        return Ok(sketches);
+    }
+
+    // 最后按sylph原生的.sylsp尝试
+    let sample_file = File::open(sample_path)
+        .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
+    let sketch = read_sylph_sequences_sketch(BufReader::new(sample_file))
+        .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
+    eprintln!("Read {} as a sylph sample sketch", sample_path);
+    Ok(vec![sketch])
+}
+
+#[allow(clippy::too_many_arguments)]
+fn query_sketch_db(
+    db_path: &str,
+    genome_sketches: &[GenomeSketch],
+    sample_files: &[&String],
+    requested_c: Option<usize>,
+    min_ani: Option<f64>,
+    thresholds: &FilterThresholds,
+    dump: Option<&UnfilteredDump>,
+    report_absent: bool,
+) -> Result<Vec<QueryResult>> {
+    // 同一对c值只提示一次，而不是每个样本×基因组各打印一行
+    let reconciled_pairs: Mutex<FxHashSet<(usize, usize)>> = Mutex::new(FxHashSet::default());
+    let per_sample: Vec<Vec<QueryResult>> = sample_files.par_iter().map(|sample_path| -> Result<Vec<QueryResult>> {
+        let sample_sketches = read_sequences_sketches(sample_path)?;
+        let mut passed_results = Vec::new();
+
+        for sample in &sample_sketches {
+            let sample_name = sample.sample_name.clone().unwrap_or_else(|| sample_path.to_string());
+
+            for genome in genome_sketches {
+                if genome.c != sample.c && reconciled_pairs.lock().unwrap().insert((genome.c, sample.c)) {
+                    eprintln!("Reconciling c={} (database) and c={} (sample) to c={}",
+                             genome.c, sample.c, reconciled_c(genome.c, sample.c, requested_c));
+                }
+
+                let (shared_kmers, ref_kmers, query_kmers) = reconcile_and_intersect(genome, sample, requested_c);
+                let mut result = calculate_statistics_with(shared_kmers, query_kmers, ref_kmers, thresholds.min_shared_tags);
+
+                result.sample_file = sample_name.clone();
+                result.genome_file = db_path.to_string();
+                result.contig_name = genome.first_contig_name.clone();
+
+                if filter_results(&result, min_ani, thresholds, dump) {
+                    passed_results.push(result);
+                } else if report_absent {
+                    passed_results.push(absent_result(&result));
+                }
+            }
+        }
+        Ok(passed_results)
+    }).collect::<Result<_>>()?;
+    Ok(per_sample.into_iter().flatten().collect())
+}
+
+// profile缓存的样本标签：同一样本源中的同一标签只保存一条，count为其观测次数。
+// .sylsp中重复的标签条目读入时合并，sketch样本直接取k-mer计数
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SampleTag {
+    sample_source: String,
+    tag: Hash,
+    count: usize,
+}
+
+// 样本文件 -> 样本标签
+type SampleTags = FxHashMap<String, Vec<SampleTag>>;
+
+// .sylsp条目 -> 样本标签：按(样本源, 标签)合并计数，保持首次出现的顺序
+fn collapse_sample_entries(entries: Vec<SylspEntry>) -> Vec<SampleTag> {
+    let mut index: FxHashMap<(String, Hash), usize> = FxHashMap::default();
+    let mut tags: Vec<SampleTag> = Vec::new();
+    for entry in entries {
+        match index.entry((entry.sample_source, entry.tag)) {
+            std::collections::hash_map::Entry::Occupied(slot) => tags[*slot.get()].count += 1,
+            std::collections::hash_map::Entry::Vacant(slot) => {
+                let (sample_source, tag) = slot.key().clone();
+                slot.insert(tags.len());
+                tags.push(SampleTag { sample_source, tag, count: 1 });
+            }
+        }
+    }
+    tags
+}
+
+// 每个样本源的标签观测总数
+fn observations_per_source(tags: &[SampleTag]) -> FxHashMap<String, usize> {
+    let mut totals: FxHashMap<String, usize> = FxHashMap::default();
+    for tag in tags {
+        *totals.entry(tag.sample_source.clone()).or_insert(0) += tag.count;
+    }
+    totals
+}
+
+// profile对sketch数据库：取数据库和所有样本sketch中最粗的c，两边都过滤到同一阈值后
+// 把k-mer当作标签、样本k-mer的计数当作观测次数，之后与extract数据库走同一套流程。
+// k-mer没有记录位置，positions全为0。返回(粒度, 数据库条目, 样本文件 -> 样本标签)
+fn sketch_profile_inputs(
+    genome_sketches: &[GenomeSketch],
+    sample_files: &[String],
+) -> Result<(DbGranularity, Vec<SyldbEntry>, SampleTags)> {
+    let samples: Vec<(&String, Vec<SequencesSketch>)> = sample_files.iter()
+        .map(|path| Ok((path, read_sequences_sketches(path)?)))
+        .collect::<Result<_>>()?;
+    let mut cs: Vec<usize> = genome_sketches.iter().map(|sketch| sketch.c)
+        .chain(samples.iter().flat_map(|(_, sketches)| sketches.iter().map(|sketch| sketch.c)))
+        .collect();
+    cs.sort_unstable();
+    cs.dedup();
+    let c = cs.last().copied().unwrap_or(1).max(1);
+    if cs.len() > 1 {
+        eprintln!("Reconciling sketches with c={:?} to c={}", cs, c);
+    }
+    let threshold = u64::MAX / c as u64;
+
+    // sketch数据库没有格式头：sketch --individual每个contig一个sketch，同一文件会出现多次
+    let sketched_files: FxHashSet<&str> = genome_sketches.iter().map(|sketch| sketch.file_name.as_str()).collect();
+    let granularity = if sketched_files.len() < genome_sketches.len() { DbGranularity::Contig } else { DbGranularity::Genome };
+
+    // 条目ID在数据库内必须唯一：整基因组sketch用文件名，per-contig sketch用contig名
+    let db_entries = genome_sketches.iter()
+        .map(|sketch| {
+            let tags: Vec<Hash> = sketch.genome_kmers.iter().copied().filter(|hash| *hash < threshold).collect();
+            SyldbEntry {
+                sequence_id: match granularity {
+                    DbGranularity::Genome => sketch.file_name.clone(),
+                    DbGranularity::Contig => sketch.first_contig_name.clone(),
+                },
+                positions: vec![0; tags.len()],
+                tags,
+                genome_source: sketch.file_name.clone(),
+                tag_uniqueness: None,
+                gn_size: sketch.gn_size,
+                tag_sequences: None,
+                enzyme: String::new(),
+            }
+        })
+        .collect();
+
+    let mut sample_tags: SampleTags = FxHashMap::default();
+    for (sample_path, sketches) in samples {
+        let tags = sample_tags.entry(sample_path.clone()).or_default();
+        for sketch in sketches {
+            let sample_source = sketch.sample_name.clone().unwrap_or_else(|| sample_path.clone());
+            tags.extend(sketch.kmer_counts.iter()
+                .filter(|&(&kmer, &count)| kmer < threshold && count > 0)
+                .map(|(&kmer, &count)| SampleTag { sample_source: sample_source.clone(), tag: kmer, count: count as usize }));
+        }
+    }
+    Ok((granularity, db_entries, sample_tags))
+}
+
+// --report-absent：未检出（没有共享标签或未通过阈值）的基因组也输出一行，统计量全为0。
+// query和profile共用同一规则：只要没有通过过滤就按未检出处理
+fn absent_result(result: &QueryResult) -> QueryResult {
+    let mut absent = calculate_statistics(0, 0, result.ref_tags);
+    absent.sample_file = result.sample_file.clone();
+    absent.genome_file = result.genome_file.clone();
+    absent.contig_name = result.contig_name.clone();
+    absent.query_tags = result.query_tags;
+    absent
+}
+
+// profile的--report-absent：samples为样本源，每个样本补齐未检出的基因组（丰度为0）。
+// 在丰度计算之后补，不影响已检出基因组的丰度
+fn add_absent_genomes(
+    sample_groups: &mut HashMap<String, Vec<GenomeProfileResult>>,
+    samples: &[String],
+    genome_tag_totals: &FxHashMap<String, usize>,
+) {
+    let mut genome_ids: Vec<&String> = genome_tag_totals.keys().collect();
+    genome_ids.sort_by(|a, b| natural_cmp(a, b));
+    for sample_id in samples {
+        let group = sample_groups.entry(sample_id.clone()).or_default();
+        let detected: FxHashSet<String> = group.iter().map(|r| r.genome_id.clone()).collect();
+        for genome_id in genome_ids.iter().filter(|id| !detected.contains(id.as_str())) {
+            group.push(GenomeProfileResult {
+                genome_id: genome_id.to_string(),
+                sample_id: sample_id.clone(),
+                adjusted_ani: 0.0,
+                taxonomic_abundance: 0.0,
+                sequence_abundance: 0.0,
+                common_tags: 0,
+                total_tags: genome_tag_totals[genome_id.as_str()],
+                eff_cov: 0.0,
+                breadth_bins: (0, 0),
+                shared_span: 0,
+            });
+        }
+    }
 }
 
-fn create_multi_writer(out_file_name: &Option<String>) -> Result<Box<dyn Write + Send>> {
+fn create_multi_writer(output: &OutputOptions, out_file_name: &Option<String>) -> Result<Box<dyn Write + Send>> {
     let mut mw = MultiWriter::new();
-    mw.add_writer(Box::new(BufWriter::new(std::io::stdout())));
+    mw.add_writer(Box::new(output.buf_writer(std::io::stdout())));
     if let Some(path) = out_file_name {
-        let file = File::create(path)
+        let file = output.create_file(path)
             .with_context(|| format!("Failed to create output file: {}", path))?;
-        mw.add_writer(Box::new(BufWriter::new(file)));
+        mw.add_writer(Box::new(output.buf_writer(file)));
     }
     Ok(Box::new(mw))
 }
 
-fn print_header(writer: &Arc<Mutex<Box<dyn Write + Send>>>) -> Result<()> {
-    let mut writer = writer.lock().unwrap();
-    writeln!(writer, "{:<20} {:<20} {:<10} {:<10} {:<15} {:<15} {:<10} {:<10} {:<10} {:<15} {:<10} {:<10}",
-        "Sample_file", "Genome_file", "ANI(%)", "Eff_cov", "ANI_5-95%", "Eff_lambda", "Lambda_5-95%", "Median_cov", "Mean_cov", "Containment", "Naive_ANI", "Contig_name")?;
+fn print_header(writer: &mut dyn Write) -> Result<()> {
+    writeln!(writer, "{:<20} {:<20} {:<10} {:<10} {:<15} {:<15} {:<10} {:<10} {:<10} {:<15} {:<12} {:<10} {:<10} {:<10} {:<10}",
+        "Sample_file", "Genome_file", "ANI(%)", "Eff_cov", "ANI_5-95%", "Eff_lambda", "Lambda_5-95%", "Median_cov", "Mean_cov", "Containment", "Contain_ratio", "Jaccard", "Breadth", "Naive_ANI", "Contig_name")?;
     writeln!(writer, "{:-<150}", "")?;
     Ok(())
 }
 
-fn print_result(result: &QueryResult, writer: &Arc<Mutex<Box<dyn Write + Send>>>) -> Result<()> {
-    let mut writer = writer.lock().unwrap();
-    writeln!(writer, "{:<20} {:<20} {:<10.2} {:<10.3} {:<7.2}-{:<7.2} {:<10.3} {:<7.2}-{:<7.2} {:<10.3} {:<10.3} {:<7} {:<10.2} {:<10}",
+// precision为--precision；未指定时各列沿用默认小数位数
+fn print_result(result: &QueryResult, precision: Option<usize>, writer: &mut dyn Write) -> Result<()> {
+    let p = |default: usize| precision.unwrap_or(default);
+    writeln!(writer, "{:<20} {:<20} {:<10.*} {:<10.*} {:<7.*}-{:<7.*} {:<10.*} {:<7.*}-{:<7.*} {:<10.*} {:<10.*} {:<7} {:<12.*} {:<10.*} {:<10.*} {:<10.*} {:<10}",
         result.sample_file,
         result.genome_file,
-        result.adjusted_ani,
-        result.eff_cov,
-        result.ani_percentile.0,
-        result.ani_percentile.1,
-        result.eff_lambda,
-        result.lambda_percentile.0,
-        result.lambda_percentile.1,
-        result.median_cov,
-        result.mean_cov_geq1,
+        p(2), result.adjusted_ani,
+        p(3), result.eff_cov,
+        p(2), result.ani_percentile.0,
+        p(2), result.ani_percentile.1,
+        p(3), result.eff_lambda,
+        p(2), result.lambda_percentile.0,
+        p(2), result.lambda_percentile.1,
+        p(3), result.median_cov,
+        p(3), result.mean_cov_geq1,
         result.containment_ind,
-        result.naive_ani,
+        p(6), result.containment,
+        p(6), result.jaccard,
+        p(3), breadth_fraction(result.breadth_bins),
+        p(2), result.naive_ani,
         result.contig_name
     )?;
     Ok(())
 }
 
+// 覆盖广度：contig按BREADTH_BIN_SIZE分窗，统计含共享标签的窗口数与含参考标签的窗口数。
+// 真实存在的基因组，共享标签沿全长散布；只有少数重复区标签命中时集中在个别窗口，广度很低
+const BREADTH_BIN_SIZE: usize = 10_000;
+
+fn breadth_bins<F: Fn(&Hash) -> bool>(entry: &SyldbEntry, is_shared: F) -> (usize, usize) {
+    let mut occupied: FxHashSet<usize> = FxHashSet::default();
+    let mut covered: FxHashSet<usize> = FxHashSet::default();
+    for (tag, &position) in entry.tags.iter().zip(&entry.positions) {
+        let bin = position / BREADTH_BIN_SIZE;
+        occupied.insert(bin);
+        if is_shared(tag) {
+            covered.insert(bin);
+        }
+    }
+    (covered.len(), occupied.len())
+}
+
+// 共享标签在contig上的跨度：第一个到最后一个共享标签的坐标差。
+// 依赖positions为真实坐标；只有一个共享标签时跨度为0
+fn shared_span<F: Fn(&Hash) -> bool>(entry: &SyldbEntry, is_shared: F) -> usize {
+    let mut shared_positions = entry.tags.iter().zip(&entry.positions)
+        .filter(|(tag, _)| is_shared(tag))
+        .map(|(_, &position)| position);
+    let Some(first) = shared_positions.next() else {
+        return 0;
+    };
+    let (min, max) = shared_positions.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+    max - min
+}
+
+// --min-breadth：共享标签跨度占基因组长度的比例不足时拒绝。
+// 没有记录基因组大小(gn_size)的旧数据库无法判断，一律通过
+fn passes_min_breadth(shared_span: usize, genome_size: usize, min_breadth: f64) -> bool {
+    if min_breadth <= 0.0 || genome_size == 0 {
+        return true;
+    }
+    shared_span as f64 / genome_size as f64 >= min_breadth
+}
+
+fn breadth_fraction((covered, occupied): (usize, usize)) -> f64 {
+    if occupied == 0 {
+        0.0
+    } else {
+        covered as f64 / occupied as f64
+    }
+}
+
+// containment = shared/ref；Jaccard = shared/(ref+query-shared)，即两个标签集合的交并比
+fn containment_and_jaccard(shared_tags: usize, ref_tags: usize, query_tags: usize) -> (f64, f64) {
+    let containment = if ref_tags == 0 { 0.0 } else { shared_tags as f64 / ref_tags as f64 };
+    let union = (ref_tags + query_tags).saturating_sub(shared_tags);
+    let jaccard = if union == 0 { 0.0 } else { shared_tags as f64 / union as f64 };
+    (containment, jaccard)
+}
+
 // ==================== 修复的统计计算函数 ====================
 // FIX: 删除 coverage_factor 调整，使用纯 containment ANI
 fn calculate_statistics(shared_tags: usize, query_tags: usize, total_ref_tags: usize) -> QueryResult {
+    calculate_statistics_with(shared_tags, query_tags, total_ref_tags, MIN_SHARED_TAGS)
+}
+
+// min_shared_tags：共享标签不足此数时ANI按比例压低（与过滤阈值一致）
+fn calculate_statistics_with(shared_tags: usize, query_tags: usize, total_ref_tags: usize, min_shared_tags: usize) -> QueryResult {
     if query_tags == 0 || total_ref_tags == 0 {
         return QueryResult {
             sample_file: String::new(),
@@ -332,27 +997,32 @@ fn calculate_statistics(shared_tags: usize, query_tags: usize, total_ref_tags: u
             median_cov: 0.0,
             mean_cov_geq1: 0.0,
             containment_ind: format!("{}/{}", shared_tags, total_ref_tags),
+            containment: 0.0,
+            jaccard: 0.0,
             naive_ani: 0.0,
             ref_tags: total_ref_tags,
             shared_tags: 0,
             query_tags: 0,
             taxonomic_abundance: 0.0,
             sequence_abundance: 0.0,
+            breadth_bins: (0, 0),
+            shared_span: 0,
         };
     }
 
     let shared_tags_f64 = shared_tags as f64;
     let total_ref_tags_f64 = total_ref_tags as f64;
     let containment_ratio = shared_tags_f64 / total_ref_tags_f64;
+    let (containment, jaccard) = containment_and_jaccard(shared_tags, total_ref_tags, query_tags);
     
     // FIX: 只有当共享标签数大于最小要求时才计算 ANI
-    let (naive_ani, adjusted_ani) = if shared_tags >= MIN_SHARED_TAGS {
+    let (naive_ani, adjusted_ani) = if shared_tags >= min_shared_tags {
         let naive = f64::powf(containment_ratio, 1.0 / K) * 100.0;
         // FIX: 删除 coverage_factor 调整，使用纯 containment ANI
         (naive, naive)
     } else {
         // FIX: 共享标签不足时，ANI 应该接近 0 而不是 80%
-        let base_ani = (shared_tags_f64 / MIN_SHARED_TAGS as f64) * 30.0;
+        let base_ani = (shared_tags_f64 / min_shared_tags as f64) * 30.0;
         (base_ani, base_ani)
     };
     
@@ -388,417 +1058,361 @@ fn calculate_statistics(shared_tags: usize, query_tags: usize, total_ref_tags: u
         median_cov: 1.0,
         mean_cov_geq1: 1.0,
         containment_ind: format!("{}/{}", shared_tags, total_ref_tags),
+        containment,
+        jaccard,
         naive_ani,
         ref_tags: total_ref_tags,
         shared_tags,
         query_tags,
         taxonomic_abundance: 0.0,
         sequence_abundance: 0.0,
+        breadth_bins: (0, 0),
+        shared_span: 0,
     }
 }
 
 // ==================== 修复的过滤函数 ====================
-// FIX: 删除早期返回，强制执行所有过滤条件
-fn filter_results(result: &QueryResult, min_ani: Option<f64>) -> bool {
+// 结果被过滤的原因，None表示通过。query与profile的过滤只在默认ANI阈值上不同
+fn rejection_reason(result: &QueryResult, effective_min_ani: f64, thresholds: &FilterThresholds) -> Option<&'static str> {
     // 没有共享标签直接过滤
     if result.shared_tags == 0 {
-        return false;
+        return Some("no_shared_tags");
     }
 
     // FIX: 删除这个导致假阳性的早期返回！
     // if result.shared_tags > 0 { return true; }
 
     // FIX: 强制执行最小共享标签数过滤
-    if result.shared_tags < MIN_SHARED_TAGS {
-        return false;
+    if result.shared_tags < thresholds.min_shared_tags {
+        return Some("min_shared_tags");
     }
 
     // FIX: 强制执行最小覆盖度过滤
-    if result.eff_cov < MIN_COVERAGE {
-        return false;
+    if result.eff_cov < thresholds.min_coverage {
+        return Some("min_coverage");
+    }
+
+    // 参考标签太少的基因组（-M/--min-number-kmers）
+    if result.ref_tags < thresholds.min_tags_for_genome {
+        return Some("min_number_kmers");
     }
 
     // FIX: 强制执行 ANI 过滤
-    let effective_min_ani = min_ani.unwrap_or(MIN_ANI);
     if result.adjusted_ani < effective_min_ani {
-        return false;
+        return Some("minimum_ani");
     }
 
-    true
+    None
 }
 
-// FIX: 同样修复 profile 专用的过滤函数
-fn filter_results_for_profile(result: &QueryResult, min_ani: Option<f64>) -> bool {
-    if result.shared_tags == 0 {
-        return false;
+// FIX: 删除早期返回，强制执行所有过滤条件。dump为Some时同时记录过滤前的结果（--unfiltered-dump）
+fn filter_results(result: &QueryResult, min_ani: Option<f64>, thresholds: &FilterThresholds, dump: Option<&UnfilteredDump>) -> bool {
+    let reason = rejection_reason(result, min_ani.unwrap_or(MIN_ANI), thresholds);
+    if let Some(dump) = dump {
+        dump.record(result, reason);
     }
+    reason.is_none()
+}
 
-    // FIX: profile 模式需要更严格的过滤
-    if result.shared_tags < MIN_SHARED_TAGS {
-        return false;
-    }
+// FIX: 同样修复 profile 专用的过滤函数（默认ANI阈值更严格）
+fn filter_results_for_profile(result: &QueryResult, min_ani: Option<f64>, thresholds: &FilterThresholds, dump: Option<&UnfilteredDump>) -> bool {
+    filter_results(result, Some(min_ani.unwrap_or(PROFILE_MIN_ANI)), thresholds, dump)
+}
 
-    if result.eff_cov < PROFILE_MIN_COVERAGE {
-        return false;
+// --unfiltered-dump：过滤前的全部候选命中，每行注明通过(pass)或被哪个阈值过滤，用于调整阈值。
+// 并行写入，写入错误留到finish时返回
+struct UnfilteredDump {
+    writer: Mutex<Box<dyn Write + Send>>,
+    error: Mutex<Option<io::Error>>,
+}
+
+impl UnfilteredDump {
+    fn create(output: &OutputOptions, path: &str) -> Result<Self> {
+        let file = output.create_file(path)
+            .with_context(|| format!("Failed to create unfiltered dump: {}", path))?;
+        let mut writer: Box<dyn Write + Send> = Box::new(output.buf_writer(file));
+        writeln!(writer, "Sample_file\tGenome_file\tContig_name\tANI(%)\tEff_cov\tShared_tags\tRef_tags\tQuery_tags\tStatus")
+            .with_context(|| format!("Failed to write unfiltered dump: {}", path))?;
+        Ok(UnfilteredDump { writer: Mutex::new(writer), error: Mutex::new(None) })
     }
 
-    let effective_min_ani = min_ani.unwrap_or(PROFILE_MIN_ANI);
-    if result.adjusted_ani < effective_min_ani {
-        return false;
+    fn record(&self, result: &QueryResult, reason: Option<&str>) {
+        let written = writeln!(self.writer.lock().unwrap(), "{}\t{}\t{}\t{:.4}\t{:.6}\t{}\t{}\t{}\t{}",
+            result.sample_file, result.genome_file, result.contig_name,
+            result.adjusted_ani, result.eff_cov,
+            result.shared_tags, result.ref_tags, result.query_tags,
+            reason.unwrap_or("pass"));
+        if let Err(e) = written {
+            self.error.lock().unwrap().get_or_insert(e);
+        }
     }
 
-    if result.ref_tags < MIN_TAGS_FOR_GENOME {
-        return false;
+    fn finish(self) -> Result<()> {
+        if let Some(e) = self.error.into_inner().unwrap() {
+            return Err(e).context("Failed to write unfiltered dump");
+        }
+        self.writer.into_inner().unwrap().flush().context("Failed to write unfiltered dump")
     }
+}
+
+// 检查点对应的输入：影响初始比对与重分配结果的参数的哈希，以及数据库文件和样本文件的大小和修改时间。
+// 数据库或样本被重建、参数改变后，旧检查点不再复用
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CheckpointKey {
+    db_file: String,
+    args_hash: Hash,
+    db_size: u64,
+    // 修改时间（自UNIX纪元的秒和纳秒），文件系统不提供时为None
+    db_modified: Option<(u64, u32)>,
+    // for_profile只填数据库部分，样本部分由for_sample逐样本填入
+    sample_size: u64,
+    sample_modified: Option<(u64, u32)>,
+}
 
-    true
+// 文件的大小与修改时间（自UNIX纪元的秒和纳秒，文件系统不提供时为None）
+fn file_stamp(path: &str) -> Result<(u64, Option<(u64, u32)>)> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read metadata of {}", path))?;
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since| (since.as_secs(), since.subsec_nanos()));
+    Ok((metadata.len(), modified))
 }
+
+impl CheckpointKey {
+    fn for_profile(args: &ProfileArgs, min_ani: f64) -> Result<Self> {
+        let (db_size, db_modified) = file_stamp(&args.db_file)?;
+        // 只计入检查点之前各步骤用到的参数；输出格式、线程数等只影响之后的步骤
+        let relevant = format!(
+            "{:?}",
+            (
+                min_ani,
+                (args.min_shared_tags, args.min_tag_depth, args.min_coverage, args.min_breadth, args.min_number_kmers),
+                (&args.aggregate, &args.include_genomes, &args.exclude_genomes, args.no_dealias),
+                (&args.host_db, args.keep_shared_host_tags),
+                (args.depth_normalize, args.rarefy, args.rarefy_seed, args.preview, args.min_sample_tags),
+            )
+        );
+        Ok(CheckpointKey {
+            db_file: args.db_file.clone(),
+            args_hash: crate::constants::hash_string(&relevant),
+            db_size,
+            db_modified,
+            sample_size: 0,
+            sample_modified: None,
+        })
+    }
+
+    fn for_sample(&self, sample_file: &str) -> Result<Self> {
+        let (sample_size, sample_modified) = file_stamp(sample_file)?;
+        Ok(CheckpointKey { sample_size, sample_modified, ..self.clone() })
     }
 }
 
-impl Write for MultiWriter {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        for w in &mut self.writers {
-            w.write_all(buf)?;
+// (样本源, contig)与其(breadth_bins, shared_span)
+type ContigBreadth = ((String, String), ((usize, usize), usize));
+
+// --checkpoint：样本文件完成初始比对与winner table重分配后的中间结果，文件中写在CheckpointKey之后；
+// --resume时数据库文件和相关参数都未改变的样本直接读取，不再重算
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SampleCheckpoint {
+    sample_file: String,
+    reassigned_results: Vec<QueryResult>,
+    // (样本源, contig) -> (breadth_bins, shared_span)，取自初始比对
+    contig_breadth: Vec<ContigBreadth>,
+}
+
+fn checkpoint_path(dir: &str, sample_file: &str) -> PathBuf {
+    // 用完整路径命名，避免不同目录下的同名样本互相覆盖
+    PathBuf::from(dir).join(format!("{}.ckpt", sample_file.replace(['/', '\\'], "_")))
+}
+
+fn load_checkpoint(dir: &str, sample_file: &str, key: &CheckpointKey) -> Option<SampleCheckpoint> {
+    let path = checkpoint_path(dir, sample_file);
+    let file = File::open(&path).ok()?;
+    match bincode::deserialize_from::<_, (CheckpointKey, SampleCheckpoint)>(BufReader::new(file)) {
+        Ok((stored_key, checkpoint)) if checkpoint.sample_file == sample_file && stored_key == *key => {
+            Some(checkpoint)
         }
-        Ok(buf.len())
-    }
-    fn flush(&mut self) -> io::Result<()> {
-        for w in &mut self.writers {
-            w.flush()?;
+        Ok(_) => {
+            eprintln!("Warning: checkpoint {} was written for a different or modified database or sample file, or with different profiling options; recomputing {}", path.display(), sample_file);
+            None
+        }
+        Err(e) => {
+            eprintln!("Warning: ignoring unreadable checkpoint {}: {}", path.display(), e);
+            None
         }
-        Ok(())
     }
 }
 
-pub fn query(args: ContainArgs) -> Result<()> {
-    // 首先测试文件格式
-    let db_files: Vec<_> = args.files.iter()
-        .filter(|f| f.ends_with(".syldb"))
-        .collect();
-    
-    let sample_files: Vec<_> = args.files.iter()
-        .filter(|f| f.ends_with(".sylsp"))
-        .collect();
+fn write_checkpoint(dir: &str, key: &CheckpointKey, checkpoint: &SampleCheckpoint) -> Result<()> {
+    let path = checkpoint_path(dir, &checkpoint.sample_file);
+    // 检查点是本工具的中间文件，重跑时总是覆盖
+    OutputOptions::overwriting().write_atomically(&path, |writer| {
+        bincode::serialize_into(writer, &(key, checkpoint)).context("Failed to serialize checkpoint")
+    })
+    .with_context(|| format!("Failed to write checkpoint: {}", path.display()))
+}
 
-    if db_files.is_empty() {
-        return Err(anyhow!("No .syldb files found in input files"));
+// 有可用检查点时直接返回，否则调用compute计算并写出检查点
+fn checkpointed_stage<F>(checkpoint: Option<(&str, &CheckpointKey)>, resume: bool, sample_file: &str, compute: F) -> Option<SampleCheckpoint>
+where
+    F: FnOnce() -> Option<SampleCheckpoint>,
+{
+    let Some((dir, key)) = checkpoint else {
+        return compute();
+    };
+    let key = match key.for_sample(sample_file) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("Warning: {:#}; not checkpointing {}", e, sample_file);
+            return compute();
+        }
+    };
+    if resume {
+        if let Some(checkpoint) = load_checkpoint(dir, sample_file, &key) {
+            eprintln!("Resuming {} from checkpoint", sample_file);
+            return Some(checkpoint);
+        }
     }
-
-    if sample_files.is_empty() {
-        return Err(anyhow!("No .sylsp files found in input files"));
+    let stage = compute()?;
+    if let Err(e) = write_checkpoint(dir, &key, &stage) {
+        eprintln!("Warning: {:#}", e);
     }
+    Some(stage)
+}
 
-    // 创建输出写入器
-    let writer = Arc::new(Mutex::new(create_multi_writer(&args.out_file_name)?));
+// profile只在一个层级并行：样本文件之间，或单个样本内的数据库基因组之间。
+// 两层都用par_iter时rayon任务嵌套，大样本集上反而因争用变慢
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Parallelism {
+    Samples,
+    Genomes,
+}
 
-    // 打印表头（只打印一次）
-    print_header(&writer)?;
+impl Parallelism {
+    fn for_profile(parallel_over: &str, sample_files: usize, threads: usize) -> Self {
+        match parallel_over {
+            "samples" => Parallelism::Samples,
+            "genomes" => Parallelism::Genomes,
+            _ if sample_files >= threads => Parallelism::Samples,
+            _ => Parallelism::Genomes,
+        }
+    }
+}
 
-    // 并行处理所有数据库文件
-    for db_path in db_files {
-        eprintln!("Processing database file: {}", db_path);
-        
-        // 读取数据库文件
-        let db_file = File::open(db_path)
-            .with_context(|| format!("Failed to open database file: {}", db_path))?;
-        let db_reader = BufReader::new(db_file);
-        let db_entries: Vec<SyldbEntry> = bincode::deserialize_from(db_reader)
-            .with_context(|| format!("Failed to deserialize database file: {}", db_path))?;
+// --shared-tags-dump：每个(样本, contig)命中的共享标签，记为contig标签列表中的下标，
+// 写出时再取哈希和（--store-seq时的）序列
+struct SharedTagCapture {
+    tags: Mutex<FxHashMap<(String, String), Vec<usize>>>,
+}
 
-        eprintln!("Found {} entries in database", db_entries.len());
+impl SharedTagCapture {
+    fn new() -> Self {
+        SharedTagCapture { tags: Mutex::new(FxHashMap::default()) }
+    }
 
-        // 并行处理所有样本文件
-        sample_files.par_iter().try_for_each(|sample_path| -> Result<()> {
-            eprintln!("Processing sample file: {}", sample_path);
-            
-            let sample_file = File::open(sample_path)
-                .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
-            let sample_reader = BufReader::new(sample_file);
-            let sample_entries: Vec<SylspEntry> = bincode::deserialize_from(sample_reader)
-                .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
+    fn record(&self, sample_source: &str, db_entry: &SyldbEntry, sample_tags: &HashSet<Hash>) {
+        let shared: Vec<usize> = db_entry.tags.iter().enumerate()
+            .filter(|(_, tag)| sample_tags.contains(*tag))
+            .map(|(i, _)| i)
+            .collect();
+        self.tags.lock().unwrap().insert((sample_source.to_string(), db_entry.sequence_id.clone()), shared);
+    }
 
-            eprintln!("Found {} entries in sample", sample_entries.len());
-
-            // 检查样本数据的有效性
-            if sample_entries.is_empty() {
-                eprintln!("Warning: Sample {} has no tags", sample_path);
-                return Ok(());
-            }
-
-                    // 构建样本标签的哈希表
-        let sample_tags: HashMap<Hash, usize> = sample_entries.iter()
-            .map(|entry| (entry.tag.clone(), 1))
+    // 重分配后每个标签只归winner所有，与common_tags的计数保持一致
+    fn keep_winners(&self, results: &[QueryResult], winners: &FxHashMap<Hash, WinnerTableEntry>, cached_db_entries: &[SyldbEntry]) {
+        let db_by_id: FxHashMap<&str, &SyldbEntry> = cached_db_entries.iter()
+            .map(|entry| (entry.sequence_id.as_str(), entry))
             .collect();
+        let mut tags = self.tags.lock().unwrap();
+        for result in results {
+            let (Some(shared), Some(db_entry)) = (
+                tags.get_mut(&(result.sample_file.clone(), result.contig_name.clone())),
+                db_by_id.get(result.contig_name.as_str()),
+            ) else {
+                continue;
+            };
+            shared.retain(|&i| winners.get(&db_entry.tags[i]).is_none_or(|w| w.genome_id == result.contig_name));
+        }
+    }
+}
 
-            let total_sample_tags = sample_entries.len();
-            eprintln!("Total unique tags in sample: {}", total_sample_tags);
-
-            // 对每个基因组记录进行比对
-            for db_entry in &db_entries {
-                // 计算共享标签和统计信息
-                let mut shared_tags = 0;
-                let mut coverages = Vec::new();
-                let total_ref_tags = db_entry.tags.len();
-
-                for tag in &db_entry.tags {
-                    if sample_tags.contains_key(tag) {
-                        shared_tags += 1;
-                        coverages.push(1.0); // 简化的覆盖度计算
-                    }
-                }
-
-                eprintln!("Found {} shared tags between sample and reference {}", 
-                         shared_tags, db_entry.sequence_id);
-
-                // 计算统计数据
-                let mut result = calculate_statistics(
-                    shared_tags,
-                    total_sample_tags,
-                    total_ref_tags,
-                );
-
-                // 设置基本信息
-                result.sample_file = sample_path.to_string();
-                result.genome_file = db_path.to_string();
-                result.contig_name = db_entry.sequence_id.clone();
-                result.shared_tags = shared_tags;
-                result.query_tags = total_sample_tags;
-                result.ref_tags = total_ref_tags;
-
-                // 计算平均深度和覆盖度
-                if shared_tags > 0 {
-                    result.mean_cov_geq1 = 1.0; // 简化的深度计算
-                    result.eff_cov = shared_tags as f64 / total_ref_tags as f64;
-                    
-                    // 计算中位数覆盖度
-                    if !coverages.is_empty() {
-                        coverages.sort_by(|a, b| a.partial_cmp(b).unwrap());
-                        result.median_cov = if coverages.len() % 2 == 0 {
-                            (coverages[coverages.len()/2 - 1] + coverages[coverages.len()/2]) / 2.0
-                        } else {
-                            coverages[coverages.len()/2]
-                        };
+// 每个样本一个<sample>.shared_tags.tsv，只包含最终检出的基因组
+fn write_shared_tags_dump(
+    output: &OutputOptions,
+    dir: &str,
+    capture: &SharedTagCapture,
+    genome_results: &HashMap<String, Vec<GenomeProfileResult>>,
+    genome_mapping: &FxHashMap<String, (String, String)>,
+    cached_db_entries: &[SyldbEntry],
+) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create shared tags dump directory: {}", dir))?;
+    let db_by_id: FxHashMap<&str, &SyldbEntry> = cached_db_entries.iter()
+        .map(|entry| (entry.sequence_id.as_str(), entry))
+        .collect();
+    let tags = capture.tags.lock().unwrap();
+
+    for (sample_id, results) in genome_results {
+        let mut genome_ids: Vec<&str> = results.iter().map(|r| r.genome_id.as_str()).collect();
+        genome_ids.sort_by(|a, b| natural_cmp(a, b));
+        let mut contigs: Vec<&str> = tags.keys()
+            .filter(|(sample, _)| sample == sample_id)
+            .map(|(_, contig)| contig.as_str())
+            .collect();
+        contigs.sort_by(|a, b| natural_cmp(a, b));
+
+        let path = PathBuf::from(dir).join(format!("{}.shared_tags.tsv", sample_id));
+        output.write_atomically(&path, |writer| {
+            writeln!(writer, "Genome\tContig\tTag_hash\tSequence")?;
+            for genome_id in &genome_ids {
+                for contig in contigs.iter().filter(|c| genome_mapping.get(**c).map(|(id, _)| id.as_str()) == Some(*genome_id)) {
+                    let Some(db_entry) = db_by_id.get(contig) else {
+                        continue;
+                    };
+                    for &i in &tags[&(sample_id.clone(), contig.to_string())] {
+                        let sequence = db_entry.tag_sequences.as_ref().and_then(|seqs| seqs.get(i)).map_or("NA", |s| s.as_str());
+                        writeln!(writer, "{}\t{}\t{}\t{}", genome_id, contig, db_entry.tags[i], sequence)?;
                     }
                 }
-
-                // 应用过滤条件
-                if filter_results(&result, args.minimum_ani) {
-                    eprintln!("Result passed filters: ANI={:.2}, Coverage={:.3}", 
-                            result.adjusted_ani, result.eff_cov);
-                    // 输出结果
-                    print_result(&result, &writer)?;
-                } else {
-                    eprintln!("Result filtered out: ANI={:.2}, Coverage={:.3}", 
-                            result.adjusted_ani, result.eff_cov);
-                }
             }
             Ok(())
-        })?;
-    }
-
-    Ok(())
-}
-
-fn create_multi_writer(out_file_name: &Option<String>) -> Result<Box<dyn Write + Send>> {
-    let mut mw = MultiWriter::new();
-    mw.add_writer(Box::new(BufWriter::new(std::io::stdout())));
-    if let Some(path) = out_file_name {
-        let file = File::create(path)
-            .with_context(|| format!("Failed to create output file: {}", path))?;
-        mw.add_writer(Box::new(BufWriter::new(file)));
+        })
+        .with_context(|| format!("Failed to write shared tags dump: {}", path.display()))?;
     }
-    Ok(Box::new(mw))
-}
-
-fn print_header(writer: &Arc<Mutex<Box<dyn Write + Send>>>) -> Result<()> {
-    let mut writer = writer.lock().unwrap();
-    writeln!(writer, "{:<20} {:<20} {:<10} {:<10} {:<15} {:<15} {:<10} {:<10} {:<10} {:<15} {:<10} {:<10}",
-        "Sample_file", "Genome_file", "ANI(%)", "Eff_cov", "ANI_5-95%", "Eff_lambda", "Lambda_5-95%", "Median_cov", "Mean_cov", "Containment", "Naive_ANI", "Contig_name")?;
-    writeln!(writer, "{:-<150}", "")?;
-    Ok(())
-}
-
-fn print_result(result: &QueryResult, writer: &Arc<Mutex<Box<dyn Write + Send>>>) -> Result<()> {
-    let mut writer = writer.lock().unwrap();
-    writeln!(writer, "{:<20} {:<20} {:<10.2} {:<10.3} {:<7.2}-{:<7.2} {:<10.3} {:<7.2}-{:<7.2} {:<10.3} {:<10.3} {:<7} {:<10.2} {:<10}",
-        result.sample_file,
-        result.genome_file,
-        result.adjusted_ani,
-        result.eff_cov,
-        result.ani_percentile.0,
-        result.ani_percentile.1,
-        result.eff_lambda,
-        result.lambda_percentile.0,
-        result.lambda_percentile.1,
-        result.median_cov,
-        result.mean_cov_geq1,
-        result.containment_ind,
-        result.naive_ani,
-        result.contig_name
-    )?;
     Ok(())
 }
 
-fn calculate_statistics(shared_tags: usize, query_tags: usize, total_ref_tags: usize) -> QueryResult {
-    // 避免除零错误
-    if query_tags == 0 || total_ref_tags == 0 {
-        return QueryResult {
-            sample_file: String::new(),
-            genome_file: String::new(),
-            contig_name: String::new(),
-            adjusted_ani: 0.0,
-            eff_cov: 0.0,
-            ani_percentile: (0.0, 0.0),
-            eff_lambda: 0.0,
-            lambda_percentile: (0.0, 0.0),
-            median_cov: 0.0,
-            mean_cov_geq1: 0.0,
-            containment_ind: format!("{}/{}", shared_tags, total_ref_tags),
-            naive_ani: 0.0,
-            ref_tags: total_ref_tags,
-            shared_tags: 0,
-            query_tags: 0,
-            taxonomic_abundance: 0.0,
-            sequence_abundance: 0.0,
-        };
-    }
-
-    // 使用 f64 进行所有计算
-    let shared_tags_f64 = shared_tags as f64;
-    let total_ref_tags_f64 = total_ref_tags as f64;
-
-    // 计算基础 ANI（使用 sylph 的方法）
-    let containment_ratio = shared_tags_f64 / total_ref_tags_f64;
-    
-    // 只有当共享标签数大于最小要求时才计算 ANI
-    let (naive_ani, adjusted_ani) = if shared_tags >= MIN_SHARED_TAGS {
-        let naive = f64::powf(containment_ratio, 1.0 / K) * 100.0;
-        
-        // 计算调整后的 ANI
-        let coverage_factor = if containment_ratio < 0.1 {
-            1.0 + (0.1 - containment_ratio) * 0.5
-        } else {
-            1.0
-        };
-        let adjusted = (naive * coverage_factor).min(100.0);
-        (naive, adjusted)
-    } else {
-        // 当共享标签数太少时，ANI 应该很低但不一定是 0
-        let base_ani = (shared_tags_f64 / MIN_SHARED_TAGS as f64) * 80.0; // 使用 80% 作为基准
-        (base_ani, base_ani)
-    };
-    
-    // 计算有效覆盖度
-    let eff_cov = containment_ratio;
-    
-    // 计算 Lambda 值
-    let eff_lambda = if eff_cov < LAMBDA_THRESHOLD {
-        eff_cov * 1.2
-    } else {
-        eff_cov
-    };
-
-    // 计算置信区间
-    let base_uncertainty = 1.0;
-    let coverage_uncertainty = (1.0 - eff_cov) * 1.5;
-    let total_uncertainty = base_uncertainty + coverage_uncertainty;
-    
-    let ani_low = (adjusted_ani - total_uncertainty).max(0.0);
-    let ani_high = (adjusted_ani + total_uncertainty).min(100.0);
-    
-    // Lambda 置信区间
-    let lambda_uncertainty = 0.02 + (1.0 - eff_lambda) * 0.04;
-    let lambda_low = (eff_lambda - lambda_uncertainty).max(0.0);
-    let lambda_high = (eff_lambda + lambda_uncertainty).min(1.0);
-
-    QueryResult {
-        sample_file: String::new(),
-        genome_file: String::new(),
-        contig_name: String::new(),
-        adjusted_ani,
-        eff_cov,
-        ani_percentile: (ani_low, ani_high),
-        eff_lambda,
-        lambda_percentile: (lambda_low, lambda_high),
-        median_cov: 1.0,
-        mean_cov_geq1: 1.0,
-        containment_ind: format!("{}/{}", shared_tags, total_ref_tags),
-        naive_ani,
-        ref_tags: total_ref_tags,
-        shared_tags,
-        query_tags,
-        taxonomic_abundance: 0.0,
-        sequence_abundance: 0.0,
-    }
-}
-
-fn filter_results(result: &QueryResult, min_ani: Option<f64>) -> bool {
-    // 只有在有共享标签时才进行过滤
-    if result.shared_tags == 0 {
-        return false;
-    }
-
-    // 当计算丰度时，只要有共享标签就包含在结果中
-    if result.shared_tags > 0 {
-        return true;
-    }
-
-    // 基本过滤条件
-    if result.eff_cov < MIN_COVERAGE {
-        return false;
-    }
-
-    // ANI 过滤
-    if let Some(min_ani) = min_ani {
-        if result.adjusted_ani < min_ani {
-            return false;
+// 构建样本标签的哈希表：每个tag条目是一次观测，出现次数不足min_tag_depth的tag
+// （多为测序错误产生的单次tag）视为不存在
+fn sample_tag_set(entries: &[&SampleTag], min_tag_depth: usize) -> HashSet<Hash> {
+    if min_tag_depth > 1 {
+        let mut depth: FxHashMap<Hash, usize> = FxHashMap::default();
+        for entry in entries {
+            *depth.entry(entry.tag).or_insert(0) += entry.count;
         }
-    } else if result.adjusted_ani < MIN_ANI {
-        return false;
-    }
-
-    true
-}
-
-// 新增profile专用的过滤函数
-fn filter_results_for_profile(result: &QueryResult, min_ani: Option<f64>) -> bool {
-    // 只有在有共享标签时才进行过滤
-    if result.shared_tags == 0 {
-        return false;
-    }
-
-    // profile模式下的最小共享标签数过滤（更严格）
-    if result.shared_tags < MIN_SHARED_TAGS {
-        return false;
-    }
-
-    // profile模式下的最小覆盖率过滤（更严格）
-    if result.eff_cov < PROFILE_MIN_COVERAGE {
-        return false;
-    }
-
-    // profile模式下的ANI过滤（更严格）
-    let effective_min_ani = min_ani.unwrap_or(PROFILE_MIN_ANI);
-    if result.adjusted_ani < effective_min_ani {
-        return false;
-    }
-
-    // 最小标签数过滤（确保genome有足够的标签）
-    if result.ref_tags < MIN_TAGS_FOR_GENOME {
-        return false;
+        depth.into_iter()
+            .filter(|&(_, count)| count >= min_tag_depth)
+            .map(|(tag, _)| tag)
+            .collect()
+    } else {
+        entries.iter().map(|entry| entry.tag).collect()
     }
-
-    true
 }
 
 // 内部函数：使用缓存的数据库数据进行查询 - 优化大文件读取
+#[allow(clippy::too_many_arguments)]
 fn query_single_file_with_cached_db(
     sample_path: &str, 
     db_path: &str, 
     cached_db_entries: &[SyldbEntry], 
-    cached_sample_entries: &FxHashMap<String, Vec<SylspEntry>>,
-    min_ani: f64
+    cached_sample_entries: &SampleTags,
+    min_ani: f64,
+    thresholds: &FilterThresholds,
+    dump: Option<&UnfilteredDump>,
+    shared: Option<&SharedTagCapture>,
+    parallelism: Parallelism,
 ) -> Result<Vec<QueryResult>> {
     eprintln!("Processing sample file with cached database: {}", sample_path);
     
@@ -815,7 +1429,7 @@ fn query_single_file_with_cached_db(
     }
 
     // 按样本源分组 - 这是关键：处理合并文件中的多个样本
-    let mut sample_groups: FxHashMap<String, Vec<&SylspEntry>> = FxHashMap::default();
+    let mut sample_groups: FxHashMap<String, Vec<&SampleTag>> = FxHashMap::default();
     for entry in sample_entries {
         sample_groups.entry(entry.sample_source.clone())
             .or_default()
@@ -826,257 +1440,214 @@ fn query_single_file_with_cached_db(
               sample_groups.len(), 
               sample_groups.keys().collect::<Vec<_>>());
 
-    // 并行处理每个样本组，然后合并结果
-    let mut all_results: Vec<QueryResult> = sample_groups.par_iter()
-        .flat_map(|(sample_source, entries)| {
-            eprintln!("Processing sample source: {} with {} entries", sample_source, entries.len());
-            
-            // 构建样本标签的哈希表 - 使用更高效的HashSet
-            let sample_tags: HashSet<Hash> = entries.iter()
-                .map(|entry| entry.tag.clone())
-                .collect();
-
-            let total_sample_tags = entries.len();
-
-            // 并行处理每个基因组记录进行比对
-            cached_db_entries.par_iter().filter_map(|db_entry| {
-                // 最小标签数过滤（参考sylph的min_number_kmers）
-                if db_entry.tags.len() < MIN_TAGS_FOR_GENOME {
-                    return None;
-                }
-
-                // 计算共享标签和统计信息 - 优化计算方式
-                let shared_tags = db_entry.tags.iter()
-                    .filter(|tag| sample_tags.contains(tag))
-                    .count();
-
-                let total_ref_tags = db_entry.tags.len();
-
-                // 计算统计数据
-                let mut result = calculate_statistics(
-                    shared_tags,
-                    total_sample_tags,
-                    total_ref_tags,
-                );
+    // 逐个处理样本组；基因组比对是否并行由parallelism决定
+    let compare_group = |(sample_source, entries): (&String, &Vec<&SampleTag>)| -> Vec<QueryResult> {
+        let total_sample_tags: usize = entries.iter().map(|entry| entry.count).sum();
+        eprintln!("Processing sample source: {} with {} entries", sample_source, total_sample_tags);
+        
+        let sample_tags = sample_tag_set(entries, thresholds.min_tag_depth);
 
-                // 设置基本信息 - 关键：使用实际的样本源ID
-                result.sample_file = sample_source.clone();
-                result.genome_file = db_path.to_string();
-                result.contig_name = db_entry.sequence_id.clone();
-                result.shared_tags = shared_tags;
-                result.query_tags = total_sample_tags;
-                result.ref_tags = total_ref_tags;
+        // 每个基因组记录进行比对
+        let compare_genome = |db_entry: &SyldbEntry| -> Option<QueryResult> {
+            // 最小标签数过滤（参考sylph的min_number_kmers）
+            if db_entry.tags.len() < thresholds.min_tags_for_genome {
+                return None;
+            }
 
-                // 计算平均深度和覆盖度
-                if shared_tags > 0 {
-                    result.mean_cov_geq1 = 1.0;
-                    result.eff_cov = shared_tags as f64 / total_ref_tags as f64;
-                    result.median_cov = 1.0;
-                }
+            // 计算共享标签和统计信息 - 优化计算方式
+            let shared_tags = db_entry.tags.iter()
+                .filter(|tag| sample_tags.contains(tag))
+                .count();
+
+            let total_ref_tags = db_entry.tags.len();
+
+            // 计算统计数据
+            let mut result = calculate_statistics_with(
+                shared_tags,
+                total_sample_tags,
+                total_ref_tags,
+                thresholds.min_shared_tags,
+            );
+
+            // 设置基本信息 - 关键：使用实际的样本源ID
+            result.sample_file = sample_source.clone();
+            result.genome_file = db_path.to_string();
+            result.contig_name = db_entry.sequence_id.clone();
+            result.shared_tags = shared_tags;
+            result.query_tags = total_sample_tags;
+            result.ref_tags = total_ref_tags;
+            result.breadth_bins = breadth_bins(db_entry, |tag| sample_tags.contains(tag));
+            result.shared_span = shared_span(db_entry, |tag| sample_tags.contains(tag));
+
+            // 计算平均深度和覆盖度
+            if shared_tags > 0 {
+                result.mean_cov_geq1 = 1.0;
+                result.eff_cov = shared_tags as f64 / total_ref_tags as f64;
+                result.median_cov = 1.0;
+            }
 
-                // 应用profile专用的过滤条件
-                if filter_results_for_profile(&result, Some(min_ani)) {
-                    Some(result)
-                } else {
-                    None
+            // 应用profile专用的过滤条件
+            if filter_results_for_profile(&result, Some(min_ani), thresholds, dump) {
+                if let Some(capture) = shared {
+                    capture.record(sample_source, db_entry, &sample_tags);
                 }
-            }).collect::<Vec<QueryResult>>()
-        })
-        .collect();
+                Some(result)
+            } else {
+                None
+            }
+        };
+        match parallelism {
+            Parallelism::Genomes => cached_db_entries.par_iter().filter_map(compare_genome).collect(),
+            Parallelism::Samples => cached_db_entries.iter().filter_map(compare_genome).collect(),
+        }
+    };
+    let mut all_results: Vec<QueryResult> = sample_groups.iter().flat_map(compare_group).collect();
 
     // 按ANI排序（参考sylph的排序机制）
-    all_results.sort_by(|a, b| b.adjusted_ani.partial_cmp(&a.adjusted_ani).unwrap());
+    all_results.sort_by(|a, b| compare_by(a, b, "ani"));
 
     eprintln!("Generated {} results for file {}", all_results.len(), sample_path);
     Ok(all_results)
 }
 
-// 添加新的公共函数用于单个文件的查询（保持原有接口不变）
-pub fn query_single_file(sample_path: &str, db_path: &str, min_ani: f64) -> Result<Vec<QueryResult>> {
-    eprintln!("Processing database file: {}", db_path);
-    
-    // 读取数据库文件
-    let db_file = File::open(db_path)
-        .with_context(|| format!("Failed to open database file: {}", db_path))?;
-    let db_reader = BufReader::new(db_file);
-    let db_entries: Vec<SyldbEntry> = bincode::deserialize_from(db_reader)
-        .with_context(|| format!("Failed to deserialize database file: {}", db_path))?;
+const TAXONOMY_CHUNK_LINES: usize = 4096;
 
-    eprintln!("Found {} entries in database", db_entries.len());
+// --taxonomy-max-skipped的默认值，report等不暴露该选项的调用方也使用它
+pub(crate) const DEFAULT_TAXONOMY_MAX_SKIPPED: f64 = 0.5;
 
-    // 读取样本文件 - 优化大文件读取
-    let sample_file = File::open(sample_path)
-        .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
-    let sample_reader = BufReader::with_capacity(100_000_000, sample_file); // 100MB 缓冲区
-    let sample_entries: Vec<SylspEntry> = bincode::deserialize_from(sample_reader)
-        .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
+pub(crate) fn read_taxonomy_file(taxonomy_file: &str, format: &str, has_header: &str, max_skipped_fraction: f64) -> Result<FxHashMap<String, Arc<TaxonomyInfo>>> {
+    // GTDB元数据可达数十万行：整体读入内存后并行解析
+    let content = std::fs::read_to_string(taxonomy_file)
+        .with_context(|| format!("Failed to read taxonomy file: {}", taxonomy_file))?;
+    let (taxonomy_map, stats) = parse_taxonomy_content(&content, format, has_header, true);
 
-    eprintln!("Found {} entries in sample", sample_entries.len());
+    eprintln!("Taxonomy file: {} data lines, {} parsed, {} skipped", stats.total(), stats.parsed, stats.skipped);
+    if stats.skipped_fraction() > max_skipped_fraction {
+        return Err(anyhow!(
+            "{} of {} lines in taxonomy file {} could not be parsed; check --taxonomy-format ({}) and that columns are tab-separated",
+            stats.skipped, stats.total(), taxonomy_file, format
+        ));
+    }
+    eprintln!("Loaded taxonomy information for {} genome variants", taxonomy_map.len());
+    Ok(taxonomy_map)
+}
 
-    // 检查样本数据的有效性
-    if sample_entries.is_empty() {
-        eprintln!("Warning: Sample {} has no tags", sample_path);
-        return Ok(Vec::new());
+// 数据行（不含标题、空行和注释行）的解析计数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct TaxonomyParseStats {
+    parsed: usize,
+    skipped: usize,
+}
+
+impl TaxonomyParseStats {
+    fn total(&self) -> usize {
+        self.parsed + self.skipped
     }
 
-    // 按样本源分组
-    let mut sample_groups: FxHashMap<String, Vec<&SylspEntry>> = FxHashMap::default();
-    for entry in &sample_entries {
-        sample_groups.entry(entry.sample_source.clone())
-            .or_default()
-            .push(entry);
+    fn skipped_fraction(&self) -> f64 {
+        if self.total() == 0 { 0.0 } else { self.skipped as f64 / self.total() as f64 }
     }
 
-    // 并行处理每个样本组，然后合并结果
-    let all_results: Vec<QueryResult> = sample_groups.par_iter()
-        .flat_map(|(sample_source, entries)| {
-            // 构建样本标签的哈希表 - 使用更高效的HashSet
-            let sample_tags: HashSet<Hash> = entries.iter()
-                .map(|entry| entry.tag.clone())
-                .collect();
+    fn merge(self, other: TaxonomyParseStats) -> TaxonomyParseStats {
+        TaxonomyParseStats { parsed: self.parsed + other.parsed, skipped: self.skipped + other.skipped }
+    }
+}
 
-            let total_sample_tags = entries.len();
-            eprintln!("Total unique tags in sample {}: {}", sample_source, total_sample_tags);
+// 常见的accession列名
+const TAXONOMY_HEADER_LABELS: &[&str] = &["accession", "user_genome", "genome", "genome_id", "assembly_accession", "id"];
 
-            // 并行处理每个基因组记录进行比对
-            db_entries.par_iter().filter_map(|db_entry| {
-                // 计算共享标签和统计信息
-                let shared_tags = db_entry.tags.par_iter()
-                    .map(|tag| if sample_tags.contains(tag) { 1 } else { 0 })
-                    .sum::<usize>();
+// --taxonomy-has-header auto：首列是列名，或GTDB格式的谱系列不含任何d__/p__/...等级前缀时视为标题
+fn looks_like_taxonomy_header(line: &str, format: &str) -> bool {
+    let mut columns = line.trim().split('\t');
+    let first = columns.next().unwrap_or("").trim().to_ascii_lowercase();
+    if TAXONOMY_HEADER_LABELS.contains(&first.as_str()) {
+        return true;
+    }
+    format == "gtdb" && !columns.next().is_some_and(|lineage| lineage.contains("__"))
+}
 
-                let total_ref_tags = db_entry.tags.len();
+// has_header为yes/no/auto决定是否跳过第一行。并行时按块解析，各块的FxHashMap按原文件顺序合并，
+// 因此重复的accession与逐行解析一样以后出现的为准
+fn parse_taxonomy_content(content: &str, format: &str, has_header: &str, parallel: bool) -> (FxHashMap<String, Arc<TaxonomyInfo>>, TaxonomyParseStats) {
+    // GTDB元数据文件至少3列；NCBI谱系和custom格式只要求accession加谱系
+    let min_columns = if format == "gtdb" { 3 } else { 2 };
+    let skip_header = match has_header {
+        "yes" => true,
+        "no" => false,
+        _ => content.lines().next().is_some_and(|line| looks_like_taxonomy_header(line, format)),
+    };
+    let lines: Vec<(usize, &str)> = content.lines().enumerate().skip(usize::from(skip_header)).collect();
+
+    let parse_chunk = |chunk: &[(usize, &str)]| {
+        let mut taxonomy_map = FxHashMap::default();
+        let mut stats = TaxonomyParseStats::default();
+        for &(index, line) in chunk {
+            match insert_taxonomy_line(&mut taxonomy_map, index + 1, line, format, min_columns) {
+                Some(true) => stats.parsed += 1,
+                Some(false) => stats.skipped += 1,
+                None => {}
+            }
+        }
+        (taxonomy_map, stats)
+    };
 
-                eprintln!("Found {} shared tags between sample {} and reference {}", 
-                         shared_tags, sample_source, db_entry.sequence_id);
+    if parallel {
+        lines.par_chunks(TAXONOMY_CHUNK_LINES)
+            .map(parse_chunk)
+            .reduce(|| (FxHashMap::default(), TaxonomyParseStats::default()), |(mut merged, stats), (chunk_map, chunk_stats)| {
+                merged.extend(chunk_map);
+                (merged, stats.merge(chunk_stats))
+            })
+    } else {
+        parse_chunk(&lines)
+    }
+}
 
-                // 计算统计数据
-                let mut result = calculate_statistics(
-                    shared_tags,
-                    total_sample_tags,
-                    total_ref_tags,
-                );
+// 返回None表示空行或注释行，Some(false)表示该行无法解析而被跳过
+fn insert_taxonomy_line(
+    taxonomy_map: &mut FxHashMap<String, Arc<TaxonomyInfo>>,
+    line_number: usize,
+    line: &str,
+    format: &str,
+    min_columns: usize,
+) -> Option<bool> {
+    let line = line.trim();
+
+    // 跳过空行和注释行
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
 
-                // 设置基本信息
-                result.sample_file = sample_source.clone();
-                result.genome_file = db_path.to_string();
-                result.contig_name = db_entry.sequence_id.clone();
-                result.shared_tags = shared_tags;
-                result.query_tags = total_sample_tags;
-                result.ref_tags = total_ref_tags;
-
-                // 计算平均深度和覆盖度
-                if shared_tags > 0 {
-                    result.mean_cov_geq1 = 1.0;
-                    result.eff_cov = shared_tags as f64 / total_ref_tags as f64;
-                    
-                    // 简化的中位数覆盖度计算（避免生成大量向量）
-                    result.median_cov = 1.0;
-                }
+    let parts: Vec<&str> = line.split('\t').collect();
+    if parts.len() < min_columns {
+        eprintln!("Warning: Invalid line format at line {}: {}", line_number, line);
+        return Some(false);
+    }
 
-                // 应用过滤条件
-                if filter_results(&result, Some(min_ani)) {
-                    eprintln!("Result passed filters: ANI={:.2}, Coverage={:.3}", 
-                            result.adjusted_ani, result.eff_cov);
-                    Some(result)
-                } else {
-                    eprintln!("Result filtered out: ANI={:.2}, Coverage={:.3}", 
-                            result.adjusted_ani, result.eff_cov);
-                    None
-                }
-            }).collect::<Vec<QueryResult>>()
-        })
-        .collect();
+    let accession = parts[0].trim();
 
-    Ok(all_results)
-}
+    // 处理genome ID：去掉前缀 (RS_, GB_, 等)
+    let genome_id = ["RS_", "GB_", "GS_"].iter()
+        .find_map(|prefix| accession.strip_prefix(prefix))
+        .unwrap_or(accession);
 
-// 从文件路径中提取基因组ID的函数
-fn extract_genome_id(file_path: &str) -> String {
-    let path = std::path::Path::new(file_path);
-    
-    // 获取文件名（不含路径）
-    let file_name = path.file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
-    
-    // 移除.fasta.gz或.fasta扩展名
-    let name_without_ext = file_name
-        .strip_suffix(".fasta.gz")
-        .or_else(|| file_name.strip_suffix(".fasta"))
-        .unwrap_or(file_name);
-    
-    // 从test_files/目录中提取
-    let clean_name = if let Some(stripped) = name_without_ext.strip_prefix("test_files/") {
-        stripped
-    } else {
-        name_without_ext
-    };
-    
-    clean_name.to_string()
-}
+    // 解析分类信息并包装在 Arc 中
+    match TaxonomyInfo::parse(&parts[1..], format) {
+        Ok(taxonomy) => {
+            let taxonomy_arc = Arc::new(taxonomy);
+            // 添加基本ID（例如：GCF_000006685.1）
+            taxonomy_map.insert(genome_id.to_string(), Arc::clone(&taxonomy_arc));
 
-// 读取taxonomy文件并建立genome到分类信息的映射
-fn read_taxonomy_file(taxonomy_file: &str) -> Result<FxHashMap<String, Arc<TaxonomyInfo>>> {
-    use std::io::BufRead;
-    
-    let file = File::open(taxonomy_file)
-        .with_context(|| format!("Failed to open taxonomy file: {}", taxonomy_file))?;
-    let reader = BufReader::new(file);
-    
-    // 预分配 HashMap 容量以提高性能
-    let mut taxonomy_map = FxHashMap::default(); // 使用默认容量
-    let mut line_count = 0;
-    
-    for line in reader.lines() {
-        line_count += 1;
-        let line = line.context("Failed to read line from taxonomy file")?;
-        let line = line.trim();
-        
-        // 跳过标题行和空行
-        if line_count == 1 || line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-        
-        let parts: Vec<&str> = line.split('\t').collect();
-        if parts.len() < 3 {
-            eprintln!("Warning: Invalid line format at line {}: {}", line_count, line);
-            continue;
+            // 添加带_genomic后缀的ID（例如：GCF_000006685.1_genomic），与基本ID共享同一个Arc
+            let genomic_id = format!("{}_genomic", genome_id);
+            taxonomy_map.insert(genomic_id, taxonomy_arc);
+            Some(true)
         }
-        
-        let accession = parts[0].trim();
-        let gtdb_taxonomy = parts[1].trim();
-        
-        // 处理genome ID：去掉前缀 (RS_, GB_, 等)
-        let genome_id = if accession.starts_with("RS_") {
-            &accession[3..]
-        } else if accession.starts_with("GB_") {
-            &accession[3..]
-        } else if accession.starts_with("GS_") {
-            &accession[3..]
-        } else {
-            accession
-        };
-        
-        // 解析GTDB分类信息并包装在 Arc 中
-        match TaxonomyInfo::from_gtdb_string(gtdb_taxonomy) {
-            Ok(taxonomy) => {
-                let taxonomy_arc = Arc::new(taxonomy);
-                // 添加基本ID（例如：GCF_000006685.1）
-                taxonomy_map.insert(genome_id.to_string(), Arc::clone(&taxonomy_arc));
-                
-                // 添加带_genomic后缀的ID（例如：GCF_000006685.1_genomic）
-                let genomic_id = format!("{}_genomic", genome_id);
-                taxonomy_map.insert(genomic_id, taxonomy_arc);
-            }
-            Err(e) => {
-                eprintln!("Warning: Failed to parse taxonomy for {}: {}", genome_id, e);
-            }
+        Err(e) => {
+            eprintln!("Warning: Failed to parse taxonomy for {}: {}", genome_id, e);
+            Some(false)
         }
     }
-    
-    eprintln!("Loaded taxonomy information for {} genome variants", taxonomy_map.len());
-    Ok(taxonomy_map)
 }
 
 // 从基因组级别聚合到物种级别
@@ -1084,6 +1655,9 @@ fn aggregate_to_species_level(
     sample_groups: &HashMap<String, Vec<GenomeProfileResult>>,
     taxonomy_map: &FxHashMap<String, Arc<TaxonomyInfo>>,
     effective_min_ani: f64,
+    thresholds: &FilterThresholds,
+    min_genomes_per_species: usize,
+    collapse_strains: bool,
 ) -> Result<Vec<SpeciesAbundanceResult>> {
     use std::sync::Mutex;
     
@@ -1097,12 +1671,14 @@ fn aggregate_to_species_level(
         .for_each(|(sample_id, genome_results)| {
             // 为每个样本组创建局部聚合结果 - 预分配容量
             let mut local_species_map: FxHashMap<String, SpeciesAbundanceResult> = FxHashMap::default();
+            // 同一基因组的别名结果只计一次genome_count和total_tags
+            let mut counted_genomes: FxHashSet<(String, &str)> = FxHashSet::default();
             
             for genome_result in genome_results {
                 // 额外的过滤条件：确保只有高质量的genome参与物种聚合
                 if genome_result.adjusted_ani < effective_min_ani || 
-                   genome_result.eff_cov < PROFILE_MIN_COVERAGE ||
-                   genome_result.common_tags < MIN_SHARED_TAGS {
+                   genome_result.eff_cov < thresholds.min_coverage ||
+                   genome_result.common_tags < thresholds.min_shared_tags {
                     continue;
                 }
                 
@@ -1111,33 +1687,62 @@ fn aggregate_to_species_level(
                 
                 // 查找对应的分类信息 - 使用字符串切片
                 if let Some(taxonomy_arc) = taxonomy_map.get(genome_id) {
+                    // --collapse-strains：同一物种的不同菌株/基因组种按规范化后的种名合并
+                    let taxonomy_arc = if collapse_strains {
+                        Arc::new(taxonomy_arc.collapse_strains())
+                    } else {
+                        Arc::clone(taxonomy_arc)
+                    };
                     let species_key = taxonomy_arc.get_species_key();
+                    let first_alias = counted_genomes.insert((species_key.clone(), genome_id));
                     
                     // 获取或创建物种条目 - 使用 Arc 共享而不是克隆
                     let species_result = local_species_map.entry(species_key).or_insert_with(|| {
                         SpeciesAbundanceResult {
-                            taxonomy: Arc::clone(taxonomy_arc),
+                            taxonomy: Arc::clone(&taxonomy_arc),
                             sample_abundances: FxHashMap::default(),
+                            sample_shared_tags: FxHashMap::default(),
                             total_tags: 0,
                             genome_count: 0,
                             reads_count: 0,
                             gscore: 0.0,
+                            sample_representatives: FxHashMap::default(),
                         }
                     });
+
+                    let candidate = SpeciesRepresentative {
+                        genome_id: genome_id.to_string(),
+                        shared_tags: genome_result.common_tags,
+                        adjusted_ani: genome_result.adjusted_ani,
+                    };
+                    match species_result.sample_representatives.get(sample_id) {
+                        Some(current) if !candidate.beats(current) => {}
+                        _ => {
+                            species_result.sample_representatives.insert(sample_id.clone(), candidate);
+                        }
+                    }
                     
                     // 累加样本丰度
                     *species_result.sample_abundances.entry(sample_id.clone()).or_insert(0.0) += 
                         genome_result.taxonomic_abundance;
+                    *species_result.sample_shared_tags.entry(sample_id.clone()).or_insert(0) +=
+                        genome_result.common_tags;
                     
                     // 累加标签数、基因组计数和 reads 数
-                    species_result.total_tags += genome_result.total_tags;
-                    species_result.genome_count += 1;
+                    if first_alias {
+                        species_result.total_tags += genome_result.total_tags;
+                        species_result.genome_count += 1;
+                    }
                     // 使用 common_tags 作为该基因组在该样本中的 reads 数代理
                     species_result.reads_count += genome_result.common_tags;
                 } else {
                     eprintln!("Warning: No taxonomy information found for genome: {}", genome_id);
                 }
             }
+
+            // --min-genomes-per-species：本样本中检出的该物种基因组太少时不报告，
+            // 避免多基因组物种因单个孤立命中被误报
+            local_species_map.retain(|_, species_result| species_result.genome_count >= min_genomes_per_species);
             
             // 将局部结果合并到全局结果中
             let mut global_map = species_map.lock().unwrap();
@@ -1146,10 +1751,12 @@ fn aggregate_to_species_level(
                     SpeciesAbundanceResult {
                         taxonomy: Arc::clone(&local_result.taxonomy),
                         sample_abundances: FxHashMap::default(),
+                        sample_shared_tags: FxHashMap::default(),
                         total_tags: 0,
                         genome_count: 0,
                         reads_count: 0,
                         gscore: 0.0,
+                        sample_representatives: FxHashMap::default(),
                     }
                 });
                 
@@ -1157,6 +1764,11 @@ fn aggregate_to_species_level(
                 for (sample_id, abundance) in local_result.sample_abundances {
                     *global_result.sample_abundances.entry(sample_id).or_insert(0.0) += abundance;
                 }
+                for (sample_id, shared_tags) in local_result.sample_shared_tags {
+                    *global_result.sample_shared_tags.entry(sample_id).or_insert(0) += shared_tags;
+                }
+                // 每个样本只在一个局部结果中出现
+                global_result.sample_representatives.extend(local_result.sample_representatives);
                 
                 // 合并标签数、基因组计数和 reads 数
                 global_result.total_tags += local_result.total_tags;
@@ -1182,8 +1794,34 @@ fn aggregate_to_species_level(
     Ok(results)
 }
 
+// G-score = sqrt((reads + p) * (tags + p))；p为--gscore-pseudocount。
+// p = 0时，reads或tags任一为零则得分为零
+fn calculate_gscore(reads_count: usize, tag_count: usize, pseudocount: f64) -> f64 {
+    ((reads_count as f64 + pseudocount) * (tag_count as f64 + pseudocount)).sqrt()
+}
+
+// 计算每个物种的G-score并保留得分不低于阈值的物种。
+// 得分为零的物种只在阈值不大于零时保留，阈值为0即不过滤
+fn filter_species_by_gscore(species_results: &mut [SpeciesAbundanceResult], threshold: f64, pseudocount: f64) -> Vec<SpeciesAbundanceResult> {
+    for species_result in species_results.iter_mut() {
+        species_result.gscore = calculate_gscore(species_result.reads_count, species_result.total_tags, pseudocount);
+    }
+    let kept: Vec<SpeciesAbundanceResult> = species_results.iter()
+        .filter(|species_result| species_result.gscore >= threshold)
+        .cloned()
+        .collect();
+    let zero_scored = species_results.iter()
+        .filter(|species_result| species_result.gscore == 0.0 && threshold > 0.0)
+        .count();
+    if zero_scored > 0 {
+        eprintln!("G-score filtering: {} species with zero reads or zero tags dropped (use --gscore-pseudocount to keep borderline species)", zero_scored);
+    }
+    eprintln!("G-score filtering: kept {} of {} species", kept.len(), species_results.len());
+    kept
+}
+
 // 从文件路径或genome_id中提取标准化的genome标识符
-fn extract_genome_id_from_path(input: &str) -> &str {
+pub(crate) fn extract_genome_id_from_path(input: &str) -> &str {
     // 如果输入包含路径分隔符，提取文件名
     let file_name = if input.contains('/') {
         std::path::Path::new(input)
@@ -1202,39 +1840,6 @@ fn extract_genome_id_from_path(input: &str) -> &str {
         .unwrap_or(file_name)
 }
 
-// 从syldb文件中读取基因组映射关系
-fn read_genome_mapping(db_path: &str) -> Result<FxHashMap<String, (String, String)>> {
-    let db_file = File::open(db_path)?;
-    let db_reader = BufReader::new(db_file);
-    let db_entries: Vec<SyldbEntry> = bincode::deserialize_from(db_reader)
-        .with_context(|| format!("Failed to deserialize database file: {}", db_path))?;
-    
-    // 并行处理数据库条目生成映射
-    let genome_map: FxHashMap<String, (String, String)> = db_entries.par_iter()
-        .map(|entry| {
-            // 获取原始基因组文件路径
-            let genome_source = entry.genome_source.clone();
-            let genome_id = if let Some(file_name) = std::path::Path::new(&genome_source)
-                .file_name()
-                .and_then(|s| s.to_str()) 
-            {
-                // 移除.fasta.gz或.fasta扩展名
-                file_name.strip_suffix(".fasta.gz")
-                    .or_else(|| file_name.strip_suffix(".fasta"))
-                    .unwrap_or(file_name)
-                    .to_string()
-            } else {
-                genome_source.clone()
-            };
-            
-            // 返回(序列ID, (基因组ID, 基因组源文件))
-            (entry.sequence_id.clone(), (genome_id, genome_source))
-        })
-        .collect();
-    
-    Ok(genome_map)
-}
-
 // 读取样本文件列表
 fn read_sample_list(list_file: &str) -> Result<Vec<String>> {
     let content = std::fs::read_to_string(list_file)?;
@@ -1247,13 +1852,97 @@ fn read_sample_list(list_file: &str) -> Result<Vec<String>> {
     Ok(lines)
 }
 
-// 生成TSV格式的丰度矩阵
+// --missing-value na：每个样本源实际比对过的基因组（至少一条contig达到--min-number-kmers）。
+// 不在其中的基因组在矩阵中记为NA，比对过但未检出的记为0。
+// skipped_samples中的样本源（去宿主后为空、被--rarefy或--min-sample-tags丢弃）没有比对任何基因组
+fn evaluated_genomes_per_sample(
+    cached_db_entries: &[SyldbEntry],
+    genome_mapping: &FxHashMap<String, (String, String)>,
+    cached_sample_entries: &SampleTags,
+    skipped_samples: &[String],
+    min_tags_for_genome: usize,
+) -> FxHashMap<String, FxHashSet<String>> {
+    let compared: FxHashSet<String> = cached_db_entries.iter()
+        .filter(|entry| entry.tags.len() >= min_tags_for_genome)
+        .filter_map(|entry| genome_mapping.get(&entry.sequence_id).map(|(genome_id, _)| genome_id.clone()))
+        .collect();
+    let mut evaluated: FxHashMap<String, FxHashSet<String>> = skipped_samples.iter()
+        .map(|sample_source| (sample_source.clone(), FxHashSet::default()))
+        .collect();
+    for entries in cached_sample_entries.values() {
+        for entry in entries {
+            if !evaluated.contains_key(&entry.sample_source) {
+                evaluated.insert(entry.sample_source.clone(), compared.clone());
+            }
+        }
+    }
+    evaluated
+}
+
+// --precision未指定时丰度矩阵的默认小数位数
+const GENOME_ABUNDANCE_PRECISION: usize = 4;
+const SPECIES_ABUNDANCE_PRECISION: usize = 6;
+
+fn format_abundance(abundance: f64, precision: usize) -> String {
+    if abundance.is_nan() {
+        "NA".to_string()
+    } else {
+        format!("{:.*}", precision, abundance)
+    }
+}
+
+// --abundance-units：relative为百分比丰度；cpm为共享标签数占样本全部共享标签的百万分比；raw为共享标签数
+fn abundance_in_units(relative: f64, shared_tags: usize, sample_total_tags: usize, units: &str) -> f64 {
+    match units {
+        "cpm" => {
+            if sample_total_tags > 0 {
+                shared_tags as f64 / sample_total_tags as f64 * 1_000_000.0
+            } else {
+                0.0
+            }
+        }
+        "raw" => shared_tags as f64,
+        _ => relative,
+    }
+}
+
+fn matrix_header_row<S: AsRef<str>>(label: &str, sample_ids: &[S]) -> String {
+    let mut header = label.to_string();
+    for sample_id in sample_ids {
+        header.push('\t');
+        header.push_str(sample_id.as_ref());
+    }
+    header.push('\n');
+    header
+}
+
+// 把一行丰度拼到row中（覆盖原内容，复用缓冲区），含行尾换行符
+fn format_matrix_row(row: &mut String, label: &str, abundances: &[f64], precision: usize) {
+    use std::fmt::Write as _;
+    row.clear();
+    row.push_str(label);
+    for abundance in abundances {
+        let _ = write!(row, "\t{}", format_abundance(*abundance, precision));
+    }
+    row.push('\n');
+}
+
+// 生成TSV格式的丰度矩阵。evaluated为Some时，未比对过的样本-基因组组合写NA（值为NaN）。
+// writer为Some时同时把矩阵写入主报告；--matrix-only-tsv时为None，矩阵只写入TSV文件
+#[allow(clippy::too_many_arguments)]
 fn write_abundance_matrix(
+    output: &OutputOptions,
     sample_groups: &HashMap<String, Vec<GenomeProfileResult>>,
     all_genomes: &HashSet<String>,
+    evaluated: Option<&FxHashMap<String, FxHashSet<String>>>,
+    names: Option<&FxHashMap<String, String>>,
     log_path: Option<String>,
     tsv_name: &str,
-    writer: &mut Box<dyn Write + Send>,
+    long_format: bool,
+    units: &str,
+    run_id: Option<&str>,
+    precision: usize,
+    mut writer: Option<&mut Box<dyn Write + Send>>,
 ) -> Result<()> {
     // 如果指定了log_path，使用它，否则使用当前目录
     let output_dir = if let Some(path) = log_path {
@@ -1267,62 +1956,90 @@ fn write_abundance_matrix(
 
     // 构建TSV文件路径
     let tsv_path = output_dir.join(tsv_name);
-    let mut tsv_writer = BufWriter::new(File::create(tsv_path)?);
-
-    // 获取所有样本ID并排序
-    let mut sample_ids: Vec<_> = sample_groups.keys().collect();
-    sample_ids.sort();
-
-    // 写入表头
-    write!(tsv_writer, "Genome")?;
-    write!(writer, "\nAbundance Matrix:\n")?;
-    write!(writer, "Genome")?;
-    for sample_id in &sample_ids {
-        write!(tsv_writer, "\t{}", sample_id)?;
-        write!(writer, "\t{}", sample_id)?;
-    }
-    writeln!(tsv_writer)?;
-    writeln!(writer)?;
-
-    // 采用 sylph 的高效并行数据收集策略
-    let genome_data: Vec<(String, Vec<f64>)> = all_genomes.par_iter()
-        .map(|genome_id| {
-            let abundances: Vec<f64> = sample_ids.iter()
-                .map(|sample_id| {
-                    sample_groups.get(sample_id.as_str())
-                        .and_then(|results| results.iter()
-                            .find(|r| r.genome_id == *genome_id))
-                        .map(|r| r.taxonomic_abundance)
-                        .unwrap_or(0.0)
-                })
-                .collect();
-            (genome_id.clone(), abundances)
-        })
-        .collect();
+    output.write_atomically(&tsv_path, |tsv_writer| {
+        // 获取所有样本ID并排序
+        let mut sample_ids: Vec<_> = sample_groups.keys().collect();
+        sample_ids.sort_by(|a, b| natural_cmp(a, b));
+
+        // 写入表头；每行先拼成字符串再一次写出，大矩阵不会产生逐格的小写入
+        let header = matrix_header_row("Genome", &sample_ids);
+        if !long_format {
+            tsv_writer.write_all(header.as_bytes())?;
+        }
+        if let Some(writer) = writer.as_mut() {
+            writer.write_all(format!("\nAbundance Matrix:\n{}", header).as_bytes())?;
+        }
+
+        // 每个样本保留下来的基因组共享标签总数（cpm的分母）
+        let sample_totals: FxHashMap<&str, usize> = sample_groups.iter()
+            .map(|(sample_id, results)| (sample_id.as_str(), results.iter().map(|r| r.common_tags).sum()))
+            .collect();
 
-    // 写入每个基因组的丰度数据
-    for (genome_id, abundances) in genome_data {
-        write!(tsv_writer, "{}", genome_id)?;
-        write!(writer, "{}", genome_id)?;
-        for abundance in abundances {
-            write!(tsv_writer, "\t{:.4}", abundance)?;
-            write!(writer, "\t{:.4}", abundance)?;
+        // 采用 sylph 的高效并行数据收集策略
+        let mut genome_data: Vec<(String, Vec<f64>)> = all_genomes.par_iter()
+            .map(|genome_id| {
+                let abundances: Vec<f64> = sample_ids.iter()
+                    .map(|sample_id| {
+                        sample_groups.get(sample_id.as_str())
+                            .and_then(|results| results.iter()
+                                .find(|r| r.genome_id == *genome_id))
+                            .map(|r| abundance_in_units(r.taxonomic_abundance, r.common_tags, sample_totals[sample_id.as_str()], units))
+                            .unwrap_or_else(|| match evaluated {
+                                Some(e) if !e.get(sample_id.as_str()).is_some_and(|g| g.contains(genome_id)) => f64::NAN,
+                                _ => 0.0,
+                            })
+                    })
+                    .collect();
+                (genome_id.clone(), abundances)
+            })
+            .collect();
+        // all_genomes是HashSet，按基因组ID排序使行序固定；--genome-name-map只改变行标签
+        genome_data.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+        if let Some(names) = names {
+            for (genome_id, _) in genome_data.iter_mut() {
+                *genome_id = display_genome_name(genome_id, names).to_string();
+            }
         }
-        writeln!(tsv_writer)?;
-        writeln!(writer)?;
-    }
-    writeln!(writer)?;
 
-    Ok(())
+        // 写入每个基因组的丰度数据；非long格式时同一行也写入TSV文件
+        let mut row = String::new();
+        for (genome_id, abundances) in &genome_data {
+            format_matrix_row(&mut row, genome_id, abundances, precision);
+            if let Some(writer) = writer.as_mut() {
+                writer.write_all(row.as_bytes())?;
+            }
+            if !long_format {
+                tsv_writer.write_all(row.as_bytes())?;
+            }
+        }
+        if let Some(writer) = writer.as_mut() {
+            writeln!(writer)?;
+        }
+
+        if long_format {
+            let rows: Vec<(Vec<String>, Vec<f64>)> = genome_data.into_iter()
+                .map(|(genome_id, abundances)| (vec![genome_id], abundances))
+                .collect();
+            write_long_abundance_rows(tsv_writer, &["Genome"], &sample_ids, &rows, precision, run_id)?;
+        }
+
+        Ok(())
+    })
 }
 
 // 生成物种级别的TSV格式丰度矩阵
+#[allow(clippy::too_many_arguments)]
 fn write_species_abundance_matrix(
+    output: &OutputOptions,
     species_results: &[SpeciesAbundanceResult],
     all_samples: &HashSet<String>,
     log_path: Option<String>,
     tsv_name: &str,
-    writer: &mut Box<dyn Write + Send>,
+    long_format: bool,
+    units: &str,
+    run_id: Option<&str>,
+    precision: usize,
+    mut writer: Option<&mut Box<dyn Write + Send>>,
 ) -> Result<()> {
     // 如果指定了log_path，使用它，否则使用当前目录
     let output_dir = if let Some(path) = log_path {
@@ -1336,390 +2053,3436 @@ fn write_species_abundance_matrix(
 
     // 构建TSV文件路径
     let tsv_path = output_dir.join(tsv_name);
-    let mut tsv_writer = BufWriter::new(File::create(tsv_path)?);
-
-    // 获取所有样本ID并排序
-    let mut sample_ids: Vec<_> = all_samples.iter().collect();
-    sample_ids.sort();
-
-    // 写入表头 (参考Abundance_Stat.all.xls格式)
-    write!(tsv_writer, "#Kingdom\tPhylum\tClass\tOrder\tFamily\tGenus\tSpecies")?;
-    write!(writer, "\nSpecies-level Abundance Matrix:\n")?;
-    write!(writer, "#Kingdom\tPhylum\tClass\tOrder\tFamily\tGenus\tSpecies")?;
-    for sample_id in &sample_ids {
-        write!(tsv_writer, "\t{}", sample_id)?;
-        write!(writer, "\t{}", sample_id)?;
-    }
-    writeln!(tsv_writer)?;
-    writeln!(writer)?;
-
-    // 采用 sylph 的高效并行数据收集策略
-    let species_data: Vec<(Arc<TaxonomyInfo>, Vec<f64>)> = species_results.par_iter()
-        .map(|species_result| {
-            let abundances: Vec<f64> = sample_ids.iter()
-                .map(|sample_id| {
-                    species_result.sample_abundances
-                        .get(sample_id.as_str())
-                        .copied()
-                        .unwrap_or(0.0)
-                })
-                .collect();
-            (Arc::clone(&species_result.taxonomy), abundances)
-        })
-        .collect();
+    output.write_atomically(&tsv_path, |tsv_writer| {
+        // 获取所有样本ID并排序
+        let mut sample_ids: Vec<_> = all_samples.iter().collect();
+        sample_ids.sort_by(|a, b| natural_cmp(a, b));
+
+        // 写入表头 (参考Abundance_Stat.all.xls格式)
+        let header = matrix_header_row("#Kingdom\tPhylum\tClass\tOrder\tFamily\tGenus\tSpecies", &sample_ids);
+        if !long_format {
+            tsv_writer.write_all(header.as_bytes())?;
+        }
+        if let Some(writer) = writer.as_mut() {
+            writer.write_all(format!("\nSpecies-level Abundance Matrix:\n{}", header).as_bytes())?;
+        }
 
-    // 写入每个物种的丰度数据
-    for (taxonomy_arc, abundances) in species_data {
-        // 写入分类学信息（7列）
-        write!(tsv_writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}", 
-               taxonomy_arc.kingdom, taxonomy_arc.phylum, taxonomy_arc.class,
-               taxonomy_arc.order, taxonomy_arc.family, taxonomy_arc.genus, taxonomy_arc.species)?;
-        write!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}", 
-               taxonomy_arc.kingdom, taxonomy_arc.phylum, taxonomy_arc.class,
-               taxonomy_arc.order, taxonomy_arc.family, taxonomy_arc.genus, taxonomy_arc.species)?;
-        
-        // 写入各个样本的丰度值
-        for abundance in abundances {
-            write!(tsv_writer, "\t{:.6}", abundance)?;
-            write!(writer, "\t{:.6}", abundance)?;
+        // 每个样本保留下来的物种共享标签总数（cpm的分母）
+        let mut sample_totals: FxHashMap<&str, usize> = FxHashMap::default();
+        for species_result in species_results {
+            for (sample_id, shared_tags) in &species_result.sample_shared_tags {
+                *sample_totals.entry(sample_id.as_str()).or_insert(0) += shared_tags;
+            }
+        }
+
+        // 采用 sylph 的高效并行数据收集策略
+        let species_data: Vec<(Arc<TaxonomyInfo>, Vec<f64>)> = species_results.par_iter()
+            .map(|species_result| {
+                let abundances: Vec<f64> = sample_ids.iter()
+                    .map(|sample_id| {
+                        let relative = species_result.sample_abundances
+                            .get(sample_id.as_str())
+                            .copied()
+                            .unwrap_or(0.0);
+                        let shared_tags = species_result.sample_shared_tags.get(sample_id.as_str()).copied().unwrap_or(0);
+                        let total = sample_totals.get(sample_id.as_str()).copied().unwrap_or(0);
+                        abundance_in_units(relative, shared_tags, total, units)
+                    })
+                    .collect();
+                (Arc::clone(&species_result.taxonomy), abundances)
+            })
+            .collect();
+
+        // 分类学信息（7列）
+        let species_rows: Vec<(Vec<String>, Vec<f64>)> = species_data.into_iter()
+            .map(|(taxonomy_arc, abundances)| {
+                let labels = vec![
+                    taxonomy_arc.kingdom.clone(), taxonomy_arc.phylum.clone(), taxonomy_arc.class.clone(),
+                    taxonomy_arc.order.clone(), taxonomy_arc.family.clone(), taxonomy_arc.genus.clone(),
+                    taxonomy_arc.species.clone(),
+                ];
+                (labels, abundances)
+            })
+            .collect();
+
+        // 写入每个物种的丰度数据
+        let mut row = String::new();
+        for (labels, abundances) in &species_rows {
+            format_matrix_row(&mut row, &labels.join("\t"), abundances, precision);
+            if let Some(writer) = writer.as_mut() {
+                writer.write_all(row.as_bytes())?;
+            }
+            if !long_format {
+                tsv_writer.write_all(row.as_bytes())?;
+            }
+        }
+        if let Some(writer) = writer.as_mut() {
+            writeln!(writer)?;
+        }
+
+        if long_format {
+            write_long_abundance_rows(tsv_writer, &TAXONOMY_RANKS, &sample_ids, &species_rows, precision, run_id)?;
+        }
+
+        Ok(())
+    })
+}
+
+const TAXONOMY_RANKS: [&str; 7] = ["Kingdom", "Phylum", "Class", "Order", "Family", "Genus", "Species"];
+
+// 长格式（tidy）丰度表：每个非零的(样本, taxon)一行，按样本、taxon排序。返回写入的数据行数。
+// run_id为Some时每行前加Run列，多次运行的结果可直接拼接
+fn write_long_abundance_rows<W: Write, S: AsRef<str>>(
+    writer: &mut W,
+    label_columns: &[&str],
+    sample_ids: &[S],
+    rows: &[(Vec<String>, Vec<f64>)],
+    precision: usize,
+    run_id: Option<&str>,
+) -> Result<usize> {
+    let mut sorted: Vec<&(Vec<String>, Vec<f64>)> = rows.iter().collect();
+    sorted.sort_by(|a, b| {
+        a.0.iter().zip(&b.0)
+            .map(|(x, y)| natural_cmp(x, y))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.0.len().cmp(&b.0.len()))
+    });
+
+    let run_prefix = run_id.map(|id| format!("{}\t", id)).unwrap_or_default();
+    let run_header = if run_id.is_some() { "Run\t" } else { "" };
+    writeln!(writer, "{}Sample\t{}\tAbundance", run_header, label_columns.join("\t"))?;
+    let mut written = 0;
+    for (i, sample_id) in sample_ids.iter().enumerate() {
+        for (labels, abundances) in &sorted {
+            let abundance = abundances[i];
+            // 0和NA（NaN）都不写
+            if abundance.is_nan() || abundance <= 0.0 {
+                continue;
+            }
+            writeln!(writer, "{}{}\t{}\t{:.*}", run_prefix, sample_id.as_ref(), labels.join("\t"), precision, abundance)?;
+            written += 1;
         }
-        writeln!(tsv_writer)?;
-        writeln!(writer)?;
     }
-    writeln!(writer)?;
+    Ok(written)
+}
 
-    Ok(())
+// 样本间平均丰度；没有任何样本丰度时为0而不是NaN
+fn average_abundance(sample_abundances: &FxHashMap<String, f64>) -> f64 {
+    if sample_abundances.is_empty() {
+        return 0.0;
+    }
+    sample_abundances.values().sum::<f64>() / sample_abundances.len() as f64
 }
 
-// 从缓存的数据库条目中构建基因组映射关系
-fn build_genome_mapping_from_cache(cached_db_entries: &[SyldbEntry]) -> FxHashMap<String, (String, String)> {
-    // 预分配 HashMap 容量以提高性能
-    let mut genome_map = FxHashMap::default();
-    
-    // 并行处理数据库条目生成映射
-    for entry in cached_db_entries {
-        // 获取原始基因组文件路径
-        let genome_source = entry.genome_source.clone();
-        let genome_id = if let Some(file_name) = std::path::Path::new(&genome_source)
-            .file_name()
-            .and_then(|s| s.to_str()) 
-        {
-            // 移除.fasta.gz或.fasta扩展名
-            file_name.strip_suffix(".fasta.gz")
-                .or_else(|| file_name.strip_suffix(".fasta"))
-                .unwrap_or(file_name)
-                .to_string()
+fn format_species_summary_row(species_result: &SpeciesAbundanceResult, precision: Option<usize>) -> String {
+    let species_name = if species_result.taxonomy.species.is_empty() {
+        format!("{}_sp", species_result.taxonomy.genus)
+    } else {
+        species_result.taxonomy.species.clone()
+    };
+    format!("{:<50} {:<15} {:<15} {:<15} {:<10.*} {:<10.*}",
+        species_name,
+        species_result.genome_count,
+        species_result.total_tags,
+        species_result.reads_count,
+        precision.unwrap_or(2), species_result.gscore,
+        precision.unwrap_or(GENOME_ABUNDANCE_PRECISION), average_abundance(&species_result.sample_abundances))
+}
+
+// --species-representative-genome：每个物种在每个检出样本中的代表基因组，样本按自然顺序
+fn species_representative_rows(species_results: &[SpeciesAbundanceResult], sample_ids: &[String]) -> Vec<String> {
+    let mut rows = Vec::new();
+    for species_result in species_results {
+        let species_name = if species_result.taxonomy.species.is_empty() {
+            format!("{}_sp", species_result.taxonomy.genus)
         } else {
-            genome_source.clone()
+            species_result.taxonomy.species.clone()
         };
-        
-        // 返回(序列ID, (基因组ID, 基因组源文件))
-        genome_map.insert(entry.sequence_id.clone(), (genome_id, genome_source));
+        for sample_id in sample_ids {
+            if let Some(representative) = species_result.sample_representatives.get(sample_id) {
+                rows.push(format!("{:<50} {:<30} {:<30} {:<15} {:<10.2}",
+                    species_name, sample_id, representative.genome_id, representative.shared_tags, representative.adjusted_ani));
+            }
+        }
     }
-    
-    genome_map
+    rows
 }
 
-// 更新profile函数
-pub fn profile(args: ProfileArgs) -> Result<()> {
-    // 处理minimum_ani参数：如果没有传入参数，使用默认值
-    let effective_min_ani = args.minimum_ani.unwrap_or(PROFILE_MIN_ANI);
-    eprintln!("Using minimum ANI threshold: {:.1}%", effective_min_ani);
-    
-    // 优化线程池配置 - 采用 sylph 的策略
-    let _max_ram = args.threads * 2; // 简单的内存限制，每线程2GB
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(args.threads)
-        .build_global()
-        .context("Failed to initialize thread pool")?;
+// --min-sample-tags：标签观测数低于阈值的样本源（如建库失败）整体剔除，返回被剔除的样本及其标签数
+fn drop_small_samples(entries: Vec<SampleTag>, min_tags: usize) -> (Vec<SampleTag>, Vec<(String, usize)>) {
+    let counts = observations_per_source(&entries);
+    let mut skipped: Vec<(String, usize)> = counts.iter()
+        .filter(|(_, &n)| n < min_tags)
+        .map(|(source, &n)| (source.clone(), n))
+        .collect();
+    skipped.sort();
 
-    // 一次性读取并缓存数据库文件 - 优化大文件读取
-    eprintln!("Loading database file: {}", args.db_file);
-    
-    let db_file = File::open(&args.db_file)
-        .with_context(|| format!("Failed to open database file: {}", args.db_file))?;
-    let db_reader = BufReader::with_capacity(100_000_000, db_file); // 100MB 缓冲区
-    let cached_db_entries: Vec<SyldbEntry> = bincode::deserialize_from(db_reader)
-        .with_context(|| format!("Failed to deserialize database file: {}", args.db_file))?;
-    
-    eprintln!("Cached {} entries from database", cached_db_entries.len());
+    let kept = entries.into_iter()
+        .filter(|e| counts[&e.sample_source] >= min_tags)
+        .collect();
+    (kept, skipped)
+}
 
-    // 一次性读取并缓存所有样本文件 - 优化大文件读取
-    eprintln!("Loading sample files: {}", args.sample_file);
-    let sample_files: Vec<String> = if args.sample_file.ends_with(".txt") {
-        read_sample_list(&args.sample_file)?
-    } else {
-        vec![args.sample_file.clone()]
-    };
+// --host-db：去掉落在宿主标签集合中的样本标签，返回剩余条目和每个样本源的(样本源, 去除数, 原标签数)
+fn subtract_host_tags(entries: Vec<SampleTag>, host_tags: &FxHashSet<Hash>) -> (Vec<SampleTag>, Vec<(String, usize, usize)>) {
+    let mut counts: FxHashMap<String, (usize, usize)> = FxHashMap::default();
+    let kept = entries.into_iter()
+        .filter(|entry| {
+            let is_host = host_tags.contains(&entry.tag);
+            let count = counts.entry(entry.sample_source.clone()).or_insert((0, 0));
+            count.1 += entry.count;
+            if is_host {
+                count.0 += entry.count;
+            }
+            !is_host
+        })
+        .collect();
 
-    let mut cached_sample_entries: FxHashMap<String, Vec<SylspEntry>> = FxHashMap::default();
-    for sample_path in &sample_files {
-        let sample_file = File::open(sample_path)
-            .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
-        let sample_reader = BufReader::with_capacity(100_000_000, sample_file); // 100MB 缓冲区
-        let sample_entries: Vec<SylspEntry> = bincode::deserialize_from(sample_reader)
-            .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
-        cached_sample_entries.insert(sample_path.clone(), sample_entries);
+    let mut host_fractions: Vec<(String, usize, usize)> = counts.into_iter()
+        .map(|(source, (removed, total))| (source, removed, total))
+        .collect();
+    host_fractions.sort();
+    (kept, host_fractions)
+}
+
+// 宿主数据库的全部标签；--keep-shared-host-tags时去掉目标数据库中也有的标签
+fn load_host_tags(host_db: &str, db_entries: &[SyldbEntry], keep_shared: bool) -> Result<FxHashSet<Hash>> {
+    let host_file = File::open(host_db)
+        .with_context(|| format!("Failed to open host database file: {}", host_db))?;
+    let host_entries = read_syldb(BufReader::new(host_file))
+        .with_context(|| format!("Failed to deserialize host database file: {}", host_db))?;
+    let mut host_tags: FxHashSet<Hash> = host_entries.iter().flat_map(|entry| entry.tags.iter().copied()).collect();
+    eprintln!("Loaded {} host tags from {}", host_tags.len(), host_db);
+
+    if keep_shared {
+        let target_tags: FxHashSet<Hash> = db_entries.iter().flat_map(|entry| entry.tags.iter().copied()).collect();
+        host_tags.retain(|tag| !target_tags.contains(tag));
+        eprintln!("{} host tags left after keeping those shared with the target database", host_tags.len());
     }
-    eprintln!("Cached {} sample files", cached_sample_entries.len());
+    Ok(host_tags)
+}
 
-    // 从缓存的数据库构建基因组映射关系
-    let genome_mapping = build_genome_mapping_from_cache(&cached_db_entries);
-    
-    // 创建输出写入器
-    let mut writer = create_multi_writer(&args.out_file_name)?;
+const EM_MAX_ITERATIONS: usize = 1000;
+const EM_TOLERANCE: f64 = 1e-8;
+
+// EM丰度估计。模型：theta_g为样本中标签观测来自基因组g的比例（即序列丰度），
+// 基因组g的L_g个标签（基因组标签总数）被观测到的机会均等，单个标签来自g的概率与theta_g / L_g成正比。
+// E步：每个观测到的标签按theta_g / L_g在其候选基因组（仅限candidates）间分配；M步：theta_g = 分配到的计数 / 总计数。
+// 细胞数意义上的taxonomic丰度与theta_g / L_g成正比，见em_group_abundances。
+// 返回(theta, 每个基因组分配到的期望标签数)
+fn em_abundances(
+    observed: &FxHashMap<Hash, usize>,
+    tag_to_genomes: &FxHashMap<Hash, Vec<String>>,
+    genome_tag_totals: &FxHashMap<String, usize>,
+    candidates: &FxHashSet<String>,
+    max_iterations: usize,
+    tolerance: f64,
+) -> (FxHashMap<String, f64>, FxHashMap<String, f64>) {
+    // 每个观测标签的候选集合（与candidates取交集），没有候选的标签不参与
+    let assignments: Vec<(f64, Vec<&str>)> = observed.iter()
+        .filter_map(|(tag, &count)| {
+            let genomes: Vec<&str> = tag_to_genomes.get(tag)?
+                .iter()
+                .filter(|g| candidates.contains(*g))
+                .map(|g| g.as_str())
+                .collect();
+            if genomes.is_empty() { None } else { Some((count as f64, genomes)) }
+        })
+        .collect();
+    let total: f64 = assignments.iter().map(|(count, _)| count).sum();
+    if total == 0.0 {
+        return (FxHashMap::default(), FxHashMap::default());
+    }
 
+    let length = |g: &str| genome_tag_totals.get(g).copied().unwrap_or(1).max(1) as f64;
+    let mut theta: FxHashMap<String, f64> = candidates.iter()
+        .map(|g| (g.clone(), 1.0 / candidates.len() as f64))
+        .collect();
+    let mut expected: FxHashMap<String, f64> = FxHashMap::default();
+
+    for _ in 0..max_iterations {
+        expected.clear();
+        for (count, genomes) in &assignments {
+            let weights: Vec<f64> = genomes.iter().map(|g| theta[*g] / length(g)).collect();
+            let sum: f64 = weights.iter().sum();
+            for (g, w) in genomes.iter().zip(&weights) {
+                // 所有候选的theta都收敛到0时退化为均分
+                let share = if sum > 0.0 { w / sum } else { 1.0 / genomes.len() as f64 };
+                *expected.entry(g.to_string()).or_insert(0.0) += count * share;
+            }
+        }
 
+        let mut delta: f64 = 0.0;
+        for (g, t) in theta.iter_mut() {
+            let updated = expected.get(g).copied().unwrap_or(0.0) / total;
+            delta = delta.max((updated - *t).abs());
+            *t = updated;
+        }
+        if delta < tolerance {
+            break;
+        }
+    }
+    (theta, expected)
+}
 
-    // 存储所有样本的结果 - 预分配容量，使用 Mutex 保护
-    let all_results = Arc::new(Mutex::new(FxHashMap::<(String, String), GenomeProfileResult>::default()));
+// 由EM的theta得到一个样本内的丰度：sequence_abundance即theta_g；
+// taxonomic_abundance按theta_g / L_g归一化，大基因组贡献的标签多，不代表细胞多
+fn em_group_abundances(group: &mut [GenomeProfileResult], theta: &FxHashMap<String, f64>, genome_tag_totals: &FxHashMap<String, usize>) {
+    let theta_of = |genome_id: &str| theta.get(genome_id).copied().unwrap_or(0.0);
+    let length = |genome_id: &str| genome_tag_totals.get(genome_id).copied().unwrap_or(1).max(1) as f64;
+    let total_cells: f64 = group.iter().map(|r| theta_of(&r.genome_id) / length(&r.genome_id)).sum();
+    for result in group.iter_mut() {
+        let t = theta_of(&result.genome_id);
+        result.sequence_abundance = t * 100.0;
+        result.taxonomic_abundance = if total_cells > 0.0 { t / length(&result.genome_id) / total_cells * 100.0 } else { 0.0 };
+    }
+}
 
-        // 采用 sylph 的简化并行处理策略
-    let step = usize::max(args.threads/3 + 1, usize::min(sample_files.len(), args.threads));
-    let chunks: Vec<Vec<String>> = sample_files.chunks(step).map(|chunk| chunk.to_vec()).collect();
-    
-    // 使用 sylph 风格的分块处理，集成k-mer重新分配机制
-    chunks.into_iter().for_each(|chunk| {
-        chunk.into_par_iter().for_each(|sample_file| {
-            // 第一阶段：计算初步结果（不使用重新分配）
-            if let Ok(initial_results) = query_single_file_with_cached_db(&sample_file, &args.db_file, &cached_db_entries, &cached_sample_entries, effective_min_ani) {
-                // 按ANI排序
-                let mut initial_results = initial_results;
-                initial_results.sort_by(|a, b| b.adjusted_ani.partial_cmp(&a.adjusted_ani).unwrap());
-                
-                // 第二阶段：构建winner table并重新分配（模仿sylph的两阶段处理）
-                eprintln!("{} taxonomic profiling; reassigning tags for {} genomes...", &sample_file, initial_results.len());
-                
-                // 构建winner table
-                let winner_map = build_winner_table(&initial_results, &cached_db_entries, true); // 启用日志
-                
-                // 使用winner table重新计算结果
-                if let Some(sample_entries) = cached_sample_entries.get(&sample_file) {
-                    let mut reassigned_results = recalculate_with_winner_table(
-                        &cached_db_entries,
-                        sample_entries,
-                        &winner_map,
-                        effective_min_ani,
-                        false
-                    );
-                    
-                    // 第三阶段：过滤过度重新分配的基因组
-                    reassigned_results = filter_over_reassigned_genomes(
-                        &initial_results,
-                        &reassigned_results,
-                        effective_min_ani,
-                        K
-                    );
-                    
-                    // 第四阶段：重新计算丰度
-                    recalculate_abundances_after_reassignment(&mut reassigned_results, sample_entries);
-                    
-                    eprintln!("{} has {} genomes passing profiling threshold after reassignment.", &sample_file, reassigned_results.len());
-                    
-                    // 按基因组ID分组结果 - 修复：确保每个样本源都被正确处理
-                    for result in reassigned_results {
-                        if let Some((genome_id, _)) = genome_mapping.get(&result.contig_name) {
-                            // 关键修复：使用实际的样本源ID作为key的一部分
-                            let key = (genome_id.clone(), result.sample_file.clone());
-                            let mut all_results = all_results.lock().unwrap();
-                            let entry = all_results.entry(key)
-                                .or_insert_with(|| {
-                                    GenomeProfileResult {
-                                        genome_id: genome_id.clone(),
-                                        sample_id: result.sample_file.clone(), // 这里保存的是实际的样本源ID
-                                        file_path: sample_file.clone(),
-                                        adjusted_ani: 0.0,
-                                        taxonomic_abundance: 0.0,
-                                        sequence_abundance: 0.0,
-                                        common_tags: 0,
-                                        total_tags: 0,
-                                        eff_cov: 0.0,
-                                    }
-                                });
-                            
-                            // 累加标签数
-                            entry.common_tags += result.shared_tags;
-                            entry.total_tags += result.ref_tags;
-                            entry.eff_cov += result.eff_cov;
-                            
-                            // 使用共享标签数作为权重计算加权平均ANI
-                            if entry.common_tags > 0 {
-                                entry.adjusted_ani = (entry.adjusted_ani * (entry.common_tags - result.shared_tags) as f64 
-                                    + result.adjusted_ani * result.shared_tags as f64) / entry.common_tags as f64;
-                            }
-                        }
-                    }
+// splitmix64：--rarefy用的可复现伪随机数
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// 按sample_source分组，保持各组内标签的原有顺序；组按样本名排序
+fn group_by_source(entries: Vec<SampleTag>) -> Vec<(String, Vec<SampleTag>)> {
+    let mut groups: FxHashMap<String, Vec<SampleTag>> = FxHashMap::default();
+    for entry in entries {
+        groups.entry(entry.sample_source.clone()).or_default().push(entry);
+    }
+    let mut groups: Vec<(String, Vec<SampleTag>)> = groups.into_iter().collect();
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+    groups
+}
+
+// 稀疏化(rarefaction)：样本标签的每次观测（count中的每一次）是一个抽样单位。
+// 按sample_source分组，每组无放回地随机保留depth次观测；观测数不足depth的样本整体丢弃并返回其名称。
+// 随机种子由seed和样本名共同决定，结果与样本的处理顺序无关
+fn rarefy_sample_entries(entries: Vec<SampleTag>, depth: usize, seed: u64) -> (Vec<SampleTag>, Vec<String>) {
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for (source, group) in group_by_source(entries) {
+        if group.iter().map(|entry| entry.count).sum::<usize>() < depth {
+            dropped.push(source);
+            continue;
+        }
+        let state = seed ^ crate::constants::hash_string(&source);
+        kept.extend(subsample_without_replacement(group, depth, state));
+    }
+    (kept, dropped)
+}
+
+// 顺序抽样(Knuth算法S)：依次考察每次观测，以 还需数/剩余数 的概率保留，恰好保留depth次观测。
+// 直接在count上计数，不按观测展开条目；一次也没保留的标签被去掉
+fn subsample_without_replacement(group: Vec<SampleTag>, depth: usize, mut state: u64) -> Vec<SampleTag> {
+    let mut remaining: usize = group.iter().map(|entry| entry.count).sum();
+    let mut needed = depth.min(remaining);
+    group.into_iter()
+        .filter_map(|mut entry| {
+            let mut kept = 0;
+            for _ in 0..entry.count {
+                if needed == 0 {
+                    break;
                 }
+                if splitmix64(&mut state) % (remaining as u64) < needed as u64 {
+                    kept += 1;
+                    needed -= 1;
+                }
+                remaining -= 1;
             }
-        });
-    });
-    
-    // 收集所有基因组ID
-    let mut all_genomes: HashSet<String> = HashSet::new();
-    for entry in genome_mapping.values() {
-        all_genomes.insert(entry.0.clone());
+            entry.count = kept;
+            (kept > 0).then_some(entry)
+        })
+        .collect()
+}
+
+// --depth-normalize：把一个合并.sylsp中的样本都稀释到最浅样本的深度，
+// 深度取extract --depth-normalize记录在格式头中的标签总数，未记录时按文件中的观测数。
+// 去宿主后的样本可能比记录的总数更浅，因此目标深度同时不超过各样本当前的观测数
+fn depth_normalize_entries(entries: Vec<SampleTag>, recorded: &[(String, u64)], sample_path: &str, seed: u64) -> Vec<SampleTag> {
+    if recorded.is_empty() {
+        eprintln!("Warning: {} has no recorded sample depths (extract --depth-normalize); using the tag counts in the file", sample_path);
     }
+    let current = observations_per_source(&entries);
+    let target = recorded.iter()
+        .map(|(_, depth)| *depth as usize)
+        .chain(current.values().copied())
+        .min();
+    let Some(depth) = target else {
+        return entries;
+    };
+    eprintln!("Depth-normalizing {} samples in {} to {} tag observations", current.len(), sample_path, depth);
+    rarefy_sample_entries(entries, depth, seed).0
+}
 
-    // 转换为向量以便排序和分组
-    let results: Vec<_> = all_results.lock().unwrap().values().cloned().collect();
-    
-    // 按样本分组计算丰度
-    let mut sample_groups: HashMap<String, Vec<GenomeProfileResult>> = HashMap::new();
-    for result in results {
-        // 使用实际的样本来源而不是文件名
-        sample_groups.entry(result.sample_id.clone())
-            .or_default()
-            .push(result);
+// --preview：每个样本按比例随机保留标签观测（四舍五入，至少1条），用于快速估算丰度。
+// fraction为1时原样返回，结果与完整运行一致
+fn preview_sample_entries(entries: Vec<SampleTag>, fraction: f64, seed: u64) -> Vec<SampleTag> {
+    if fraction >= 1.0 {
+        return entries;
     }
-    
-    // 采用 sylph 的简单策略 - 顺序计算丰度，避免复杂的并行迭代器组合
-    for (_sample_id, group) in sample_groups.iter_mut() {
-        // 按ANI排序（参考sylph的排序机制）
-        group.sort_by(|a, b| b.adjusted_ani.partial_cmp(&a.adjusted_ani).unwrap());
-        
-        // 过滤掉不符合profile要求的genome
-        group.retain(|r| {
-            r.common_tags >= MIN_SHARED_TAGS && 
-            r.eff_cov >= PROFILE_MIN_COVERAGE && 
-            r.adjusted_ani >= effective_min_ani &&
-            r.total_tags >= MIN_TAGS_FOR_GENOME
-        });
-        
-        // 计算总覆盖度，包括所有检测到的标签
-        let total_genome_cov: f64 = group.iter()
-            .map(|r| if r.common_tags > 0 { r.eff_cov } else { 0.0 })
-            .sum();
-        
-        let total_seq_cov: f64 = group.iter()
-            .map(|r| if r.common_tags > 0 { 
-                r.eff_cov * r.total_tags as f64 
-            } else { 
-                0.0 
+    let mut kept = Vec::new();
+    for (source, group) in group_by_source(entries) {
+        let observations: usize = group.iter().map(|entry| entry.count).sum();
+        let depth = ((observations as f64 * fraction).round() as usize).max(1);
+        kept.extend(subsample_without_replacement(group, depth, seed ^ crate::constants::hash_string(&source)));
+    }
+    kept
+}
+
+fn contig_rows_from_results(
+    results: &[QueryResult],
+    genome_mapping: &FxHashMap<String, (String, String)>,
+) -> Vec<ContigProfileRow> {
+    results.iter()
+        .filter_map(|result| {
+            let (genome_id, _) = genome_mapping.get(&result.contig_name)?;
+            Some(ContigProfileRow {
+                genome_id: genome_id.clone(),
+                contig_name: result.contig_name.clone(),
+                sample_id: result.sample_file.clone(),
+                adjusted_ani: result.adjusted_ani,
+                shared_tags: result.shared_tags,
+                total_tags: result.ref_tags,
+                eff_cov: result.eff_cov,
             })
-            .sum();
-        
-        // 计算每个结果的丰度 - 采用 sylph 的顺序处理方式
-        for result in group.iter_mut() {
-            // 只要有共享标签就计算丰度
-            if result.common_tags > 0 {
-                result.taxonomic_abundance = if total_genome_cov > 0.0 {
-                    result.eff_cov / total_genome_cov * 100.0
-                } else {
-                    0.0
-                };
-                
-                result.sequence_abundance = if total_seq_cov > 0.0 {
-                    result.eff_cov * result.total_tags as f64 / total_seq_cov * 100.0
-                } else {
-                    0.0
-                };
-            } else {
-                result.taxonomic_abundance = 0.0;
-                result.sequence_abundance = 0.0;
+        })
+        .collect()
+}
+
+fn write_contig_table(output: &OutputOptions, path: &str, mut rows: Vec<ContigProfileRow>, precision: Option<usize>) -> Result<()> {
+    rows.sort_by(|a, b| {
+        a.genome_id.cmp(&b.genome_id)
+            .then_with(|| a.contig_name.cmp(&b.contig_name))
+            .then_with(|| a.sample_id.cmp(&b.sample_id))
+    });
+    output.write_atomically(path, |writer| {
+        writeln!(writer, "Genome_ID\tContig\tSample_ID\tANI(%)\tShared_Tags\tTotal_Tags\tEff_cov")?;
+        for row in &rows {
+            writeln!(writer, "{}\t{}\t{}\t{:.*}\t{}\t{}\t{:.*}",
+                row.genome_id, row.contig_name, row.sample_id, precision.unwrap_or(2), row.adjusted_ani,
+                row.shared_tags, row.total_tags, precision.unwrap_or(4), row.eff_cov)?;
+        }
+        Ok(())
+    })
+}
+
+// 只有请求了样本间比较时才需要构建稠密的样本×分类单元矩阵
+fn wants_sample_comparisons(args: &ProfileArgs) -> bool {
+    args.distance_matrix.is_some() || args.clr_output.is_some()
+}
+
+// --distance-matrix / --clr-output：基于最终丰度表的样本间比较
+fn write_sample_comparisons(output: &OutputOptions, args: &ProfileArgs, taxa: &[String], sample_ids: &[String], samples: &[Vec<f64>]) -> Result<()> {
+    if let Some(path) = &args.distance_matrix {
+        let matrix = distance_matrix(samples, &args.distance_metric, args.clr_pseudocount)?;
+        write_distance_matrix(output, path, sample_ids, &matrix)
+            .with_context(|| format!("Failed to write distance matrix: {}", path))?;
+        eprintln!("{} distance matrix saved to: {}", args.distance_metric, path);
+    }
+    if let Some(path) = &args.clr_output {
+        write_clr_matrix(output, path, taxa, sample_ids, samples, args.clr_pseudocount)
+            .with_context(|| format!("Failed to write CLR matrix: {}", path))?;
+        eprintln!("CLR-transformed abundance matrix saved to: {}", path);
+    }
+    Ok(())
+}
+
+// 基因组ID列表文件：每行一个ID，忽略空行和#注释；ID按extract_genome_id_from_path规范化
+fn read_genome_id_list(path: &str) -> Result<FxHashSet<String>> {
+    use std::io::BufRead;
+
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open genome ID list: {}", path))?;
+    let mut ids = FxHashSet::default();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read genome ID list: {}", path))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        ids.insert(extract_genome_id_from_path(line).to_string());
+    }
+    Ok(ids)
+}
+
+// --genome-name-map：两列TSV（基因组ID、显示名称），忽略空行和#注释；ID按extract_genome_id_from_path规范化
+fn read_genome_name_map(path: &str) -> Result<FxHashMap<String, String>> {
+    use std::io::BufRead;
+
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open genome name map: {}", path))?;
+    let mut names = FxHashMap::default();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read genome name map: {}", path))?;
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (genome_id, name) = line.split_once('\t')
+            .filter(|(_, name)| !name.trim().is_empty())
+            .ok_or_else(|| anyhow!("{} line {}: expected <genome ID>\t<display name>", path, i + 1))?;
+        names.insert(extract_genome_id_from_path(genome_id.trim()).to_string(), name.trim().to_string());
+    }
+    Ok(names)
+}
+
+// 有映射时返回显示名称，否则原样返回
+fn display_genome_name<'a>(genome_id: &'a str, names: &'a FxHashMap<String, String>) -> &'a str {
+    names.get(extract_genome_id_from_path(genome_id)).map(|name| name.as_str()).unwrap_or(genome_id)
+}
+
+// 先应用include再应用exclude；列表中在数据库里找不到的ID给出警告
+fn filter_db_entries(
+    entries: Vec<SyldbEntry>,
+    include: Option<&FxHashSet<String>>,
+    exclude: Option<&FxHashSet<String>>,
+) -> Vec<SyldbEntry> {
+    let db_ids: FxHashSet<&str> = entries
+        .iter()
+        .map(|e| extract_genome_id_from_path(&e.genome_source))
+        .collect();
+    for (flag, ids) in [("--include-genomes", include), ("--exclude-genomes", exclude)] {
+        if let Some(ids) = ids {
+            let mut missing: Vec<&String> = ids.iter().filter(|id| !db_ids.contains(id.as_str())).collect();
+            missing.sort();
+            for id in missing {
+                eprintln!("Warning: genome {} from {} not found in database", id, flag);
             }
         }
     }
 
-    // 检查是否提供了taxonomy文件以进行物种级别聚合
-    if let Some(taxonomy_file) = &args.taxonomy_file {
-        eprintln!("Loading taxonomy information from: {}", taxonomy_file);
-        
-        // 读取分类学信息
-        let taxonomy_map = read_taxonomy_file(taxonomy_file)?;
-        
-        // 聚合到物种级别
-        let mut species_results = aggregate_to_species_level(&sample_groups, &taxonomy_map, effective_min_ani)?;
-        
-        // 获取所有样本ID
-        let all_samples: HashSet<String> = sample_groups.keys().cloned().collect();
-        
-        // 生成过滤前的物种级别TSV格式丰度矩阵
-        let pre_filter_tsv_name = format!("pre_gscore_filter_{}", args.tsv_name);
-        eprintln!("Writing pre-filter species abundance matrix: {}", pre_filter_tsv_name);
-        write_species_abundance_matrix(&species_results, &all_samples, args.log_path.clone(), &pre_filter_tsv_name, &mut writer)?;
-        
-        // 应用 G-score 过滤
-        eprintln!("Applying G-score filtering with threshold: {:.2}", args.gscore_threshold);
-        species_results = filter_species_by_gscore(&mut species_results, args.gscore_threshold);
-        
-        // 生成过滤后的物种级别TSV格式丰度矩阵
-        eprintln!("Writing post-filter species abundance matrix: {}", args.tsv_name);
-        write_species_abundance_matrix(&species_results, &all_samples, args.log_path.clone(), &args.tsv_name, &mut writer)?;
-        
-        // 输出物种级别的统计信息
-        writeln!(writer, "Species-level Profile Results:")?;
-        writeln!(writer, "------------------------------")?;
-        writeln!(writer, "Sample files: {} files processed", sample_files.len())?;
-        writeln!(writer, "Database file: {}", args.db_file)?;
-        writeln!(writer, "Taxonomy file: {}", taxonomy_file)?;
-        writeln!(writer, "Total species detected: {}", species_results.len())?;
-        writeln!(writer, "\nSpecies composition summary:")?;
-        writeln!(writer, "{:<50} {:<15} {:<15} {:<15} {:<10} {:<10}", 
-            "Species", "Genomes", "Total_Tags", "Reads_Count", "G-score", "Avg_Abundance")?;
-        writeln!(writer, "{:-<120}", "")?;
-        
-        for species_result in &species_results {
-            let avg_abundance: f64 = species_result.sample_abundances.values().sum::<f64>() 
-                / species_result.sample_abundances.len() as f64;
-            let species_name = if species_result.taxonomy.species.is_empty() {
-                format!("{}_sp", species_result.taxonomy.genus)
+    entries
+        .into_iter()
+        .filter(|e| {
+            let id = extract_genome_id_from_path(&e.genome_source);
+            include.is_none_or(|ids| ids.contains(id)) && exclude.is_none_or(|ids| !ids.contains(id))
+        })
+        .collect()
+}
+
+// --aggregate contig：每个条目作为独立的分析单元，ID为"基因组ID|序列ID"。
+// 条目的gn_size是整个基因组的大小，对单个contig不成立，清零后大小相关的校正按缺失处理
+fn split_db_entries_by_contig(entries: Vec<SyldbEntry>) -> Vec<SyldbEntry> {
+    entries
+        .into_iter()
+        .map(|mut entry| {
+            entry.genome_source = format!("{}|{}", extract_genome_id_from_path(&entry.genome_source), entry.sequence_id);
+            entry.gn_size = 0;
+            entry
+        })
+        .collect()
+}
+
+// 同一基因组以不同路径重复建库（如/a/GCF_x.fasta与/b/GCF_x.fasta）时去重，否则genome_count会虚高、丰度被拆分。
+// 只有能确认是同一基因组时才去掉后出现的路径：基因组ID是GCF_/GCA_ accession，或两者的标签集合完全相同。
+// 其他同名文件（如/a/genome.fa与/b/genome.fa）可能是不同的基因组，全部保留（--no-dealias时一律保留）
+fn dealias_db_entries(entries: Vec<SyldbEntry>) -> Vec<SyldbEntry> {
+    let mut source_tags: FxHashMap<&str, Vec<Hash>> = FxHashMap::default();
+    let mut sources: Vec<&str> = Vec::new();
+    for entry in &entries {
+        let tags = source_tags.entry(entry.genome_source.as_str()).or_insert_with(|| {
+            sources.push(entry.genome_source.as_str());
+            Vec::new()
+        });
+        tags.extend(entry.tags.iter().copied());
+    }
+    for tags in source_tags.values_mut() {
+        tags.sort_unstable();
+        tags.dedup();
+    }
+
+    // 每个基因组ID第一次出现的路径为规范路径
+    let mut canonical_sources: FxHashMap<&str, &str> = FxHashMap::default();
+    let mut aliases: Vec<(&str, &str, &str)> = Vec::new();
+    let mut ambiguous: Vec<(&str, &str, &str)> = Vec::new();
+    for &source in &sources {
+        let genome_id = extract_genome_id_from_path(source);
+        let canonical = *canonical_sources.entry(genome_id).or_insert(source);
+        if canonical == source {
+            continue;
+        }
+        let is_accession = genome_id.starts_with("GCF_") || genome_id.starts_with("GCA_");
+        if is_accession || source_tags[source] == source_tags[canonical] {
+            aliases.push((genome_id, source, canonical));
+        } else {
+            ambiguous.push((genome_id, source, canonical));
+        }
+    }
+    for (genome_id, source, canonical) in &aliases {
+        eprintln!("Warning: genome {} appears under multiple sources; ignoring {} in favour of {}", genome_id, source, canonical);
+    }
+    for (genome_id, source, canonical) in &ambiguous {
+        eprintln!("Warning: {} and {} have the same genome ID {} but different tags; keeping both, reported under one ID (rename one file to tell them apart)", canonical, source, genome_id);
+    }
+
+    let dropped: FxHashSet<String> = aliases.iter().map(|(_, source, _)| source.to_string()).collect();
+    entries.into_iter().filter(|entry| !dropped.contains(&entry.genome_source)).collect()
+}
+
+// 按覆盖度归一化一个样本内的丰度；总量为0（没有共享标签）时全部为0而不是NaN
+fn normalize_abundances(group: &mut [GenomeProfileResult]) {
+    // 计算总覆盖度，包括所有检测到的标签
+    let total_genome_cov: f64 = group.iter()
+        .map(|r| if r.common_tags > 0 { r.eff_cov } else { 0.0 })
+        .sum();
+    
+    let total_seq_cov: f64 = group.iter()
+        .map(|r| if r.common_tags > 0 { 
+            r.eff_cov * r.total_tags as f64 
+        } else { 
+            0.0 
+        })
+        .sum();
+    
+    // 计算每个结果的丰度 - 采用 sylph 的顺序处理方式
+    for result in group.iter_mut() {
+        // 只要有共享标签就计算丰度
+        if result.common_tags > 0 {
+            result.taxonomic_abundance = if total_genome_cov > 0.0 {
+                result.eff_cov / total_genome_cov * 100.0
             } else {
-                species_result.taxonomy.species.clone()
+                0.0
             };
             
-            writeln!(writer, "{:<50} {:<15} {:<15} {:<15} {:<10.2} {:<10.4}", 
-                species_name,
-                species_result.genome_count,
-                species_result.total_tags,
-                species_result.reads_count,
-                species_result.gscore,
-                avg_abundance)?;
+            result.sequence_abundance = if total_seq_cov > 0.0 {
+                result.eff_cov * result.total_tags as f64 / total_seq_cov * 100.0
+            } else {
+                0.0
+            };
+        } else {
+            result.taxonomic_abundance = 0.0;
+            result.sequence_abundance = 0.0;
         }
-        
-    } else {
-        // 原始的基因组级别输出
-        // 生成TSV格式的丰度矩阵
-        write_abundance_matrix(&sample_groups, &all_genomes, args.log_path.clone(), &args.tsv_name, &mut writer)?;
+    }
+}
 
-        // 将所有结果收集到一个新的向量中
-        let mut final_results: Vec<GenomeProfileResult> = sample_groups.into_values().flatten().collect();
-        
-        // 按基因组ID和ANI值排序
-        final_results.sort_by(|a, b| {
-            a.genome_id.cmp(&b.genome_id)
-                .then_with(|| b.adjusted_ani.partial_cmp(&a.adjusted_ani).unwrap())
-        });
-        
-        // 输出结果
-        writeln!(writer, "Genome-level Profile Results:")?;
-        writeln!(writer, "-----------------------------")?;
-        writeln!(writer, "Sample files: {} files processed", sample_files.len())?;
-        writeln!(writer, "Database file: {}", args.db_file)?;
-        writeln!(writer, "\nGenome composition:")?;
-        writeln!(writer, "{:<30} {:<20} {:<10} {:<12} {:<12} {:<12} {:<12} {:<10}", 
-            "Genome_ID", "Sample_ID", "ANI(%)", "Tax_Abund(%)", "Seq_Abund(%)", "Common_Tags", "Total_Tags", "Eff_cov")?;
-        writeln!(writer, "{:-<110}", "")?;
+// 基因组ID -> 基因组大小；每个contig条目记录的是整个基因组的gn_size
+fn genome_sizes_from_cache(
+    cached_db_entries: &[SyldbEntry],
+    genome_mapping: &FxHashMap<String, (String, String)>,
+) -> FxHashMap<String, usize> {
+    let mut genome_sizes: FxHashMap<String, usize> = FxHashMap::default();
+    for entry in cached_db_entries {
+        if let Some((genome_id, _)) = genome_mapping.get(&entry.sequence_id) {
+            let size = genome_sizes.entry(genome_id.clone()).or_insert(0);
+            *size = (*size).max(entry.gn_size);
+        }
+    }
+    genome_sizes
+}
+
+// 序列丰度（标签/reads占比）偏向大基因组：除以基因组大小后重新归一化，
+// 得到细胞数意义上的taxonomic丰度。缺少gn_size的旧数据库保持原值
+fn size_normalize_abundances(group: &mut [GenomeProfileResult], genome_sizes: &FxHashMap<String, usize>) {
+    let size_of = |r: &GenomeProfileResult| genome_sizes.get(&r.genome_id).copied().unwrap_or(0);
+    if let Some(missing) = group.iter().find(|r| r.sequence_abundance > 0.0 && size_of(r) == 0) {
+        eprintln!("Warning: genome {} has no gn_size in the database; skipping --size-normalize for sample {}", missing.genome_id, missing.sample_id);
+        return;
+    }
+
+    let total: f64 = group.iter()
+        .filter(|r| size_of(r) > 0)
+        .map(|r| r.sequence_abundance / size_of(r) as f64)
+        .sum();
+    for result in group.iter_mut() {
+        let size = size_of(result);
+        result.taxonomic_abundance = if total > 0.0 && size > 0 {
+            result.sequence_abundance / size as f64 / total * 100.0
+        } else {
+            0.0
+        };
+    }
+}
+
+// --coverage-corrected-matrix：有效覆盖度除以基因组大小后重新归一化，写入独立矩阵，
+// 不改变taxonomic/sequence丰度。缺少gn_size时该样本保持原值并告警
+fn coverage_correct_abundances(group: &mut [GenomeProfileResult], genome_sizes: &FxHashMap<String, usize>) {
+    let size_of = |r: &GenomeProfileResult| genome_sizes.get(&r.genome_id).copied().unwrap_or(0);
+    if let Some(missing) = group.iter().find(|r| r.common_tags > 0 && size_of(r) == 0) {
+        eprintln!("Warning: genome {} has no gn_size in the database; coverage-corrected abundances for sample {} are not size-corrected", missing.genome_id, missing.sample_id);
+        return;
+    }
+
+    let total: f64 = group.iter()
+        .filter(|r| r.common_tags > 0 && size_of(r) > 0)
+        .map(|r| r.eff_cov / size_of(r) as f64)
+        .sum();
+    for result in group.iter_mut() {
+        let size = size_of(result);
+        result.taxonomic_abundance = if total > 0.0 && size > 0 && result.common_tags > 0 {
+            result.eff_cov / size as f64 / total * 100.0
+        } else {
+            0.0
+        };
+    }
+}
+
+// 从缓存的数据库条目中构建基因组映射关系
+fn build_genome_mapping_from_cache(cached_db_entries: &[SyldbEntry]) -> FxHashMap<String, (String, String)> {
+    // 预分配 HashMap 容量以提高性能
+    let mut genome_map = FxHashMap::default();
+    
+    // 并行处理数据库条目生成映射
+    for entry in cached_db_entries {
+        // 获取原始基因组文件路径
+        let genome_source = entry.genome_source.clone();
+        // 按accession规范化，同一基因组的不同路径/扩展名得到同一ID
+        let genome_id = extract_genome_id_from_path(&genome_source).to_string();
         
-        let mut current_genome = String::new();
-        for result in final_results {
-            if current_genome != result.genome_id {
-                if !current_genome.is_empty() {
-                    writeln!(writer)?;
+        // 返回(序列ID, (基因组ID, 基因组源文件))
+        genome_map.insert(entry.sequence_id.clone(), (genome_id, genome_source));
+    }
+    
+    genome_map
+}
+
+// ANI相差在此范围内视为相同
+const WINNER_ANI_EPSILON: f64 = 1e-9;
+
+// 候选基因组的优先级比较，Greater表示a胜出。
+// 规则：ANI更高者胜；ANI相同（差值<=WINNER_ANI_EPSILON）时参考标签数更多者胜；
+// 再相同则genome_id字典序更小者胜。这样winner table与results的顺序（并行收集，不固定）无关
+fn compare_winner_candidates(a: (f64, usize, &str), b: (f64, usize, &str)) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    if (a.0 - b.0).abs() > WINNER_ANI_EPSILON {
+        return a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal);
+    }
+    a.1.cmp(&b.1).then_with(|| b.2.cmp(a.2))
+}
+
+// 构建winner table：每个标签分配给含有该标签、优先级最高的基因组（见compare_winner_candidates）。
+// 只维护一张标签表，重叠统计在分配时同步累计，避免GTDB规模数据库上再建一张全量计数表
+fn build_winner_table(
+    results: &[QueryResult],
+    cached_db_entries: &[SyldbEntry],
+    log: bool,
+) -> (FxHashMap<Hash, WinnerTableEntry>, WinnerTableStats) {
+    let db_by_id: FxHashMap<&str, &SyldbEntry> = cached_db_entries
+        .iter()
+        .map(|entry| (entry.sequence_id.as_str(), entry))
+        .collect();
+
+    let mut winners: FxHashMap<Hash, WinnerTableEntry> = FxHashMap::default();
+    let mut stats = WinnerTableStats::default();
+    for result in results {
+        let Some(db_entry) = db_by_id.get(result.contig_name.as_str()) else {
+            continue;
+        };
+        for tag in &db_entry.tags {
+            match winners.get_mut(tag) {
+                Some(current) => {
+                    let candidate = (result.adjusted_ani, result.ref_tags, result.contig_name.as_str());
+                    let incumbent = (current.ani, current.ref_tags, current.genome_id.as_str());
+                    let changed = current.genome_id != result.contig_name;
+                    if compare_winner_candidates(candidate, incumbent) == std::cmp::Ordering::Greater {
+                        current.ani = result.adjusted_ani;
+                        current.genome_id = result.contig_name.clone();
+                        current.ref_tags = result.ref_tags;
+                    }
+                    // 标签第一次出现第二个基因组时计为共享
+                    if changed && !current.was_reassigned {
+                        current.was_reassigned = true;
+                        stats.contested += 1;
+                    }
+                }
+                None => {
+                    winners.insert(
+                        *tag,
+                        WinnerTableEntry {
+                            ani: result.adjusted_ani,
+                            genome_id: result.contig_name.clone(),
+                            was_reassigned: false,
+                            ref_tags: result.ref_tags,
+                        },
+                    );
+                    stats.tags += 1;
                 }
-                current_genome = result.genome_id.clone();
             }
-            
-            writeln!(writer, "{:<30} {:<20} {:<10.2} {:<12.2} {:<12.2} {:<12} {:<12} {:<10.3}", 
-                result.genome_id,
-                result.sample_id,  // 使用实际的样本来源
-                result.adjusted_ani,
-                result.taxonomic_abundance,
-                result.sequence_abundance,
-                result.common_tags,
-                result.total_tags,
-                result.eff_cov)?;
         }
     }
-    
-    Ok(())
-}
\ No newline at end of file
+
+    if log {
+        eprintln!(
+            "Winner table built: {} tags, {} shared between multiple genomes",
+            stats.tags,
+            stats.contested
+        );
+    }
+    (winners, stats)
+}
+
+// 按winner table重新计算每个(样本源, contig)的结果：共享标签只计入分配给该contig的标签。
+// 样本标签集合与初始比对相同（含--min-tag-depth），没有分到标签的contig不返回结果
+fn recalculate_with_winner_table(
+    cached_db_entries: &[SyldbEntry],
+    sample_entries: &[SampleTag],
+    winner_map: &FxHashMap<Hash, WinnerTableEntry>,
+    thresholds: &FilterThresholds,
+) -> Vec<QueryResult> {
+    let mut sample_groups: FxHashMap<&str, Vec<&SampleTag>> = FxHashMap::default();
+    for entry in sample_entries {
+        sample_groups.entry(entry.sample_source.as_str()).or_default().push(entry);
+    }
+
+    let mut results = Vec::new();
+    for (sample_source, entries) in sample_groups {
+        let sample_tags = sample_tag_set(&entries, thresholds.min_tag_depth);
+        let total_sample_tags: usize = entries.iter().map(|entry| entry.count).sum();
+        results.par_extend(cached_db_entries.par_iter().filter_map(|db_entry| {
+            let is_won = |tag: &Hash| winner_map.get(tag).is_some_and(|winner| winner.genome_id == db_entry.sequence_id);
+            let shared_tags = db_entry.tags.iter()
+                .filter(|tag| sample_tags.contains(tag) && is_won(tag))
+                .count();
+            if shared_tags == 0 {
+                return None;
+            }
+            let total_ref_tags = db_entry.tags.len();
+            let mut result = calculate_statistics_with(shared_tags, total_sample_tags, total_ref_tags, thresholds.min_shared_tags);
+            result.sample_file = sample_source.to_string();
+            result.genome_file = db_entry.genome_source.clone();
+            result.contig_name = db_entry.sequence_id.clone();
+            result.shared_tags = shared_tags;
+            result.query_tags = total_sample_tags;
+            result.ref_tags = total_ref_tags;
+            result.mean_cov_geq1 = 1.0;
+            result.eff_cov = shared_tags as f64 / total_ref_tags as f64;
+            result.median_cov = 1.0;
+            Some(result)
+        }));
+    }
+    results
+}
+
+// 去掉重新分配后不再可信的contig：初始比对中不存在的，或被其他基因组夺走标签后ANI低于阈值的
+fn filter_over_reassigned_genomes(
+    initial_results: &[QueryResult],
+    reassigned_results: &[QueryResult],
+    min_ani: f64,
+) -> Vec<QueryResult> {
+    let initial: FxHashSet<(&str, &str)> = initial_results.iter()
+        .map(|r| (r.sample_file.as_str(), r.contig_name.as_str()))
+        .collect();
+    reassigned_results.iter()
+        .filter(|r| initial.contains(&(r.sample_file.as_str(), r.contig_name.as_str())))
+        .filter(|r| r.adjusted_ani >= min_ani)
+        .cloned()
+        .collect()
+}
+
+// 重新分配后按样本源重新计算contig级丰度：序列丰度按共享标签数、分类丰度按有效覆盖度归一化
+fn recalculate_abundances_after_reassignment(results: &mut [QueryResult]) {
+    let mut totals: FxHashMap<String, (usize, f64)> = FxHashMap::default();
+    for result in results.iter() {
+        let total = totals.entry(result.sample_file.clone()).or_insert((0, 0.0));
+        total.0 += result.shared_tags;
+        total.1 += result.eff_cov;
+    }
+    for result in results.iter_mut() {
+        let (total_tags, total_cov) = totals[&result.sample_file];
+        result.sequence_abundance = if total_tags > 0 { result.shared_tags as f64 / total_tags as f64 * 100.0 } else { 0.0 };
+        result.taxonomic_abundance = if total_cov > 0.0 { result.eff_cov / total_cov * 100.0 } else { 0.0 };
+    }
+}
+
+// --timing：profile各阶段的耗时。逐样本并行的阶段按样本累加，因此可能超过总墙钟时间
+const PROFILE_PHASES: [&str; 8] = [
+    "DB load",
+    "Sample load",
+    "Initial query",
+    "Winner table",
+    "Reassignment",
+    "Abundance calculation",
+    "Aggregation",
+    "Writing",
+];
+
+struct PhaseTimer {
+    elapsed: Mutex<[Duration; PROFILE_PHASES.len()]>,
+}
+
+impl PhaseTimer {
+    fn new() -> Self {
+        PhaseTimer { elapsed: Mutex::new([Duration::ZERO; PROFILE_PHASES.len()]) }
+    }
+
+    fn add(&self, phase: &str, start: Instant) {
+        let index = PROFILE_PHASES.iter().position(|p| *p == phase).expect("unknown profile phase");
+        self.elapsed.lock().unwrap()[index] += start.elapsed();
+    }
+
+    fn seconds(&self) -> Vec<PhaseSeconds> {
+        let elapsed = self.elapsed.lock().unwrap();
+        PROFILE_PHASES.iter().zip(elapsed.iter())
+            .map(|(phase, time)| PhaseSeconds { phase: phase.to_string(), seconds: time.as_secs_f64() })
+            .collect()
+    }
+
+    fn summary(&self) -> String {
+        let elapsed = self.elapsed.lock().unwrap();
+        let total: Duration = elapsed.iter().sum();
+        let mut out = String::from("Profile timing summary:\n");
+        out.push_str(&format!("{:<25} {:>12} {:>8}\n", "Phase", "Seconds", "%"));
+        for (phase, time) in PROFILE_PHASES.iter().zip(elapsed.iter()) {
+            let pct = if total.is_zero() { 0.0 } else { time.as_secs_f64() / total.as_secs_f64() * 100.0 };
+            out.push_str(&format!("{:<25} {:>12.3} {:>8.1}\n", phase, time.as_secs_f64(), pct));
+        }
+        out.push_str(&format!("{:<25} {:>12.3}\n", "Total", total.as_secs_f64()));
+        out
+    }
+}
+
+// --run-summary：一次运行的元数据，汇总原本分散在标准错误输出中的信息
+#[derive(Debug, Serialize)]
+struct RunSummary {
+    db_file: String,
+    sample_files: Vec<String>,
+    num_samples: usize,
+    database_genomes: usize,
+    // 只在提供--taxonomy-file时有意义
+    gscore_threshold: Option<f64>,
+    species_detected: Option<usize>,
+    samples: Vec<SampleRunSummary>,
+    timing: Vec<PhaseSeconds>,
+}
+
+#[derive(Debug, Serialize)]
+struct SampleRunSummary {
+    sample: String,
+    total_tags: usize,
+    // 检出基因组的共享标签数之和
+    shared_tags: usize,
+    detected_genomes: usize,
+    species_detected: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct PhaseSeconds {
+    phase: String,
+    seconds: f64,
+}
+
+// timing在写出前由PhaseTimer填入
+fn build_run_summary(
+    args: &ProfileArgs,
+    sample_files: &[String],
+    sample_groups: &HashMap<String, Vec<GenomeProfileResult>>,
+    all_genomes: &HashSet<String>,
+    species_results: Option<&[SpeciesAbundanceResult]>,
+    sample_tag_totals: &FxHashMap<String, usize>,
+) -> RunSummary {
+    let mut sample_ids: Vec<&String> = sample_tag_totals.keys().chain(sample_groups.keys()).collect::<HashSet<_>>().into_iter().collect();
+    sample_ids.sort_by(|a, b| natural_cmp(a, b));
+    let samples = sample_ids.into_iter()
+        .map(|sample| {
+            let detected = sample_groups.get(sample).map(Vec::as_slice).unwrap_or_default();
+            SampleRunSummary {
+                sample: sample.clone(),
+                total_tags: sample_tag_totals.get(sample).copied().unwrap_or(0),
+                shared_tags: detected.iter().map(|r| r.common_tags).sum(),
+                detected_genomes: detected.len(),
+                species_detected: species_results.map(|species| species.iter()
+                    .filter(|s| s.sample_abundances.get(sample).is_some_and(|&a| a > 0.0))
+                    .count()),
+            }
+        })
+        .collect::<Vec<_>>();
+    RunSummary {
+        db_file: args.db_file.clone(),
+        sample_files: sample_files.to_vec(),
+        num_samples: samples.len(),
+        database_genomes: all_genomes.len(),
+        gscore_threshold: species_results.map(|_| args.gscore_threshold),
+        species_detected: species_results.map(<[SpeciesAbundanceResult]>::len),
+        samples,
+        timing: Vec::new(),
+    }
+}
+
+// 在读入任何数据前检查所有输出位置都可写
+fn check_profile_outputs(output: &OutputOptions, args: &ProfileArgs) -> Result<()> {
+    let matrix_dir = PathBuf::from(args.log_path.as_deref().unwrap_or("."));
+    let mut outputs = vec![matrix_dir.join(&args.tsv_name)];
+    if args.taxonomy_file.is_some() {
+        outputs.push(matrix_dir.join(format!("pre_gscore_filter_{}", args.tsv_name)));
+    }
+    if args.coverage_corrected_matrix {
+        outputs.push(matrix_dir.join(format!("coverage_corrected_{}", args.tsv_name)));
+    }
+    for path in [&args.out_file_name, &args.distance_matrix, &args.clr_output, &args.per_contig, &args.run_summary].into_iter().flatten() {
+        outputs.push(PathBuf::from(path));
+    }
+    for path in outputs {
+        output.check_file(&path).with_context(|| format!("Output check failed for {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// profile的计算结果，见compute_profile
+#[derive(Debug, Clone)]
+pub struct ProfileResults {
+    pub sample_files: Vec<String>,
+    // 样本源 -> 通过过滤的基因组结果（已计算丰度）
+    pub genome_results: HashMap<String, Vec<GenomeProfileResult>>,
+    // 数据库中的全部基因组ID
+    pub all_genomes: HashSet<String>,
+    // 提供--taxonomy-file时为物种级结果，pre_gscore_species_results为G-score过滤前的版本
+    pub species_results: Option<Vec<SpeciesAbundanceResult>>,
+    pub pre_gscore_species_results: Vec<SpeciesAbundanceResult>,
+    // --coverage-corrected-matrix时为genome_results的副本，taxonomic_abundance换成按基因组大小校正的覆盖度占比
+    pub coverage_corrected_results: Option<HashMap<String, Vec<GenomeProfileResult>>>,
+    evaluated: Option<FxHashMap<String, FxHashSet<String>>>,
+    contig_rows: Vec<ContigProfileRow>,
+    // 样本源 -> 载入（及去宿主、稀释等处理）后的标签数
+    sample_tag_totals: FxHashMap<String, usize>,
+}
+
+fn profile_output_options(args: &ProfileArgs) -> OutputOptions {
+    OutputOptions::new(args.force, args.temp_dir.as_deref()).with_write_buffer_size(args.write_buffer_size)
+}
+
+// 更新profile函数
+pub fn profile(args: ProfileArgs) -> Result<()> {
+    if args.write_buffer_size == Some(0) {
+        return Err(anyhow!("--write-buffer-size must be greater than 0"));
+    }
+    let output = profile_output_options(&args);
+    check_profile_outputs(&output, &args)?;
+    let progress = Progress::new(args.progress_json.as_deref());
+    let long_matrix = args.matrix_format == "long";
+    if args.run_id.is_some() && !long_matrix {
+        return Err(anyhow!("--run-id requires --matrix-format long"));
+    }
+    let run_id = args.run_id.as_deref();
+    let names = args.genome_name_map.as_deref().map(read_genome_name_map).transpose()?;
+
+    let timer = PhaseTimer::new();
+    let ProfileResults {
+        sample_files,
+        genome_results: sample_groups,
+        all_genomes,
+        pre_gscore_species_results,
+        species_results,
+        coverage_corrected_results,
+        evaluated,
+        contig_rows,
+        sample_tag_totals,
+    } = compute_profile(&args, &timer, &progress)?;
+    // 后面的输出会消耗结果，先汇总
+    let mut run_summary = args.run_summary.as_ref().map(|_| build_run_summary(
+        &args, &sample_files, &sample_groups, &all_genomes, species_results.as_deref(), &sample_tag_totals,
+    ));
+
+    // 创建输出写入器
+    progress.phase("writing", sample_files.len());
+    let mut writer = create_multi_writer(&output, &args.out_file_name)?;
+
+    if let Some(corrected) = &coverage_corrected_results {
+        let corrected_tsv_name = format!("coverage_corrected_{}", args.tsv_name);
+        eprintln!("Writing coverage-corrected abundance matrix: {}", corrected_tsv_name);
+        write_abundance_matrix(&output, corrected, &all_genomes, evaluated.as_ref(), names.as_ref(), args.log_path.clone(), &corrected_tsv_name, long_matrix, &args.abundance_units, run_id, args.precision.unwrap_or(GENOME_ABUNDANCE_PRECISION), None)?;
+    }
+
+    if let Some(species_results) = species_results {
+        let taxonomy_file = args.taxonomy_file.as_deref().unwrap_or_default();
+        // 获取所有样本ID
+        let all_samples: HashSet<String> = sample_groups.keys().cloned().collect();
+        
+        // 生成过滤前的物种级别TSV格式丰度矩阵
+        let phase_start = Instant::now();
+        let pre_filter_tsv_name = format!("pre_gscore_filter_{}", args.tsv_name);
+        eprintln!("Writing pre-filter species abundance matrix: {}", pre_filter_tsv_name);
+        write_species_abundance_matrix(&output, &pre_gscore_species_results, &all_samples, args.log_path.clone(), &pre_filter_tsv_name, long_matrix, &args.abundance_units, run_id, args.precision.unwrap_or(SPECIES_ABUNDANCE_PRECISION), (!args.matrix_only_tsv).then_some(&mut writer))?;
+        
+        // 生成过滤后的物种级别TSV格式丰度矩阵
+        eprintln!("Writing post-filter species abundance matrix: {}", args.tsv_name);
+        write_species_abundance_matrix(&output, &species_results, &all_samples, args.log_path.clone(), &args.tsv_name, long_matrix, &args.abundance_units, run_id, args.precision.unwrap_or(SPECIES_ABUNDANCE_PRECISION), (!args.matrix_only_tsv).then_some(&mut writer))?;
+
+        let mut sample_ids: Vec<String> = all_samples.iter().cloned().collect();
+        sample_ids.sort_by(|a, b| natural_cmp(a, b));
+        if wants_sample_comparisons(&args) {
+            let taxa: Vec<String> = species_results.iter()
+                .map(|r| if r.taxonomy.species.is_empty() { format!("{}_sp", r.taxonomy.genus) } else { r.taxonomy.species.clone() })
+                .collect();
+            let samples: Vec<Vec<f64>> = sample_ids.iter()
+                .map(|sample_id| species_results.iter()
+                    .map(|r| r.sample_abundances.get(sample_id.as_str()).copied().unwrap_or(0.0))
+                    .collect())
+                .collect();
+            write_sample_comparisons(&output, &args, &taxa, &sample_ids, &samples)?;
+        }
+        
+        // 输出物种级别的统计信息
+        writeln!(writer, "Species-level Profile Results:")?;
+        writeln!(writer, "------------------------------")?;
+        writeln!(writer, "Sample files: {} files processed", sample_files.len())?;
+        writeln!(writer, "Database file: {}", args.db_file)?;
+        writeln!(writer, "Taxonomy file: {}", taxonomy_file)?;
+        writeln!(writer, "Total species detected: {}", species_results.len())?;
+        writeln!(writer, "\nSpecies composition summary:")?;
+        writeln!(writer, "{:<50} {:<15} {:<15} {:<15} {:<10} {:<10}", 
+            "Species", "Genomes", "Total_Tags", "Reads_Count", "G-score", "Avg_Abundance")?;
+        writeln!(writer, "{:-<120}", "")?;
+        
+        for species_result in &species_results {
+            writeln!(writer, "{}", format_species_summary_row(species_result, args.precision))?;
+        }
+        if args.species_representative_genome {
+            writeln!(writer, "\nRepresentative genomes (most shared tags per species and sample):")?;
+            writeln!(writer, "{:<50} {:<30} {:<30} {:<15} {:<10}", "Species", "Sample", "Genome", "Shared_Tags", "ANI")?;
+            writeln!(writer, "{:-<140}", "")?;
+            for row in species_representative_rows(&species_results, &sample_ids) {
+                writeln!(writer, "{}", row)?;
+            }
+        }
+        timer.add("Writing", phase_start);
+        
+    } else {
+        // 原始的基因组级别输出
+        // 生成TSV格式的丰度矩阵
+        let phase_start = Instant::now();
+        write_abundance_matrix(&output, &sample_groups, &all_genomes, evaluated.as_ref(), names.as_ref(), args.log_path.clone(), &args.tsv_name, long_matrix, &args.abundance_units, run_id, args.precision.unwrap_or(GENOME_ABUNDANCE_PRECISION), (!args.matrix_only_tsv).then_some(&mut writer))?;
+
+        if wants_sample_comparisons(&args) {
+            let mut sample_ids: Vec<String> = sample_groups.keys().cloned().collect();
+            sample_ids.sort_by(|a, b| natural_cmp(a, b));
+            let mut taxa: Vec<String> = all_genomes.iter().cloned().collect();
+            taxa.sort_by(|a, b| natural_cmp(a, b));
+            let samples: Vec<Vec<f64>> = sample_ids.iter()
+                .map(|sample_id| {
+                    let abundances: FxHashMap<&str, f64> = sample_groups[sample_id].iter()
+                        .map(|r| (r.genome_id.as_str(), r.taxonomic_abundance))
+                        .collect();
+                    taxa.iter()
+                        .map(|genome_id| abundances.get(genome_id.as_str()).copied().unwrap_or(0.0))
+                        .collect()
+                })
+                .collect();
+            let taxa: Vec<String> = match &names {
+                Some(names) => taxa.iter().map(|genome_id| display_genome_name(genome_id, names).to_string()).collect(),
+                None => taxa,
+            };
+            write_sample_comparisons(&output, &args, &taxa, &sample_ids, &samples)?;
+        }
+
+        // 将所有结果收集到一个新的向量中
+        let mut final_results: Vec<GenomeProfileResult> = sample_groups.into_values().flatten().collect();
+        
+        // 默认按基因组ID和ANI值排序；--sort-by指定时按该指标排序
+        match &args.sort_by {
+            Some(key) => final_results.sort_by(|a, b| compare_by(a, b, key)),
+            None => final_results.sort_by(|a, b| {
+                a.genome_id.cmp(&b.genome_id)
+                    .then_with(|| compare_by(a, b, "ani"))
+            }),
+        }
+        
+        // 输出结果
+        writeln!(writer, "Genome-level Profile Results:")?;
+        writeln!(writer, "-----------------------------")?;
+        writeln!(writer, "Sample files: {} files processed", sample_files.len())?;
+        writeln!(writer, "Database file: {}", args.db_file)?;
+        writeln!(writer, "\nGenome composition:")?;
+        writeln!(writer, "{:<30} {:<20} {:<10} {:<12} {:<12} {:<12} {:<12} {:<10} {:<10}", 
+            "Genome_ID", "Sample_ID", "ANI(%)", "Tax_Abund(%)", "Seq_Abund(%)", "Common_Tags", "Total_Tags", "Eff_cov", "Breadth")?;
+        writeln!(writer, "{:-<120}", "")?;
+        
+        let mut current_genome = String::new();
+        for result in final_results {
+            if current_genome != result.genome_id {
+                if !current_genome.is_empty() {
+                    writeln!(writer)?;
+                }
+                current_genome = result.genome_id.clone();
+            }
+            
+            let p = |default: usize| args.precision.unwrap_or(default);
+            let genome_label = match &names {
+                Some(names) => display_genome_name(&result.genome_id, names),
+                None => result.genome_id.as_str(),
+            };
+            writeln!(writer, "{:<30} {:<20} {:<10.*} {:<12.*} {:<12.*} {:<12} {:<12} {:<10.*} {:<10.*}", 
+                genome_label,
+                result.sample_id,  // 使用实际的样本来源
+                p(2), result.adjusted_ani,
+                p(2), result.taxonomic_abundance,
+                p(2), result.sequence_abundance,
+                result.common_tags,
+                result.total_tags,
+                p(3), result.eff_cov,
+                p(3), breadth_fraction(result.breadth_bins))?;
+        }
+        timer.add("Writing", phase_start);
+    }
+
+    if let Some(path) = &args.per_contig {
+        write_contig_table(&output, path, contig_rows, args.precision)
+            .with_context(|| format!("Failed to write per-contig table: {}", path))?;
+        eprintln!("Per-contig table saved to: {}", path);
+    }
+
+    if args.timing {
+        eprint!("{}", timer.summary());
+    }
+
+    if let (Some(path), Some(summary)) = (&args.run_summary, run_summary.as_mut()) {
+        summary.timing = timer.seconds();
+        output.write_atomically(path, |writer| {
+            serde_json::to_writer_pretty(&mut *writer, summary).context("Failed to serialize run summary")?;
+            writeln!(writer)?;
+            Ok(())
+        })
+        .with_context(|| format!("Failed to write run summary: {}", path))?;
+        eprintln!("Run summary saved to: {}", path);
+    }
+
+    if let Some(fraction) = args.preview {
+        eprintln!("Note: results are approximate (--preview {}); rerun without --preview for the full profile", fraction);
+    }
+    progress.finish();
+    
+    Ok(())
+}
+
+// profile的计算部分：返回结构化结果，不写矩阵、表格或标准输出
+fn compute_profile(args: &ProfileArgs, timer: &PhaseTimer, progress: &Progress) -> Result<ProfileResults> {
+    if let Some(fraction) = args.preview {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(anyhow!("--preview must be in (0, 1], got {}", fraction));
+        }
+        eprintln!("Preview mode: profiling a random {:.1}% of each sample's tags; results are approximate", fraction * 100.0);
+    }
+    if !(0.0..=1.0).contains(&args.min_breadth) {
+        return Err(anyhow!("--min-breadth must be in [0, 1], got {}", args.min_breadth));
+    }
+    if !(0.0..=1.0).contains(&args.taxonomy_max_skipped) {
+        return Err(anyhow!("--taxonomy-max-skipped must be in [0, 1], got {}", args.taxonomy_max_skipped));
+    }
+    // NaN也拒绝
+    if args.gscore_pseudocount.is_nan() || args.gscore_pseudocount < 0.0 {
+        return Err(anyhow!("--gscore-pseudocount must be non-negative, got {}", args.gscore_pseudocount));
+    }
+    // 处理minimum_ani参数：如果没有传入参数，使用默认值
+    let effective_min_ani = args.minimum_ani.unwrap_or(PROFILE_MIN_ANI);
+    eprintln!("Using minimum ANI threshold: {:.1}%", effective_min_ani);
+    let thresholds = FilterThresholds::for_profile(args);
+    let output = profile_output_options(args);
+    let dump = args.unfiltered_dump.as_deref().map(|path| UnfilteredDump::create(&output, path)).transpose()?;
+    let shared_capture = args.shared_tags_dump.is_some().then(SharedTagCapture::new);
+    let use_em = args.abundance_method == "em";
+    
+    // 优化线程池配置 - 采用 sylph 的策略
+    let _max_ram = args.threads * 2; // 简单的内存限制，每线程2GB
+    init_thread_pool(args.threads);
+
+    // 一次性读取并缓存数据库文件 - 优化大文件读取
+    let phase_start = Instant::now();
+    progress.phase("loading", 0);
+    eprintln!("Loading database file: {}", args.db_file);
+    
+    let sample_files: Vec<String> = if args.sample_file.ends_with(".txt") {
+        read_sample_list(&args.sample_file)?
+    } else {
+        vec![args.sample_file.clone()]
+    };
+
+    let db_file = File::open(&args.db_file)
+        .with_context(|| format!("Failed to open database file: {}", args.db_file))?;
+    let db_reader = BufReader::with_capacity(100_000_000, db_file); // 100MB 缓冲区
+    // 不是extract格式时按sketch数据库读取，样本sketch在这里一并读入以协调c值
+    let (db_header, cached_db_entries, mut sketch_samples) = match read_database(db_reader, &args.db_file)? {
+        Database::Tags(header, entries) => (header, entries, None),
+        Database::Sketches(genome_sketches) => {
+            if args.min_breadth > 0.0 {
+                return Err(anyhow!("--min-breadth needs tag positions, which sketch databases such as {} do not record", args.db_file));
+            }
+            eprintln!("Found {} genome sketches in database", genome_sketches.len());
+            let (granularity, db_entries, samples) = sketch_profile_inputs(&genome_sketches, &sample_files)?;
+            let header = SyldbHeader { version: SYLDB_FORMAT_VERSION, subsample_rate: 1, granularity };
+            (Some(header), db_entries, Some(samples))
+        }
+    };
+    
+    eprintln!("Cached {} entries from database", cached_db_entries.len());
+    let db_enzyme = database_enzyme(&cached_db_entries).with_context(|| format!("Cannot profile against {}", args.db_file))?;
+
+    let include = args.include_genomes.as_deref().map(read_genome_id_list).transpose()?;
+    let exclude = args.exclude_genomes.as_deref().map(read_genome_id_list).transpose()?;
+    let cached_db_entries = if include.is_some() || exclude.is_some() {
+        let filtered = filter_db_entries(cached_db_entries, include.as_ref(), exclude.as_ref());
+        eprintln!("{} database entries left after genome include/exclude filtering", filtered.len());
+        filtered
+    } else {
+        cached_db_entries
+    };
+    let cached_db_entries = if args.no_dealias { cached_db_entries } else { dealias_db_entries(cached_db_entries) };
+    // 粒度取自建库时写入格式头的记录，而不是按条目数推测：单记录基因组的contig级库同样按contig报告
+    let db_genomes = cached_db_entries.iter().map(|entry| entry.genome_source.as_str()).collect::<FxHashSet<&str>>().len();
+    let cached_db_entries = match (syldb_granularity(db_header.as_ref()), args.aggregate.as_str()) {
+        (DbGranularity::Contig, "contig") => {
+            eprintln!("Contig-level database ({} entries from {} genomes); profiling each entry separately (--aggregate contig)", cached_db_entries.len(), db_genomes);
+            split_db_entries_by_contig(cached_db_entries)
+        }
+        (DbGranularity::Contig, _) => {
+            eprintln!("Contig-level database ({} entries from {} genomes); summing entries per genome (--aggregate genome)", cached_db_entries.len(), db_genomes);
+            cached_db_entries
+        }
+        (DbGranularity::Genome, "contig") => {
+            eprintln!("Warning: {} holds one entry per genome; --aggregate contig reports whole genomes", args.db_file);
+            cached_db_entries
+        }
+        (DbGranularity::Genome, _) => cached_db_entries,
+    };
+    timer.add("DB load", phase_start);
+
+    // 一次性读取并缓存所有样本文件 - 优化大文件读取
+    let phase_start = Instant::now();
+    eprintln!("Loading sample files: {}", args.sample_file);
+
+    let host_tags = args.host_db.as_deref()
+        .map(|host_db| load_host_tags(host_db, &cached_db_entries, args.keep_shared_host_tags))
+        .transpose()?;
+
+    let mut cached_sample_entries: SampleTags = FxHashMap::default();
+    // 去宿主后为空、被--rarefy或--min-sample-tags丢弃的样本源：没有参与比对，--missing-value na时整列为NA
+    let mut skipped_samples: Vec<String> = Vec::new();
+    for sample_path in &sample_files {
+        let (header, sample_entries) = match sketch_samples.as_mut() {
+            Some(samples) => (None, samples.remove(sample_path).unwrap_or_default()),
+            None => {
+                let sample_file = File::open(sample_path)
+                    .with_context(|| format!("Failed to open sample file: {}", sample_path))?;
+                let mut sample_reader = BufReader::with_capacity(100_000_000, sample_file); // 100MB 缓冲区
+                let header = read_sylsp_header_full(&mut sample_reader)?;
+                check_subsample_rates(&args.db_file, db_header.as_ref(), sample_path, header.as_ref())?;
+                check_sample_enzyme(&args.db_file, db_enzyme.as_deref(), sample_path, header.as_ref())?;
+                let sample_entries = read_sylsp_entries(sample_reader, header.as_ref())
+                    .with_context(|| format!("Failed to deserialize sample file: {}", sample_path))?;
+                (header, collapse_sample_entries(sample_entries))
+            }
+        };
+        // 去宿主放在稀释之前，稀释深度按非宿主标签计
+        let sample_entries = match &host_tags {
+            Some(host_tags) => {
+                let (kept, host_fractions) = subtract_host_tags(sample_entries, host_tags);
+                for (sample_source, removed, total) in host_fractions {
+                    eprintln!("{}: {} of {} tags ({:.2}%) attributed to host and removed",
+                        sample_source, removed, total, removed as f64 / total as f64 * 100.0);
+                    if removed == total {
+                        skipped_samples.push(sample_source);
+                    }
+                }
+                kept
+            }
+            None => sample_entries,
+        };
+        let sample_entries = if args.depth_normalize {
+            let recorded = header.map(|h| h.sample_depths).unwrap_or_default();
+            depth_normalize_entries(sample_entries, &recorded, sample_path, args.rarefy_seed)
+        } else {
+            sample_entries
+        };
+        let sample_entries = match args.rarefy {
+            Some(depth) => {
+                let (rarefied, dropped) = rarefy_sample_entries(sample_entries, depth, args.rarefy_seed);
+                for sample_source in dropped {
+                    eprintln!("Warning: sample {} in {} has fewer than {} tag observations; dropped by --rarefy", sample_source, sample_path, depth);
+                    skipped_samples.push(sample_source);
+                }
+                rarefied
+            }
+            None => sample_entries,
+        };
+        let sample_entries = match args.preview {
+            Some(fraction) => preview_sample_entries(sample_entries, fraction, args.rarefy_seed),
+            None => sample_entries,
+        };
+        let sample_entries = match args.min_sample_tags {
+            Some(min_tags) => {
+                let (kept, skipped) = drop_small_samples(sample_entries, min_tags);
+                for (sample_source, tags) in skipped {
+                    eprintln!("Warning: skipping sample {} in {}: {} tags is below --min-sample-tags {}", sample_source, sample_path, tags, min_tags);
+                    skipped_samples.push(sample_source);
+                }
+                if kept.is_empty() {
+                    eprintln!("Warning: no samples left in {} after --min-sample-tags", sample_path);
+                }
+                kept
+            }
+            None => sample_entries,
+        };
+        cached_sample_entries.insert(sample_path.clone(), sample_entries);
+    }
+    eprintln!("Cached {} sample files", cached_sample_entries.len());
+    let mut sample_tag_totals: FxHashMap<String, usize> = FxHashMap::default();
+    for entry in cached_sample_entries.values().flatten() {
+        *sample_tag_totals.entry(entry.sample_source.clone()).or_insert(0) += entry.count;
+    }
+    timer.add("Sample load", phase_start);
+
+    // 没有任何共享标签时后续阶段只会得到空表，提前诊断
+    let db_tags: FxHashSet<Hash> = cached_db_entries.iter().flat_map(|entry| entry.tags.iter().copied()).collect();
+    let mut samples_with_shared_tags = 0;
+    for (sample_path, entries) in &cached_sample_entries {
+        if count_shared_tags(&db_tags, entries) == 0 {
+            eprintln!("Warning: {} shares no tags with the database", sample_path);
+        } else {
+            samples_with_shared_tags += 1;
+        }
+    }
+    if !cached_sample_entries.is_empty() && samples_with_shared_tags == 0 {
+        return Err(zero_shared_tags_error("profile", cached_sample_entries.len()));
+    }
+    drop(db_tags);
+
+    // 从缓存的数据库构建基因组映射关系
+    let genome_mapping = build_genome_mapping_from_cache(&cached_db_entries);
+
+    // --abundance-method em：标签->候选基因组ID，以及每个样本源的标签观测计数
+    let em_inputs = if use_em {
+        let source_to_id: FxHashMap<&str, &str> = genome_mapping.values()
+            .map(|(genome_id, source)| (source.as_str(), genome_id.as_str()))
+            .collect();
+        let tag_to_genomes: FxHashMap<Hash, Vec<String>> = build_tag_to_genomes(&cached_db_entries)
+            .into_iter()
+            .map(|(tag, sources)| {
+                let mut ids: Vec<String> = sources.iter()
+                    .filter_map(|source| source_to_id.get(source.as_str()).map(|id| id.to_string()))
+                    .collect();
+                ids.sort();
+                ids.dedup();
+                (tag, ids)
+            })
+            .collect();
+        let mut genome_tag_totals: FxHashMap<String, usize> = FxHashMap::default();
+        for entry in &cached_db_entries {
+            if let Some((genome_id, _)) = genome_mapping.get(&entry.sequence_id) {
+                *genome_tag_totals.entry(genome_id.clone()).or_insert(0) += entry.tags.len();
+            }
+        }
+        let mut sample_tag_counts: FxHashMap<String, FxHashMap<Hash, usize>> = FxHashMap::default();
+        for entries in cached_sample_entries.values() {
+            for entry in entries {
+                *sample_tag_counts.entry(entry.sample_source.clone()).or_default().entry(entry.tag).or_insert(0) += entry.count;
+            }
+        }
+        Some((tag_to_genomes, genome_tag_totals, sample_tag_counts))
+    } else {
+        None
+    };
+    
+
+
+
+    // 存储所有样本的结果 - 预分配容量，使用 Mutex 保护
+    let all_results = Arc::new(Mutex::new(FxHashMap::<(String, String), GenomeProfileResult>::default()));
+    // 聚合到基因组之前保留contig维度
+    let contig_rows: Mutex<Vec<ContigProfileRow>> = Mutex::new(Vec::new());
+
+        // 采用 sylph 的简化并行处理策略
+    let step = usize::max(args.threads/3 + 1, usize::min(sample_files.len(), args.threads));
+    let chunks: Vec<Vec<String>> = sample_files.chunks(step).map(|chunk| chunk.to_vec()).collect();
+    progress.phase("profiling", sample_files.len());
+    let parallelism = Parallelism::for_profile(&args.parallel_over, sample_files.len(), args.threads);
+    eprintln!("Parallelizing over {}", if parallelism == Parallelism::Samples { "samples" } else { "genomes" });
+    let checkpoint_key = args.checkpoint.is_some().then(|| CheckpointKey::for_profile(args, effective_min_ani)).transpose()?;
+    if let Some(dir) = &args.checkpoint {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create checkpoint directory: {}", dir))?;
+    }
+    
+    // 使用 sylph 风格的分块处理，集成k-mer重新分配机制
+    chunks.into_iter().for_each(|chunk| {
+        let process_sample = |sample_file: String| {
+            let compute_stage = || {
+                // 第一阶段：计算初步结果（不使用重新分配）
+                let phase_start = Instant::now();
+                let initial_results = query_single_file_with_cached_db(&sample_file, &args.db_file, &cached_db_entries, &cached_sample_entries, effective_min_ani, &thresholds, dump.as_ref(), shared_capture.as_ref(), parallelism);
+                timer.add("Initial query", phase_start);
+                let mut initial_results = initial_results.ok()?;
+                // 按ANI排序
+                initial_results.sort_by(|a, b| compare_by(a, b, "ani"));
+
+                // 第二阶段：构建winner table并重新分配（模仿sylph的两阶段处理）
+                eprintln!("{} taxonomic profiling; reassigning tags for {} genomes...", &sample_file, initial_results.len());
+
+                // 构建winner table
+                let phase_start = Instant::now();
+                let (winner_map, _) = build_winner_table(&initial_results, &cached_db_entries, args.log_reassignments);
+                timer.add("Winner table", phase_start);
+                if let Some(capture) = &shared_capture {
+                    capture.keep_winners(&initial_results, &winner_map, &cached_db_entries);
+                }
+
+                // 覆盖广度取自初始比对（重分配只改变标签归属，不改变命中位置的分布）
+                let contig_breadth: Vec<ContigBreadth> = initial_results.iter()
+                    .map(|r| ((r.sample_file.clone(), r.contig_name.clone()), (r.breadth_bins, r.shared_span)))
+                    .collect();
+
+                // 使用winner table重新计算结果
+                let sample_entries = cached_sample_entries.get(&sample_file)?;
+                let phase_start = Instant::now();
+                let reassigned_results = recalculate_with_winner_table(
+                    &cached_db_entries,
+                    sample_entries,
+                    &winner_map,
+                    &thresholds,
+                );
+
+                // 第三阶段：过滤过度重新分配的基因组
+                let reassigned_results = filter_over_reassigned_genomes(
+                    &initial_results,
+                    &reassigned_results,
+                    effective_min_ani,
+                );
+                timer.add("Reassignment", phase_start);
+                Some(SampleCheckpoint {
+                    sample_file: sample_file.clone(),
+                    reassigned_results,
+                    contig_breadth,
+                })
+            };
+            let stage = checkpointed_stage(args.checkpoint.as_deref().zip(checkpoint_key.as_ref()), args.resume, &sample_file, compute_stage);
+            if let Some(stage) = stage {
+                let contig_breadth: FxHashMap<(String, String), ((usize, usize), usize)> = stage.contig_breadth.into_iter().collect();
+                let mut reassigned_results = stage.reassigned_results;
+                if cached_sample_entries.contains_key(&sample_file) {
+                    // 第四阶段：重新计算丰度
+                    let phase_start = Instant::now();
+                    recalculate_abundances_after_reassignment(&mut reassigned_results);
+                    timer.add("Abundance calculation", phase_start);
+                    
+                    eprintln!("{} has {} genomes passing profiling threshold after reassignment.", &sample_file, reassigned_results.len());
+
+                    if args.per_contig.is_some() {
+                        contig_rows.lock().unwrap().extend(contig_rows_from_results(&reassigned_results, &genome_mapping));
+                    }
+                    
+                    // 按基因组ID分组结果 - 修复：确保每个样本源都被正确处理
+                    for result in reassigned_results {
+                        if let Some((genome_id, _)) = genome_mapping.get(&result.contig_name) {
+                            // 关键修复：使用实际的样本源ID作为key的一部分
+                            let key = (genome_id.clone(), result.sample_file.clone());
+                            let mut all_results = all_results.lock().unwrap();
+                            let entry = all_results.entry(key)
+                                .or_insert_with(|| {
+                                    GenomeProfileResult {
+                                        genome_id: genome_id.clone(),
+                                        sample_id: result.sample_file.clone(), // 这里保存的是实际的样本源ID
+                                        adjusted_ani: 0.0,
+                                        taxonomic_abundance: 0.0,
+                                        sequence_abundance: 0.0,
+                                        common_tags: 0,
+                                        total_tags: 0,
+                                        eff_cov: 0.0,
+                                        breadth_bins: (0, 0),
+                                        shared_span: 0,
+                                    }
+                                });
+
+                            if let Some(&((covered, occupied), span)) = contig_breadth.get(&(result.sample_file.clone(), result.contig_name.clone())) {
+                                entry.breadth_bins.0 += covered;
+                                entry.breadth_bins.1 += occupied;
+                                entry.shared_span += span;
+                            }
+                            
+                            // 累加标签数；基因组的覆盖度是各contig覆盖度按参考标签数的加权平均，
+                            // 与整个基因组作为一个条目时的覆盖度一致
+                            let previous_tags = entry.total_tags;
+                            entry.common_tags += result.shared_tags;
+                            entry.total_tags += result.ref_tags;
+                            if entry.total_tags > 0 {
+                                entry.eff_cov = (entry.eff_cov * previous_tags as f64 + result.eff_cov * result.ref_tags as f64) / entry.total_tags as f64;
+                            }
+                            
+                            // 使用共享标签数作为权重计算加权平均ANI
+                            if entry.common_tags > 0 {
+                                entry.adjusted_ani = (entry.adjusted_ani * (entry.common_tags - result.shared_tags) as f64 
+                                    + result.adjusted_ani * result.shared_tags as f64) / entry.common_tags as f64;
+                            }
+                        }
+                    }
+                }
+            }
+            progress.sample_done();
+        };
+        match parallelism {
+            Parallelism::Samples => chunk.into_par_iter().for_each(process_sample),
+            Parallelism::Genomes => chunk.into_iter().for_each(process_sample),
+        }
+    });
+    
+    // 收集所有基因组ID
+    let phase_start = Instant::now();
+    progress.phase("aggregation", sample_files.len());
+    let mut all_genomes: HashSet<String> = HashSet::new();
+    for entry in genome_mapping.values() {
+        all_genomes.insert(entry.0.clone());
+    }
+
+    // 转换为向量以便排序和分组
+    let results: Vec<_> = all_results.lock().unwrap().values().cloned().collect();
+    
+    // 按样本分组计算丰度
+    let mut sample_groups: HashMap<String, Vec<GenomeProfileResult>> = HashMap::new();
+    for result in results {
+        // 使用实际的样本来源而不是文件名
+        sample_groups.entry(result.sample_id.clone())
+            .or_default()
+            .push(result);
+    }
+    
+    timer.add("Aggregation", phase_start);
+    
+    // 采用 sylph 的简单策略 - 顺序计算丰度，避免复杂的并行迭代器组合
+    let phase_start = Instant::now();
+    let breadth_genome_sizes = (thresholds.min_breadth > 0.0).then(|| genome_sizes_from_cache(&cached_db_entries, &genome_mapping));
+    if breadth_genome_sizes.as_ref().is_some_and(|sizes| sizes.values().any(|&size| size == 0)) {
+        eprintln!("Warning: some database genomes have no recorded genome size; --min-breadth is not applied to them");
+    }
+    for (sample_id, group) in sample_groups.iter_mut() {
+        // 按ANI排序（参考sylph的排序机制）
+        group.sort_by(|a, b| compare_by(a, b, "ani"));
+        
+        // 过滤掉不符合profile要求的genome
+        group.retain(|r| {
+            r.common_tags >= thresholds.min_shared_tags && 
+            r.eff_cov >= thresholds.min_coverage && 
+            r.adjusted_ani >= effective_min_ani &&
+            r.total_tags >= thresholds.min_tags_for_genome &&
+            breadth_genome_sizes.as_ref().is_none_or(|sizes| {
+                passes_min_breadth(r.shared_span, sizes.get(&r.genome_id).copied().unwrap_or(0), thresholds.min_breadth)
+            })
+        });
+        
+        normalize_abundances(group);
+
+        // EM：共享标签按当前丰度在候选基因组间按比例分配，取代上面的winner-take-all覆盖度
+        if let Some((tag_to_genomes, genome_tag_totals, sample_tag_counts)) = &em_inputs {
+            let candidates: FxHashSet<String> = group.iter().map(|r| r.genome_id.clone()).collect();
+            let empty = FxHashMap::default();
+            let observed = sample_tag_counts.get(sample_id).unwrap_or(&empty);
+            let (theta, _) = em_abundances(observed, tag_to_genomes, genome_tag_totals, &candidates, EM_MAX_ITERATIONS, EM_TOLERANCE);
+            em_group_abundances(group, &theta, genome_tag_totals);
+        }
+    }
+    // 丰度估计方法之外的后处理：按基因组大小换算为细胞数丰度
+    if args.size_normalize {
+        let genome_sizes = genome_sizes_from_cache(&cached_db_entries, &genome_mapping);
+        for group in sample_groups.values_mut() {
+            size_normalize_abundances(group, &genome_sizes);
+        }
+    }
+    let coverage_corrected_results = args.coverage_corrected_matrix.then(|| {
+        let genome_sizes = genome_sizes_from_cache(&cached_db_entries, &genome_mapping);
+        let mut corrected = sample_groups.clone();
+        for group in corrected.values_mut() {
+            coverage_correct_abundances(group, &genome_sizes);
+        }
+        corrected
+    });
+    if args.report_absent {
+        let mut genome_tag_totals: FxHashMap<String, usize> = FxHashMap::default();
+        for entry in &cached_db_entries {
+            if let Some((genome_id, _)) = genome_mapping.get(&entry.sequence_id) {
+                *genome_tag_totals.entry(genome_id.clone()).or_insert(0) += entry.tags.len();
+            }
+        }
+        let mut samples: Vec<String> = cached_sample_entries.values()
+            .flat_map(|entries| entries.iter().map(|entry| entry.sample_source.clone()))
+            .collect::<FxHashSet<_>>()
+            .into_iter()
+            .collect();
+        samples.sort();
+        add_absent_genomes(&mut sample_groups, &samples, &genome_tag_totals);
+    }
+    timer.add("Abundance calculation", phase_start);
+
+    // 检查是否提供了taxonomy文件以进行物种级别聚合
+    let mut pre_gscore_species_results = Vec::new();
+    let species_results = match &args.taxonomy_file {
+        Some(taxonomy_file) => {
+            eprintln!("Loading taxonomy information from: {}", taxonomy_file);
+            
+            // 读取分类学信息
+            let taxonomy_map = read_taxonomy_file(taxonomy_file, &args.taxonomy_format, &args.taxonomy_has_header, args.taxonomy_max_skipped)?;
+            
+            // 聚合到物种级别
+            let phase_start = Instant::now();
+            let mut species_results = aggregate_to_species_level(&sample_groups, &taxonomy_map, effective_min_ani, &thresholds, args.min_genomes_per_species, args.collapse_strains)?;
+            timer.add("Aggregation", phase_start);
+            pre_gscore_species_results = species_results.clone();
+            
+            // 应用 G-score 过滤
+            eprintln!("Applying G-score filtering with threshold: {:.2}", args.gscore_threshold);
+            species_results = filter_species_by_gscore(&mut species_results, args.gscore_threshold, args.gscore_pseudocount);
+            Some(species_results)
+        }
+        None => None,
+    };
+
+    // 没有taxonomy时的基因组矩阵：--missing-value na需要知道每个样本实际比对过哪些基因组
+    let evaluated = (species_results.is_none() && args.missing_value == "na").then(|| evaluated_genomes_per_sample(
+        &cached_db_entries,
+        &genome_mapping,
+        &cached_sample_entries,
+        &skipped_samples,
+        thresholds.min_tags_for_genome,
+    ));
+    // 矩阵的列来自sample_groups：没有检出任何基因组的样本和被跳过的样本也要占一列（分别为0和NA）
+    if let Some(evaluated) = &evaluated {
+        for sample_id in evaluated.keys() {
+            sample_groups.entry(sample_id.clone()).or_default();
+        }
+    }
+
+    if let Some(dump) = dump {
+        dump.finish()?;
+        eprintln!("Unfiltered hits saved to: {}", args.unfiltered_dump.as_deref().unwrap_or_default());
+    }
+    if let (Some(dir), Some(capture)) = (&args.shared_tags_dump, &shared_capture) {
+        write_shared_tags_dump(&output, dir, capture, &sample_groups, &genome_mapping, &cached_db_entries)?;
+        eprintln!("Shared tags of detected genomes saved to: {}", dir);
+    }
+
+    Ok(ProfileResults {
+        sample_files,
+        genome_results: sample_groups,
+        all_genomes,
+        pre_gscore_species_results,
+        species_results,
+        coverage_corrected_results,
+        evaluated,
+        contig_rows: contig_rows.into_inner().unwrap(),
+        sample_tag_totals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::write_syldb;
+    use crate::sketch::mm_hash64;
+    use crate::test_support::{genome_result, sylsp_entry, TempDir};
+
+    fn genome_sketch_at(hashes: &[Hash], c: usize) -> GenomeSketch {
+        let threshold = u64::MAX / c as u64;
+        GenomeSketch {
+            c,
+            k: 31,
+            genome_kmers: hashes.iter().copied().filter(|h| *h < threshold).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn sample_sketch_at(hashes: &[Hash], c: usize) -> SequencesSketch {
+        let threshold = u64::MAX / c as u64;
+        let mut sketch = SequencesSketch::new("sample".to_string(), c, 31, false, None, 0.0);
+        for hash in hashes.iter().filter(|h| **h < threshold) {
+            sketch.kmer_counts.insert(*hash, 1);
+        }
+        sketch
+    }
+
+    #[test]
+    fn test_reconcile_mixed_c_matches_uniform_c() {
+        let genome_hashes: Vec<Hash> = (0..200_000u64).map(mm_hash64).collect();
+        let sample_hashes: Vec<Hash> = (100_000..300_000u64).map(mm_hash64).collect();
+
+        let sample = sample_sketch_at(&sample_hashes, 200);
+        let mixed = reconcile_and_intersect(&genome_sketch_at(&genome_hashes, 100), &sample, None);
+        let uniform = reconcile_and_intersect(&genome_sketch_at(&genome_hashes, 200), &sample, None);
+
+        assert_eq!(mixed, uniform);
+        assert!(mixed.0 > 0);
+    }
+
+    #[test]
+    fn test_read_database_reports_syldb_errors_instead_of_sketch_fallback() {
+        let tmp = TempDir::new("read_database");
+        let db_path = tmp.join_str("future.syldb");
+        // 带格式头但版本比本程序新：应报告版本问题，而不是再按sketch格式读取失败
+        let mut data = SYLDB_MAGIC.to_vec();
+        data.push(SYLDB_FORMAT_VERSION + 1);
+        data.extend_from_slice(&[0; 16]);
+        std::fs::write(&db_path, &data).unwrap();
+        let Err(err) = read_database(BufReader::new(File::open(&db_path).unwrap()), &db_path) else {
+            panic!("expected an error");
+        };
+        assert!(format!("{:#}", err).contains("Unsupported .syldb format version"));
+
+        // 没有格式头的sketch数据库照常回退到sketch读取
+        let sketch_path = tmp.join_str("sketch.syldb");
+        let hashes: Vec<Hash> = (0..1000u64).map(mm_hash64).collect();
+        let sketches = vec![GenomeSketch { file_name: "GCF_A.fa".to_string(), gn_size: 1_000_000, ..genome_sketch_at(&hashes, 1) }];
+        bincode::serialize_into(File::create(&sketch_path).unwrap(), &sketches).unwrap();
+        let database = read_database(BufReader::new(File::open(&sketch_path).unwrap()), &sketch_path).unwrap();
+        assert!(matches!(database, Database::Sketches(sketches) if sketches.len() == 1));
+    }
+
+    #[test]
+    fn test_reconciled_c_only_raised_by_explicit_c() {
+        // 未给-c时不因默认值把c相同的一对额外稀释
+        assert_eq!(reconciled_c(50, 50, None), 50);
+        assert_eq!(reconciled_c(100, 200, None), 200);
+        assert_eq!(reconciled_c(100, 200, Some(150)), 200);
+        assert_eq!(reconciled_c(100, 200, Some(1000)), 1000);
+    }
+
+    #[test]
+    fn test_profile_against_sketch_database_reconciles_c() {
+        use clap::Parser;
+        let tmp = TempDir::new("profile_sketch");
+        let genome_a: Vec<Hash> = (0..200_000u64).map(mm_hash64).collect();
+        let genome_b: Vec<Hash> = (200_000..400_000u64).map(mm_hash64).collect();
+
+        let mut sample = sample_sketch_at(&genome_a, 200);
+        sample.kmer_counts.values_mut().for_each(|count| *count = 3);
+        sample.sample_name = Some("s1".to_string());
+        let sample_path = tmp.join_str("s1.sylsp");
+        let mut sample_file = File::create(&sample_path).unwrap();
+        crate::extract::write_sylsp_header(&mut sample_file, SylspKind::SingleSketch, "", 1).unwrap();
+        bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        drop(sample_file);
+
+        let run = |c: usize| -> Vec<(String, usize)> {
+            let sketches: Vec<GenomeSketch> = [("GCF_A.fa", &genome_a), ("GCF_B.fa", &genome_b)].iter()
+                .map(|(name, hashes)| GenomeSketch { file_name: name.to_string(), gn_size: 1_000_000, ..genome_sketch_at(hashes, c) })
+                .collect();
+            let db_path = tmp.join_str(format!("db_c{}.syldb", c));
+            bincode::serialize_into(File::create(&db_path).unwrap(), &sketches).unwrap();
+            let cli = crate::cmdline::Cli::try_parse_from([
+                "meta2bseek", "profile", "--sample-file", &sample_path, "--db-file", &db_path,
+                "--log-path", &tmp.join_str(""),
+            ]).unwrap();
+            let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+            compute_profile(&args, &PhaseTimer::new(), &Progress::default()).unwrap().genome_results["s1"].iter()
+                .map(|r| (r.genome_id.clone(), r.common_tags))
+                .collect()
+        };
+
+        // 数据库c=100、样本c=200时协调到c=200，与两边都是c=200的结果相同
+        let mixed = run(100);
+        assert_eq!(mixed.len(), 1);
+        assert_eq!(mixed[0].0, "GCF_A");
+        assert_eq!(mixed, run(200));
+
+        // 样本k-mer计数记在count上，每个k-mer只占一条
+        let (_, _, samples) = sketch_profile_inputs(&[genome_sketch_at(&genome_a, 200)], std::slice::from_ref(&sample_path)).unwrap();
+        let tags = &samples[&sample_path];
+        assert_eq!(tags.len(), sample.kmer_counts.len());
+        assert!(tags.iter().all(|tag| tag.count == 3 && tag.sample_source == "s1"));
+    }
+
+    fn db_entry(genome_source: &str) -> SyldbEntry {
+        SyldbEntry {
+            sequence_id: format!("{}_contig1", genome_source),
+            tags: vec![1, 2, 3],
+            positions: vec![0, 100, 200],
+            genome_source: genome_source.to_string(),
+            tag_uniqueness: None,
+            gn_size: 1000,
+            tag_sequences: None,
+            enzyme: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_exclude_one_of_three_genomes() {
+        let entries = vec![
+            db_entry("genomes/GCF_001.fna.gz"),
+            db_entry("genomes/GCF_002.fna.gz"),
+            db_entry("genomes/GCF_003.fna.gz"),
+        ];
+        let exclude: FxHashSet<String> = ["GCF_002".to_string()].into_iter().collect();
+        let kept = filter_db_entries(entries.clone(), None, Some(&exclude));
+        let kept_ids: Vec<&str> = kept.iter().map(|e| extract_genome_id_from_path(&e.genome_source)).collect();
+        assert_eq!(kept_ids, vec!["GCF_001", "GCF_003"]);
+
+        // include先于exclude生效
+        let include: FxHashSet<String> = ["GCF_001".to_string(), "GCF_002".to_string()].into_iter().collect();
+        let kept = filter_db_entries(entries, Some(&include), Some(&exclude));
+        assert_eq!(kept.len(), 1);
+        assert_eq!(extract_genome_id_from_path(&kept[0].genome_source), "GCF_001");
+    }
+
+    #[test]
+    fn test_winner_table_tie_is_deterministic() {
+        let mut entries = vec![db_entry("genomes/GCF_001.fna"), db_entry("genomes/GCF_002.fna")];
+        entries[0].sequence_id = "contig_b".to_string();
+        entries[1].sequence_id = "contig_a".to_string();
+
+        let results: Vec<QueryResult> = ["contig_b", "contig_a"]
+            .iter()
+            .map(|name| {
+                let mut result = calculate_statistics(3, 10, 3);
+                result.contig_name = name.to_string();
+                result
+            })
+            .collect();
+        assert_eq!(results[0].adjusted_ani, results[1].adjusted_ani);
+
+        // 每轮打乱results的顺序，共享标签总是分给genome_id字典序更小的contig_a
+        let mut state: u64 = 42;
+        for _ in 0..100 {
+            let mut shuffled = results.clone();
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            if state >> 63 == 1 {
+                shuffled.reverse();
+            }
+            let (winners, _) = build_winner_table(&shuffled, &entries, false);
+            assert_eq!(winners[&1].genome_id, "contig_a");
+            assert!(winners[&1].was_reassigned);
+        }
+    }
+
+    #[test]
+    fn test_timing_summary_lists_all_phases() {
+        let timer = PhaseTimer::new();
+        timer.add("DB load", Instant::now());
+        timer.add("Writing", Instant::now());
+        let summary = timer.summary();
+        for phase in PROFILE_PHASES {
+            assert!(summary.contains(phase), "missing phase {}", phase);
+        }
+        assert!(summary.contains("Total"));
+    }
+
+    #[test]
+    fn test_long_matrix_has_row_per_nonzero_pair() {
+        let sample_ids = vec!["s1".to_string(), "s2".to_string()];
+        let rows = vec![
+            (vec!["GCF_002".to_string()], vec![40.0, 0.0]),
+            (vec!["GCF_001".to_string()], vec![60.0, 100.0]),
+            (vec!["GCF_003".to_string()], vec![0.0, 0.0]),
+        ];
+        let mut out = Vec::new();
+        let written = write_long_abundance_rows(&mut out, &["Genome"], &sample_ids, &rows, 4, None).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(written, 3);
+        assert_eq!(lines.len(), written + 1);
+        assert_eq!(lines[0], "Sample\tGenome\tAbundance");
+        assert_eq!(lines[1], "s1\tGCF_001\t60.0000");
+        assert_eq!(lines[3], "s2\tGCF_001\t100.0000");
+    }
+
+    #[test]
+    fn test_rarefied_samples_have_equal_depth() {
+        let mut entries = Vec::new();
+        entries.extend((0..500u64).map(|t| sylsp_entry("deep", t % 50)));
+        entries.extend((0..120u64).map(|t| sylsp_entry("shallow", t % 30)));
+        entries.extend((0..40u64).map(|t| sylsp_entry("tiny", t)));
+
+        let entries = collapse_sample_entries(entries);
+
+        let (rarefied, dropped) = rarefy_sample_entries(entries.clone(), 100, 7);
+        assert_eq!(dropped, vec!["tiny".to_string()]);
+        let totals = observations_per_source(&rarefied);
+        assert_eq!(totals["deep"], 100);
+        assert_eq!(totals["shallow"], totals["deep"]);
+        // 保留的观测不超过原有计数
+        assert!(rarefied.iter().all(|e| e.count > 0 && e.count <= 10));
+
+        // 相同种子结果可复现
+        let (again, _) = rarefy_sample_entries(entries, 100, 7);
+        assert_eq!(rarefied, again);
+    }
+
+    #[test]
+    fn test_preview_fraction_subsamples_tags() {
+        let entries: Vec<SylspEntry> = (0..1000u64)
+            .map(|t| sylsp_entry("sample", t % 200))
+            .collect();
+        let entries = collapse_sample_entries(entries);
+        assert_eq!(entries.len(), 200);
+        assert!(entries.iter().all(|e| e.count == 5));
+
+        let full = preview_sample_entries(entries.clone(), 1.0, 42);
+        assert_eq!(full, entries);
+
+        let preview = preview_sample_entries(entries, 0.1, 42);
+        assert_eq!(observations_per_source(&preview)["sample"], 100);
+    }
+
+    #[test]
+    fn test_per_contig_rows_keep_contigs_separate() {
+        let mut genome_mapping = FxHashMap::default();
+        for contig in ["chromosome", "plasmid"] {
+            genome_mapping.insert(contig.to_string(), ("GCF_001".to_string(), "GCF_001.fna".to_string()));
+        }
+        let results: Vec<QueryResult> = [("chromosome", 80, 100), ("plasmid", 5, 20)]
+            .iter()
+            .map(|(contig, shared, total)| {
+                let mut result = calculate_statistics(*shared, 1000, *total);
+                result.contig_name = contig.to_string();
+                result.sample_file = "s1".to_string();
+                result.shared_tags = *shared;
+                result
+            })
+            .collect();
+
+        let rows = contig_rows_from_results(&results, &genome_mapping);
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|r| r.genome_id == "GCF_001"));
+        let plasmid = rows.iter().find(|r| r.contig_name == "plasmid").unwrap();
+        assert_eq!((plasmid.shared_tags, plasmid.total_tags), (5, 20));
+        let chromosome = rows.iter().find(|r| r.contig_name == "chromosome").unwrap();
+        assert_eq!((chromosome.shared_tags, chromosome.total_tags), (80, 100));
+    }
+
+    #[test]
+    fn test_em_splits_shared_tag_between_equal_genomes() {
+        let mut tag_to_genomes: FxHashMap<Hash, Vec<String>> = FxHashMap::default();
+        let mut observed: FxHashMap<Hash, usize> = FxHashMap::default();
+        for tag in 0..10u64 {
+            tag_to_genomes.insert(tag, vec!["A".to_string()]);
+            tag_to_genomes.insert(100 + tag, vec!["B".to_string()]);
+            observed.insert(tag, 1);
+            observed.insert(100 + tag, 1);
+        }
+        tag_to_genomes.insert(999, vec!["A".to_string(), "B".to_string()]);
+        observed.insert(999, 1);
+
+        let genome_tag_totals: FxHashMap<String, usize> =
+            [("A".to_string(), 11), ("B".to_string(), 11)].into_iter().collect();
+        let candidates: FxHashSet<String> = ["A".to_string(), "B".to_string()].into_iter().collect();
+
+        let (theta, expected) = em_abundances(&observed, &tag_to_genomes, &genome_tag_totals, &candidates, EM_MAX_ITERATIONS, EM_TOLERANCE);
+        // 共享标签各分一半，而不是整个归给其中一个
+        assert!((expected["A"] - 10.5).abs() < 1e-6);
+        assert!((expected["B"] - 10.5).abs() < 1e-6);
+        assert!((theta["A"] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_em_taxonomic_abundance_corrects_for_genome_size() {
+        // A有100个标签、B有400个，细胞数相同时每个标签各观测一次
+        let mut tag_to_genomes: FxHashMap<Hash, Vec<String>> = FxHashMap::default();
+        let mut observed: FxHashMap<Hash, usize> = FxHashMap::default();
+        for tag in 0..500u64 {
+            let genome = if tag < 100 { "A" } else { "B" };
+            tag_to_genomes.insert(tag, vec![genome.to_string()]);
+            observed.insert(tag, 1);
+        }
+        let genome_tag_totals: FxHashMap<String, usize> =
+            [("A".to_string(), 100), ("B".to_string(), 400)].into_iter().collect();
+        let candidates: FxHashSet<String> = ["A".to_string(), "B".to_string()].into_iter().collect();
+        let (theta, _) = em_abundances(&observed, &tag_to_genomes, &genome_tag_totals, &candidates, EM_MAX_ITERATIONS, EM_TOLERANCE);
+
+        let result = |genome_id: &str| genome_result(genome_id, "s1").tags(genome_tag_totals[genome_id], genome_tag_totals[genome_id]).eff_cov(1.0).build();
+        let mut group = vec![result("A"), result("B")];
+        em_group_abundances(&mut group, &theta, &genome_tag_totals);
+
+        // 序列丰度即标签观测比例，taxonomic丰度按基因组大小校正后相等
+        assert!((group[0].sequence_abundance - 20.0).abs() < 1e-6);
+        assert!((group[1].sequence_abundance - 80.0).abs() < 1e-6);
+        assert!((group[0].taxonomic_abundance - 50.0).abs() < 1e-6);
+        assert!((group[1].taxonomic_abundance - 50.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_empty_sample_is_skipped_without_panic() {
+        let entries: Vec<SylspEntry> = (0..50u64).map(|t| sylsp_entry("good", t))
+            .chain((0..2u64).map(|t| sylsp_entry("failed", t)))
+            .collect();
+
+        let (kept, skipped) = drop_small_samples(collapse_sample_entries(entries), 10);
+        assert_eq!(skipped, vec![("failed".to_string(), 2)]);
+        assert!(kept.iter().all(|e| e.sample_source == "good"));
+        assert_eq!(kept.len(), 50);
+
+        let (kept, skipped) = drop_small_samples(Vec::new(), 10);
+        assert!(kept.is_empty() && skipped.is_empty());
+        assert_eq!(average_abundance(&FxHashMap::default()), 0.0);
+    }
+
+    #[test]
+    fn test_species_without_abundances_prints_zero_not_nan() {
+        let species = SpeciesAbundanceResult {
+            taxonomy: Arc::new(TaxonomyInfo { genus: "Bacteroides".to_string(), ..Default::default() }),
+            sample_abundances: FxHashMap::default(),
+            sample_shared_tags: FxHashMap::default(),
+            total_tags: 0,
+            genome_count: 1,
+            reads_count: 0,
+            gscore: 0.0,
+            sample_representatives: FxHashMap::default(),
+        };
+        let row = format_species_summary_row(&species, None);
+        assert!(row.starts_with("Bacteroides_sp"));
+        assert!(row.trim_end().ends_with("0.0000"));
+        assert!(!row.contains("NaN"));
+    }
+
+    #[test]
+    fn test_sort_by_abundance_is_descending() {
+        let result = |genome_id: &str, ani: f64, abundance: f64| genome_result(genome_id, "s1").ani(ani).abundance(abundance).build();
+        let mut results = [result("A", 99.0, 10.0), result("B", 97.0, 60.0), result("C", 98.0, 30.0)];
+
+        results.sort_by(|a, b| compare_by(a, b, "abundance"));
+        let order: Vec<&str> = results.iter().map(|r| r.genome_id.as_str()).collect();
+        assert_eq!(order, vec!["B", "C", "A"]);
+
+        results.sort_by(|a, b| compare_by(a, b, "ani"));
+        let order: Vec<&str> = results.iter().map(|r| r.genome_id.as_str()).collect();
+        assert_eq!(order, vec!["A", "C", "B"]);
+    }
+
+    #[test]
+    fn test_containment_and_jaccard_columns() {
+        // 参考400个标签，样本1000个标签，共享100个：并集1300
+        let result = calculate_statistics(100, 1000, 400);
+        assert_eq!(result.containment, 0.25);
+        assert!((result.jaccard - 100.0 / 1300.0).abs() < 1e-12);
+        assert_eq!(result.containment_ind, "100/400");
+
+        let empty = calculate_statistics(0, 0, 400);
+        assert_eq!((empty.containment, empty.jaccard), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_lower_min_shared_tags_admits_shallow_hit() {
+        // 小参考序列的5个标签全部命中，但共享数低于默认阈值
+        let strict = FilterThresholds { min_tags_for_genome: 5, ..FilterThresholds::default() };
+        let relaxed = FilterThresholds { min_shared_tags: 2, ..strict };
+
+        let result = calculate_statistics_with(5, 1000, 5, strict.min_shared_tags);
+        assert!(!filter_results(&result, None, &strict, None));
+
+        let result = calculate_statistics_with(5, 1000, 5, relaxed.min_shared_tags);
+        assert!(result.adjusted_ani >= MIN_ANI);
+        assert!(filter_results(&result, None, &relaxed, None));
+    }
+
+    #[test]
+    fn test_breadth_separates_clustered_from_spread_tags() {
+        // 100个参考标签：前20个挤在同一个重复区窗口，其余散布在全长
+        let mut entry = db_entry("genome.fa");
+        entry.tags = (0..100).collect();
+        entry.positions = (0..100).map(|i| if i < 20 { 5_000 + i } else { i * 10_000 }).collect();
+
+        // 两个样本都命中20个标签：一个全在重复区，一个每隔5个命中一个
+        let clustered: FxHashSet<Hash> = (0..20).collect();
+        let spread: FxHashSet<Hash> = (0..100).step_by(5).collect();
+        let clustered_breadth = breadth_fraction(breadth_bins(&entry, |t| clustered.contains(t)));
+        let spread_breadth = breadth_fraction(breadth_bins(&entry, |t| spread.contains(t)));
+        assert!(clustered_breadth < 0.05);
+        assert!(spread_breadth > 0.15);
+        assert_eq!(breadth_fraction((0, 0)), 0.0);
+    }
+
+    #[test]
+    fn test_unwritable_output_fails_before_reading_inputs() {
+        use clap::Parser;
+        // 以普通文件作为输出目录：无论是否root运行都无法创建
+        let tmp = TempDir::new("not_a_dir");
+        let blocker = tmp.join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        let out = blocker.join("profile.txt");
+
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile",
+            "--sample-file", "missing.sylsp",
+            "--db-file", "missing.syldb",
+            "--out-file-name", out.to_str().unwrap(),
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+        let err = profile(args).unwrap_err();
+
+        // 报的是输出问题，而不是找不到输入文件
+        let message = format!("{:#}", err);
+        assert!(message.contains("Output check failed"), "{}", message);
+        assert!(!message.contains("missing.sylsp"), "{}", message);
+    }
+
+    #[test]
+    fn test_query_checks_output_and_temp_dir_before_reading_inputs() {
+        use clap::Parser;
+        let tmp = TempDir::new("query_outputs");
+        let blocker = tmp.join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        let writable_out = tmp.join("query.tsv");
+        let query_err = |extra: &[&str]| {
+            let mut argv = vec!["meta2bseek", "query", "missing.syldb", "missing.sylsp"];
+            argv.extend_from_slice(extra);
+            let cli = crate::cmdline::Cli::try_parse_from(argv).unwrap();
+            let crate::cmdline::Mode::Query(args) = cli.mode else { panic!("expected query") };
+            format!("{:#}", query(args).unwrap_err())
+        };
+
+        let unwritable_out = blocker.join("query.tsv");
+        for extra in [
+            ["-o", unwritable_out.to_str().unwrap()],
+            ["--unfiltered-dump", unwritable_out.to_str().unwrap()],
+        ] {
+            let message = query_err(&extra);
+            assert!(message.contains("Output check failed"), "{}", message);
+            assert!(!message.contains("missing.syldb"), "{}", message);
+        }
+
+        let unwritable_temp = blocker.join("tmp");
+        let message = query_err(&["-o", writable_out.to_str().unwrap(), "--temp-dir", unwritable_temp.to_str().unwrap()]);
+        assert!(message.contains("Output check failed"), "{}", message);
+        assert!(!writable_out.exists());
+    }
+
+    #[test]
+    fn test_ncbi_and_gtdb_lineages_parse_to_same_taxonomy() {
+        let gtdb = TaxonomyInfo::parse(
+            &["d__Bacteria;p__Pseudomonadota;c__Gammaproteobacteria;o__Enterobacterales;f__Enterobacteriaceae;g__Escherichia;s__Escherichia coli"],
+            "gtdb",
+        ).unwrap();
+        let ncbi = TaxonomyInfo::parse(
+            &["Bacteria;Pseudomonadota;Gammaproteobacteria;Enterobacterales;Enterobacteriaceae;Escherichia;Escherichia coli"],
+            "ncbi",
+        ).unwrap();
+        let labelled = TaxonomyInfo::parse(
+            &["superkingdom:Bacteria;phylum:Pseudomonadota;class:Gammaproteobacteria;order:Enterobacterales;family:Enterobacteriaceae;genus:Escherichia;species:Escherichia coli;strain:K-12"],
+            "ncbi",
+        ).unwrap();
+        assert_eq!(ncbi, gtdb);
+        assert_eq!(labelled, gtdb);
+        assert_eq!(gtdb.kingdom, "Bacteria");
+
+        // 缺失的级别留空
+        let partial = TaxonomyInfo::parse(&["Bacteria;Pseudomonadota"], "ncbi").unwrap();
+        assert_eq!(partial.phylum, "Pseudomonadota");
+        assert!(partial.species.is_empty());
+    }
+
+    #[test]
+    fn test_species_with_single_detected_genome_is_suppressed() {
+        let genome = |genome_id: &str| genome_result(genome_id, "s1").abundance(50.0).build();
+        let taxonomy = |species: &str| Arc::new(TaxonomyInfo::from_ranks(&["Bacteria", "", "", "", "", "", species]));
+        let mut taxonomy_map: FxHashMap<String, Arc<TaxonomyInfo>> = FxHashMap::default();
+        taxonomy_map.insert("G1".to_string(), taxonomy("single"));
+        taxonomy_map.insert("G2".to_string(), taxonomy("paired"));
+        taxonomy_map.insert("G3".to_string(), taxonomy("paired"));
+
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome("G1"), genome("G2"), genome("G3")]);
+        let thresholds = FilterThresholds::default();
+
+        let all = aggregate_to_species_level(&sample_groups, &taxonomy_map, 95.0, &thresholds, 1, false).unwrap();
+        assert_eq!(all.len(), 2);
+
+        let strict = aggregate_to_species_level(&sample_groups, &taxonomy_map, 95.0, &thresholds, 2, false).unwrap();
+        assert_eq!(strict.len(), 1);
+        assert_eq!(strict[0].taxonomy.species, "paired");
+        assert_eq!(strict[0].genome_count, 2);
+    }
+
+    #[test]
+    fn test_unevaluated_genome_is_na_and_filtered_genome_is_zero() {
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome_result("G1", "s1").abundance(100.0).build()]);
+        let all_genomes: HashSet<String> = ["G1", "G2", "G3"].iter().map(|g| g.to_string()).collect();
+        // G2比对过但被过滤；G3从未比对（例如标签数低于--min-number-kmers）
+        let mut evaluated: FxHashMap<String, FxHashSet<String>> = FxHashMap::default();
+        evaluated.insert("s1".to_string(), ["G1", "G2"].iter().map(|g| g.to_string()).collect());
+
+        let tmp = TempDir::new("na_matrix");
+
+        let dir = tmp.path();
+        let mut sink: Box<dyn Write + Send> = Box::new(std::io::sink());
+        write_abundance_matrix(&OutputOptions::overwriting(), &sample_groups, &all_genomes, Some(&evaluated), None, Some(dir.to_string_lossy().to_string()), "matrix.tsv", false, "relative", None, GENOME_ABUNDANCE_PRECISION, Some(&mut sink)).unwrap();
+        let content = std::fs::read_to_string(dir.join("matrix.tsv")).unwrap();
+
+        assert!(content.lines().any(|l| l == "G1\t100.0000"));
+        assert!(content.lines().any(|l| l == "G2\t0.0000"));
+        assert!(content.lines().any(|l| l == "G3\tNA"));
+    }
+
+    #[test]
+    fn test_parallel_taxonomy_parsing_matches_serial() {
+        let mut content = String::from("accession\tgtdb_taxonomy\tcheckm_completeness\n");
+        for i in 0..10_000 {
+            let prefix = ["RS_", "GB_", ""][i % 3];
+            // 每隔一段重复出现一次accession，检验合并后仍以后出现的行为准
+            let accession = format!("{}GCF_{:09}.1", prefix, i % 9_000);
+            content.push_str(&format!(
+                "{}\td__Bacteria;p__P{};c__C;o__O;f__F;g__G{};s__G{} sp{}\t99.0\n",
+                accession, i % 7, i % 50, i % 50, i
+            ));
+        }
+        content.push_str("# comment\n\nbroken_line\n");
+
+        let (serial, serial_stats) = parse_taxonomy_content(&content, "gtdb", "auto", false);
+        let (parallel, parallel_stats) = parse_taxonomy_content(&content, "gtdb", "auto", true);
+        assert_eq!(serial_stats, TaxonomyParseStats { parsed: 10_000, skipped: 1 });
+        assert_eq!(parallel_stats, serial_stats);
+        assert_eq!(serial.len(), 18_000);
+        assert_eq!(parallel.len(), serial.len());
+        for (genome_id, taxonomy) in &serial {
+            assert_eq!(parallel[genome_id].as_ref(), taxonomy.as_ref(), "{}", genome_id);
+        }
+
+        let base = &parallel["GCF_000000001.1"];
+        assert_eq!(base.species, "G1 sp9001");
+        assert!(Arc::ptr_eq(base, &parallel["GCF_000000001.1_genomic"]));
+    }
+
+    #[test]
+    fn test_size_normalize_favours_smaller_genome() {
+        let result = |genome_id: &str| genome_result(genome_id, "s1").abundance(50.0).tags(500, 1000).eff_cov(2.0).build();
+        // 两个基因组覆盖完全相同，只是大小相差3倍
+        let mut group = vec![result("small"), result("large")];
+        let genome_sizes: FxHashMap<String, usize> = [("small".to_string(), 1_000_000), ("large".to_string(), 3_000_000)]
+            .into_iter()
+            .collect();
+
+        size_normalize_abundances(&mut group, &genome_sizes);
+
+        assert!((group[0].taxonomic_abundance - 75.0).abs() < 1e-9);
+        assert!((group[1].taxonomic_abundance - 25.0).abs() < 1e-9);
+        assert_eq!(group[0].sequence_abundance, 50.0);
+
+        // 缺少gn_size时不做换算
+        let mut unsized_group = vec![result("small"), result("unknown")];
+        size_normalize_abundances(&mut unsized_group, &genome_sizes);
+        assert_eq!(unsized_group[0].taxonomic_abundance, 50.0);
+    }
+
+    #[test]
+    fn test_disjoint_tags_give_diagnostic_and_no_nan() {
+        let db_tags: FxHashSet<Hash> = [1, 2, 3].into_iter().collect();
+        let disjoint = collapse_sample_entries(vec![sylsp_entry("s1", 7), sylsp_entry("s1", 8), sylsp_entry("s1", 8)]);
+        assert_eq!(count_shared_tags(&db_tags, &disjoint), 0);
+        let overlapping = collapse_sample_entries(vec![sylsp_entry("s1", 2), sylsp_entry("s1", 2), sylsp_entry("s1", 9)]);
+        assert_eq!(count_shared_tags(&db_tags, &overlapping), 1);
+
+        let message = zero_shared_tags_error("profile", 1).to_string();
+        assert!(message.contains("--enzyme"));
+        assert!(message.contains("meta2bseek inspect"));
+
+        let mut group = vec![genome_result("g1", "s1").ani(0.0).tags(0, 100).eff_cov(0.0).build()];
+        normalize_abundances(&mut group);
+        assert_eq!(group[0].taxonomic_abundance, 0.0);
+        assert_eq!(group[0].sequence_abundance, 0.0);
+    }
+
+    #[test]
+    fn test_cpm_sums_to_million_and_raw_is_common_tags() {
+        let result = |genome_id: &str, sample_id: &str, common_tags: usize| genome_result(genome_id, sample_id).abundance(50.0).tags(common_tags, 1000).build();
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![result("G1", "s1", 30), result("G2", "s1", 90)]);
+        sample_groups.insert("s2".to_string(), vec![result("G1", "s2", 7)]);
+        let all_genomes: HashSet<String> = ["G1", "G2"].iter().map(|g| g.to_string()).collect();
+
+        let tmp = TempDir::new("units_matrix");
+
+        let dir = tmp.path();
+        let mut sink: Box<dyn Write + Send> = Box::new(std::io::sink());
+        let mut read_columns = |units: &str| -> Vec<Vec<f64>> {
+            write_abundance_matrix(&OutputOptions::overwriting(), &sample_groups, &all_genomes, None, None, Some(dir.to_string_lossy().to_string()), "matrix.tsv", false, units, None, GENOME_ABUNDANCE_PRECISION, Some(&mut sink)).unwrap();
+            let content = std::fs::read_to_string(dir.join("matrix.tsv")).unwrap();
+            let mut rows: Vec<&str> = content.lines().skip(1).collect();
+            rows.sort();
+            // 每行：基因组, s1, s2
+            rows.iter().map(|l| l.split('\t').skip(1).map(|v| v.parse().unwrap()).collect()).collect()
+        };
+
+        let cpm = read_columns("cpm");
+        for sample in 0..2 {
+            let total: f64 = cpm.iter().map(|row| row[sample]).sum();
+            assert!((total - 1_000_000.0).abs() < 1e-3, "{}", total);
+        }
+        assert!((cpm[0][0] - 250_000.0).abs() < 1e-3);
+
+        let raw = read_columns("raw");
+        assert_eq!(raw, vec![vec![30.0, 7.0], vec![90.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_host_tags_are_subtracted_from_sample() {
+        let entries: Vec<SylspEntry> = (0..100).map(|tag| sylsp_entry("s1", tag)).collect();
+        // 一半标签（偶数）来自宿主
+        let host_tags: FxHashSet<Hash> = (0..100).filter(|t| t % 2 == 0).collect();
+
+        let (kept, host_fractions) = subtract_host_tags(collapse_sample_entries(entries), &host_tags);
+        assert_eq!(kept.len(), 50);
+        assert!(kept.iter().all(|e| e.tag % 2 == 1));
+        assert_eq!(host_fractions, vec![("s1".to_string(), 50, 100)]);
+    }
+
+    #[test]
+    fn test_run_id_prefixes_every_long_row() {
+        let sample_ids = vec!["s1".to_string(), "s2".to_string()];
+        let rows = vec![
+            (vec!["GCF_001".to_string()], vec![60.0, 100.0]),
+            (vec!["GCF_002".to_string()], vec![40.0, 0.0]),
+        ];
+        let mut out = Vec::new();
+        let written = write_long_abundance_rows(&mut out, &["Genome"], &sample_ids, &rows, 4, Some("batch7")).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "Run\tSample\tGenome\tAbundance");
+        assert_eq!(lines.len(), written + 1);
+        assert!(lines[1..].iter().all(|l| l.starts_with("batch7\t") && l.split('\t').count() == 4));
+        assert_eq!(lines[1], "batch7\ts1\tGCF_001\t60.0000");
+    }
+
+    #[test]
+    fn test_winner_table_overlap_counts_match_two_pass() {
+        let mut entries = vec![
+            db_entry("genomes/GCF_001.fna"),
+            db_entry("genomes/GCF_002.fna"),
+            db_entry("genomes/GCF_003.fna"),
+        ];
+        for (entry, (name, tags)) in entries.iter_mut().zip([
+            ("contig_a", vec![1, 2, 3, 4]),
+            ("contig_b", vec![3, 4, 5]),
+            ("contig_c", vec![4, 6]),
+        ]) {
+            entry.sequence_id = name.to_string();
+            entry.tags = tags;
+        }
+        // 同一contig在两个样本中各出现一次，不应计为共享
+        let results: Vec<QueryResult> = [("contig_a", 0.99), ("contig_b", 0.97), ("contig_c", 0.98), ("contig_a", 0.95)]
+            .iter()
+            .map(|&(name, ani)| {
+                let mut result = calculate_statistics(3, 10, 3);
+                result.contig_name = name.to_string();
+                result.adjusted_ani = ani;
+                result
+            })
+            .collect();
+
+        // 原来的两遍做法：先统计每个标签出现在多少个基因组中
+        let mut tag_counts: FxHashMap<Hash, FxHashSet<&str>> = FxHashMap::default();
+        for result in &results {
+            let entry = entries.iter().find(|e| e.sequence_id == result.contig_name).unwrap();
+            for tag in &entry.tags {
+                tag_counts.entry(*tag).or_default().insert(result.contig_name.as_str());
+            }
+        }
+        let expected = WinnerTableStats {
+            tags: tag_counts.len(),
+            contested: tag_counts.values().filter(|genomes| genomes.len() > 1).count(),
+        };
+
+        let (winners, stats) = build_winner_table(&results, &entries, false);
+        assert_eq!(stats, expected);
+        assert_eq!(stats, WinnerTableStats { tags: 6, contested: 2 });
+        assert_eq!(winners.len(), stats.tags);
+        assert_eq!(winners.values().filter(|w| w.was_reassigned).count(), stats.contested);
+        assert_eq!(winners[&4].genome_id, "contig_a");
+    }
+
+    #[test]
+    fn test_precision_eight_gives_eight_decimals() {
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome_result("G1", "s1").abundance(100.0 / 3.0).build()]);
+        let all_genomes: HashSet<String> = ["G1".to_string()].into_iter().collect();
+
+        let tmp = TempDir::new("precision_matrix");
+
+        let dir = tmp.path();
+        let mut sink: Box<dyn Write + Send> = Box::new(std::io::sink());
+        write_abundance_matrix(&OutputOptions::overwriting(), &sample_groups, &all_genomes, None, None, Some(dir.to_string_lossy().to_string()), "matrix.tsv", false, "relative", None, 8, Some(&mut sink)).unwrap();
+        let content = std::fs::read_to_string(dir.join("matrix.tsv")).unwrap();
+
+        assert!(content.lines().any(|l| l == "G1\t33.33333333"), "{}", content);
+        assert_eq!(format_abundance(1.0 / 3.0, GENOME_ABUNDANCE_PRECISION), "0.3333");
+    }
+
+    #[test]
+    fn test_unfiltered_dump_keeps_rejected_hits_with_reason() {
+        let thresholds = FilterThresholds { min_tags_for_genome: 5, ..FilterThresholds::default() };
+        let tmp = TempDir::new("unfiltered_dump");
+        let path = tmp.join("unfiltered_dump.tsv");
+        let dump = UnfilteredDump::create(&OutputOptions::default(), path.to_str().unwrap()).unwrap();
+
+        let hits = [
+            ("strong", calculate_statistics_with(400, 1000, 400, thresholds.min_shared_tags)),
+            ("shallow", calculate_statistics_with(5, 1000, 5, thresholds.min_shared_tags)),
+            ("absent", calculate_statistics_with(0, 1000, 400, thresholds.min_shared_tags)),
+        ];
+        let mut passed = Vec::new();
+        for (name, mut result) in hits {
+            result.contig_name = name.to_string();
+            if filter_results(&result, None, &thresholds, Some(&dump)) {
+                passed.push(name);
+            }
+        }
+        assert_eq!(passed, vec!["strong"]);
+
+        dump.finish().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        // 过滤后的输出只有strong，dump里三条都在并注明原因
+        let status: FxHashMap<&str, &str> = content.lines().skip(1)
+            .map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                (fields[2], fields[8])
+            })
+            .collect();
+        assert_eq!(status.len(), 3);
+        assert_eq!(status["strong"], "pass");
+        assert_eq!(status["shallow"], "min_shared_tags");
+        assert_eq!(status["absent"], "no_shared_tags");
+    }
+
+    #[test]
+    fn test_compute_profile_returns_structs_without_writing() {
+        use clap::Parser;
+        let tmp = TempDir::new("profile_lib");
+        let dir = tmp.path();
+
+        // 样本含有GCF_001的全部标签，与GCF_002没有共享
+        let mut present = db_entry("genomes/GCF_001.fasta");
+        present.tags = (0..200).collect();
+        present.positions = (0..200).map(|i| i * 1000).collect();
+        let mut absent = db_entry("genomes/GCF_002.fasta");
+        absent.tags = (1000..1200).collect();
+        absent.positions = present.positions.clone();
+        let db_path = dir.join("db.syldb");
+        write_syldb(&mut File::create(&db_path).unwrap(), 1, DbGranularity::Contig, &[present, absent]).unwrap();
+
+        let sample: Vec<SylspEntry> = (0..200)
+            .map(|tag| sylsp_entry("s1", tag))
+            .collect();
+        let sample_path = dir.join("s1.sylsp");
+        let mut sample_file = File::create(&sample_path).unwrap();
+        crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, "", 1).unwrap();
+        bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        drop(sample_file);
+
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile",
+            "--sample-file", sample_path.to_str().unwrap(),
+            "--db-file", db_path.to_str().unwrap(),
+            "--log-path", dir.to_str().unwrap(),
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+        let results = compute_profile(&args, &PhaseTimer::new(), &Progress::default()).unwrap();
+        let matrix_written = dir.join(&args.tsv_name).exists();
+
+        assert!(!matrix_written);
+        assert!(results.species_results.is_none());
+        assert_eq!(results.all_genomes.len(), 2);
+        let hits = &results.genome_results["s1"];
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].genome_id, "GCF_001");
+        assert_eq!(hits[0].common_tags, 200);
+        assert!((hits[0].taxonomic_abundance - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_profile_rejects_sample_with_different_subsample_rate() {
+        use clap::Parser;
+        let tmp = TempDir::new("profile_rate");
+        let dir = tmp.path();
+
+        let mut genome = db_entry("genomes/GCF_001.fasta");
+        genome.tags = (0..50).collect();
+        genome.positions = (0..50).map(|i| i * 1000).collect();
+        let db_path = dir.join("db.syldb");
+        write_syldb(&mut File::create(&db_path).unwrap(), 4, DbGranularity::Contig, &[genome]).unwrap();
+
+        let sample: Vec<SylspEntry> = (0..50)
+            .map(|tag| sylsp_entry("s1", tag))
+            .collect();
+        let sample_path = dir.join("s1.sylsp");
+        let write_sample = |rate: u64| {
+            let mut sample_file = File::create(&sample_path).unwrap();
+            crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, "", rate).unwrap();
+            bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        };
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile",
+            "--sample-file", sample_path.to_str().unwrap(),
+            "--db-file", db_path.to_str().unwrap(),
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+
+        write_sample(1);
+        let err = compute_profile(&args, &PhaseTimer::new(), &Progress::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("--subsample-rate 4"), "{:#}", err);
+
+        write_sample(4);
+        assert!(compute_profile(&args, &PhaseTimer::new(), &Progress::default()).is_ok());
+    }
+
+    #[test]
+    fn test_profile_and_query_reject_sample_from_other_enzyme() {
+        use clap::Parser;
+        let tmp = TempDir::new("enzyme_mismatch");
+
+        let mut genome = db_entry("genomes/GCF_001.fasta");
+        genome.tags = (0..50).collect();
+        genome.positions = (0..50).map(|i| i * 1000).collect();
+        genome.enzyme = "BcgI".to_string();
+        let db_path = tmp.join_str("db.syldb");
+        write_syldb(&mut File::create(&db_path).unwrap(), 1, DbGranularity::Contig, &[genome]).unwrap();
+
+        let sample: Vec<SylspEntry> = (0..50)
+            .map(|tag| sylsp_entry("s1", tag))
+            .collect();
+        let sample_path = tmp.join_str("s1.sylsp");
+        let write_sample = |enzyme: &str| {
+            let mut sample_file = File::create(&sample_path).unwrap();
+            crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, enzyme, 1).unwrap();
+            bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        };
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile", "--sample-file", &sample_path, "--db-file", &db_path,
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(profile_args) = cli.mode else { panic!("expected profile") };
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "query", &db_path, &sample_path,
+        ]).unwrap();
+        let crate::cmdline::Mode::Query(query_args) = cli.mode else { panic!("expected query") };
+
+        write_sample("CspCI");
+        let err = compute_profile(&profile_args, &PhaseTimer::new(), &Progress::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("enzyme BcgI"), "{:#}", err);
+        let err = query_to_results(&query_args).unwrap_err();
+        assert!(format!("{:#}", err).contains("extracted with CspCI"), "{:#}", err);
+
+        write_sample("BcgI");
+        assert!(compute_profile(&profile_args, &PhaseTimer::new(), &Progress::default()).is_ok());
+        assert!(query_to_results(&query_args).is_ok());
+
+        // 未记录酶的旧样本无法判断，照常处理
+        write_sample("");
+        assert!(compute_profile(&profile_args, &PhaseTimer::new(), &Progress::default()).is_ok());
+    }
+
+    #[test]
+    fn test_extract_then_profile_with_different_threads_in_one_process() {
+        use clap::Parser;
+        let tmp = TempDir::new("two_modes");
+        let site = "ACGTTGCAACCGAGATTCATGCACGTTGCAA";
+        std::fs::write(tmp.join("GCF_001.fa"), format!(">c1\nTTTT{}TTTT\n", site)).unwrap();
+        let seq = format!("GGGG{}T", site);
+        std::fs::write(tmp.join("s1.fq"), format!("@r1\n{}\n+\n{}\n", seq, "I".repeat(seq.len()))).unwrap();
+
+        // 同一进程先后运行两个模式，各自的-t不同：第二个模式复用已有的线程池，不会失败
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "extract", "-g", &tmp.join_str("GCF_001.fa"), "-r", &tmp.join_str("s1.fq"),
+            "--sample-output-dir", &tmp.join_str("out"), "--format", "fa", "-t", "2",
+        ]).unwrap();
+        let crate::cmdline::Mode::Extract(extract_args) = cli.mode else { panic!("expected extract") };
+        crate::extract::extract(extract_args).unwrap();
+
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile", "--sample-file", &tmp.join_str("out/reads.sylsp"), "--db-file", &tmp.join_str("out/combined.syldb"),
+            "--log-path", &tmp.join_str("out"), "--min-shared-tags", "1", "--threads", "3",
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(profile_args) = cli.mode else { panic!("expected profile") };
+        assert!(compute_profile(&profile_args, &PhaseTimer::new(), &Progress::default()).is_ok());
+    }
+
+    #[test]
+    fn test_min_tag_depth_drops_singleton_supported_genome() {
+        let thresholds = FilterThresholds { min_shared_tags: 5, min_tags_for_genome: 5, ..FilterThresholds::default() };
+        let genome = |name: &str, tags: std::ops::Range<Hash>| SyldbEntry {
+            sequence_id: name.to_string(),
+            tags: tags.clone().collect(),
+            positions: (0..tags.clone().count()).collect(),
+            genome_source: format!("{}.fa", name),
+            tag_uniqueness: None,
+            gn_size: 10_000,
+            tag_sequences: None,
+            enzyme: String::new(),
+        };
+        let db = vec![genome("deep", 0..10), genome("singleton", 100..110)];
+
+        // deep的每个tag出现两次，singleton的tag各只出现一次
+        let mut entries = Vec::new();
+        for tag in (0..10).chain(0..10).chain(100..110) {
+            entries.push(sylsp_entry("s1", tag));
+        }
+        let mut cached = FxHashMap::default();
+        cached.insert("s1.sylsp".to_string(), collapse_sample_entries(entries));
+
+        let hits = |min_tag_depth: usize| -> Vec<String> {
+            let thresholds = FilterThresholds { min_tag_depth, ..thresholds };
+            let mut hits: Vec<String> = query_single_file_with_cached_db("s1.sylsp", "db.syldb", &db, &cached, 90.0, &thresholds, None, None, Parallelism::Genomes)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.contig_name)
+                .collect();
+            hits.sort();
+            hits
+        };
+        assert_eq!(hits(1), vec!["deep", "singleton"]);
+        assert_eq!(hits(2), vec!["deep"]);
+    }
+
+    #[test]
+    fn test_shared_tags_dump_count_matches_common_tags() {
+        let thresholds = FilterThresholds { min_shared_tags: 5, min_tags_for_genome: 5, ..FilterThresholds::default() };
+        let contig = |name: &str, tags: std::ops::Range<Hash>, store_seq: bool| SyldbEntry {
+            sequence_id: name.to_string(),
+            tags: tags.clone().collect(),
+            positions: (0..tags.clone().count()).collect(),
+            genome_source: "refs/G1.fasta".to_string(),
+            tag_uniqueness: None,
+            gn_size: 10_000,
+            tag_sequences: store_seq.then(|| tags.map(|t| format!("SEQ{}", t)).collect()),
+            enzyme: String::new(),
+        };
+        let db = vec![contig("G1_c1", 0..10, true), contig("G1_c2", 10..20, false)];
+        let entries: Vec<SylspEntry> = (0..6).chain(10..18)
+            .map(|tag| sylsp_entry("s1", tag))
+            .collect();
+        let mut cached = FxHashMap::default();
+        cached.insert("s1.sylsp".to_string(), collapse_sample_entries(entries));
+
+        let capture = SharedTagCapture::new();
+        let results = query_single_file_with_cached_db("s1.sylsp", "db.syldb", &db, &cached, 0.0, &thresholds, None, Some(&capture), Parallelism::Genomes).unwrap();
+        let (winners, _) = build_winner_table(&results, &db, false);
+        capture.keep_winners(&results, &winners, &db);
+
+        let genome_mapping = build_genome_mapping_from_cache(&db);
+        let common_tags: usize = results.iter().map(|r| r.shared_tags).sum();
+        assert_eq!(common_tags, 14);
+        let mut genome_results = HashMap::new();
+        genome_results.insert("s1".to_string(), vec![genome_result("G1", "s1").abundance(100.0).tags(common_tags, 20).eff_cov(0.7).build()]);
+
+        let tmp = TempDir::new("shared_tags");
+
+        let dir = tmp.path();
+        write_shared_tags_dump(&OutputOptions::default(), dir.to_str().unwrap(), &capture, &genome_results, &genome_mapping, &db).unwrap();
+        let content = std::fs::read_to_string(dir.join("s1.shared_tags.tsv")).unwrap();
+
+        let rows: Vec<Vec<&str>> = content.lines().skip(1).map(|l| l.split('\t').collect()).collect();
+        assert_eq!(rows.iter().filter(|r| r[0] == "G1").count(), common_tags);
+        assert_eq!(rows[0], vec!["G1", "G1_c1", "0", "SEQ0"]);
+        assert_eq!(rows[13], vec!["G1", "G1_c2", "17", "NA"]);
+    }
+
+    #[test]
+    fn test_profile_parallelism_levels_give_identical_results() {
+        let thresholds = FilterThresholds { min_shared_tags: 3, min_tags_for_genome: 5, ..FilterThresholds::default() };
+        let db: Vec<SyldbEntry> = (0..20u64)
+            .map(|g| SyldbEntry {
+                sequence_id: format!("G{}_c1", g),
+                tags: (g * 10..g * 10 + 10).collect(),
+                positions: (0..10).collect(),
+                genome_source: format!("G{}.fa", g),
+                tag_uniqueness: None,
+                gn_size: 10_000,
+                tag_sequences: None,
+                enzyme: String::new(),
+            })
+            .collect();
+        // 两个样本源，各自命中一部分基因组的一部分标签
+        let entries: Vec<SylspEntry> = (0..200u64)
+            .filter(|tag| tag % 3 != 0)
+            .map(|tag| sylsp_entry(if tag % 2 == 0 { "s1" } else { "s2" }, tag))
+            .collect();
+        let mut cached = FxHashMap::default();
+        cached.insert("combined.sylsp".to_string(), collapse_sample_entries(entries));
+
+        let run = |parallelism: Parallelism| -> Vec<(String, String, usize, String)> {
+            let mut rows: Vec<_> = query_single_file_with_cached_db("combined.sylsp", "db.syldb", &db, &cached, 0.0, &thresholds, None, None, parallelism)
+                .unwrap()
+                .into_iter()
+                .map(|r| (r.sample_file, r.contig_name, r.shared_tags, format!("{:.6}", r.adjusted_ani)))
+                .collect();
+            rows.sort();
+            rows
+        };
+        let by_samples = run(Parallelism::Samples);
+        assert!(!by_samples.is_empty());
+        assert_eq!(by_samples, run(Parallelism::Genomes));
+
+        assert_eq!(Parallelism::for_profile("auto", 8, 4), Parallelism::Samples);
+        assert_eq!(Parallelism::for_profile("auto", 1, 4), Parallelism::Genomes);
+        assert_eq!(Parallelism::for_profile("genomes", 8, 4), Parallelism::Genomes);
+    }
+
+    #[test]
+    fn test_skipped_sample_is_na_in_genome_matrix() {
+        use clap::Parser;
+        let tmp = TempDir::new("skipped_na");
+
+        let mut genome = db_entry("genomes/GCF_001.fasta");
+        genome.tags = (0..200).collect();
+        genome.positions = (0..200).map(|i| i * 1000).collect();
+        let db_path = tmp.join_str("db.syldb");
+        write_syldb(&mut File::create(&db_path).unwrap(), 1, DbGranularity::Contig, &[genome]).unwrap();
+
+        // s1正常检出；s2标签太少被--min-sample-tags跳过；s3比对过但没有检出任何基因组
+        let sample: Vec<SylspEntry> = (0..200).map(|tag| sylsp_entry("s1", tag))
+            .chain((0..5).map(|tag| sylsp_entry("s2", tag)))
+            .chain((5000..5050).map(|tag| sylsp_entry("s3", tag)))
+            .collect();
+        let sample_path = tmp.join_str("samples.sylsp");
+        let mut sample_file = File::create(&sample_path).unwrap();
+        crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, "", 1).unwrap();
+        bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        drop(sample_file);
+
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile", "--sample-file", &sample_path, "--db-file", &db_path,
+            "--log-path", &tmp.join_str(""), "--min-sample-tags", "10", "--missing-value", "na",
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+        profile(args).unwrap();
+
+        let matrix = std::fs::read_to_string(tmp.join("abundance_matrix.tsv")).unwrap();
+        let mut lines = matrix.lines();
+        assert_eq!(lines.next().unwrap(), "Genome\ts1\ts2\ts3");
+        let row: Vec<&str> = lines.next().unwrap().split('\t').collect();
+        assert_eq!(row[0], "GCF_001");
+        assert_ne!(row[1], "NA");
+        assert_eq!(row[2], "NA");
+        assert_eq!(row[3].parse::<f64>().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_report_absent_lists_every_database_genome() {
+        use clap::Parser;
+        let tmp = TempDir::new("report_absent");
+        let dir = tmp.path();
+
+        let mut present = db_entry("genomes/GCF_001.fasta");
+        present.tags = (0..200).collect();
+        present.positions = (0..200).map(|i| i * 1000).collect();
+        let mut absent = db_entry("genomes/GCF_002.fasta");
+        absent.tags = (1000..1200).collect();
+        absent.positions = present.positions.clone();
+        let db_path = dir.join("db.syldb");
+        write_syldb(&mut File::create(&db_path).unwrap(), 1, DbGranularity::Contig, &[present, absent]).unwrap();
+
+        let sample: Vec<SylspEntry> = (0..200)
+            .map(|tag| sylsp_entry("s1", tag))
+            .collect();
+        let sample_path = dir.join("s1.sylsp");
+        let mut sample_file = File::create(&sample_path).unwrap();
+        crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, "", 1).unwrap();
+        bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        drop(sample_file);
+
+        let query_args = |extra: &[&str]| {
+            let mut argv = vec!["meta2bseek", "query", db_path.to_str().unwrap(), sample_path.to_str().unwrap()];
+            argv.extend_from_slice(extra);
+            let cli = crate::cmdline::Cli::try_parse_from(argv).unwrap();
+            let crate::cmdline::Mode::Query(args) = cli.mode else { panic!("expected query") };
+            args
+        };
+        let default_rows = query_to_results(&query_args(&[])).unwrap();
+        let all_rows = query_to_results(&query_args(&["--report-absent"])).unwrap();
+
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile",
+            "--sample-file", sample_path.to_str().unwrap(),
+            "--db-file", db_path.to_str().unwrap(),
+            "--log-path", dir.to_str().unwrap(),
+            "--report-absent",
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(profile_args) = cli.mode else { panic!("expected profile") };
+        let profile = compute_profile(&profile_args, &PhaseTimer::new(), &Progress::default()).unwrap();
+
+        assert_eq!(default_rows.len(), 1);
+        let mut contigs: Vec<&str> = all_rows.iter().map(|r| r.contig_name.as_str()).collect();
+        contigs.sort();
+        assert_eq!(contigs, vec!["genomes/GCF_001.fasta_contig1", "genomes/GCF_002.fasta_contig1"]);
+        let missing = all_rows.iter().find(|r| r.contig_name.contains("GCF_002")).unwrap();
+        assert_eq!((missing.shared_tags, missing.adjusted_ani, missing.ref_tags), (0, 0.0, 200));
+
+        let genomes = &profile.genome_results["s1"];
+        assert_eq!(genomes.len(), 2);
+        let missing = genomes.iter().find(|r| r.genome_id == "GCF_002").unwrap();
+        assert_eq!((missing.common_tags, missing.taxonomic_abundance, missing.total_tags), (0, 0.0, 200));
+        let detected = genomes.iter().find(|r| r.genome_id == "GCF_001").unwrap();
+        assert!((detected.taxonomic_abundance - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_gscore_pseudocount_keeps_borderline_species() {
+        let species = |genus: &str, reads_count: usize, total_tags: usize| SpeciesAbundanceResult {
+            taxonomy: Arc::new(TaxonomyInfo { genus: genus.to_string(), ..Default::default() }),
+            sample_abundances: FxHashMap::default(),
+            sample_shared_tags: FxHashMap::default(),
+            total_tags,
+            genome_count: 1,
+            reads_count,
+            gscore: 0.0,
+            sample_representatives: FxHashMap::default(),
+        };
+        // 标签很多但没有比对上的reads：无伪计数时得分为零
+        let mut results = vec![species("Strong", 400, 900), species("Borderline", 0, 400)];
+        let kept = filter_species_by_gscore(&mut results, 10.0, 0.0);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].taxonomy.genus, "Strong");
+        assert_eq!(results[1].gscore, 0.0);
+        assert!((results[0].gscore - 600.0).abs() < 1e-9);
+
+        let kept = filter_species_by_gscore(&mut results, 10.0, 1.0);
+        assert_eq!(kept.len(), 2);
+        assert!((results[1].gscore - 401f64.sqrt()).abs() < 1e-9);
+
+        // 阈值为0时零得分也保留
+        assert_eq!(filter_species_by_gscore(&mut results, 0.0, 0.0).len(), 2);
+    }
+
+    #[test]
+    fn test_resume_reuses_checkpoint_after_interruption() {
+        let tmp = TempDir::new("checkpoint");
+        let dir = tmp.path();
+        let dir = dir.to_str().unwrap().to_string();
+        let key = CheckpointKey {
+            db_file: "db.syldb".to_string(),
+            args_hash: 42,
+            db_size: 1_000,
+            db_modified: Some((1_700_000_000, 0)),
+            sample_size: 0,
+            sample_modified: None,
+        };
+        let sample_a = tmp.join_str("a.sylsp");
+        let sample_b = tmp.join_str("b.sylsp");
+        std::fs::write(&sample_a, b"sample a").unwrap();
+        std::fs::write(&sample_b, b"sample b").unwrap();
+        let stage = |sample_file: &str| SampleCheckpoint {
+            sample_file: sample_file.to_string(),
+            reassigned_results: vec![QueryResult {
+                sample_file: "s1".to_string(),
+                genome_file: "G1.fa".to_string(),
+                contig_name: "G1_c1".to_string(),
+                ..calculate_statistics(30, 100, 50)
+            }],
+            contig_breadth: vec![(("s1".to_string(), "G1_c1".to_string()), ((3, 4), 12_000))],
+        };
+        let calls = std::cell::Cell::new(0);
+        let run = |sample_file: &str, resume: bool| {
+            checkpointed_stage(Some((&dir, &key)), resume, sample_file, || {
+                calls.set(calls.get() + 1);
+                Some(stage(sample_file))
+            })
+        };
+
+        // 第一次运行只完成了a.sylsp就被中断
+        let first = run(&sample_a, false).unwrap();
+        assert_eq!(calls.get(), 1);
+
+        // 续跑：a.sylsp读取检查点，b.sylsp重新计算
+        let resumed = run(&sample_a, true).unwrap();
+        run(&sample_b, true).unwrap();
+        assert_eq!(calls.get(), 2);
+        assert_eq!(bincode::serialize(&resumed).unwrap(), bincode::serialize(&first).unwrap());
+
+        // 数据库路径、参数、数据库或样本的大小、修改时间不同的检查点不复用
+        let sample_key = key.for_sample(&sample_a).unwrap();
+        assert!(load_checkpoint(&dir, &sample_a, &sample_key).is_some());
+        for changed in [
+            CheckpointKey { db_file: "other.syldb".to_string(), ..sample_key.clone() },
+            CheckpointKey { args_hash: 43, ..sample_key.clone() },
+            CheckpointKey { db_size: 1_001, ..sample_key.clone() },
+            CheckpointKey { db_modified: Some((1_700_000_001, 0)), ..sample_key.clone() },
+            CheckpointKey { sample_size: sample_key.sample_size + 1, ..sample_key.clone() },
+            CheckpointKey { sample_modified: Some((1_700_000_001, 0)), ..sample_key.clone() },
+        ] {
+            assert!(load_checkpoint(&dir, &sample_a, &changed).is_none());
+        }
+
+        // 样本文件被改写后重新计算
+        std::fs::write(&sample_a, b"sample a, rewritten").unwrap();
+        run(&sample_a, true).unwrap();
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn test_resumed_profile_matches_uninterrupted_run() {
+        use clap::Parser;
+        let tmp = TempDir::new("resume_profile");
+
+        let write_db = |name: &str| {
+            let mut genome = db_entry(&format!("genomes/{}.fasta", name));
+            genome.tags = (0..200).collect();
+            genome.positions = (0..200).map(|i| i * 1000).collect();
+            write_syldb(&mut File::create(tmp.join("db.syldb")).unwrap(), 1, DbGranularity::Contig, &[genome]).unwrap();
+        };
+        write_db("GCF_001");
+
+        let sample_path = tmp.join_str("s1.sylsp");
+        let write_sample = |tags: std::ops::Range<Hash>| {
+            let sample: Vec<SylspEntry> = tags
+                .map(|tag| sylsp_entry("s1", tag))
+                .collect();
+            let mut sample_file = File::create(&sample_path).unwrap();
+            crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, "", 1).unwrap();
+            bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        };
+        write_sample(0..150);
+
+        let checkpoint_dir = tmp.join_str("ckpt");
+        let run = |out: &str, extra: &[&str]| {
+            let db_path = tmp.join_str("db.syldb");
+            let out_dir = tmp.join_str(out);
+            let mut argv = vec![
+                "meta2bseek", "profile", "--sample-file", &sample_path, "--db-file", &db_path,
+                "--log-path", &out_dir, "--checkpoint", &checkpoint_dir,
+            ];
+            argv.extend_from_slice(extra);
+            let cli = crate::cmdline::Cli::try_parse_from(argv).unwrap();
+            let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+            profile(args).unwrap();
+            std::fs::read_to_string(tmp.join(out).join("abundance_matrix.tsv")).unwrap()
+        };
+
+        let uninterrupted = run("full", &[]);
+        assert!(uninterrupted.contains("GCF_001"));
+        assert!(std::fs::read_dir(&checkpoint_dir).unwrap().count() > 0);
+
+        // 续跑读取检查点，最终矩阵与完整运行一致
+        assert_eq!(run("resumed", &["--resume"]), uninterrupted);
+
+        // 数据库重建后检查点作废，续跑按新数据库重新计算
+        write_db("GCF_0002");
+        let rebuilt = run("rebuilt", &["--resume"]);
+        assert!(rebuilt.contains("GCF_0002"));
+        assert!(!rebuilt.contains("GCF_001"));
+
+        // 样本文件被改写后检查点同样作废：共享标签数取自新样本
+        let raw = ["--abundance-units", "raw"];
+        assert!(run("raw", &raw).contains("GCF_0002\t150"));
+        write_sample(0..120);
+        let rewritten = run("rewritten", &["--resume", "--abundance-units", "raw"]);
+        assert!(rewritten.contains("GCF_0002\t120"), "{}", rewritten);
+    }
+
+    #[test]
+    fn test_resume_rejects_dumps_that_would_miss_resumed_samples() {
+        use clap::Parser;
+        for dump in ["--unfiltered-dump", "--shared-tags-dump"] {
+            let err = crate::cmdline::Cli::try_parse_from([
+                "meta2bseek", "profile",
+                "--sample-file", "s1.sylsp",
+                "--db-file", "db.syldb",
+                "--checkpoint", "checkpoints",
+                "--resume",
+                dump, "dump_out",
+            ]).err().unwrap_or_else(|| panic!("--resume with {} should be rejected", dump));
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict, "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_genome_name_map_labels_matrix_rows() {
+        let tmp = TempDir::new("name_map");
+        let dir = tmp.path();
+        let map_path = dir.join("names.tsv");
+        std::fs::write(&map_path, "# genome\tname\n/refs/GCF_000006685.1_genomic.fasta.gz\tChlamydia pneumoniae\n").unwrap();
+        let names = read_genome_name_map(map_path.to_str().unwrap()).unwrap();
+        assert_eq!(display_genome_name("GCF_000006685.1_genomic", &names), "Chlamydia pneumoniae");
+        assert_eq!(display_genome_name("GCF_999.1", &names), "GCF_999.1");
+
+        let result = |genome_id: &str| genome_result(genome_id, "s1").abundance(50.0).build();
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![result("GCF_000006685.1_genomic"), result("GCF_999.1")]);
+        let all_genomes: HashSet<String> = ["GCF_000006685.1_genomic".to_string(), "GCF_999.1".to_string()].into_iter().collect();
+
+        let mut sink: Box<dyn Write + Send> = Box::new(std::io::sink());
+        write_abundance_matrix(&OutputOptions::overwriting(), &sample_groups, &all_genomes, None, Some(&names), Some(dir.to_string_lossy().to_string()), "matrix.tsv", false, "relative", None, 2, Some(&mut sink)).unwrap();
+        let content = std::fs::read_to_string(dir.join("matrix.tsv")).unwrap();
+
+        assert!(content.lines().any(|l| l == "Chlamydia pneumoniae\t50.00"), "{}", content);
+        assert!(content.lines().any(|l| l == "GCF_999.1\t50.00"), "{}", content);
+        assert!(!content.contains("GCF_000006685.1_genomic"));
+    }
+
+    #[test]
+    fn test_min_breadth_rejects_clustered_hit_and_keeps_spread_hit() {
+        let mut entry = db_entry("G1.fa");
+        entry.tags = (0..100).collect();
+        // 前20个标签挤在一个5kb的重复区里，其余沿1Mb基因组均匀分布
+        entry.positions = (0..100).map(|i| if i < 20 { 5_000 + i * 100 } else { i * 10_000 }).collect();
+        entry.gn_size = 1_000_000;
+
+        let clustered: FxHashSet<Hash> = (0..20).collect();
+        let spread: FxHashSet<Hash> = (20..100).step_by(4).collect();
+        let clustered_span = shared_span(&entry, |t| clustered.contains(t));
+        let spread_span = shared_span(&entry, |t| spread.contains(t));
+        assert_eq!(clustered_span, 1_900);
+        assert_eq!(spread_span, 960_000 - 200_000);
+
+        assert!(!passes_min_breadth(clustered_span, entry.gn_size, 0.1));
+        assert!(passes_min_breadth(spread_span, entry.gn_size, 0.1));
+        // 未设置阈值或缺少基因组大小时不过滤
+        assert!(passes_min_breadth(clustered_span, entry.gn_size, 0.0));
+        assert!(passes_min_breadth(clustered_span, 0, 0.1));
+        assert_eq!(shared_span(&entry, |_| false), 0);
+    }
+
+    #[test]
+    fn test_collapse_strains_merges_suffixed_species() {
+        let genome = |genome_id: &str, abundance: f64| genome_result(genome_id, "s1").abundance(abundance).build();
+        let taxonomy = |species: &str| Arc::new(TaxonomyInfo::from_ranks(&["Bacteria", "Bacteroidota", "", "", "", "Prevotella", species]));
+        let mut taxonomy_map: FxHashMap<String, Arc<TaxonomyInfo>> = FxHashMap::default();
+        taxonomy_map.insert("G1".to_string(), taxonomy("Prevotella copri_A"));
+        taxonomy_map.insert("G2".to_string(), taxonomy("Prevotella copri_B"));
+        taxonomy_map.insert("G3".to_string(), taxonomy("Prevotella sp. AB12"));
+
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome("G1", 30.0), genome("G2", 20.0), genome("G3", 50.0)]);
+        let thresholds = FilterThresholds::default();
+
+        let separate = aggregate_to_species_level(&sample_groups, &taxonomy_map, 95.0, &thresholds, 1, false).unwrap();
+        assert_eq!(separate.len(), 3);
+
+        let collapsed = aggregate_to_species_level(&sample_groups, &taxonomy_map, 95.0, &thresholds, 1, true).unwrap();
+        assert_eq!(collapsed.len(), 2);
+        let copri = collapsed.iter().find(|s| s.taxonomy.species == "Prevotella copri").unwrap();
+        assert_eq!(copri.genome_count, 2);
+        assert!((copri.sample_abundances["s1"] - 50.0).abs() < 1e-12);
+        // 未定名物种不被规范化
+        assert!(collapsed.iter().any(|s| s.taxonomy.species == "Prevotella sp. AB12"));
+    }
+
+    #[test]
+    fn test_matrix_rows_are_written_once_with_identical_bytes() {
+        // 记录写入次数和内容的writer
+        #[derive(Clone, Default)]
+        struct CountingWriter(Arc<Mutex<(Vec<u8>, usize)>>);
+        impl Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let mut inner = self.0.lock().unwrap();
+                inner.0.extend_from_slice(buf);
+                inner.1 += 1;
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let result = |genome_id: &str, sample_id: &str, abundance: f64| genome_result(genome_id, sample_id).abundance(abundance).tags(10, 1000).build();
+        let samples = ["s1", "s2", "s3", "s4"];
+        let genomes: Vec<String> = (1..=50).map(|g| format!("G{}", g)).collect();
+        let mut sample_groups = HashMap::new();
+        for (si, sample) in samples.iter().enumerate() {
+            let rows = genomes.iter().enumerate()
+                .map(|(gi, genome)| result(genome, sample, (gi * 4 + si) as f64 / 7.0))
+                .collect();
+            sample_groups.insert(sample.to_string(), rows);
+        }
+        let all_genomes: HashSet<String> = genomes.iter().cloned().collect();
+
+        let tmp = TempDir::new("row_writes");
+
+        let dir = tmp.path();
+        let counter = CountingWriter::default();
+        let mut writer: Box<dyn Write + Send> = Box::new(counter.clone());
+        write_abundance_matrix(&OutputOptions::overwriting(), &sample_groups, &all_genomes, None, None, Some(dir.to_string_lossy().to_string()), "matrix.tsv", false, "relative", None, 4, Some(&mut writer)).unwrap();
+        let tsv = std::fs::read_to_string(dir.join("matrix.tsv")).unwrap();
+
+        // 逐格写出时的参考输出
+        let mut expected = "Genome\ts1\ts2\ts3\ts4\n".to_string();
+        for (gi, genome) in genomes.iter().enumerate() {
+            expected.push_str(genome);
+            for si in 0..samples.len() {
+                expected.push_str(&format!("\t{:.4}", (gi * 4 + si) as f64 / 7.0));
+            }
+            expected.push('\n');
+        }
+        assert_eq!(tsv, expected);
+
+        let (bytes, calls) = counter.0.lock().unwrap().clone();
+        assert_eq!(String::from_utf8(bytes).unwrap(), format!("\nAbundance Matrix:\n{}\n", expected));
+        // 表头一次、每行一次、结尾空行一次；逐格写出需要 (样本数 + 2) × 行数 次以上
+        assert_eq!(calls, 1 + genomes.len() + 1);
+        assert!(calls < genomes.len() * (samples.len() + 2));
+    }
+
+    #[test]
+    fn test_matrix_only_tsv_keeps_matrix_out_of_report() {
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome_result("G1", "s1").abundance(100.0).build()]);
+        let all_genomes: HashSet<String> = ["G1".to_string()].into_iter().collect();
+        let species = SpeciesAbundanceResult {
+            taxonomy: Arc::new(TaxonomyInfo::from_ranks(&["Bacteria", "", "", "", "", "Escherichia", "Escherichia coli"])),
+            sample_abundances: [("s1".to_string(), 100.0)].into_iter().collect(),
+            sample_shared_tags: [("s1".to_string(), 100)].into_iter().collect(),
+            total_tags: 1000,
+            genome_count: 1,
+            reads_count: 100,
+            gscore: 0.0,
+            sample_representatives: FxHashMap::default(),
+        };
+        let all_samples: HashSet<String> = ["s1".to_string()].into_iter().collect();
+
+        let tmp = TempDir::new("matrix_only_tsv");
+
+        let dir = tmp.path();
+        let out = dir.to_string_lossy().to_string();
+        let report = |echo: bool| -> String {
+            let report_path = dir.join("report.txt");
+            let mut writer: Box<dyn Write + Send> = Box::new(File::create(&report_path).unwrap());
+            let _ = std::fs::remove_file(dir.join("genomes.tsv"));
+            let _ = std::fs::remove_file(dir.join("species.tsv"));
+            write_abundance_matrix(&OutputOptions::overwriting(), &sample_groups, &all_genomes, None, None, Some(out.clone()), "genomes.tsv", false, "relative", None, 2, echo.then_some(&mut writer)).unwrap();
+            write_species_abundance_matrix(&OutputOptions::overwriting(), std::slice::from_ref(&species), &all_samples, Some(out.clone()), "species.tsv", false, "relative", None, 2, echo.then_some(&mut writer)).unwrap();
+            drop(writer);
+            std::fs::read_to_string(report_path).unwrap()
+        };
+
+        let echoed = report(true);
+        assert!(echoed.contains("Abundance Matrix:") && echoed.contains("G1\t100.00"));
+        assert!(echoed.contains("Escherichia coli\t100.00"));
+
+        let quiet = report(false);
+        assert!(quiet.is_empty());
+        // TSV文件内容不受影响
+        let genome_tsv = std::fs::read_to_string(dir.join("genomes.tsv")).unwrap();
+        assert_eq!(genome_tsv, "Genome\ts1\nG1\t100.00\n");
+        let species_tsv = std::fs::read_to_string(dir.join("species.tsv")).unwrap();
+        assert!(species_tsv.contains("Escherichia coli\t100.00"));
+    }
+
+    #[test]
+    fn test_run_summary_has_one_entry_per_sample() {
+        use clap::Parser;
+        let cli = crate::cmdline::Cli::try_parse_from([
+            "meta2bseek", "profile",
+            "--sample-file", "samples.sylsp",
+            "--db-file", "genomes.syldb",
+            "--run-summary", "summary.json",
+        ]).unwrap();
+        let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+
+        let genome = |genome_id: &str, sample_id: &str, common_tags: usize| genome_result(genome_id, sample_id).abundance(50.0).tags(common_tags, 1000).build();
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome("G1", "s1", 30), genome("G2", "s1", 20)]);
+        sample_groups.insert("s2".to_string(), vec![genome("G1", "s2", 5)]);
+        let all_genomes: HashSet<String> = ["G1", "G2", "G3"].iter().map(|g| g.to_string()).collect();
+        // s3载入了标签但没有检出任何基因组
+        let sample_tag_totals: FxHashMap<String, usize> = [("s1", 500), ("s2", 80), ("s3", 40)].iter()
+            .map(|(sample, tags)| (sample.to_string(), *tags))
+            .collect();
+
+        let summary = build_run_summary(&args, &["samples.sylsp".to_string()], &sample_groups, &all_genomes, None, &sample_tag_totals);
+        let json: serde_json::Value = serde_json::to_value(&summary).unwrap();
+
+        assert_eq!(json["num_samples"], 3);
+        assert_eq!(json["database_genomes"], 3);
+        assert!(json["gscore_threshold"].is_null());
+        let samples = json["samples"].as_array().unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0]["sample"], "s1");
+        assert_eq!(samples[0]["detected_genomes"], 2);
+        assert_eq!(samples[0]["total_tags"], 500);
+        assert_eq!(samples[0]["shared_tags"], 50);
+        assert_eq!(samples[1]["detected_genomes"], 1);
+        assert_eq!(samples[2]["detected_genomes"], 0);
+    }
+
+    #[test]
+    fn test_dealias_keeps_same_named_genomes_with_different_tags() {
+        let genome = |source: &str, tags: std::ops::Range<Hash>| SyldbEntry {
+            sequence_id: format!("{}_c1", source),
+            positions: tags.clone().map(|tag| tag as usize).collect(),
+            tags: tags.collect(),
+            genome_source: source.to_string(),
+            tag_uniqueness: None,
+            gn_size: 10_000,
+            tag_sequences: None,
+            enzyme: String::new(),
+        };
+        // 不是accession的同名文件：标签不同则是不同的基因组，都保留
+        let db = dealias_db_entries(vec![genome("/a/genome.fa", 0..10), genome("/b/genome.fa", 100..110)]);
+        assert_eq!(db.len(), 2);
+
+        // 标签完全相同时仍视为同一基因组
+        let db = dealias_db_entries(vec![genome("/a/genome.fa", 0..10), genome("/b/genome.fa", 0..10)]);
+        assert_eq!(db.len(), 1);
+        assert_eq!(db[0].genome_source, "/a/genome.fa");
+
+        // accession相同时即使标签不同（如不同版本的组装）也只保留第一个
+        let db = dealias_db_entries(vec![genome("/a/GCA_000001.fa", 0..10), genome("/b/GCA_000001.fa", 100..110)]);
+        assert_eq!(db.len(), 1);
+    }
+
+    #[test]
+    fn test_genome_under_two_paths_is_counted_once() {
+        let contig = |name: &str, source: &str| SyldbEntry {
+            sequence_id: name.to_string(),
+            tags: (0..10).collect(),
+            positions: (0..10).collect(),
+            genome_source: source.to_string(),
+            tag_uniqueness: None,
+            gn_size: 10_000,
+            tag_sequences: None,
+            enzyme: String::new(),
+        };
+        let db = vec![
+            contig("c1", "/a/GCF_000001.fasta"),
+            contig("c1", "/b/GCF_000001.fasta"),
+            contig("c2", "/b/GCF_000002.fna.gz"),
+        ];
+        let db = dealias_db_entries(db);
+        assert_eq!(db.len(), 2);
+        assert_eq!(db[0].genome_source, "/a/GCF_000001.fasta");
+
+        let genome_mapping = build_genome_mapping_from_cache(&db);
+        assert_eq!(genome_mapping["c1"].0, "GCF_000001");
+        assert_eq!(genome_mapping["c2"].0, "GCF_000002");
+
+        // 已经按路径拆开的结果在物种聚合时也只计一个基因组
+        let genome = |genome_id: &str| genome_result(genome_id, "s1").abundance(50.0).build();
+        let mut taxonomy_map: FxHashMap<String, Arc<TaxonomyInfo>> = FxHashMap::default();
+        taxonomy_map.insert("GCF_000001".to_string(), Arc::new(TaxonomyInfo::from_ranks(&["Bacteria", "", "", "", "", "Escherichia", "Escherichia coli"])));
+        let mut sample_groups = HashMap::new();
+        sample_groups.insert("s1".to_string(), vec![genome("/a/GCF_000001.fasta"), genome("/b/GCF_000001.fasta")]);
+
+        let species = aggregate_to_species_level(&sample_groups, &taxonomy_map, 95.0, &FilterThresholds::default(), 1, false).unwrap();
+        assert_eq!(species.len(), 1);
+        assert_eq!(species[0].genome_count, 1);
+        assert_eq!(species[0].total_tags, 1000);
+        assert!((species[0].sample_abundances["s1"] - 100.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_species_representative_is_genome_with_most_shared_tags() {
+        let genome = |genome_id: &str, sample_id: &str, common_tags: usize, ani: f64| genome_result(genome_id, sample_id).ani(ani).abundance(25.0).tags(common_tags, 1000).build();
+        let taxonomy = |species: &str| Arc::new(TaxonomyInfo::from_ranks(&["Bacteria", "", "", "", "", "Escherichia", species]));
+        let mut taxonomy_map: FxHashMap<String, Arc<TaxonomyInfo>> = FxHashMap::default();
+        taxonomy_map.insert("G1".to_string(), taxonomy("Escherichia coli"));
+        taxonomy_map.insert("G2".to_string(), taxonomy("Escherichia coli"));
+        taxonomy_map.insert("G3".to_string(), taxonomy("Escherichia fergusonii"));
+
+        let mut sample_groups = HashMap::new();
+        // s1中G1的ANI更高，但G2共享标签更多
+        sample_groups.insert("s1".to_string(), vec![genome("G1", "s1", 30, 99.5), genome("G2", "s1", 80, 97.0), genome("G3", "s1", 500, 99.0)]);
+        sample_groups.insert("s2".to_string(), vec![genome("G1", "s2", 60, 98.0), genome("G2", "s2", 60, 96.0)]);
+
+        let species = aggregate_to_species_level(&sample_groups, &taxonomy_map, 95.0, &FilterThresholds::default(), 1, false).unwrap();
+        let coli = species.iter().find(|s| s.taxonomy.species == "Escherichia coli").unwrap();
+        assert_eq!(coli.sample_representatives["s1"], SpeciesRepresentative { genome_id: "G2".to_string(), shared_tags: 80, adjusted_ani: 97.0 });
+        // 共享标签相同时取ANI更高者
+        assert_eq!(coli.sample_representatives["s2"].genome_id, "G1");
+        let fergusonii = species.iter().find(|s| s.taxonomy.species == "Escherichia fergusonii").unwrap();
+        assert_eq!(fergusonii.sample_representatives.len(), 1);
+
+        let rows = species_representative_rows(std::slice::from_ref(coli), &["s1".to_string(), "s2".to_string()]);
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].contains("s1") && rows[0].contains("G2"));
+    }
+
+    #[test]
+    fn test_headerless_taxonomy_file_parses_first_line() {
+        let content = "GCF_000005845.2\td__Bacteria;p__Pseudomonadota;c__C;o__O;f__F;g__Escherichia;s__Escherichia coli\t99.0\n\
+                       RS_GCF_000006945.2\td__Bacteria;p__Pseudomonadota;c__C;o__O;f__F;g__Salmonella;s__Salmonella enterica\t98.0\n";
+
+        let (taxonomy_map, stats) = parse_taxonomy_content(content, "gtdb", "no", false);
+        assert_eq!(stats, TaxonomyParseStats { parsed: 2, skipped: 0 });
+        assert_eq!(taxonomy_map["GCF_000005845.2"].species, "Escherichia coli");
+        assert_eq!(taxonomy_map["GCF_000006945.2"].species, "Salmonella enterica");
+
+        // auto识别出第一行是数据；yes则照旧丢掉第一行
+        let (auto_map, _) = parse_taxonomy_content(content, "gtdb", "auto", false);
+        assert!(auto_map.contains_key("GCF_000005845.2"));
+        let (header_map, header_stats) = parse_taxonomy_content(content, "gtdb", "yes", false);
+        assert!(!header_map.contains_key("GCF_000005845.2"));
+        assert_eq!(header_stats.total(), 1);
+
+        // 逗号分隔的文件几乎每行都无法解析，超过--taxonomy-max-skipped时报错
+        let tmp = TempDir::new("taxonomy");
+        let path = tmp.join("taxonomy.csv");
+        std::fs::write(&path, content.replace('\t', ",")).unwrap();
+        let result = read_taxonomy_file(path.to_str().unwrap(), "gtdb", "no", DEFAULT_TAXONOMY_MAX_SKIPPED);
+        let lenient = read_taxonomy_file(path.to_str().unwrap(), "gtdb", "no", 1.0);
+        assert!(result.unwrap_err().to_string().contains("2 of 2 lines"));
+        assert!(lenient.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_coverage_correction_separates_equally_covered_genomes() {
+        let genome = |genome_id: &str| genome_result(genome_id, "s1").eff_cov(2.0).build();
+        let mut group = vec![genome("small"), genome("large")];
+        normalize_abundances(&mut group);
+        // 覆盖度相同时默认的taxonomic丰度各占一半
+        assert!((group[0].taxonomic_abundance - 50.0).abs() < 1e-9);
+        assert!((group[1].taxonomic_abundance - 50.0).abs() < 1e-9);
+
+        let genome_sizes: FxHashMap<String, usize> = [("small".to_string(), 1_000_000), ("large".to_string(), 3_000_000)].into_iter().collect();
+        let mut corrected = group.clone();
+        coverage_correct_abundances(&mut corrected, &genome_sizes);
+        assert!((corrected[0].taxonomic_abundance - 75.0).abs() < 1e-9);
+        assert!((corrected[1].taxonomic_abundance - 25.0).abs() < 1e-9);
+        // 序列丰度不受影响
+        assert_eq!(corrected[0].sequence_abundance, group[0].sequence_abundance);
+
+        // 缺少基因组大小时保持原值
+        let mut unsized_group = group.clone();
+        coverage_correct_abundances(&mut unsized_group, &FxHashMap::default());
+        assert_eq!(unsized_group[0].taxonomic_abundance, group[0].taxonomic_abundance);
+    }
+
+    #[test]
+    fn test_aggregate_genome_matches_for_contig_and_genome_level_databases() {
+        use clap::Parser;
+        let tmp = TempDir::new("aggregate");
+        let dir = tmp.path();
+
+        let genome = |source: &str, sequence_id: &str, tags: std::ops::Range<Hash>| {
+            let mut entry = db_entry(source);
+            entry.sequence_id = sequence_id.to_string();
+            entry.positions = tags.clone().map(|tag| tag as usize * 1000).collect();
+            entry.tags = tags.collect();
+            entry
+        };
+        let other = genome("genomes/GCF_002.fasta", "GCF_002_c1", 1000..1200);
+        let contig_db = vec![
+            genome("genomes/GCF_001.fasta", "GCF_001_c1", 0..100),
+            genome("genomes/GCF_001.fasta", "GCF_001_c2", 100..200),
+            other.clone(),
+        ];
+        let genome_db = vec![genome("genomes/GCF_001.fasta", "GCF_001_c1", 0..200), other];
+
+        let sample: Vec<SylspEntry> = (0..200).chain(1000..1200)
+            .map(|tag| sylsp_entry("s1", tag))
+            .collect();
+        let sample_path = dir.join("s1.sylsp");
+        let mut sample_file = File::create(&sample_path).unwrap();
+        crate::extract::write_sylsp_header(&mut sample_file, SylspKind::TagEntries, "", 1).unwrap();
+        bincode::serialize_into(&mut sample_file, &sample).unwrap();
+        drop(sample_file);
+
+        let run = |db: &[SyldbEntry], granularity: DbGranularity, name: &str, aggregate: &str| -> Vec<(String, f64)> {
+            let db_path = dir.join(name);
+            write_syldb(&mut File::create(&db_path).unwrap(), 1, granularity, db).unwrap();
+            let cli = crate::cmdline::Cli::try_parse_from([
+                "meta2bseek", "profile",
+                "--sample-file", sample_path.to_str().unwrap(),
+                "--db-file", db_path.to_str().unwrap(),
+                "--log-path", dir.to_str().unwrap(),
+                "--aggregate", aggregate,
+            ]).unwrap();
+            let crate::cmdline::Mode::Profile(args) = cli.mode else { panic!("expected profile") };
+            let mut hits: Vec<(String, f64)> = compute_profile(&args, &PhaseTimer::new(), &Progress::default()).unwrap().genome_results["s1"].iter()
+                .map(|r| (r.genome_id.clone(), r.taxonomic_abundance))
+                .collect();
+            hits.sort_by(|a, b| a.0.cmp(&b.0));
+            hits
+        };
+
+        let from_contigs = run(&contig_db, DbGranularity::Contig, "contigs.syldb", "genome");
+        let from_genomes = run(&genome_db, DbGranularity::Genome, "genomes.syldb", "genome");
+        assert_eq!(from_contigs.len(), 2);
+        for ((id_a, abundance_a), (id_b, abundance_b)) in from_contigs.iter().zip(&from_genomes) {
+            assert_eq!(id_a, id_b);
+            assert!((abundance_a - abundance_b).abs() < 1e-9);
+        }
+
+        // 粒度来自格式头：单记录的GCF_002在contig级库中同样按contig报告
+        let per_contig = run(&contig_db, DbGranularity::Contig, "contigs.syldb", "contig");
+        let ids: Vec<&str> = per_contig.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["GCF_001|GCF_001_c1", "GCF_001|GCF_001_c2", "GCF_002|GCF_002_c1"]);
+
+        let whole_genomes = run(&genome_db, DbGranularity::Genome, "genomes.syldb", "contig");
+        let ids: Vec<&str> = whole_genomes.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["GCF_001", "GCF_002"]);
+    }
+}
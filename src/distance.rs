@@ -0,0 +1,144 @@
+use crate::constants::OutputOptions;
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::path::Path;
+
+// 样本间距离：输入为每个样本在同一组taxa上的丰度向量
+pub const DISTANCE_METRICS: [&str; 3] = ["bray-curtis", "cosine", "aitchison"];
+
+// 中心对数比(CLR)变换：clr(x)_i = ln(x_i) - mean(ln(x))。
+// 成分数据中的0无法取对数，先统一加上pseudocount
+pub fn clr_transform(abundances: &[f64], pseudocount: f64) -> Vec<f64> {
+    if abundances.is_empty() {
+        return Vec::new();
+    }
+    let logs: Vec<f64> = abundances.iter().map(|x| (x + pseudocount).ln()).collect();
+    let mean = logs.iter().sum::<f64>() / logs.len() as f64;
+    logs.into_iter().map(|l| l - mean).collect()
+}
+
+pub fn bray_curtis(a: &[f64], b: &[f64]) -> f64 {
+    let total: f64 = a.iter().chain(b.iter()).sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let diff: f64 = a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum();
+    diff / total
+}
+
+// 1 - 余弦相似度；全零向量与任何向量的距离记为1（两者都为零时为0）
+pub fn cosine_distance(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return if norm_a == norm_b { 0.0 } else { 1.0 };
+    }
+    (1.0 - dot / (norm_a * norm_b)).max(0.0)
+}
+
+// Aitchison距离 = CLR变换后的欧氏距离
+pub fn aitchison_distance(a: &[f64], b: &[f64], pseudocount: f64) -> f64 {
+    let clr_a = clr_transform(a, pseudocount);
+    let clr_b = clr_transform(b, pseudocount);
+    clr_a.iter().zip(&clr_b).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()
+}
+
+// 两个样本丰度向量之间的距离
+type DistanceFn = Box<dyn Fn(&[f64], &[f64]) -> f64>;
+
+pub fn distance_matrix(samples: &[Vec<f64>], metric: &str, pseudocount: f64) -> Result<Vec<Vec<f64>>> {
+    let distance: DistanceFn = match metric {
+        "bray-curtis" => Box::new(bray_curtis),
+        "cosine" => Box::new(cosine_distance),
+        "aitchison" => Box::new(move |a: &[f64], b: &[f64]| aitchison_distance(a, b, pseudocount)),
+        _ => {
+            return Err(anyhow!(
+                "Unknown distance metric '{}'; expected one of: {}",
+                metric,
+                DISTANCE_METRICS.join(", ")
+            ))
+        }
+    };
+
+    let n = samples.len();
+    let mut matrix = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let d = distance(&samples[i], &samples[j]);
+            matrix[i][j] = d;
+            matrix[j][i] = d;
+        }
+    }
+    Ok(matrix)
+}
+
+// 方阵，行列均为样本
+pub fn write_distance_matrix<P: AsRef<Path>>(output: &OutputOptions, path: P, sample_ids: &[String], matrix: &[Vec<f64>]) -> Result<()> {
+    output.write_atomically(path, |writer| {
+        write!(writer, "Sample")?;
+        for sample_id in sample_ids {
+            write!(writer, "\t{}", sample_id)?;
+        }
+        writeln!(writer)?;
+        for (sample_id, row) in sample_ids.iter().zip(matrix) {
+            write!(writer, "{}", sample_id)?;
+            for d in row {
+                write!(writer, "\t{:.6}", d)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    })
+}
+
+// CLR矩阵（taxa x samples），可直接用于PCA
+pub fn write_clr_matrix<P: AsRef<Path>>(
+    output: &OutputOptions,
+    path: P,
+    taxa: &[String],
+    sample_ids: &[String],
+    samples: &[Vec<f64>],
+    pseudocount: f64,
+) -> Result<()> {
+    let clr: Vec<Vec<f64>> = samples.iter().map(|s| clr_transform(s, pseudocount)).collect();
+    output.write_atomically(path, |writer| {
+        write!(writer, "Taxon")?;
+        for sample_id in sample_ids {
+            write!(writer, "\t{}", sample_id)?;
+        }
+        writeln!(writer)?;
+        for (t, taxon) in taxa.iter().enumerate() {
+            write!(writer, "{}", taxon)?;
+            for values in &clr {
+                write!(writer, "\t{:.6}", values[t])?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clr_sums_to_zero_and_aitchison_self_distance_is_zero() {
+        let samples = vec![
+            vec![60.0, 30.0, 10.0, 0.0],
+            vec![5.0, 0.0, 45.0, 50.0],
+        ];
+        for sample in &samples {
+            let clr = clr_transform(sample, 0.5);
+            assert!(clr.iter().sum::<f64>().abs() < 1e-9);
+            assert_eq!(aitchison_distance(sample, sample, 0.5), 0.0);
+        }
+
+        let matrix = distance_matrix(&samples, "aitchison", 0.5).unwrap();
+        assert_eq!(matrix[0][0], 0.0);
+        assert!(matrix[0][1] > 0.0);
+        assert_eq!(matrix[0][1], matrix[1][0]);
+        assert!(distance_matrix(&samples, "euclid", 0.5).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::cmdline::{ExportArgs, ExportFastaArgs};
+use crate::constants::OutputOptions;
+use crate::contain::{extract_genome_id_from_path, read_genome_sketches, read_sequences_sketches};
+use crate::extract::{read_sequence_index, read_syldb, read_sylsp_entries, read_sylsp_header_full, GenomeSketch, SyldbEntry, SylspEntry, SylspKind};
+use crate::sketch::SequencesSketch;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+// sourmash签名JSON（signature格式0.4）。一个文件是签名列表，每个签名含一个scaled MinHash。
+// meta2bseek的k-mer哈希是2bit编码k-mer的mm_hash64而不是DNA字符串的MurmurHash3，
+// hash_function写成自己的名字（HASH_FUNCTION），不冒充sourmash的"0.murmur64"，
+// 以免与sourmash自己构建的签名混在一起比较
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SourmashSignature {
+    class: String,
+    email: String,
+    hash_function: String,
+    filename: String,
+    name: String,
+    license: String,
+    signatures: Vec<SourmashMinHash>,
+    version: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SourmashMinHash {
+    num: u32,
+    ksize: usize,
+    seed: u64,
+    // scaled = u64::MAX / max_hash，与sketch的-c一致
+    max_hash: u64,
+    molecule: String,
+    mins: Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    abundances: Option<Vec<u32>>,
+}
+
+// sourmash默认种子；签名格式要求该字段
+const SOURMASH_SEED: u64 = 42;
+const HASH_FUNCTION: &str = "0.meta2bseek_mm64";
+
+fn signature(name: &str, filename: &str, ksize: usize, c: usize, mut hashes: Vec<(u64, Option<u32>)>) -> SourmashSignature {
+    hashes.sort_unstable();
+    // 样本sketch带计数时写abundances（sourmash的track_abundance）
+    let abundances = hashes.iter().map(|(_, count)| *count).collect::<Option<Vec<u32>>>().filter(|counts| !counts.is_empty());
+    SourmashSignature {
+        class: "sourmash_signature".to_string(),
+        email: String::new(),
+        hash_function: HASH_FUNCTION.to_string(),
+        filename: filename.to_string(),
+        name: name.to_string(),
+        license: "CC0".to_string(),
+        signatures: vec![SourmashMinHash {
+            num: 0,
+            ksize,
+            seed: SOURMASH_SEED,
+            max_hash: u64::MAX / c.max(1) as u64,
+            molecule: "DNA".to_string(),
+            mins: hashes.into_iter().map(|(hash, _)| hash).collect(),
+            abundances,
+        }],
+        version: 0.4,
+    }
+}
+
+fn genome_signature(sketch: &GenomeSketch) -> SourmashSignature {
+    let hashes = sketch.genome_kmers.iter().map(|&hash| (hash, None)).collect();
+    signature(&sketch.first_contig_name, &sketch.file_name, sketch.k, sketch.c, hashes)
+}
+
+fn sample_signature(sketch: &SequencesSketch) -> SourmashSignature {
+    let name = sketch.sample_name.as_deref().unwrap_or(&sketch.file_name);
+    let hashes = sketch.kmer_counts.iter().map(|(&hash, &count)| (hash, Some(count))).collect();
+    signature(name, &sketch.file_name, sketch.k, sketch.c, hashes)
+}
+
+// 读取sketch生成的.syldb（基因组）或.sylsp（样本），每个sketch一个签名
+fn signatures_from_file(path: &str) -> Result<Vec<SourmashSignature>> {
+    if path.ends_with(".syldb") {
+        let sketches = read_genome_sketches(path)
+            .with_context(|| format!("{} is not a k-mer sketch database (export needs files from `sketch`)", path))?;
+        Ok(sketches.iter().map(genome_signature).collect())
+    } else if path.ends_with(".sylsp") {
+        let sketches = read_sequences_sketches(path)?;
+        Ok(sketches.iter().map(sample_signature).collect())
+    } else {
+        Err(anyhow!("{} is not a .syldb or .sylsp file", path))
+    }
+}
+
+pub fn export(args: ExportArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, None);
+    let signatures = signatures_from_file(&args.file)?;
+    eprintln!("Exporting {} signatures from {}", signatures.len(), args.file);
+
+    match &args.output {
+        Some(path) => output.write_atomically(path, |writer| {
+            serde_json::to_writer(writer, &signatures).context("Failed to write signature JSON")
+        })
+        .with_context(|| format!("Failed to write {}", path))?,
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer(&mut stdout, &signatures).context("Failed to write signature JSON")?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+// export-fasta：extract生成的.syldb/.sylsp中每个标签一条FASTA记录。
+// 序列来自--store-seq保存在条目里的序列，没有时取.seqidx旁路索引
+fn missing_sequences(path: &str) -> anyhow::Error {
+    anyhow!("{} has no stored tag sequences; re-run extract with --store-seq (or --index-sequences) to export FASTA", path)
+}
+
+fn syldb_fasta_records(path: &str, entries: &[SyldbEntry]) -> Result<Vec<(String, String)>> {
+    let index = if entries.iter().all(|e| e.tag_sequences.is_some()) { None } else { read_sequence_index(Path::new(path))? };
+    let mut records = Vec::new();
+    for entry in entries {
+        let genome = extract_genome_id_from_path(&entry.genome_source);
+        for (i, tag) in entry.tags.iter().enumerate() {
+            let sequence = match (&entry.tag_sequences, &index) {
+                (Some(sequences), _) => sequences.get(i).cloned(),
+                (None, Some(index)) => index.get(tag).cloned(),
+                (None, None) => None,
+            }
+            .ok_or_else(|| missing_sequences(path))?;
+            records.push((format!("{}|{}|tag{}", genome, entry.sequence_id, i + 1), sequence));
+        }
+    }
+    Ok(records)
+}
+
+fn sylsp_fasta_records(path: &str, entries: &[SylspEntry]) -> Result<Vec<(String, String)>> {
+    let index = if entries.iter().all(|e| e.sequence.is_some()) { None } else { read_sequence_index(Path::new(path))? };
+    // 合并的.sylsp含多个样本，标签编号按样本各自计数
+    let mut counters: fxhash::FxHashMap<&str, usize> = fxhash::FxHashMap::default();
+    let mut records = Vec::new();
+    for entry in entries {
+        let sequence = match (&entry.sequence, &index) {
+            (Some(sequence), _) => Some(sequence.clone()),
+            (None, Some(index)) => index.get(&entry.tag).cloned(),
+            (None, None) => None,
+        }
+        .ok_or_else(|| missing_sequences(path))?;
+        let n = counters.entry(entry.sample_source.as_str()).or_insert(0);
+        *n += 1;
+        records.push((format!("{}|tag{}", entry.sample_source, n), sequence));
+    }
+    Ok(records)
+}
+
+fn fasta_records_from_file(path: &str) -> Result<Vec<(String, String)>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut reader = BufReader::new(file);
+    if path.ends_with(".syldb") {
+        let entries = read_syldb(reader)
+            .with_context(|| format!("{} is not a tag database (export-fasta needs files from `extract`)", path))?;
+        syldb_fasta_records(path, &entries)
+    } else if path.ends_with(".sylsp") {
+        let header = read_sylsp_header_full(&mut reader)?;
+        match header.as_ref().map(|header| header.kind) {
+            Some(SylspKind::TagEntries) | None => {}
+            Some(_) => return Err(anyhow!("{} is a k-mer sketch; export-fasta needs a .sylsp from `extract`", path)),
+        }
+        let entries = read_sylsp_entries(reader, header.as_ref())
+            .with_context(|| format!("Failed to deserialize {}", path))?;
+        sylsp_fasta_records(path, &entries)
+    } else {
+        Err(anyhow!("{} is not a .syldb or .sylsp file", path))
+    }
+}
+
+fn write_fasta<W: Write>(writer: &mut W, records: &[(String, String)]) -> Result<()> {
+    for (header, sequence) in records {
+        writeln!(writer, ">{}\n{}", header, sequence)?;
+    }
+    Ok(())
+}
+
+pub fn export_fasta(args: ExportFastaArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, None);
+    let records = fasta_records_from_file(&args.file)?;
+    eprintln!("Exporting {} tags from {}", records.len(), args.file);
+
+    match &args.output {
+        Some(path) => output.write_atomically(path, |writer| write_fasta(writer, &records))
+            .with_context(|| format!("Failed to write {}", path))?,
+        None => write_fasta(&mut std::io::stdout().lock(), &records)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sylsp_entry;
+    use fxhash::FxHashMap;
+
+    #[test]
+    fn test_exported_signature_parses_as_sourmash_json() {
+        let mut kmer_counts = FxHashMap::default();
+        for (hash, count) in [(900u64, 3u32), (15, 1), (4_000, 2)] {
+            kmer_counts.insert(hash, count);
+        }
+        let sample = SequencesSketch {
+            kmer_counts,
+            file_name: "reads.fq".to_string(),
+            c: 200,
+            k: 31,
+            paired: false,
+            sample_name: Some("s1".to_string()),
+            mean_read_length: 150.0,
+            duplication_rate: None,
+        };
+        let genome = GenomeSketch {
+            file_name: "genome.fa".to_string(),
+            first_contig_name: "contig1".to_string(),
+            gn_size: 1_000,
+            c: 200,
+            k: 31,
+            min_spacing: 30,
+            genome_kmers: vec![7, 3, 11, 5],
+        };
+
+        let json = serde_json::to_string(&vec![sample_signature(&sample), genome_signature(&genome)]).unwrap();
+
+        // 按sourmash的签名JSON结构解析，不依赖本模块的类型
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let signatures = parsed.as_array().unwrap();
+        assert_eq!(signatures.len(), 2);
+        for signature in signatures {
+            assert_eq!(signature["class"], "sourmash_signature");
+            assert_eq!(signature["hash_function"], "0.meta2bseek_mm64");
+            let minhash = &signature["signatures"][0];
+            assert_eq!(minhash["ksize"], 31);
+            assert_eq!(minhash["max_hash"].as_u64().unwrap(), u64::MAX / 200);
+            let mins: Vec<u64> = minhash["mins"].as_array().unwrap().iter().map(|v| v.as_u64().unwrap()).collect();
+            assert!(mins.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        let sample_minhash = &signatures[0]["signatures"][0];
+        assert_eq!(signatures[0]["name"], "s1");
+        assert_eq!(sample_minhash["mins"].as_array().unwrap().len(), 3);
+        assert_eq!(sample_minhash["abundances"], serde_json::json!([1, 3, 2]));
+        let genome_minhash = &signatures[1]["signatures"][0];
+        assert_eq!(genome_minhash["mins"], serde_json::json!([3, 5, 7, 11]));
+        assert!(genome_minhash.get("abundances").is_none());
+
+        let round_trip: Vec<SourmashSignature> = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_trip[1], genome_signature(&genome));
+    }
+
+    #[test]
+    fn test_export_fasta_writes_one_record_per_tag() {
+        let db = vec![
+            SyldbEntry {
+                sequence_id: "contig1".to_string(),
+                tags: vec![11, 12],
+                positions: vec![0, 100],
+                genome_source: "genomes/GCF_001.fasta".to_string(),
+                tag_uniqueness: None,
+                gn_size: 1_000,
+                tag_sequences: Some(vec!["ACGTACGT".to_string(), "TTGCAACC".to_string()]),
+                enzyme: String::new(),
+            },
+            SyldbEntry {
+                sequence_id: "contig2".to_string(),
+                tags: vec![13],
+                positions: vec![50],
+                genome_source: "genomes/GCF_001.fasta".to_string(),
+                tag_uniqueness: None,
+                gn_size: 1_000,
+                tag_sequences: Some(vec!["GGGCCCAA".to_string()]),
+                enzyme: String::new(),
+            },
+        ];
+        let records = syldb_fasta_records("db.syldb", &db).unwrap();
+        let mut fasta = Vec::new();
+        write_fasta(&mut fasta, &records).unwrap();
+        let fasta = String::from_utf8(fasta).unwrap();
+        let headers: Vec<&str> = fasta.lines().filter(|l| l.starts_with('>')).collect();
+        assert_eq!(headers.len(), db.iter().map(|e| e.tags.len()).sum::<usize>());
+        assert_eq!(headers, vec![">GCF_001|contig1|tag1", ">GCF_001|contig1|tag2", ">GCF_001|contig2|tag1"]);
+        assert!(fasta.contains(">GCF_001|contig1|tag2\nTTGCAACC\n"));
+
+        let sample: Vec<SylspEntry> = ["s1", "s2", "s1"].iter().enumerate()
+            .map(|(i, source)| SylspEntry { sequence: Some("ACGTTGCA".to_string()), ..sylsp_entry(source, i as u64) })
+            .collect();
+        let headers: Vec<String> = sylsp_fasta_records("s.sylsp", &sample).unwrap().into_iter().map(|(h, _)| h).collect();
+        assert_eq!(headers, vec!["s1|tag1", "s2|tag1", "s1|tag2"]);
+
+        // 没有存序列也没有.seqidx时提示--store-seq
+        let mut bare = db.clone();
+        bare[0].tag_sequences = None;
+        let err = syldb_fasta_records("/nonexistent/db.syldb", &bare).unwrap_err();
+        assert!(err.to_string().contains("--store-seq"), "{}", err);
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use bio::io::{fasta, fastq};
 use needletail::parse_fastx_file;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
-use regex::Regex;
+use regex::bytes::Regex;
 use std::{
     fs::File,
     io::{BufRead, BufReader, BufWriter, Read, Write},
@@ -15,13 +15,18 @@ use crate::cmdline::ExtractArgs;
 use serde::{Serialize, Deserialize};
 use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
-use crate::constants::{Hash, hash_bytes};
+use crate::sketch::{mm_hash64, mm_hash_tag};
+use crate::constants::{Hash, hash_bytes, init_thread_pool, check_output_dir, OutputOptions, Progress};
 // 添加fxhash导入
 use fxhash::{FxHashMap, FxHashSet};
 
 // 添加内存统计导入
 use memory_stats::memory_stats;
-use log::*;
+
+// 与sketch的extract_kmers相同的哈希抽样：保留约1/c的标签，且数据库和样本选中的是同一批标签
+pub fn subsample_keeps(tag_hash: Hash, rate: u64) -> bool {
+    rate <= 1 || mm_hash64(tag_hash) < u64::MAX / rate
+}
 
 // 类型别名，与sylph保持一致
 pub type TagHash = Vec<u8>;
@@ -29,69 +34,31 @@ pub type TagCount = u32;
 pub type SampleId = String;
 pub type TagFrequencyMap = FxHashMap<TagHash, TagCount>;
 pub type SampleStatsMap = FxHashMap<SampleId, ExtractionStats>;
+// 单个样本文件的提取结果：(样本名, (序列ID, 标签)列表, 样本条目)
+type SampleExtraction = (String, Vec<(String, TagHash)>, Vec<SylspEntry>);
 
 // AVX2相关导入
-#[cfg(any(target_arch = "x86_64"))]
+#[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-// AVX2优化的DNA序列匹配函数
-#[cfg(any(target_arch = "x86_64"))]
+// AVX2优化的DNA序列匹配函数（只供差分测试与标量路径对照）
+#[cfg(all(test, target_arch = "x86_64"))]
 unsafe fn extract_tags_avx2(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<TagHash>> {
-    if !is_x86_feature_detected!("avx2") {
-        return extract_and_validate_tags(seq, enzyme);
-    }
-
-    let seq_str = String::from_utf8_lossy(seq);
-    let mut tags = Vec::with_capacity(64);
-    let mut seen_tags = FxHashSet::default();
-
-    // 获取酶的标签长度
-    let tag_length = ENZYME_TAG_LENGTHS
-        .iter()
-        .find(|(name, _)| *name == enzyme.name)
-        .map(|(_, len)| *len)
-        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
+    Ok(without_positions(extract_positioned_tags_avx2(seq, enzyme)?))
+}
 
-    // 使用AVX2优化的模式匹配
-    for pattern in &enzyme.patterns {
-        for m in pattern.find_iter(&seq_str) {
-            let matched = m.as_str().as_bytes();
-            
-            // 使用AVX2优化的序列处理
-            let tag = if matched.len() > tag_length {
-                let start = (matched.len() - tag_length) / 2;
-                let tag_slice = &matched[start..start + tag_length];
-                
-                // AVX2优化的序列验证
-                if is_valid_dna_avx2(tag_slice) {
-                    tag_slice.to_vec()
-                } else {
-                    continue;
-                }
-            } else {
-                // AVX2优化的序列验证
-                if is_valid_dna_avx2(matched) {
-                    matched.to_vec()
-                } else {
-                    continue;
-                }
-            };
-            
-            // 获取 canonical 版本的 tag
-            let canonical_tag = get_canonical_sequence(&tag);
-            
-            // 使用FxHashSet进行去重
-            if seen_tags.insert(canonical_tag.clone()) {
-                tags.push(canonical_tag);
-            }
-        }
+#[cfg(target_arch = "x86_64")]
+unsafe fn extract_positioned_tags_avx2(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<(usize, TagHash)>> {
+    if !is_x86_feature_detected!("avx2") {
+        return collect_tags_with(seq, enzyme, is_valid_dna_scalar);
     }
 
-    Ok(tags)
+    // 只有碱基校验走AVX2，匹配/切片/去重与标量路径共用，保证两条路径结果一致
+    collect_tags_with(seq, enzyme, |tag| is_valid_dna_avx2(tag))
 }
 
 // AVX2优化的DNA序列验证函数
-#[cfg(any(target_arch = "x86_64"))]
+#[cfg(target_arch = "x86_64")]
 unsafe fn is_valid_dna_avx2(seq: &[u8]) -> bool {
     if seq.len() < 32 {
         // 对于短序列，使用标准方法
@@ -181,7 +148,7 @@ fn calculate_optimal_buffer_size(file_size: u64, is_compressed: bool) -> usize {
 // 内存监控函数，参考sketch中的check_vram_and_block
 pub fn check_vram_and_block(max_ram: usize, file: &str) {
     if let Some(usage) = memory_stats() {
-        let mut gb_usage_curr = usage.virtual_mem as f64 / 1_000_000_000 as f64;
+        let mut gb_usage_curr = usage.virtual_mem as f64 / 1_000_000_000_f64;
         if (max_ram as f64) < gb_usage_curr {
             log::debug!(
                 "Max memory reached. Blocking extract for {}. Curr memory {}, max mem {}",
@@ -194,7 +161,7 @@ pub fn check_vram_and_block(max_ram: usize, file: &str) {
             let five_second = Duration::from_secs(1);
             thread::sleep(five_second);
             if let Some(usage) = memory_stats() {
-                gb_usage_curr = usage.virtual_mem as f64 / 1_000_000_000 as f64;
+                gb_usage_curr = usage.virtual_mem as f64 / 1_000_000_000_f64;
                 if (max_ram as f64) >= gb_usage_curr {
                     log::debug!("Extract for {} freed", file);
                 }
@@ -207,7 +174,7 @@ pub fn check_vram_and_block(max_ram: usize, file: &str) {
 
 // 动态内存管理函数
 pub fn get_memory_usage() -> Option<f64> {
-    memory_stats().map(|usage| usage.virtual_mem as f64 / 1_000_000_000 as f64)
+    memory_stats().map(|usage| usage.virtual_mem as f64 / 1_000_000_000_f64)
 }
 
 
@@ -313,9 +280,9 @@ pub const ENZYME_DEFINITIONS: &[(&str, &[&str])] = &[
 // 定义每个内切酶的标签长度（固定匹配碱基数 + 自由匹配碱基数）
 pub const ENZYME_TAG_LENGTHS: &[(&str, usize)] = &[
     ("CspCI", 33),  // 11 + 3 + 5 + 4 + 10 = 33
-    ("AloI", 20),   // 7 + 4 + 6 + 3 = 20
+    ("AloI", 27),   // 7 + 4 + 6 + 3 + 7 = 27
     ("BsaXI", 27),  // 9 + 2 + 5 + 4 + 7 = 27
-    ("BaeI", 27),   // 10 + 2 + 4 + 4 + 7 = 27
+    ("BaeI", 28),   // 10 + 2 + 4 + 5 + 7 = 28
     ("BcgI", 32),   // 10 + 3 + 6 + 3 + 10 = 32
     ("CjeI", 28),   // 8 + 3 + 6 + 2 + 9 = 28
     ("PpiI", 27),   // 7 + 4 + 5 + 3 + 8 = 27
@@ -323,17 +290,156 @@ pub const ENZYME_TAG_LENGTHS: &[(&str, usize)] = &[
     ("BplI", 27),   // 8 + 3 + 5 + 3 + 8 = 27
     ("FalI", 27),   // 8 + 3 + 5 + 3 + 8 = 27
     ("Bsp24I", 27), // 8 + 3 + 6 + 3 + 7 = 27
-    ("HaeIV", 25),  // 7 + 2 + 5 + 2 + 9 = 25
+    ("HaeIV", 27),  // 7 + 3 + 5 + 3 + 9 = 27
     ("CjePI", 27),  // 7 + 3 + 7 + 2 + 8 = 27
-    ("Hin4I", 25),  // 8 + 2 + 5 + 2 + 8 = 25
+    ("Hin4I", 27),  // 8 + 3 + 5 + 3 + 8 = 27
     ("AlfI", 32),   // 10 + 3 + 6 + 3 + 10 = 32
     ("BslFI", 25),  // 6 + 5 + 14 = 25
 ];
 
+// 声明的标签长度必须等于每个识别模式的完整宽度（侧翼+识别位点，简并碱基算1位），
+// 否则标签会被截短并错误地居中
+fn check_tag_length(name: &str, patterns: &[&str]) -> Result<usize> {
+    let tag_length = ENZYME_TAG_LENGTHS
+        .iter()
+        .find(|(e, _)| *e == name)
+        .map(|(_, len)| *len)
+        .ok_or_else(|| anyhow::anyhow!("No tag length declared for enzyme {}", name))?;
+    for pattern in patterns {
+        let width = parse_site_masks(pattern).len();
+        if width != tag_length {
+            return Err(anyhow::anyhow!(
+                "Enzyme {}: declared tag length {} does not match the width {} of pattern {}",
+                name, tag_length, width, pattern
+            ));
+        }
+    }
+    Ok(tag_length)
+}
+
+// 碱基到位掩码：A=1, C=2, G=4, T=8，非ACGT为0
+fn base_mask(base: u8) -> u8 {
+    match base {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' => 8,
+        _ => 0,
+    }
+}
+
+// 把识别模式展开成逐位置的允许碱基掩码，[ACGT]对应0b1111（自由位置）
+pub fn parse_site_masks(pattern: &str) -> Vec<u8> {
+    let bytes = pattern.as_bytes();
+    let mut masks = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let mask = if bytes[i] == b'[' {
+            let close = bytes[i..].iter().position(|&c| c == b']').map_or(bytes.len(), |p| i + p);
+            let mask = bytes[i + 1..close].iter().fold(0, |acc, &b| acc | base_mask(b));
+            i = close + 1;
+            mask
+        } else {
+            i += 1;
+            base_mask(bytes[i - 1])
+        };
+
+        let mut repeat = 1;
+        if i < bytes.len() && bytes[i] == b'{' {
+            let close = bytes[i..].iter().position(|&c| c == b'}').map_or(bytes.len(), |p| i + p);
+            repeat = String::from_utf8_lossy(&bytes[i + 1..close]).parse().unwrap_or(1);
+            i = close + 1;
+        }
+
+        masks.extend(std::iter::repeat_n(mask, repeat));
+    }
+    masks
+}
+
+// 随机序列中某一位置出现该识别位点的概率：
+// 固定碱基贡献1/4，简并碱基[XY]贡献n/4，[ACGT]{n}不受约束
+pub fn site_probability(pattern: &str) -> f64 {
+    parse_site_masks(pattern).iter()
+        .map(|mask| mask.count_ones() as f64 / 4.0)
+        .product()
+}
+
+// 每bp期望的标签数（各方向识别模式的概率之和）
+pub fn expected_tags_per_bp(enzyme: &str) -> Option<f64> {
+    ENZYME_DEFINITIONS.iter()
+        .find(|(name, _)| *name == enzyme)
+        .map(|(_, patterns)| patterns.iter().map(|p| site_probability(p)).sum())
+}
+
 #[derive(Debug)]
 pub struct EnzymeSpec {
     pub name: String,
     pub patterns: Vec<Regex>,
+    // 与patterns一一对应的逐位置碱基掩码，供--long-read的容错匹配使用
+    pub site_masks: Vec<Vec<u8>>,
+    // 识别位点允许的最大编辑数（替换、插入、缺失），0表示精确的正则匹配
+    pub max_mismatches: usize,
+    // --strand-specific：按观测到的方向保存tag，不与反向互补合并
+    pub strand_specific: bool,
+    // --min-complexity：碱基Shannon熵(bits)低于此值的tag被丢弃，0表示不过滤
+    pub min_complexity: f64,
+    pub low_complexity_filtered: std::sync::atomic::AtomicUsize,
+    // --subsample-rate：只保留约1/c的标签，1表示不抽样
+    pub subsample_rate: u64,
+    // --fnv-tag-hash：改用旧版FNV-1a标签哈希，以便与之前提取的.syldb/.sylsp比较
+    pub fnv_tag_hash: bool,
+    // --adapter：接头序列（正反两链）的所有ADAPTER_MATCH_LEN-mer，为空表示不检查
+    pub adapter_kmers: FxHashSet<Vec<u8>>,
+    adapter_kmer_lengths: Vec<usize>,
+    // --flag-adapters：只统计含接头的tag，不丢弃
+    pub flag_adapters: bool,
+    pub adapter_tags: std::sync::atomic::AtomicUsize,
+    // --tag-offset：位点长于标签时标签在位点内的起点，None表示居中
+    pub tag_offset: Option<usize>,
+    // --min-read-length：短于此长度的read不提取标签，0表示不过滤
+    pub min_read_length: usize,
+    pub short_reads_skipped: std::sync::atomic::AtomicUsize,
+    // --strict-length：长度与酶标签长度不符的tag被丢弃；否则只计数告警
+    pub strict_length: bool,
+    pub length_mismatched_tags: std::sync::atomic::AtomicUsize,
+    // --error-correct：低频标签并入丰度至少此倍数的单碱基邻居，None表示不纠错
+    pub error_correct_ratio: Option<f64>,
+    pub error_corrected_tags: std::sync::atomic::AtomicUsize,
+    // --prefilter-db：参考面板的标签哈希，没有标签命中面板的read整条丢弃，None表示不过滤
+    pub prefilter_tags: Option<Arc<FxHashSet<Hash>>>,
+    pub prefilter_reads_dropped: std::sync::atomic::AtomicUsize,
+}
+
+// tag与接头共享这么长的连续序列即视为接头污染；短于此长度的接头要求整段出现
+const ADAPTER_MATCH_LEN: usize = 12;
+
+// 内置接头：Illumina TruSeq、Nextera和small RNA 3'接头
+const BUILTIN_ADAPTERS: &[(&str, &str)] = &[
+    ("truseq", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCA"),
+    ("nextera", "CTGTCTCTTATACACATCTCCGAGCCCACGAGAC"),
+    ("smallrna", "TGGAATTCTCGGGTGCCAAGG"),
+];
+
+// --adapter的取值可以是内置名称或ACGT序列
+fn resolve_adapters(values: &[String]) -> Result<Vec<Vec<u8>>> {
+    let mut adapters = Vec::new();
+    for value in values {
+        let name = value.to_ascii_lowercase();
+        if name == "all" {
+            adapters.extend(BUILTIN_ADAPTERS.iter().map(|(_, seq)| seq.as_bytes().to_vec()));
+        } else if let Some((_, seq)) = BUILTIN_ADAPTERS.iter().find(|(n, _)| *n == name) {
+            adapters.push(seq.as_bytes().to_vec());
+        } else {
+            let seq = value.to_ascii_uppercase().into_bytes();
+            if seq.is_empty() || !seq.iter().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T')) {
+                return Err(anyhow::anyhow!(
+                    "Invalid --adapter {}: expected an ACGT sequence or one of truseq, nextera, smallrna, all", value
+                ));
+            }
+            adapters.push(seq);
+        }
+    }
+    Ok(adapters)
 }
 
 impl EnzymeSpec {
@@ -342,6 +448,7 @@ impl EnzymeSpec {
             .iter()
             .find(|(e, _)| *e == name)
             .ok_or_else(|| anyhow::anyhow!("Unsupported enzyme: {}", name))?;
+        check_tag_length(def.0, def.1)?;
 
         let patterns = def.1
             .iter()
@@ -351,386 +458,1088 @@ impl EnzymeSpec {
         Ok(Self {
             name: def.0.to_string(),
             patterns,
+            site_masks: def.1.iter().map(|p| parse_site_masks(p)).collect(),
+            max_mismatches: 0,
+            strand_specific: false,
+            min_complexity: 0.0,
+            low_complexity_filtered: std::sync::atomic::AtomicUsize::new(0),
+            subsample_rate: 1,
+            fnv_tag_hash: false,
+            adapter_kmers: FxHashSet::default(),
+            adapter_kmer_lengths: Vec::new(),
+            flag_adapters: false,
+            adapter_tags: std::sync::atomic::AtomicUsize::new(0),
+            tag_offset: None,
+            min_read_length: 0,
+            short_reads_skipped: std::sync::atomic::AtomicUsize::new(0),
+            strict_length: false,
+            length_mismatched_tags: std::sync::atomic::AtomicUsize::new(0),
+            error_correct_ratio: None,
+            error_corrected_tags: std::sync::atomic::AtomicUsize::new(0),
+            prefilter_tags: None,
+            prefilter_reads_dropped: std::sync::atomic::AtomicUsize::new(0),
         })
     }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SyldbEntry {
-    pub sequence_id: String,
-    pub tags: Vec<Hash>,
-    pub positions: Vec<usize>,
-    pub genome_source: String,
-    // 新增字段：标记每个tag是否为unique（taxa-specific）
-    pub tag_uniqueness: Option<Vec<bool>>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct SylspEntry {
-    pub sequence_id: String,
-    pub tag: Hash,
-    pub quality: Option<String>,
-    pub sample_source: String,
-}
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Hash, PartialOrd, Eq, Ord, Default, Clone)]
-pub struct GenomeSketch {
-    pub file_name: String,
-    pub first_contig_name: String,
-    pub gn_size: usize,
-    pub c: usize,
-    pub k: usize,
-    pub min_spacing: usize,
-    pub genome_kmers: Vec<Hash>,
-}
+    pub fn with_tag_offset(mut self, tag_offset: Option<usize>) -> Self {
+        self.tag_offset = tag_offset;
+        self
+    }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Hash, PartialOrd, Eq, Ord, Default, Clone)]
-pub struct GenomeSketchInspect {
-    pub file_name: String,
-    pub genome_kmers_num: usize,
-    pub first_contig_name: String,
-    pub genome_size: usize,
-}
+    // 位点长于标签时截取的起点：默认居中（假定两侧侧翼对称），非对称位点按tag_offset对齐
+    fn tag_start(&self, site_len: usize, tag_length: usize) -> usize {
+        let slack = site_len - tag_length;
+        self.tag_offset.map_or(slack / 2, |offset| offset.min(slack))
+    }
 
-impl From<GenomeSketch> for GenomeSketchInspect {
-    fn from(sk: GenomeSketch) -> Self {
-        GenomeSketchInspect {
-            genome_kmers_num: sk.genome_kmers.len(),
-            file_name: sk.file_name,
-            first_contig_name: sk.first_contig_name,
-            genome_size: sk.gn_size,
+    pub fn with_adapters(mut self, adapters: &[String], flag_only: bool) -> Result<Self> {
+        for adapter in resolve_adapters(adapters)? {
+            for strand in [adapter.clone(), reverse_complement(&adapter)] {
+                let k = ADAPTER_MATCH_LEN.min(strand.len());
+                self.adapter_kmers.extend(strand.windows(k).map(|w| w.to_vec()));
+                if !self.adapter_kmer_lengths.contains(&k) {
+                    self.adapter_kmer_lengths.push(k);
+                }
+            }
         }
+        self.flag_adapters = flag_only;
+        Ok(self)
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Hash, PartialOrd, Eq, Ord, Default, Clone)]
-pub struct DatabaseSketch {
-    pub database_file: String,
-    pub c: usize,
-    pub k: usize,
-    pub min_spacing_parameter: usize,
-    pub genome_files: Vec<GenomeSketchInspect>,
-}
+    // 残留的接头/linker会产生在无关样本间共享的高频假tag
+    fn passes_adapter(&self, tag: &[u8]) -> bool {
+        if self.adapter_kmers.is_empty() {
+            return true;
+        }
+        let contains_adapter = self.adapter_kmer_lengths.iter()
+            .any(|&k| tag.len() >= k && tag.windows(k).any(|w| self.adapter_kmers.contains(w)));
+        if !contains_adapter {
+            return true;
+        }
+        self.adapter_tags.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.flag_adapters
+    }
 
-#[allow(dead_code)]
-pub fn process_input(
-    input_files: Vec<PathBuf>,
-    sample_output_dir: &Path,
-    enzyme_name: &str,
-    _threads: usize,
-    format: &str,
-) -> Result<()> {
-    let enzyme = EnzymeSpec::new(enzyme_name)
-        .context(format!("Unsupported enzyme: {}", enzyme_name))?;
+    fn report_filters(&self) {
+        if self.min_read_length > 0 {
+            eprintln!(
+                "Skipped {} reads shorter than {} bp",
+                self.short_reads_skipped.load(std::sync::atomic::Ordering::Relaxed),
+                self.min_read_length
+            );
+        }
+        self.report_complexity_filter();
+        let length_mismatched = self.length_mismatched_tags.load(std::sync::atomic::Ordering::Relaxed);
+        if self.strict_length {
+            eprintln!("Removed {} tags whose length differs from the {} tag length", length_mismatched, self.name);
+        } else if length_mismatched > 0 {
+            eprintln!("Warning: {} tags differ from the {} tag length (use --strict-length to drop them)", length_mismatched, self.name);
+        }
+        if self.prefilter_tags.is_some() {
+            eprintln!(
+                "Prefilter: discarded the tags of {} reads with no tag in the --prefilter-db panel",
+                self.prefilter_reads_dropped.load(std::sync::atomic::Ordering::Relaxed)
+            );
+        }
+        if self.error_correct_ratio.is_some() {
+            eprintln!(
+                "Error correction: {} tags rewritten to an abundant single-substitution neighbor",
+                self.error_corrected_tags.load(std::sync::atomic::Ordering::Relaxed)
+            );
+        }
+        if !self.adapter_kmers.is_empty() {
+            eprintln!(
+                "{} {} tags containing adapter sequence",
+                if self.flag_adapters { "Flagged" } else { "Removed" },
+                self.adapter_tags.load(std::sync::atomic::Ordering::Relaxed)
+            );
+        }
+    }
 
-    for input_path in &input_files {
-        // 确定输入文件类型
-        let is_fasta = is_fasta_file(input_path)
-            .context("Failed to determine if file is FASTA")?;
-        let is_fastq = is_fastq_file(input_path)
-            .context("Failed to determine if file is FASTQ")?;
+    pub fn with_min_read_length(mut self, min_read_length: usize) -> Self {
+        self.min_read_length = min_read_length;
+        self
+    }
 
-        if !is_fasta && !is_fastq {
-            return Err(anyhow::anyhow!("Unsupported file format: {}", input_path.display()));
+    // 接头二聚体、碎片等短read通常装不下完整的识别位点，只会带来截断的假标签
+    fn passes_read_length(&self, read_len: usize) -> bool {
+        if read_len >= self.min_read_length {
+            return true;
         }
+        self.short_reads_skipped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        false
+    }
 
-        let file_stem = input_path.file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("output");
-            
-        let output_name = if format == "fq" {
-            format!("{}.fq", file_stem)
-        } else {
-            format!("{}.fa", file_stem)
-        };
-        
-        let mut output_path = PathBuf::from(sample_output_dir);
-        output_path.push(output_name);
+    pub fn with_strict_length(mut self, strict_length: bool) -> Self {
+        self.strict_length = strict_length;
+        self
+    }
 
-        // 根据文件类型处理
-        if is_fasta {
-            process_fasta(input_path, &output_path, &enzyme, format, input_path.to_string_lossy().ends_with(".gz"))?;
-        } else {
-            process_fastq(input_path, &output_path, &enzyme, format, input_path.to_string_lossy().ends_with(".gz"))?;
+    // 比标签短的位点匹配会被整段保留，长度不一的tag哈希后与正常tag不可比
+    fn passes_tag_length(&self, tag: &[u8], tag_length: usize) -> bool {
+        if tag.len() == tag_length {
+            return true;
         }
+        self.length_mismatched_tags.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        !self.strict_length
     }
 
-    Ok(())
-}
-
+    pub fn with_error_correction(mut self, ratio: Option<f64>) -> Self {
+        self.error_correct_ratio = ratio;
+        self
+    }
 
+    // 纠错要靠标签序列找邻居，开启时条目总是先带上序列，纠错后再按--store-seq去掉
+    fn keeps_sequences(&self, store_seq: bool) -> bool {
+        store_seq || self.error_correct_ratio.is_some()
+    }
 
-#[allow(dead_code)]
-fn is_fasta_file(path: &Path) -> Result<bool> {
-    // 检查文件扩展名
-    let ext = path.extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+    // 一个样本的标签全部提取后：第一遍统计频次，第二遍把低频标签改写为其丰度邻居并重新哈希。
+    // 返回标签序列 -> 纠错后序列，供同一样本的FASTA输出使用；未开启纠错时为空
+    fn correct_tag_errors(&self, entries: &mut [SylspEntry], store_seq: bool) -> FxHashMap<TagHash, TagHash> {
+        let Some(ratio) = self.error_correct_ratio else {
+            return FxHashMap::default();
+        };
+        let corrections = tag_error_corrections(
+            entries.iter().filter_map(|entry| entry.sequence.as_deref().map(str::as_bytes)),
+            ratio,
+            |tag| self.orient_tag(tag),
+        );
+        let mut corrected = 0;
+        for entry in entries.iter_mut() {
+            if let Some(parent) = entry.sequence.as_deref().and_then(|seq| corrections.get(seq.as_bytes())) {
+                entry.tag = self.hash_tag(parent);
+                entry.sequence = Some(String::from_utf8_lossy(parent).into_owned());
+                corrected += 1;
+            }
+            if !store_seq {
+                entry.sequence = None;
+            }
+        }
+        self.error_corrected_tags.fetch_add(corrected, std::sync::atomic::Ordering::Relaxed);
+        corrections
+    }
 
-    // 如果是压缩文件，获取原始扩展名
-    let base_name = path.file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
-    
-    let is_fasta_ext = if base_name.ends_with(".gz") {
-        // 移除 .gz 后缀并检查原始扩展名
-        let without_gz = base_name.trim_end_matches(".gz");
-        without_gz.ends_with(".fa") || 
-        without_gz.ends_with(".fasta") || 
-        without_gz.ends_with(".fna") || 
-        without_gz.ends_with(".ffn") || 
-        without_gz.ends_with(".faa") || 
-        without_gz.ends_with(".frn")
-    } else {
-        matches!(ext.as_str(), 
-            "fa" | "fasta" | "fna" | "ffn" | "faa" | "frn"
-        )
-    };
+    pub fn with_prefilter(mut self, panel: Option<Arc<FxHashSet<Hash>>>) -> Self {
+        self.prefilter_tags = panel;
+        self
+    }
 
-    // 如果扩展名不明确，检查文件内容
-    if !is_fasta_ext {
-        let mut reader = create_reader(path)?;
-        let mut first_char = [0u8; 1];
-        if reader.read_exact(&mut first_char).is_ok() {
-            return Ok(first_char[0] == b'>');
+    // 一条read（双端为整个片段）的标签只要有一个在面板中就全部保留；没有标签的read不计入丢弃数
+    fn passes_prefilter<'a>(&self, tags: impl IntoIterator<Item = &'a TagHash>) -> bool {
+        let Some(panel) = &self.prefilter_tags else {
+            return true;
+        };
+        let mut has_tags = false;
+        for tag in tags {
+            if panel.contains(&self.hash_tag(tag)) {
+                return true;
+            }
+            has_tags = true;
         }
+        if has_tags {
+            self.prefilter_reads_dropped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+        false
     }
 
-    Ok(is_fasta_ext)
-}
+    pub fn with_subsample_rate(mut self, subsample_rate: u64) -> Self {
+        // 命令行保证>=1；ExtractArgs::default()（库调用、测试）为0，视同不抽样
+        self.subsample_rate = subsample_rate.max(1);
+        self
+    }
 
-#[allow(dead_code)]
-fn is_fastq_file(path: &Path) -> Result<bool> {
-    // 检查文件扩展名
-    let base_name = path.file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
-    
-    let is_fastq_ext = if base_name.ends_with(".gz") {
-        // 移除 .gz 后缀并检查原始扩展名
-        let without_gz = base_name.trim_end_matches(".gz");
-        without_gz.ends_with(".fq") || 
-        without_gz.ends_with(".fastq")
-    } else {
-        base_name.ends_with(".fq") || 
-        base_name.ends_with(".fastq")
-    };
+    pub fn with_fnv_tag_hash(mut self, fnv_tag_hash: bool) -> Self {
+        self.fnv_tag_hash = fnv_tag_hash;
+        self
+    }
 
-    // 如果扩展名不明确，检查文件内容
-    if !is_fastq_ext {
-        let mut reader = create_reader(path)?;
-        let mut first_char = [0u8; 1];
-        if reader.read_exact(&mut first_char).is_ok() {
-            return Ok(first_char[0] == b'@');
+    // 数据库和样本必须用同一种标签哈希，否则不会有任何共享标签
+    pub fn hash_tag(&self, tag: &[u8]) -> Hash {
+        if self.fnv_tag_hash {
+            hash_bytes(tag)
+        } else {
+            mm_hash_tag(tag)
         }
     }
 
-    Ok(is_fastq_ext)
-}
+    // 抽样必须基于canonical标签的哈希，正反链读到的同一标签才会一起保留或丢弃
+    fn passes_subsample(&self, canonical_tag: &[u8]) -> bool {
+        subsample_keeps(self.hash_tag(canonical_tag), self.subsample_rate)
+    }
 
-// 完全按照sylph方式处理FASTA文件
-fn process_fasta_sylph_style(
-    input: &Path,
-    output: &Path,
-    enzyme: &EnzymeSpec,
-    format: &str,
-    compress: bool,
-) -> Result<()> {
-    let mut writer = create_writer(output, compress)?;
-    let mut stats = ExtractionStats::new();
-    
-    // 完全按照sylph的模式
-    let reader = parse_fastx_file(input);
-    if !reader.is_ok() {
-        warn!("{} is not a valid fasta/fastq file; skipping.", input.display());
-        return Ok(());
+    pub fn with_min_complexity(mut self, min_complexity: f64) -> Self {
+        self.min_complexity = min_complexity;
+        self
     }
-    
-    let mut reader = reader.unwrap();
-    let mut kmer_to_tag_table = FxHashSet::default();
-    
-    while let Some(record) = reader.next() {
-        if record.is_ok() {
-            let record = record.expect(&format!("Invalid record for file {} ", input.display()));
-            let seq = record.seq();
-            let seq_id = String::from_utf8_lossy(record.id());
-            
-            stats.total_sequences += 1;
-            stats.total_sequence_length += seq.len();
-            
-            // 使用sylph风格的标签提取（现在包含canonical处理）
-            let tags = extract_and_validate_tags(&seq, enzyme)
-                .context(format!("Failed to process sequence: {}", seq_id))?;
-            
-            // 按照sylph的去重模式（现在使用canonical tags）
-            for tag in tags {
-                if kmer_to_tag_table.insert(tag.clone()) {
-                    stats.total_tags += 1;
-                    write_tags(&mut *writer, &seq_id, &[tag], format)?;
-                }
-            }
-        } else {
-            warn!("Invalid record in file {}", input.display());
+
+    // 低复杂度（均聚物、简单重复）tag在无关基因组间也会共享，没有区分度
+    fn passes_complexity(&self, tag: &[u8]) -> bool {
+        if self.min_complexity <= 0.0 || tag_entropy(tag) >= self.min_complexity {
+            return true;
         }
+        self.low_complexity_filtered.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        false
     }
-    
-    log_stats(stats, enzyme);
-    Ok(())
-}
-
 
+    fn report_complexity_filter(&self) {
+        if self.min_complexity > 0.0 {
+            eprintln!(
+                "Filtered {} low-complexity tags (base entropy < {:.2} bits)",
+                self.low_complexity_filtered.load(std::sync::atomic::Ordering::Relaxed),
+                self.min_complexity
+            );
+        }
+    }
 
-fn process_fasta(
-    input: &Path,
-    output: &Path,
-    enzyme: &EnzymeSpec,
-    format: &str,
-    compress: bool,
-) -> Result<()> {
-    // 直接使用sylph风格的处理
-    process_fasta_sylph_style(input, output, enzyme, format, compress)
-}
+    pub fn with_max_mismatches(mut self, max_mismatches: usize) -> Self {
+        self.max_mismatches = max_mismatches;
+        self
+    }
 
-// 按照sylph风格处理FASTQ文件
-fn process_fastq_sylph_style(
-    input: &Path,
-    output: &Path,
-    enzyme: &EnzymeSpec,
-    format: &str,
-    compress: bool,
-) -> Result<()> {
-    let mut writer = create_writer(output, compress)?;
-    let mut stats = ExtractionStats::new();
-    
-    // 完全按照sylph的模式
-    let reader = parse_fastx_file(input);
-    if !reader.is_ok() {
-        warn!("{} is not a valid fasta/fastq file; skipping.", input.display());
-        return Ok(());
+    pub fn with_strand_specific(mut self, strand_specific: bool) -> Self {
+        self.strand_specific = strand_specific;
+        self
     }
-    
-    let mut reader = reader.unwrap();
-    let mut kmer_to_tag_table = FxHashSet::default();
-    
-    while let Some(record) = reader.next() {
-        if record.is_ok() {
-            let record = record.expect(&format!("Invalid record for file {} ", input.display()));
-            let seq = record.seq();
-            let seq_id = String::from_utf8_lossy(record.id());
-            
-            stats.total_sequences += 1;
-            stats.total_sequence_length += seq.len();
-            
-            // 使用sylph风格的标签提取（现在包含canonical处理）
-            let tags = extract_and_validate_tags(&seq, enzyme)
-                .context(format!("Failed to process sequence: {}", seq_id))?;
-            
-            // 按照sylph的去重模式（现在使用canonical tags）
-            for tag in tags {
-                if kmer_to_tag_table.insert(tag.clone()) {
-                    stats.total_tags += 1;
-                    write_tags(&mut *writer, &seq_id, &[tag], format)?;
-                }
-            }
+
+    // 默认取canonical版本；strand-specific时保持原方向
+    fn orient_tag(&self, tag: &[u8]) -> TagHash {
+        if self.strand_specific {
+            tag.to_vec()
         } else {
-            warn!("Invalid record in file {}", input.display());
+            get_canonical_sequence(tag)
         }
     }
-    
-    log_stats(stats, enzyme);
-    Ok(())
 }
 
-fn process_fastq(
-    input: &Path,
-    output: &Path,
-    enzyme: &EnzymeSpec,
-    format: &str,
-    compress: bool,
-) -> Result<()> {
-    // 直接使用sylph风格的处理
-    process_fastq_sylph_style(input, output, enzyme, format, compress)
-}
+// 2bRAD测序错误集中在个别循环位置，产生与真实标签只差一个碱基的低频变体。
+// 对每个标签找丰度最高、且不低于ratio倍的单碱基替换邻居，沿邻居链取到最终的父标签；
+// ratio > 1 保证链上丰度严格递增，不会成环。orient把邻居转换成与计数键相同的方向
+pub fn tag_error_corrections<'a, F: Fn(&[u8]) -> TagHash>(
+    tags: impl IntoIterator<Item = &'a [u8]>,
+    ratio: f64,
+    orient: F,
+) -> FxHashMap<TagHash, TagHash> {
+    let mut counts: FxHashMap<&[u8], usize> = FxHashMap::default();
+    for tag in tags {
+        *counts.entry(tag).or_insert(0) += 1;
+    }
 
-fn extract_and_validate_tags(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<TagHash>> {
-    #[cfg(any(target_arch = "x86_64"))]
-    {
-        if is_x86_feature_detected!("avx2") {
-            unsafe {
-                return extract_tags_avx2(seq, enzyme);
+    let mut parents: FxHashMap<&[u8], TagHash> = FxHashMap::default();
+    for (&tag, &count) in &counts {
+        let mut best: Option<(usize, TagHash)> = None;
+        let mut neighbor = tag.to_vec();
+        for i in 0..tag.len() {
+            for &base in b"ACGT" {
+                if base == tag[i] {
+                    continue;
+                }
+                neighbor[i] = base;
+                let oriented = orient(&neighbor);
+                if let Some(&n) = counts.get(oriented.as_slice()) {
+                    let better = best.as_ref().is_none_or(|(b, seq)| n > *b || (n == *b && oriented < *seq));
+                    if n as f64 >= ratio * count as f64 && better {
+                        best = Some((n, oriented));
+                    }
+                }
             }
+            neighbor[i] = tag[i];
+        }
+        if let Some((_, parent)) = best {
+            parents.insert(tag, parent);
         }
     }
-    
-    // 标准实现（非AVX2或非x86_64架构）
-    let seq_str = String::from_utf8_lossy(seq);
-    // 预估每个序列可能产生的标签数量，减少重新分配
-    let mut tags = Vec::with_capacity(64);
-    // 使用FxHashSet进行去重，提高性能
-    let mut seen_tags = FxHashSet::default();
 
-    // 获取酶的标签长度
-    let tag_length = ENZYME_TAG_LENGTHS
+    parents
         .iter()
-        .find(|(name, _)| *name == enzyme.name)
-        .map(|(_, len)| *len)
-        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
-
-    for pattern in &enzyme.patterns {
-        for m in pattern.find_iter(&seq_str) {
-            let matched = m.as_str().as_bytes();
-            // 只保留酶切位点之间的序列
-            let tag = if matched.len() > tag_length {
-                let start = (matched.len() - tag_length) / 2;
-                matched[start..start + tag_length].to_vec()
-            } else {
-                matched.to_vec()
-            };
-            
-            // 获取 canonical 版本的 tag
-            let canonical_tag = get_canonical_sequence(&tag);
-            
-            // 使用FxHashSet进行去重
-            if seen_tags.insert(canonical_tag.clone()) {
-                tags.push(canonical_tag);
+        .map(|(&tag, parent)| {
+            let mut root = parent;
+            while let Some(next) = parents.get(root.as_slice()) {
+                root = next;
             }
+            (tag.to_vec(), root.clone())
+        })
+        .collect()
+}
+
+// --error-correct：FASTA中的标签换成纠错后的序列，与.sylsp中的哈希一致
+fn correct_fasta_tags(fa_entries: &mut [(String, TagHash)], corrections: &FxHashMap<TagHash, TagHash>) {
+    if corrections.is_empty() {
+        return;
+    }
+    for (_, tag) in fa_entries.iter_mut() {
+        if let Some(parent) = corrections.get(tag) {
+            tag.clone_from(parent);
         }
     }
-
-    Ok(tags)
 }
 
-fn write_tags(
-    writer: &mut dyn Write,
-    seq_id: &str,
-    tags: &[TagHash],
-    format: &str,
-) -> Result<()> {
-    for (i, tag) in tags.iter().enumerate() {
-        let header = format!("{}_tag{}", seq_id, i + 1);
-        match format {
-            "fq" => writeln!(writer, "@{}\n{}\n+\n{}", 
-                           header, 
-                           String::from_utf8_lossy(tag),
-                           "~".repeat(tag.len()))
-                .context("Failed to write FASTQ record")?,
-            _ => writeln!(writer, ">{}\n{}", 
-                        header, 
-                        String::from_utf8_lossy(tag))
-                .context("Failed to write FASTA record")?,
+// tag的碱基组成Shannon熵(bits)，范围0（均聚物）到2（四种碱基等量）
+pub fn tag_entropy(tag: &[u8]) -> f64 {
+    if tag.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 4];
+    for &b in tag {
+        match b {
+            b'A' => counts[0] += 1,
+            b'C' => counts[1] += 1,
+            b'G' => counts[2] += 1,
+            b'T' => counts[3] += 1,
+            _ => {}
         }
     }
-    Ok(())
+    let total = tag.len() as f64;
+    counts.iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
 }
 
-fn create_reader(path: &Path) -> Result<Box<dyn BufRead>> {
-    let file = File::open(path)
-        .context(format!("Failed to open input file: {}", path.display()))?;
-
-    // 使用优化的文件大小检测
-    let file_size = get_file_size_optimized(path)?;
-    let is_compressed = path.to_string_lossy().ends_with(".gz");
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyldbEntry {
+    pub sequence_id: String,
+    pub tags: Vec<Hash>,
+    pub positions: Vec<usize>,
+    pub genome_source: String,
+    // 新增字段：标记每个tag是否为unique（taxa-specific）
+    pub tag_uniqueness: Option<Vec<bool>>,
+    // 所属基因组的总长度(bp)，同一基因组的所有条目相同
+    pub gn_size: usize,
+    // --store-seq 时保存的canonical标签序列，与tags一一对应
+    pub tag_sequences: Option<Vec<String>>,
+    // 提取该条目所用的酶；不同酶的标签不可比较，query/profile据此拒绝混合库
+    pub enzyme: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SylspEntry {
+    pub sequence_id: String,
+    pub tag: Hash,
+    pub quality: Option<String>,
+    pub sample_source: String,
+    // --store-seq 时保存的canonical标签序列
+    pub sequence: Option<String>,
+}
+
+// ==================== .sylsp 文件格式头 ====================
+// 写在bincode数据之前，读取方据此直接分派，不再靠反序列化试错。
+// 旧文件以u64长度开头，不可能与magic冲突，因此没有格式头时按旧格式处理
+pub const SYLSP_MAGIC: [u8; 4] = *b"M2SP";
+// 版本2在kind之后追加标签抽样率（--subsample-rate），标签条目同时带上sequence字段（--store-seq）；
+// 版本3再追加每个样本的标签总数（extract --depth-normalize，未记录时为空）；
+// 版本4再追加提取所用的酶（k-mer sketch为空）
+pub const SYLSP_FORMAT_VERSION: u8 = 4;
+
+// 格式头中的全部信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct SylspHeader {
+    pub version: u8,
+    pub kind: SylspKind,
+    pub subsample_rate: u64,
+    // (sample_source, 标签观测数)，按样本名排序
+    pub sample_depths: Vec<(String, u64)>,
+    // 版本4之前没有记录，为空
+    pub enzyme: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SylspKind {
+    TagEntries,   // extract生成的Vec<SylspEntry>
+    SingleSketch, // sketch生成的单个SequencesSketch
+    SketchList,   // sketch合并生成的Vec<SequencesSketch>
+}
+
+// enzyme和subsample_rate为提取时的酶和--subsample-rate，query/profile据此检查样本与数据库是否可比；
+// k-mer sketch写空字符串和1
+pub fn write_sylsp_header<W: Write>(writer: &mut W, kind: SylspKind, enzyme: &str, subsample_rate: u64) -> Result<()> {
+    write_sylsp_header_with_depths(writer, kind, enzyme, subsample_rate, &[])
+}
+
+pub fn write_sylsp_header_with_depths<W: Write>(writer: &mut W, kind: SylspKind, enzyme: &str, subsample_rate: u64, sample_depths: &[(String, u64)]) -> Result<()> {
+    writer.write_all(&SYLSP_MAGIC)?;
+    writer.write_all(&[SYLSP_FORMAT_VERSION])?;
+    bincode::serialize_into(&mut *writer, &kind).context("Failed to write sylsp header")?;
+    bincode::serialize_into(&mut *writer, &subsample_rate).context("Failed to write sylsp header")?;
+    bincode::serialize_into(&mut *writer, sample_depths).context("Failed to write sylsp header")?;
+    bincode::serialize_into(writer, enzyme).context("Failed to write sylsp header")?;
+    Ok(())
+}
+
+// 读取格式头；旧文件没有格式头时返回None，且不消耗任何字节
+pub fn read_sylsp_header<R: BufRead>(reader: &mut R) -> Result<Option<SylspKind>> {
+    Ok(read_sylsp_header_info(reader)?.map(|(kind, _)| kind))
+}
+
+// 同read_sylsp_header，另外返回标签抽样率（版本1的文件没有记录，视为1）
+pub fn read_sylsp_header_info<R: BufRead>(reader: &mut R) -> Result<Option<(SylspKind, u64)>> {
+    Ok(read_sylsp_header_full(reader)?.map(|header| (header.kind, header.subsample_rate)))
+}
+
+// 完整读取格式头；没有格式头（旧格式）时返回None且不消耗任何字节
+pub fn read_sylsp_header_full<R: BufRead>(reader: &mut R) -> Result<Option<SylspHeader>> {
+    let buf = reader.fill_buf()?;
+    if buf.len() <= SYLSP_MAGIC.len() || buf[..SYLSP_MAGIC.len()] != SYLSP_MAGIC {
+        return Ok(None);
+    }
+
+    let version = buf[SYLSP_MAGIC.len()];
+    if version > SYLSP_FORMAT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported .sylsp format version {} (this build supports up to {})",
+            version, SYLSP_FORMAT_VERSION
+        ));
+    }
+    reader.consume(SYLSP_MAGIC.len() + 1);
+
+    let kind: SylspKind = bincode::deserialize_from(&mut *reader).context("Failed to read sylsp header")?;
+    let subsample_rate: u64 = if version >= 2 {
+        bincode::deserialize_from(&mut *reader).context("Failed to read sylsp header")?
+    } else {
+        1
+    };
+    let sample_depths: Vec<(String, u64)> = if version >= 3 {
+        bincode::deserialize_from(&mut *reader).context("Failed to read sylsp header")?
+    } else {
+        Vec::new()
+    };
+    let enzyme: String = if version >= 4 {
+        bincode::deserialize_from(reader).context("Failed to read sylsp header")?
+    } else {
+        String::new()
+    };
+    Ok(Some(SylspHeader { version, kind, subsample_rate, sample_depths, enzyme }))
+}
+
+// 没有格式头和版本1的文件是--store-seq之前写出的，条目没有sequence字段
+#[derive(Deserialize)]
+struct LegacySylspEntry {
+    sequence_id: String,
+    tag: Hash,
+    quality: Option<String>,
+    sample_source: String,
+}
+
+impl From<LegacySylspEntry> for SylspEntry {
+    fn from(entry: LegacySylspEntry) -> Self {
+        SylspEntry {
+            sequence_id: entry.sequence_id,
+            tag: entry.tag,
+            quality: entry.quality,
+            sample_source: entry.sample_source,
+            sequence: None,
+        }
+    }
+}
+
+fn sylsp_entries_have_sequence(header: Option<&SylspHeader>) -> bool {
+    header.is_some_and(|header| header.version >= 2)
+}
+
+// 读取格式头之后的全部标签条目；header为read_sylsp_header_full的结果，决定条目布局
+pub fn read_sylsp_entries<R: Read>(reader: R, header: Option<&SylspHeader>) -> Result<Vec<SylspEntry>> {
+    if sylsp_entries_have_sequence(header) {
+        return Ok(bincode::deserialize_from(reader)?);
+    }
+    let legacy: Vec<LegacySylspEntry> = bincode::deserialize_from(reader)?;
+    Ok(legacy.into_iter().map(SylspEntry::from).collect())
+}
+
+// 同read_sylsp_entries，但逐条交给visit，不把全部条目读入内存
+pub fn stream_sylsp_entries<R: Read, F: FnMut(SylspEntry)>(reader: R, header: Option<&SylspHeader>, mut visit: F) -> Result<usize> {
+    if sylsp_entries_have_sequence(header) {
+        stream_bincode_vec(reader, visit)
+    } else {
+        stream_bincode_vec(reader, |entry: LegacySylspEntry| visit(entry.into()))
+    }
+}
+
+// ==================== .syldb 文件格式头 ====================
+// 与.sylsp相同，magic和版本号写在bincode的Vec<SyldbEntry>之前。
+// 没有格式头的是基线版本的旧库，条目只有前五个字段，读取时按旧格式反序列化并补默认值
+pub const SYLDB_MAGIC: [u8; 4] = *b"M2DB";
+// 版本2在版本号之后追加标签抽样率（--subsample-rate）；版本3再追加条目粒度
+pub const SYLDB_FORMAT_VERSION: u8 = 3;
+
+// 数据库条目的粒度，建库时确定：extract从FASTA建库时每条记录（contig）一个条目，
+// --tag-fasta导入的标签FASTA每个文件（基因组）一个条目
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbGranularity {
+    Genome,
+    Contig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyldbHeader {
+    pub version: u8,
+    // 旧库和版本1没有记录，视为1（未抽样）
+    pub subsample_rate: u64,
+    // 版本3之前没有记录；那些库都由extract按记录建成，视为Contig
+    pub granularity: DbGranularity,
+}
+
+// 数据库的条目粒度；没有格式头的旧库同样按记录建成
+pub fn syldb_granularity(header: Option<&SyldbHeader>) -> DbGranularity {
+    header.map_or(DbGranularity::Contig, |header| header.granularity)
+}
+
+// 基线版本的条目布局，只用于读取旧库
+#[derive(Deserialize)]
+struct LegacySyldbEntry {
+    sequence_id: String,
+    tags: Vec<Hash>,
+    positions: Vec<usize>,
+    genome_source: String,
+    tag_uniqueness: Option<Vec<bool>>,
+}
+
+impl From<LegacySyldbEntry> for SyldbEntry {
+    // 旧库没有记录基因组大小、标签序列和酶
+    fn from(entry: LegacySyldbEntry) -> Self {
+        SyldbEntry {
+            sequence_id: entry.sequence_id,
+            tags: entry.tags,
+            positions: entry.positions,
+            genome_source: entry.genome_source,
+            tag_uniqueness: entry.tag_uniqueness,
+            gn_size: 0,
+            tag_sequences: None,
+            enzyme: String::new(),
+        }
+    }
+}
+
+pub fn write_syldb_header<W: Write>(writer: &mut W, subsample_rate: u64, granularity: DbGranularity) -> Result<()> {
+    writer.write_all(&SYLDB_MAGIC)?;
+    writer.write_all(&[SYLDB_FORMAT_VERSION])?;
+    bincode::serialize_into(&mut *writer, &subsample_rate).context("Failed to write syldb header")?;
+    bincode::serialize_into(writer, &granularity).context("Failed to write syldb header")?;
+    Ok(())
+}
+
+// 读取格式头；旧库没有格式头时返回None，且不消耗任何字节
+pub fn read_syldb_header<R: BufRead>(reader: &mut R) -> Result<Option<SyldbHeader>> {
+    let buf = reader.fill_buf()?;
+    if buf.len() <= SYLDB_MAGIC.len() || buf[..SYLDB_MAGIC.len()] != SYLDB_MAGIC {
+        return Ok(None);
+    }
+
+    let version = buf[SYLDB_MAGIC.len()];
+    if version > SYLDB_FORMAT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported .syldb format version {} (this build supports up to {})",
+            version, SYLDB_FORMAT_VERSION
+        ));
+    }
+    reader.consume(SYLDB_MAGIC.len() + 1);
+    let subsample_rate: u64 = if version >= 2 {
+        bincode::deserialize_from(&mut *reader).context("Failed to read syldb header")?
+    } else {
+        1
+    };
+    let granularity = if version >= 3 {
+        bincode::deserialize_from(reader).context("Failed to read syldb header")?
+    } else {
+        DbGranularity::Contig
+    };
+    Ok(Some(SyldbHeader { version, subsample_rate, granularity }))
+}
+
+// 写出完整的.syldb：格式头加全部条目
+pub fn write_syldb<W: Write>(writer: &mut W, subsample_rate: u64, granularity: DbGranularity, entries: &[SyldbEntry]) -> Result<()> {
+    write_syldb_header(writer, subsample_rate, granularity)?;
+    bincode::serialize_into(writer, entries).context("Failed to serialize syldb data")
+}
+
+// 读取.syldb的全部条目，同时返回格式头（旧库为None）
+pub fn read_syldb_with_header<R: BufRead>(mut reader: R) -> Result<(Option<SyldbHeader>, Vec<SyldbEntry>)> {
+    let header = read_syldb_header(&mut reader)?;
+    let entries = if header.is_some() {
+        bincode::deserialize_from(reader)?
+    } else {
+        let legacy: Vec<LegacySyldbEntry> = bincode::deserialize_from(reader)?;
+        legacy.into_iter().map(SyldbEntry::from).collect()
+    };
+    Ok((header, entries))
+}
+
+pub fn read_syldb<R: BufRead>(reader: R) -> Result<Vec<SyldbEntry>> {
+    Ok(read_syldb_with_header(reader)?.1)
+}
+
+// 数据库和样本必须以相同的--subsample-rate提取：抽样保留的是哈希值最小的一批标签，
+// 抽样率不同时两边的标签子集不同，共享标签数和ANI都会被低估
+pub fn check_subsample_rates(db_path: &str, db_header: Option<&SyldbHeader>, sample_path: &str, sample_header: Option<&SylspHeader>) -> Result<()> {
+    let db_rate = db_header.map_or(1, |header| header.subsample_rate);
+    let sample_rate = sample_header.map_or(1, |header| header.subsample_rate);
+    if db_rate != sample_rate {
+        return Err(anyhow::anyhow!(
+            "{} was extracted with --subsample-rate {} but {} with --subsample-rate {}; extract both with the same rate",
+            db_path, db_rate, sample_path, sample_rate
+        ));
+    }
+    Ok(())
+}
+
+// 样本与数据库须用同一种酶提取，否则标签集合互不相干，结果只会是零匹配；
+// 任一方没有记录酶（旧文件或k-mer sketch）时无法判断，放行
+pub fn check_sample_enzyme(db_path: &str, db_enzyme: Option<&str>, sample_path: &str, sample_header: Option<&SylspHeader>) -> Result<()> {
+    let sample_enzyme = sample_header.map_or("", |header| header.enzyme.as_str());
+    if let Some(db_enzyme) = db_enzyme {
+        if !sample_enzyme.is_empty() && sample_enzyme != db_enzyme {
+            return Err(anyhow::anyhow!(
+                "{} was built with enzyme {} but {} was extracted with {}; extract both with the same enzyme",
+                db_path, db_enzyme, sample_path, sample_enzyme
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 合并.sylsp中每个样本的标签观测数（--depth-normalize写入格式头）
+pub fn sample_tag_totals(entries: &[SylspEntry]) -> Vec<(String, u64)> {
+    let mut totals: FxHashMap<&str, u64> = FxHashMap::default();
+    for entry in entries {
+        *totals.entry(entry.sample_source.as_str()).or_insert(0) += 1;
+    }
+    let mut totals: Vec<(String, u64)> = totals.into_iter().map(|(source, n)| (source.to_string(), n)).collect();
+    totals.sort();
+    totals
+}
+
+// 逐条读取bincode序列化的Vec<T>：先读u64长度，再依次反序列化每个元素交给visit，
+// 整个向量不会同时驻留内存。返回元素个数
+pub fn stream_bincode_vec<T, R, F>(mut reader: R, mut visit: F) -> Result<usize>
+where
+    T: serde::de::DeserializeOwned,
+    R: Read,
+    F: FnMut(T),
+{
+    let len: u64 = bincode::deserialize_from(&mut reader).context("Failed to read entry count")?;
+    for i in 0..len {
+        let item: T = bincode::deserialize_from(&mut reader)
+            .with_context(|| format!("Failed to deserialize entry {} of {}", i + 1, len))?;
+        visit(item);
+    }
+    Ok(len as usize)
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Hash, PartialOrd, Eq, Ord, Default, Clone)]
+pub struct GenomeSketch {
+    pub file_name: String,
+    pub first_contig_name: String,
+    pub gn_size: usize,
+    pub c: usize,
+    pub k: usize,
+    pub min_spacing: usize,
+    pub genome_kmers: Vec<Hash>,
+}
+
+fn extract_and_validate_tags(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<TagHash>> {
+    Ok(without_positions(extract_positioned_tags(seq, enzyme)?))
+}
+
+// 每个标签附带其在序列中的起始坐标（0-based），按坐标升序；
+// 同一canonical标签在序列中出现多次时只保留第一次出现的位置
+fn extract_positioned_tags(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<(usize, TagHash)>> {
+    // long-read模式：识别位点允许错配，不能用精确正则
+    if enzyme.max_mismatches > 0 {
+        return extract_tags_fuzzy(seq, enzyme);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            unsafe {
+                return extract_positioned_tags_avx2(seq, enzyme);
+            }
+        }
+    }
+    
+    // 标准实现（非AVX2或非x86_64架构）
+    collect_tags_with(seq, enzyme, is_valid_dna_scalar)
+}
+
+fn without_positions(tags: Vec<(usize, TagHash)>) -> Vec<TagHash> {
+    tags.into_iter().map(|(_, tag)| tag).collect()
+}
+
+// 各识别模式的命中按坐标合并，再按canonical标签去重
+fn sort_and_dedup_positioned(mut candidates: Vec<(usize, TagHash)>) -> Vec<(usize, TagHash)> {
+    candidates.sort_by_key(|(offset, _)| *offset);
+    let mut seen_tags = FxHashSet::default();
+    candidates.retain(|(_, tag)| seen_tags.insert(tag.clone()));
+    candidates
+}
+
+fn is_valid_dna_scalar(seq: &[u8]) -> bool {
+    seq.iter().all(|&b| matches!(b, b'A' | b'C' | b'G' | b'T'))
+}
+
+#[cfg(test)]
+fn extract_tags_scalar(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<TagHash>> {
+    Ok(without_positions(collect_tags_with(seq, enzyme, is_valid_dna_scalar)?))
+}
+
+// 直接在原始字节上跑正则（regex::bytes），避免from_utf8_lossy对非UTF8字节的改写；
+// is_valid决定切出的tag是否只含ACGT
+fn collect_tags_with<F: Fn(&[u8]) -> bool>(seq: &[u8], enzyme: &EnzymeSpec, is_valid: F) -> Result<Vec<(usize, TagHash)>> {
+    // 预估每个序列可能产生的标签数量，减少重新分配
+    let mut tags = Vec::with_capacity(64);
+
+    // 获取酶的标签长度
+    let tag_length = ENZYME_TAG_LENGTHS
+        .iter()
+        .find(|(name, _)| *name == enzyme.name)
+        .map(|(_, len)| *len)
+        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
+
+    for pattern in &enzyme.patterns {
+        for m in pattern.find_iter(seq) {
+            let matched = m.as_bytes();
+            // 只保留酶切位点之间的序列
+            let (offset, tag) = if matched.len() > tag_length {
+                let start = enzyme.tag_start(matched.len(), tag_length);
+                (m.start() + start, &matched[start..start + tag_length])
+            } else {
+                (m.start(), matched)
+            };
+            if !is_valid(tag) || !enzyme.passes_tag_length(tag, tag_length) || !enzyme.passes_complexity(tag) || !enzyme.passes_adapter(tag) {
+                continue;
+            }
+
+            // 获取 canonical 版本的 tag（--strand-specific时保持原方向）
+            let canonical_tag = enzyme.orient_tag(tag);
+            if !enzyme.passes_subsample(&canonical_tag) {
+                continue;
+            }
+            tags.push((offset, canonical_tag));
+        }
+    }
+
+    Ok(sort_and_dedup_positioned(tags))
+}
+
+// 容错的识别位点扫描（用于错误率较高的长reads）：
+// 识别位点的核心（第一个到最后一个固定碱基）按有界编辑距离匹配，替换、插入、缺失各计1，
+// 自由位置匹配任意ACGT；两侧侧翼按原长度截取。核心按比对回溯还原成位点长度，
+// 标签长度与精确匹配一致。命中后跳过整个位点，与正则find_iter的不重叠语义一致
+fn extract_tags_fuzzy(seq: &[u8], enzyme: &EnzymeSpec) -> Result<Vec<(usize, TagHash)>> {
+    let mut tags = Vec::with_capacity(64);
+
+    let tag_length = ENZYME_TAG_LENGTHS
+        .iter()
+        .find(|(name, _)| *name == enzyme.name)
+        .map(|(_, len)| *len)
+        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
+    let max_edits = enzyme.max_mismatches;
+    let mut dp = Vec::new();
+
+    for masks in &enzyme.site_masks {
+        let (Some(first_fixed), Some(last_fixed)) = (
+            masks.iter().position(|&mask| mask != 0b1111),
+            masks.iter().rposition(|&mask| mask != 0b1111),
+        ) else {
+            continue;
+        };
+        let core = &masks[first_fixed..=last_fixed];
+        let right_flank = masks.len() - last_fixed - 1;
+
+        // core_start是核心在序列中的起点，左侧翼必须完整落在序列内
+        let mut core_start = first_fixed;
+        while core_start < seq.len() {
+            let Some((mut edits, mut consumed)) = align_site_core(core, &seq[core_start..], max_edits, &mut dp) else {
+                core_start += 1;
+                continue;
+            };
+            // 起点前移一位也可能以一次插入命中；在后面max_edits个起点里取编辑数最少的
+            let mut best_start = core_start;
+            for next in core_start + 1..=(core_start + max_edits).min(seq.len() - 1) {
+                if let Some((next_edits, next_consumed)) = align_site_core(core, &seq[next..], max_edits, &mut dp) {
+                    if next_edits < edits {
+                        (best_start, edits, consumed) = (next, next_edits, next_consumed);
+                    }
+                }
+            }
+            let site_start = best_start - first_fixed;
+            let site_end = best_start + consumed + right_flank;
+            if site_end > seq.len() {
+                core_start += 1;
+                continue;
+            }
+
+            // 重新计算最佳起点的比对矩阵用于回溯
+            align_site_core(core, &seq[best_start..], max_edits, &mut dp);
+            let mut site = Vec::with_capacity(masks.len());
+            site.extend_from_slice(&seq[site_start..best_start]);
+            site.extend(realign_site_core(core, &seq[best_start..], consumed, &dp));
+            site.extend_from_slice(&seq[best_start + consumed..site_end]);
+            core_start = site_end + first_fixed;
+
+            if !site.iter().all(|&b| base_mask(b) != 0) {
+                continue;
+            }
+            let (offset, tag) = if site.len() > tag_length {
+                let start = enzyme.tag_start(site.len(), tag_length);
+                (site_start + start, &site[start..start + tag_length])
+            } else {
+                (site_start, &site[..])
+            };
+
+            if enzyme.passes_tag_length(tag, tag_length) && enzyme.passes_complexity(tag) && enzyme.passes_adapter(tag) {
+                let canonical_tag = enzyme.orient_tag(tag);
+                if enzyme.passes_subsample(&canonical_tag) {
+                    tags.push((offset, canonical_tag));
+                }
+            }
+        }
+    }
+
+    Ok(sort_and_dedup_positioned(tags))
+}
+
+// 识别位点核心与text开头一段的有界编辑距离（只算|行-列|<=max_edits的带内单元，
+// 整行都超过max_edits时提前结束）。返回(编辑数, 消耗的text长度)，同分时取最接近核心长度的；
+// dp保存比对矩阵供realign_site_core回溯
+fn align_site_core(core: &[u8], text: &[u8], max_edits: usize, dp: &mut Vec<usize>) -> Option<(usize, usize)> {
+    const FAR: usize = usize::MAX / 2;
+    let rows = core.len() + 1;
+    let cols = (core.len() + max_edits).min(text.len()) + 1;
+    if cols - 1 + max_edits < core.len() {
+        return None;
+    }
+    dp.clear();
+    dp.resize(rows * cols, FAR);
+    for (t, cell) in dp.iter_mut().take(cols).enumerate() {
+        *cell = t;
+    }
+    for j in 1..rows {
+        dp[j * cols] = j;
+        let mut row_min = j;
+        for t in j.saturating_sub(max_edits).max(1)..=(j + max_edits).min(cols - 1) {
+            let cost = usize::from(base_mask(text[t - 1]) & core[j - 1] == 0);
+            let cell = (dp[(j - 1) * cols + t - 1] + cost)
+                .min(dp[(j - 1) * cols + t] + 1)
+                .min(dp[j * cols + t - 1] + 1);
+            dp[j * cols + t] = cell;
+            row_min = row_min.min(cell);
+        }
+        if row_min > max_edits {
+            return None;
+        }
+    }
+
+    let last_row = core.len() * cols;
+    (core.len().saturating_sub(max_edits)..cols)
+        .map(|t| (dp[last_row + t], t.abs_diff(core.len()), t))
+        .min()
+        .filter(|&(edits, _, _)| edits <= max_edits)
+        .map(|(edits, _, consumed)| (edits, consumed))
+}
+
+// 按align_site_core的矩阵回溯，把text中比对上的核心还原成核心长度：
+// 替换保留read中的碱基，插入的碱基丢弃，缺失的固定碱基按位点补回；
+// 缺失的自由位置无法恢复，记为N（该位点随后被当作含非ACGT碱基丢弃）
+fn realign_site_core(core: &[u8], text: &[u8], consumed: usize, dp: &[usize]) -> Vec<u8> {
+    let cols = dp.len() / (core.len() + 1);
+    let mut realigned = vec![b'N'; core.len()];
+    let (mut j, mut t) = (core.len(), consumed);
+    while j > 0 {
+        let cell = dp[j * cols + t];
+        if t > 0 && cell == dp[(j - 1) * cols + t - 1] + usize::from(base_mask(text[t - 1]) & core[j - 1] == 0) {
+            realigned[j - 1] = text[t - 1];
+            j -= 1;
+            t -= 1;
+        } else if cell == dp[(j - 1) * cols + t] + 1 {
+            realigned[j - 1] = match core[j - 1] {
+                1 => b'A',
+                2 => b'C',
+                4 => b'G',
+                8 => b'T',
+                _ => b'N',
+            };
+            j -= 1;
+        } else {
+            t -= 1;
+        }
+    }
+    realigned
+}
+
+// 识别位点没有产生有效标签的原因（--recognition-site-report）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiteRejection {
+    // 位点内含N等非ACGT碱基
+    AmbiguousBase,
+    // 固定碱基在序列内但侧翼超出序列端点（片段太短）
+    Truncated,
+    LowComplexity,
+    Adapter,
+    Subsampled,
+    // 同一序列内已出现过的canonical标签
+    Duplicate,
+}
+
+// 与extract_and_validate_tags相同的切取/校验规则，但按固定碱基定位识别位点（侧翼允许任意字节，
+// 允许超出序列端点），从而能统计因N或短片段丢失的位点。返回有效标签和每个丢失位点的原因
+pub fn extract_tags_with_rejections(seq: &[u8], enzyme: &EnzymeSpec) -> Result<(Vec<TagHash>, Vec<SiteRejection>)> {
+    let mut tags = Vec::new();
+    let mut rejections = Vec::new();
+    let mut seen_tags = FxHashSet::default();
+
+    let tag_length = ENZYME_TAG_LENGTHS
+        .iter()
+        .find(|(name, _)| *name == enzyme.name)
+        .map(|(_, len)| *len)
+        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
+
+    for masks in &enzyme.site_masks {
+        let site_len = masks.len() as isize;
+        let fixed: Vec<(isize, u8)> = masks.iter().enumerate()
+            .filter(|(_, &mask)| mask != 0b1111)
+            .map(|(offset, &mask)| (offset as isize, mask))
+            .collect();
+        let (Some(first_fixed), Some(last_fixed)) = (fixed.first().map(|f| f.0), fixed.last().map(|f| f.0)) else {
+            continue;
+        };
+        let seq_len = seq.len() as isize;
+
+        // 窗口起点可以为负：只要固定碱基全部落在序列内就算找到位点
+        let mut i = -first_fixed;
+        while i + last_fixed < seq_len {
+            let mismatches = fixed.iter()
+                .filter(|&&(offset, mask)| base_mask(seq[(i + offset) as usize]) & mask == 0)
+                .count();
+            if mismatches > enzyme.max_mismatches {
+                i += 1;
+                continue;
+            }
+
+            if i < 0 || i + site_len > seq_len {
+                rejections.push(SiteRejection::Truncated);
+                i += site_len;
+                continue;
+            }
+            let window = &seq[i as usize..(i + site_len) as usize];
+            i += site_len;
+            if !window.iter().all(|&b| base_mask(b) != 0) {
+                rejections.push(SiteRejection::AmbiguousBase);
+                continue;
+            }
+
+            let tag = if window.len() > tag_length {
+                let start = enzyme.tag_start(window.len(), tag_length);
+                &window[start..start + tag_length]
+            } else {
+                window
+            };
+            if !enzyme.passes_complexity(tag) {
+                rejections.push(SiteRejection::LowComplexity);
+                continue;
+            }
+            if !enzyme.passes_adapter(tag) {
+                rejections.push(SiteRejection::Adapter);
+                continue;
+            }
+            let canonical_tag = enzyme.orient_tag(tag);
+            if !enzyme.passes_subsample(&canonical_tag) {
+                rejections.push(SiteRejection::Subsampled);
+                continue;
+            }
+            if seen_tags.insert(canonical_tag.clone()) {
+                tags.push(canonical_tag);
+            } else {
+                rejections.push(SiteRejection::Duplicate);
+            }
+        }
+    }
+
+    Ok((tags, rejections))
+}
+
+// 单个输入文件的识别位点统计：每个位点恰好计入valid_tags或一种丢失原因
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SiteReport {
+    pub sequences: usize,
+    pub sites: usize,
+    pub valid_tags: usize,
+    pub ambiguous_base: usize,
+    pub truncated: usize,
+    pub low_complexity: usize,
+    pub adapter: usize,
+    pub subsampled: usize,
+    pub duplicate: usize,
+}
+
+impl SiteReport {
+    fn add_sequence(&mut self, seq: &[u8], enzyme: &EnzymeSpec) -> Result<()> {
+        let (tags, rejections) = extract_tags_with_rejections(seq, enzyme)?;
+        self.sequences += 1;
+        self.valid_tags += tags.len();
+        self.sites += tags.len() + rejections.len();
+        for rejection in rejections {
+            let counter = match rejection {
+                SiteRejection::AmbiguousBase => &mut self.ambiguous_base,
+                SiteRejection::Truncated => &mut self.truncated,
+                SiteRejection::LowComplexity => &mut self.low_complexity,
+                SiteRejection::Adapter => &mut self.adapter,
+                SiteRejection::Subsampled => &mut self.subsampled,
+                SiteRejection::Duplicate => &mut self.duplicate,
+            };
+            *counter += 1;
+        }
+        Ok(())
+    }
+
+    // 酶切效率：产生有效标签的位点比例
+    fn efficiency(&self) -> f64 {
+        if self.sites == 0 {
+            0.0
+        } else {
+            self.valid_tags as f64 / self.sites as f64
+        }
+    }
+}
+
+fn site_report_for_file(path: &str, enzyme: &EnzymeSpec) -> Result<SiteReport> {
+    let mut reader = parse_fastx_file(path)
+        .map_err(|e| anyhow::anyhow!("{} is not a valid fasta/fastq file: {}", path, e))?;
+    let mut report = SiteReport::default();
+    while let Some(record) = reader.next() {
+        let record = record.map_err(|e| anyhow::anyhow!("Invalid record in {}: {}", path, e))?;
+        report.add_sequence(&record.seq(), enzyme)?;
+    }
+    Ok(report)
+}
+
+const SITE_REPORT_HEADER: &str = "Input\tEnzyme\tSequences\tRecognition_sites\tValid_tags\tEfficiency\tAmbiguous_base\tTruncated\tLow_complexity\tAdapter\tSubsampled\tDuplicate";
+
+fn write_site_report(output_options: &OutputOptions, path: &str, enzyme: &EnzymeSpec, rows: &[(String, SiteReport)]) -> Result<()> {
+    output_options.write_atomically(path, |writer| {
+        writeln!(writer, "{}", SITE_REPORT_HEADER)?;
+        for (input, r) in rows {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{:.4}\t{}\t{}\t{}\t{}\t{}\t{}",
+                input, enzyme.name, r.sequences, r.sites, r.valid_tags, r.efficiency(),
+                r.ambiguous_base, r.truncated, r.low_complexity, r.adapter, r.subsampled, r.duplicate
+            )?;
+        }
+        Ok(())
+    })
+    .context(format!("Failed to write recognition site report: {}", path))
+}
+
+fn create_reader(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path)
+        .context(format!("Failed to open input file: {}", path.display()))?;
+
+    // 使用优化的文件大小检测
+    let file_size = get_file_size_optimized(path)?;
+    let is_compressed = path.to_string_lossy().ends_with(".gz");
     
     // 使用优化的缓冲区大小计算
     let buffer_size = calculate_optimal_buffer_size(file_size, is_compressed);
@@ -742,8 +1551,8 @@ fn create_reader(path: &Path) -> Result<Box<dyn BufRead>> {
     })
 }
 
-fn create_writer(path: &Path, compress: bool) -> Result<Box<dyn Write>> {
-    let file = File::create(path)
+fn create_writer(output_options: &OutputOptions, path: &Path, compress: bool) -> Result<Box<dyn Write>> {
+    let file = output_options.create_file(path)
         .context(format!("Failed to create output file: {}", path.display()))?;
 
     // 使用优化的缓冲区大小和压缩设置
@@ -782,11 +1591,7 @@ impl ExtractionStats {
 
 fn log_stats(stats: ExtractionStats, enzyme: &EnzymeSpec) {
     let k = enzyme.patterns[0].as_str().len();
-    let total_kmers = if stats.total_sequence_length >= (k - 1) * stats.total_sequences {
-        stats.total_sequence_length - (k - 1) * stats.total_sequences
-    } else {
-        0
-    };
+    let total_kmers = stats.total_sequence_length.saturating_sub((k - 1) * stats.total_sequences);
     let percentage = calculate_tag_percentage(stats.total_tags, total_kmers);
     
     // 获取酶的标签长度
@@ -828,12 +1633,17 @@ fn log_stats(stats: ExtractionStats, enzyme: &EnzymeSpec) {
 }
 
 // 新增函数：处理单对双端测序文件
+#[allow(clippy::too_many_arguments)]
 fn process_paired_fastq_files(
+    output_options: &OutputOptions,
     first_file: &str,
     second_file: &str,
     enzyme: &EnzymeSpec,
-    _sample_output_dir: &Path,
-    _out_name: Option<&str>,
+    sample_output_dir: &Path,
+    out_name: Option<&str>,
+    store_seq: bool,
+    per_sample_file: bool,
+    merge_pairs: bool,
 ) -> Result<()> {
     // 从文件名中提取样本名
     let file_stem = Path::new(first_file)
@@ -851,12 +1661,13 @@ fn process_paired_fastq_files(
         second_file,
         enzyme,
         &file_stem,
+        merge_pairs,
     )?;
 
     // 注释掉生成单个文件的代码 - 只保留合并后的文件
     // let output_base = Path::new(sample_output_dir).join(&file_stem);
     // let fa_path = output_base.with_extension("fa");
-    // let mut fa_writer = create_writer(&fa_path, false)?;
+    // let mut fa_writer = create_writer(&output_options, &fa_path, false)?;
 
     let mut sylsp_entries = Vec::new();
     for (id, tag, sample_source) in &fa_entries {
@@ -866,79 +1677,563 @@ fn process_paired_fastq_files(
 
         let entry = SylspEntry {
             sequence_id: id.clone(),
-            tag: hash_bytes(tag),
+            tag: enzyme.hash_tag(tag),
             quality: None,
             sample_source: sample_source.clone(),
+            sequence: enzyme.keeps_sequences(store_seq).then(|| String::from_utf8_lossy(tag).to_string()),
         };
         sylsp_entries.push(entry.clone());
     }
+    enzyme.correct_tag_errors(&mut sylsp_entries, store_seq);
 
-    // 注释掉生成单个sylsp文件的代码
-    // let sylsp_path = if let Some(name) = out_name {
-    //     Path::new(sample_output_dir).join(format!("{}.sylsp", name))
-    // } else {
-    //     output_base.with_extension("sylsp")
-    // };
-    // 
-    // let sylsp_file = File::create(&sylsp_path)
-    //     .context(format!("Failed to create sylsp file: {}", sylsp_path.display()))?;
-    // let sylsp_writer = BufWriter::new(sylsp_file);
-    // bincode::serialize_into(sylsp_writer, &sylsp_entries)
-    //     .context("Failed to serialize sylsp data")?;
+    if per_sample_file {
+        let sylsp_path = sample_output_dir.join(format!("{}.sylsp", out_name.unwrap_or(&file_stem)));
+        write_individual_sylsp(output_options, &sylsp_path, enzyme, &sylsp_entries)?;
+    }
+
+    Ok(())
+}
+
+// --subsample-tags：每个基因组最多保留max_tags个标签，取mm_hash64最小的那些，结果可复现。
+// positions / tag_uniqueness / tag_sequences 与 tags 同步过滤
+pub fn subsample_tags_per_genome(entries: &mut [SyldbEntry], max_tags: usize) {
+    let mut by_genome: FxHashMap<String, Vec<(u64, usize, usize)>> = FxHashMap::default();
+    for (entry_idx, entry) in entries.iter().enumerate() {
+        let candidates = by_genome.entry(entry.genome_source.clone()).or_default();
+        for (tag_idx, tag) in entry.tags.iter().enumerate() {
+            candidates.push((mm_hash64(*tag), entry_idx, tag_idx));
+        }
+    }
+
+    let mut keep: FxHashSet<(usize, usize)> = FxHashSet::default();
+    for candidates in by_genome.values_mut() {
+        candidates.sort_unstable();
+        keep.extend(candidates.iter().take(max_tags).map(|&(_, entry_idx, tag_idx)| (entry_idx, tag_idx)));
+    }
+
+    for (entry_idx, entry) in entries.iter_mut().enumerate() {
+        let kept: Vec<bool> = (0..entry.tags.len()).map(|tag_idx| keep.contains(&(entry_idx, tag_idx))).collect();
+        retain_aligned(&mut entry.tags, &kept);
+        if entry.positions.len() == kept.len() {
+            retain_aligned(&mut entry.positions, &kept);
+        }
+        if let Some(uniqueness) = entry.tag_uniqueness.as_mut() {
+            retain_aligned(uniqueness, &kept);
+        }
+        if let Some(sequences) = entry.tag_sequences.as_mut() {
+            retain_aligned(sequences, &kept);
+        }
+    }
+}
+
+fn retain_aligned<T>(values: &mut Vec<T>, kept: &[bool]) {
+    let mut flags = kept.iter();
+    values.retain(|_| *flags.next().unwrap_or(&false));
+}
+
+// --keep-individual：每个输入文件单独的输出，与合并文件格式相同
+fn write_individual_sylsp(output_options: &OutputOptions, path: &Path, enzyme: &EnzymeSpec, entries: &[SylspEntry]) -> Result<()> {
+    output_options.write_atomically(path, |writer| {
+        write_sylsp_header(writer, SylspKind::TagEntries, &enzyme.name, enzyme.subsample_rate)?;
+        bincode::serialize_into(writer, entries)
+            .context("Failed to serialize sylsp data")
+    })
+    .context(format!("Failed to write sylsp file: {}", path.display()))
+}
+
+// --index-sequences：hash -> canonical标签序列的旁路索引，写在输出文件旁（<输出>.seqidx），
+// inspect据此显示真实序列，主文件不必带--store-seq
+pub type SequenceIndex = FxHashMap<Hash, String>;
+
+pub fn sequence_index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".seqidx");
+    PathBuf::from(name)
+}
+
+// 同一hash对应不同序列即FNV碰撞：保留先出现的序列，返回碰撞的hash数
+fn build_sequence_index<'a>(pairs: impl Iterator<Item = (Hash, &'a str)>) -> (SequenceIndex, usize) {
+    let mut index = SequenceIndex::default();
+    let mut collided: FxHashSet<Hash> = FxHashSet::default();
+    for (hash, sequence) in pairs {
+        match index.get(&hash) {
+            Some(existing) if existing != sequence => {
+                collided.insert(hash);
+            }
+            Some(_) => {}
+            None => {
+                index.insert(hash, sequence.to_string());
+            }
+        }
+    }
+    (index, collided.len())
+}
+
+fn write_sequence_index(output_options: &OutputOptions, output_path: &Path, index: &SequenceIndex, collisions: usize) -> Result<()> {
+    if collisions > 0 {
+        eprintln!("Warning: {} tag hashes map to more than one sequence (hash collision); the index keeps the first sequence seen", collisions);
+    }
+    let index_path = sequence_index_path(output_path);
+    output_options.write_atomically(&index_path, |writer| {
+        bincode::serialize_into(writer, index)
+            .context("Failed to serialize sequence index")
+    })
+    .context(format!("Failed to write sequence index: {}", index_path.display()))?;
+    eprintln!("Sequence index ({} tags) saved to: {}", index.len(), index_path.display());
+    Ok(())
+}
+
+// 读取数据文件旁的索引，不存在时返回None
+pub fn read_sequence_index(data_path: &Path) -> Result<Option<SequenceIndex>> {
+    let index_path = sequence_index_path(data_path);
+    if !index_path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(&index_path)
+        .with_context(|| format!("Failed to open sequence index: {}", index_path.display()))?;
+    let index = bincode::deserialize_from(BufReader::new(file))
+        .with_context(|| format!("Failed to deserialize sequence index: {}", index_path.display()))?;
+    Ok(Some(index))
+}
+
+// 提取时序列临时保存在条目里；未要求--store-seq时写完索引就从主文件中去掉
+fn index_sylsp_sequences(output_options: &OutputOptions, output_path: &Path, entries: &mut [SylspEntry], keep_sequences: bool) -> Result<()> {
+    let (index, collisions) = build_sequence_index(
+        entries.iter().filter_map(|e| e.sequence.as_deref().map(|seq| (e.tag, seq))),
+    );
+    write_sequence_index(output_options, output_path, &index, collisions)?;
+    if !keep_sequences {
+        entries.iter_mut().for_each(|e| e.sequence = None);
+    }
+    Ok(())
+}
 
+fn index_syldb_sequences(output_options: &OutputOptions, output_path: &Path, entries: &mut [SyldbEntry], keep_sequences: bool) -> Result<()> {
+    let (index, collisions) = build_sequence_index(entries.iter().flat_map(|e| {
+        e.tags.iter().copied().zip(e.tag_sequences.iter().flatten().map(|seq| seq.as_str()))
+    }));
+    write_sequence_index(output_options, output_path, &index, collisions)?;
+    if !keep_sequences {
+        entries.iter_mut().for_each(|e| e.tag_sequences = None);
+    }
     Ok(())
 }
 
+fn write_individual_syldb(output_options: &OutputOptions, path: &Path, enzyme: &EnzymeSpec, entries: &[SyldbEntry]) -> Result<()> {
+    output_options.write_atomically(path, |writer| {
+        write_syldb(writer, enzyme.subsample_rate, DbGranularity::Contig, entries)
+    })
+    .context(format!("Failed to write syldb file: {}", path.display()))
+}
+
+// --append：把新提取的基因组条目追加到已有的.syldb。
+// 已有库记录了酶时直接比较；否则只能在已有库带--store-seq序列时比较标签长度。已有库中与新基因组同源的条目会被替换。
+// mark的唯一性标记只对构建时的基因组集合有效，追加后一律清除
+fn append_to_existing_syldb(existing_path: &str, new_entries: Vec<SyldbEntry>, enzyme: &EnzymeSpec) -> Result<Vec<SyldbEntry>> {
+    let file = File::open(existing_path)
+        .with_context(|| format!("Failed to open database to append to: {}", existing_path))?;
+    let (header, mut existing) = read_syldb_with_header(BufReader::new(file))
+        .with_context(|| format!("Failed to deserialize database to append to: {}", existing_path))?;
+    let existing_rate = header.as_ref().map_or(1, |header| header.subsample_rate);
+    if existing_rate != enzyme.subsample_rate {
+        return Err(anyhow::anyhow!(
+            "{} was extracted with --subsample-rate {} but the new genomes use {}; the databases would keep different tag subsets",
+            existing_path, existing_rate, enzyme.subsample_rate
+        ));
+    }
+    if syldb_granularity(header.as_ref()) != DbGranularity::Contig {
+        return Err(anyhow::anyhow!(
+            "{} holds one entry per genome (imported with --tag-fasta) but the new genomes have one entry per record; rebuild it from the genome FASTAs",
+            existing_path
+        ));
+    }
+
+    if let Some(existing_enzyme) = database_enzyme(&existing).with_context(|| format!("Cannot append to {}", existing_path))? {
+        if existing_enzyme != enzyme.name {
+            return Err(anyhow::anyhow!(
+                "{} was extracted with {} but the new genomes use {}; tags from different enzymes are not comparable",
+                existing_path, existing_enzyme, enzyme.name
+            ));
+        }
+    }
+
+    let tag_length = ENZYME_TAG_LENGTHS
+        .iter()
+        .find(|(name, _)| *name == enzyme.name)
+        .map(|(_, len)| *len)
+        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
+    let existing_length = existing.iter()
+        .filter_map(|e| e.tag_sequences.as_ref())
+        .flat_map(|seqs| seqs.first())
+        .map(|seq| seq.len())
+        .next();
+    match existing_length {
+        Some(len) if len != tag_length => {
+            return Err(anyhow::anyhow!(
+                "{} has {}bp tags but {} produces {}bp tags; the existing database was built with a different enzyme",
+                existing_path, len, enzyme.name, tag_length
+            ));
+        }
+        Some(_) => {}
+        None => eprintln!(
+            "Warning: {} has no stored tag sequences, so its enzyme cannot be checked; make sure it was also extracted with {}",
+            existing_path, enzyme.name
+        ),
+    }
+
+    let new_sources: FxHashSet<&str> = new_entries.iter().map(|e| e.genome_source.as_str()).collect();
+    let before = existing.len();
+    existing.retain(|e| !new_sources.contains(e.genome_source.as_str()));
+    if existing.len() < before {
+        eprintln!("Warning: replacing {} existing entries for genomes that are being re-added", before - existing.len());
+    }
+
+    if existing.iter().any(|e| e.tag_uniqueness.is_some()) {
+        eprintln!("Warning: {} was marked; uniqueness marks are stale after appending and have been cleared. Re-run mark on the output", existing_path);
+        for entry in &mut existing {
+            entry.tag_uniqueness = None;
+        }
+    }
+
+    eprintln!("Appending {} entries to {} existing entries from {}", new_entries.len(), existing.len(), existing_path);
+    existing.extend(new_entries);
+    Ok(existing)
+}
+
+// 数据库条目记录的酶：所有条目一致时返回该酶，混合了多种酶时报错。
+// 旧版本构建的条目没有记录酶（空字符串），不参与判断；全部未记录时返回None
+pub fn database_enzyme(entries: &[SyldbEntry]) -> Result<Option<String>> {
+    let mut genomes_per_enzyme: std::collections::BTreeMap<&str, FxHashSet<&str>> = std::collections::BTreeMap::new();
+    for entry in entries.iter().filter(|e| !e.enzyme.is_empty()) {
+        genomes_per_enzyme.entry(entry.enzyme.as_str()).or_default().insert(entry.genome_source.as_str());
+    }
+    if genomes_per_enzyme.len() > 1 {
+        let summary: Vec<String> = genomes_per_enzyme.iter()
+            .map(|(name, genomes)| format!("{} ({} genomes)", name, genomes.len()))
+            .collect();
+        return Err(anyhow::anyhow!(
+            "Database mixes genomes extracted with different enzymes: {}. Tags from different enzymes are not comparable; rebuild the database with a single --enzyme",
+            summary.join(", ")
+        ));
+    }
+    Ok(genomes_per_enzyme.into_keys().next().map(str::to_string))
+}
+
+// --prefilter-db：读取参考面板的全部标签哈希；面板必须与-e是同一种酶，否则哈希不可比
+fn load_prefilter_panel(path: &str, enzyme_name: &str) -> Result<Arc<FxHashSet<Hash>>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open --prefilter-db: {}", path))?;
+    let entries = read_syldb(BufReader::new(file))
+        .with_context(|| format!("Failed to deserialize --prefilter-db: {}", path))?;
+    if let Some(panel_enzyme) = database_enzyme(&entries).with_context(|| format!("Cannot use {} as --prefilter-db", path))? {
+        if panel_enzyme != enzyme_name {
+            return Err(anyhow::anyhow!(
+                "--prefilter-db {} was extracted with {} but reads use {}; tags from different enzymes are not comparable",
+                path, panel_enzyme, enzyme_name
+            ));
+        }
+    }
+    let tags: FxHashSet<Hash> = entries.iter().flat_map(|entry| entry.tags.iter().copied()).collect();
+    eprintln!("Loaded {} panel tags from {}", tags.len(), path);
+    Ok(Arc::new(tags))
+}
+
+// reads使用的酶规格：--long-read 时识别位点的固定碱基允许少量错配
+fn read_enzyme_spec(enzyme_name: &str, long_read: bool, max_site_mismatches: usize, strand_specific: bool) -> Result<EnzymeSpec> {
+    let enzyme = EnzymeSpec::new(enzyme_name)?.with_strand_specific(strand_specific);
+    if long_read {
+        // 0表示不容错，回到精确的正则匹配
+        Ok(enzyme.with_max_mismatches(max_site_mismatches))
+    } else {
+        Ok(enzyme)
+    }
+}
+
+// 酶规格作用的输入：reads另外使用--long-read位点容错、--min-read-length、--error-correct和已加载的--prefilter-db面板
+#[derive(Clone, Copy)]
+enum ExtractInput<'a> {
+    Genomes,
+    Reads(Option<&'a Arc<FxHashSet<Hash>>>),
+}
+
+// 按extract参数构建酶规格，所有输入类型共用这一处，新增的标签选项不会漏掉某条路径
+fn enzyme_spec_from_args(args: &ExtractArgs, input: ExtractInput) -> Result<EnzymeSpec> {
+    let enzyme = match input {
+        ExtractInput::Genomes => EnzymeSpec::new(&args.enzyme)?.with_strand_specific(args.strand_specific),
+        ExtractInput::Reads(_) => read_enzyme_spec(&args.enzyme, args.long_read, args.max_site_mismatches, args.strand_specific)?,
+    };
+    let enzyme = enzyme
+        .with_min_complexity(args.min_complexity)
+        .with_subsample_rate(args.subsample_rate)
+        .with_fnv_tag_hash(args.fnv_tag_hash)
+        .with_adapters(&args.adapter, args.flag_adapters)?
+        .with_tag_offset(args.tag_offset)
+        .with_strict_length(args.strict_length);
+    Ok(match input {
+        ExtractInput::Genomes => enzyme,
+        ExtractInput::Reads(prefilter) => enzyme
+            .with_min_read_length(args.min_read_length)
+            .with_error_correction(args.error_correct.then_some(args.error_correct_ratio))
+            .with_prefilter(prefilter.cloned()),
+    })
+}
+
+// 运行前解析出的输入/输出计划：--dry-run 只打印它，正常运行也按它处理，保证两者一致
+#[derive(Debug, Default, PartialEq)]
+pub struct ExtractPlan {
+    // -1/-2
+    pub pairs: Vec<(String, String)>,
+    // --l1/--l2
+    pub list_pairs: Vec<(String, String)>,
+    // -r
+    pub reads: Vec<String>,
+    // -s
+    pub sample_list_reads: Vec<String>,
+    // -k
+    pub genome_list_genomes: Vec<String>,
+    // -g
+    pub genomes: Vec<String>,
+    // --genome-archive（成员在运行时才读取）
+    pub genome_archive: Option<String>,
+    // --tag-fasta
+    pub tag_fastas: Vec<String>,
+    pub outputs: Vec<PathBuf>,
+}
+
+// 文件名去掉所有扩展名后作为样本名（a.fq.gz -> a）
+fn sample_stem(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown")
+        .split('.')
+        .next()
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+// 单个基因组的 .syldb 路径，与 process_fasta_to_syldb 的 output_base 命名一致
+fn individual_syldb_path(sample_output_dir: &str, genome_file: &str) -> PathBuf {
+    Path::new(sample_output_dir)
+        .join(Path::new(genome_file).file_stem().unwrap_or_default())
+        .with_extension("syldb")
+}
+
+pub fn plan_extract(args: &ExtractArgs) -> Result<ExtractPlan> {
+    let mut plan = ExtractPlan::default();
+    let out_dir = Path::new(&args.sample_output_dir);
+    let combined_name = args.out_name.clone().unwrap_or_else(|| "combined".to_string());
+    // reads输入的单样本文件：--keep-individual 与合并文件并存，--separate-samples 取代合并文件
+    let per_sample_files = args.keep_individual || args.separate_samples;
+
+    if !args.first_pair.is_empty() && !args.second_pair.is_empty() {
+        plan.pairs = args.first_pair.iter().cloned().zip(args.second_pair.iter().cloned()).collect();
+        if per_sample_files {
+            for (first, _) in &plan.pairs {
+                let name = args.out_name.clone().unwrap_or_else(|| sample_stem(first));
+                plan.outputs.push(out_dir.join(format!("{}.sylsp", name)));
+            }
+        }
+    }
+
+    if let (Some(first_pair_list), Some(second_pair_list)) = (&args.first_pair_list, &args.second_pair_list) {
+        let first_pairs = read_file_list(first_pair_list)
+            .context("Failed to read first pair list")?;
+        let second_pairs = read_file_list(second_pair_list)
+            .context("Failed to read second pair list")?;
+        if first_pairs.len() != second_pairs.len() {
+            return Err(anyhow::anyhow!("Number of files in first pair list and second pair list do not match"));
+        }
+        plan.list_pairs = first_pairs.into_iter().zip(second_pairs).collect();
+        if per_sample_files {
+            for (first, _) in &plan.list_pairs {
+                plan.outputs.push(out_dir.join(format!("{}.sylsp", sample_stem(first))));
+            }
+        }
+        if !args.separate_samples {
+            plan.outputs.push(out_dir.join(format!("{}.sylsp", combined_name)));
+        }
+    }
+
+    if let Some(reads) = &args.reads {
+        plan.reads = reads.clone();
+        if per_sample_files {
+            for file in &plan.reads {
+                plan.outputs.push(out_dir.join(format!("{}.sylsp", sample_stem(file))));
+            }
+        }
+        let output_name = args.out_name.clone().unwrap_or_else(|| "reads".to_string());
+        plan.outputs.push(out_dir.join(format!("{}.fasta", output_name)));
+        if !args.separate_samples {
+            plan.outputs.push(out_dir.join(format!("{}.sylsp", output_name)));
+        }
+    }
+
+    if let Some(genome_list) = &args.genome_list {
+        plan.genome_list_genomes = read_file_list(genome_list)
+            .context(format!("Failed to open genome list file: {}", genome_list))?;
+        if args.keep_individual {
+            for file in &plan.genome_list_genomes {
+                plan.outputs.push(individual_syldb_path(&args.sample_output_dir, file));
+            }
+        }
+        plan.outputs.push(out_dir.join(format!("{}.syldb", combined_name)));
+    }
+
+    if let Some(genomes) = &args.genomes {
+        plan.genomes = genomes.clone();
+        if args.keep_individual {
+            for file in &plan.genomes {
+                plan.outputs.push(individual_syldb_path(&args.sample_output_dir, file));
+            }
+        }
+        plan.outputs.push(out_dir.join(format!("{}.syldb", combined_name)));
+    }
+
+    if let Some(archive) = &args.genome_archive {
+        plan.genome_archive = Some(archive.clone());
+        plan.outputs.push(out_dir.join(format!("{}.syldb", combined_name)));
+    }
+
+    if let Some(sample_list) = &args.sample_list {
+        plan.sample_list_reads = read_file_list(sample_list)
+            .context(format!("Failed to open sample list file: {}", sample_list))?;
+        if per_sample_files {
+            for file in &plan.sample_list_reads {
+                plan.outputs.push(out_dir.join(format!("{}.fasta", sample_stem(file))));
+                plan.outputs.push(out_dir.join(format!("{}.sylsp", sample_stem(file))));
+            }
+        }
+        if !args.separate_samples {
+            plan.outputs.push(out_dir.join(format!("{}.sylsp", combined_name)));
+        }
+    }
+
+    if let Some(tag_fastas) = &args.tag_fasta {
+        plan.tag_fastas = tag_fastas.clone();
+        plan.outputs.push(tag_fasta_output_path(args));
+    }
+
+    if let Some(report) = &args.recognition_site_report {
+        plan.outputs.push(PathBuf::from(report));
+    }
+
+    plan.outputs.dedup();
+    Ok(plan)
+}
+
+fn print_extract_plan(plan: &ExtractPlan) {
+    println!("Dry run: no files will be processed or written.");
+    if !plan.pairs.is_empty() {
+        println!("\nPaired reads (-1/-2, written only with --keep-individual or --separate-samples):");
+        for (first, second) in &plan.pairs {
+            println!("  {} + {}  [sample: {}]", first, second, sample_stem(first));
+        }
+    }
+    if !plan.list_pairs.is_empty() {
+        println!("\nPaired reads (--l1/--l2):");
+        for (first, second) in &plan.list_pairs {
+            println!("  {} + {}  [sample: {}]", first, second, sample_stem(first));
+        }
+    }
+    for (label, files) in [("Reads (-r)", &plan.reads), ("Reads (-s)", &plan.sample_list_reads)] {
+        if !files.is_empty() {
+            println!("\n{}:", label);
+            for file in files {
+                println!("  {}  [sample: {}]", file, sample_stem(file));
+            }
+        }
+    }
+    for (label, files) in [("Genomes (-k)", &plan.genome_list_genomes), ("Genomes (-g)", &plan.genomes)] {
+        if !files.is_empty() {
+            println!("\n{}:", label);
+            for file in files {
+                println!("  {}", file);
+            }
+        }
+    }
+    if let Some(archive) = &plan.genome_archive {
+        println!("\nGenome archive (--genome-archive, each FASTA member is one genome):\n  {}", archive);
+    }
+    if !plan.tag_fastas.is_empty() {
+        println!("\nTag FASTA files (--tag-fasta, imported without enzyme matching):");
+        for file in &plan.tag_fastas {
+            println!("  {}  [{}]", file, sample_stem(file));
+        }
+    }
+    println!("\nOutputs:");
+    for output in &plan.outputs {
+        println!("  {}", output.display());
+    }
+}
+
 pub fn extract(args: ExtractArgs) -> Result<()> {
+    let plan = plan_extract(&args)?;
+    if args.dry_run {
+        print_extract_plan(&plan);
+        return Ok(());
+    }
+
+    let output_options = OutputOptions::new(args.force, args.temp_dir.as_deref());
+    let progress = Progress::new(args.progress_json.as_deref());
+    if args.fnv_tag_hash {
+        eprintln!("Using legacy FNV-1a tag hashes (--fnv-tag-hash); databases and samples must use the same setting");
+    }
     // 初始化线程池
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(args.threads)
-        .build_global()?;
+    init_thread_pool(args.threads);
 
-    // 创建输出目录
-    std::fs::create_dir_all(&args.sample_output_dir)
-        .context("Failed to create output directory")?;
+    // 创建输出目录并确认可写
+    check_output_dir(&args.sample_output_dir)?;
 
     // 设置内存限制，如果没有指定则使用默认值
     let max_ram = args.max_ram.unwrap_or(16); // 默认16GB内存限制
     if max_ram < 7 {
         return Err(anyhow::anyhow!("Max ram must be >= 7. Exiting."));
     }
+    if args.error_correct && args.error_correct_ratio <= 1.0 {
+        return Err(anyhow::anyhow!("--error-correct-ratio must be > 1, got {}", args.error_correct_ratio));
+    }
+    let prefilter = args.prefilter_db.as_deref()
+        .map(|path| load_prefilter_panel(path, &args.enzyme))
+        .transpose()?;
+    // --index-sequences 需要提取时带上序列，写出前再按--store-seq决定是否留在主文件中
+    let store_seq = args.store_seq || args.index_sequences;
+    let keep_individual = args.keep_individual;
+    let separate_samples = args.separate_samples;
+    // reads输入的单样本文件：--keep-individual 与合并文件并存，--separate-samples 取代合并文件
+    let per_sample_files = keep_individual || separate_samples;
 
     // 处理单对双端测序文件（-1 和 -2 参数）
-    if !args.first_pair.is_empty() && !args.second_pair.is_empty() {
-        let enzyme = EnzymeSpec::new(&args.enzyme)?;
-        for (first_file, second_file) in args.first_pair.iter().zip(args.second_pair.iter()) {
+    if !plan.pairs.is_empty() {
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Reads(prefilter.as_ref()))?;
+        progress.phase("paired reads", plan.pairs.len());
+        for (first_file, second_file) in &plan.pairs {
             safe_process_with_memory_check(max_ram, first_file, || {
                 process_paired_fastq_files(
+                    &output_options,
                     first_file,
                     second_file,
                     &enzyme,
                     Path::new(&args.sample_output_dir),
                     args.out_name.as_deref(),
+                    store_seq,
+                    per_sample_files,
+                    args.merge_pairs,
                 )
             })?;
+            progress.sample_done();
         }
+        enzyme.report_filters();
     }
 
     // 处理批处理双端测序文件（--l1 和 --l2 参数）
-    if let (Some(first_pair_list), Some(second_pair_list)) = (&args.first_pair_list, &args.second_pair_list) {
-        // 读取文件列表
-        let first_pairs = read_file_list(first_pair_list)
-            .context("Failed to read first pair list")?;
-        let second_pairs = read_file_list(second_pair_list)
-            .context("Failed to read second pair list")?;
-
-        if first_pairs.len() != second_pairs.len() {
-            return Err(anyhow::anyhow!("Number of files in first pair list and second pair list do not match"));
-        }
-
-        let enzyme = EnzymeSpec::new(&args.enzyme)?;
+    if !plan.list_pairs.is_empty() {
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Reads(prefilter.as_ref()))?;
         let mut all_sylsp_entries = Vec::new();
+        progress.phase("paired read lists", plan.list_pairs.len());
 
         // 并行处理所有配对文件，添加内存监控
-        let results: Vec<Result<(String, Vec<SylspEntry>)>> = first_pairs.par_iter()
-            .zip(second_pairs.par_iter())
+        let results: Vec<Result<(String, Vec<SylspEntry>)>> = plan.list_pairs.par_iter()
             .map(|(first_file, second_file)| {
                 // 检查内存使用
                 if let Some(current_memory) = get_memory_usage() {
@@ -962,12 +2257,13 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                     second_file,
                     &enzyme,
                     &file_stem,
+                    args.merge_pairs,
                 )?;
 
                 // 注释掉生成单个文件的代码 - 只保留合并后的文件
                 // let output_base = Path::new(&args.sample_output_dir).join(&file_stem);
                 // let fa_path = output_base.with_extension("fa");
-                // let mut fa_writer = create_writer(&fa_path, false)?;
+                // let mut fa_writer = create_writer(&output_options, &fa_path, false)?;
 
                 let mut sylsp_entries = Vec::new();
                 for (id, tag, sample_source) in &fa_entries {
@@ -977,21 +2273,21 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
 
                     let entry = SylspEntry {
                         sequence_id: id.clone(),
-                        tag: hash_bytes(tag),
+                        tag: enzyme.hash_tag(tag),
                         quality: None,
                         sample_source: sample_source.clone(),
+                        sequence: enzyme.keeps_sequences(store_seq).then(|| String::from_utf8_lossy(tag).to_string()),
                     };
                     sylsp_entries.push(entry.clone());
                 }
+                enzyme.correct_tag_errors(&mut sylsp_entries, store_seq);
 
-                // 注释掉生成单个sylsp文件的代码
-                // let sylsp_path = output_base.with_extension("sylsp");
-                // let sylsp_file = File::create(&sylsp_path)
-                //     .context(format!("Failed to create sylsp file: {}", sylsp_path.display()))?;
-                // let sylsp_writer = BufWriter::new(sylsp_file);
-                // bincode::serialize_into(sylsp_writer, &sylsp_entries)
-                //     .context("Failed to serialize sylsp data")?;
+                if per_sample_files {
+                    let sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", file_stem));
+                    write_individual_sylsp(&output_options, &sylsp_path, &enzyme, &sylsp_entries)?;
+                }
 
+                progress.sample_done();
                 Ok((file_stem, sylsp_entries))
             })
             .collect();
@@ -1007,34 +2303,41 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
         }
 
         // 生成合并的 sylsp 文件
-        if !all_sylsp_entries.is_empty() {
+        if !all_sylsp_entries.is_empty() && !separate_samples {
             let output_name = args.out_name.as_ref().map_or_else(|| "combined".to_string(), |s| s.clone());
             let combined_sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", output_name));
-            let combined_sylsp_file = File::create(&combined_sylsp_path)
-                .context(format!("Failed to create combined sylsp file: {}", combined_sylsp_path.display()))?;
-            let combined_sylsp_writer = BufWriter::new(combined_sylsp_file);
-            
-            bincode::serialize_into(combined_sylsp_writer, &all_sylsp_entries)
-                .context("Failed to serialize combined sylsp data")?;
+            if args.index_sequences {
+                index_sylsp_sequences(&output_options, &combined_sylsp_path, &mut all_sylsp_entries, args.store_seq)?;
+            }
+            let depths = if args.depth_normalize { sample_tag_totals(&all_sylsp_entries) } else { Vec::new() };
+            output_options.write_atomically(&combined_sylsp_path, |writer| {
+                write_sylsp_header_with_depths(writer, SylspKind::TagEntries, &enzyme.name, enzyme.subsample_rate, &depths)?;
+                bincode::serialize_into(writer, &all_sylsp_entries)
+                    .context("Failed to serialize combined sylsp data")
+            })
+            .context(format!("Failed to write combined sylsp file: {}", combined_sylsp_path.display()))?;
         }
+        enzyme.report_filters();
     }
 
     // 处理单端测序文件
-    if let Some(read_files) = args.reads {
+    if args.reads.is_some() {
+        let read_files = &plan.reads;
         // 存储所有 FASTQ 文件的 sylsp 条目
         let mut all_sylsp_entries = Vec::new();
         let mut all_fa_entries = Vec::new();
-        let enzyme = EnzymeSpec::new(&args.enzyme)?;
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Reads(prefilter.as_ref()))?;
+        progress.phase("reads", read_files.len());
         
         for file in read_files {
             // 检查内存使用
             if let Some(current_memory) = get_memory_usage() {
                 if current_memory > max_ram as f64 {
-                    check_vram_and_block(max_ram, &file);
+                    check_vram_and_block(max_ram, file);
                 }
             }
             
-            let input_path = PathBuf::from(&file);
+            let input_path = PathBuf::from(file);
             let file_stem = input_path.file_stem()
                 .and_then(|s| s.to_str())
                 .unwrap_or("unknown")
@@ -1045,32 +2348,51 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                 
             let reader = fastq::Reader::new(create_reader(&input_path)?);
             let mut stats = ExtractionStats::new();
+            let mut file_fa_entries = Vec::new();
+            let mut file_sylsp_entries = Vec::new();
 
             for result in reader.records() {
                 let record = result.context("Failed to read FASTQ record")?;
+                if !enzyme.passes_read_length(record.seq().len()) {
+                    continue;
+                }
                 stats.total_sequences += 1;
                 stats.total_sequence_length += record.seq().len();
                 
                 let tags = extract_and_validate_tags(record.seq(), &enzyme)
                     .context(format!("Failed to process read: {}", record.id()))?;
+                if !enzyme.passes_prefilter(&tags) {
+                    continue;
+                }
                     
                 for (i, tag) in tags.iter().enumerate() {
                     let id = format!("{}_tag{}", record.id(), i + 1);
-                    all_fa_entries.push((id.clone(), tag.clone()));
+                    file_fa_entries.push((id.clone(), tag.clone()));
                     
                     let entry = SylspEntry {
                         sequence_id: id,
-                        tag: hash_bytes(tag),
+                        tag: enzyme.hash_tag(tag),
                         quality: Some(String::from_utf8_lossy(record.qual()).to_string()),
                         sample_source: file_stem.clone(),
+                        sequence: enzyme.keeps_sequences(store_seq).then(|| String::from_utf8_lossy(tag).to_string()),
                     };
-                    all_sylsp_entries.push(entry);
+                    file_sylsp_entries.push(entry);
                 }
                 
                 stats.total_tags += tags.len();
             }
             
             log_stats(stats, &enzyme);
+            let corrections = enzyme.correct_tag_errors(&mut file_sylsp_entries, store_seq);
+            correct_fasta_tags(&mut file_fa_entries, &corrections);
+            all_fa_entries.extend(file_fa_entries);
+
+            if per_sample_files {
+                let sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", file_stem));
+                write_individual_sylsp(&output_options, &sylsp_path, &enzyme, &file_sylsp_entries)?;
+            }
+            all_sylsp_entries.extend(file_sylsp_entries);
+            progress.sample_done();
         }
         
         // 生成合并的输出文件
@@ -1078,7 +2400,7 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
         
         // 生成 FASTA 文件
         let fa_path = Path::new(&args.sample_output_dir).join(format!("{}.fasta", output_name));
-        let mut fa_writer = create_writer(&fa_path, false)?;
+        let mut fa_writer = create_writer(&output_options, &fa_path, false)?;
         
         for (id, tag) in all_fa_entries {
             writeln!(fa_writer, ">{}\n{}", id, String::from_utf8_lossy(&tag))
@@ -1086,25 +2408,28 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
         }
 
         // 生成 .sylsp 文件
-        let sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", output_name));
-        let sylsp_file = File::create(&sylsp_path)
-            .context(format!("Failed to create sylsp file: {}", sylsp_path.display()))?;
-        let sylsp_writer = BufWriter::new(sylsp_file);
-        
-        bincode::serialize_into(sylsp_writer, &all_sylsp_entries)
-            .context("Failed to serialize sylsp data")?;
+        if !separate_samples {
+            let sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", output_name));
+            if args.index_sequences {
+                index_sylsp_sequences(&output_options, &sylsp_path, &mut all_sylsp_entries, args.store_seq)?;
+            }
+            let depths = if args.depth_normalize { sample_tag_totals(&all_sylsp_entries) } else { Vec::new() };
+            output_options.write_atomically(&sylsp_path, |writer| {
+                write_sylsp_header_with_depths(writer, SylspKind::TagEntries, &enzyme.name, enzyme.subsample_rate, &depths)?;
+                bincode::serialize_into(writer, &all_sylsp_entries)
+                    .context("Failed to serialize sylsp data")
+            })
+            .context(format!("Failed to write sylsp file: {}", sylsp_path.display()))?;
+        }
+        enzyme.report_filters();
     }
 
     // 处理基因组列表文件
-    if let Some(genome_list) = &args.genome_list {
-        let file = File::open(genome_list)
-            .context(format!("Failed to open genome list file: {}", genome_list))?;
-        let reader = BufReader::new(file);
-        let genome_files: Vec<String> = reader.lines()
-            .filter_map(|line| line.ok())
-            .collect();
+    if args.genome_list.is_some() {
+        let genome_files = &plan.genome_list_genomes;
+        progress.phase("genome list", genome_files.len());
 
-        let enzyme = EnzymeSpec::new(&args.enzyme)?;
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Genomes)?;
         let mut all_syldb_entries = Vec::new();
         
         // 并行处理所有 FASTA 文件，添加内存监控
@@ -1119,48 +2444,93 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                 
                 let input_path = Path::new(file);
                 let output_base = Path::new(&args.sample_output_dir).join(input_path.file_stem().unwrap_or_default());
-                process_fasta_to_syldb(
+                let entries = process_fasta_to_syldb(
                     input_path,
                     &output_base,
                     &enzyme,
                     &args.format,
                     file.ends_with(".gz"),
-                )
+                    store_seq,
+                );
+                progress.sample_done();
+                entries
             })
             .collect();
 
         // 收集所有结果
-        for (file, result) in genome_files.iter().zip(results) {
-            match result {
-                Ok(mut entries) => {
-                    // 为每个条目添加基因组来源信息
-                    for entry in &mut entries {
-                        entry.genome_source = file.clone();
-                    }
-                    all_syldb_entries.extend(entries);
-                },
-                Err(e) => {
-                    eprintln!("Error processing FASTA file: {}", e);
-                }
+        for (file, mut entries) in collect_genome_results(genome_files, results, args.keep_going)? {
+            // 为每个条目添加基因组来源信息
+            for entry in &mut entries {
+                entry.genome_source = file.clone();
+            }
+            if let Some(max_tags) = args.subsample_tags {
+                subsample_tags_per_genome(&mut entries, max_tags);
             }
+            if keep_individual {
+                write_individual_syldb(&output_options, &individual_syldb_path(&args.sample_output_dir, &file), &enzyme, &entries)?;
+            }
+            all_syldb_entries.extend(entries);
+        }
+
+        if let Some(existing) = &args.append {
+            all_syldb_entries = append_to_existing_syldb(existing, all_syldb_entries, &enzyme)?;
         }
 
         // 生成合并的 .syldb 文件
         if !all_syldb_entries.is_empty() {
             let output_name = args.out_name.as_ref().map_or_else(|| "combined".to_string(), |s| s.clone());
             let combined_syldb_path = Path::new(&args.sample_output_dir).join(format!("{}.syldb", output_name));
-            let combined_syldb_file = File::create(&combined_syldb_path)
-                .context(format!("Failed to create combined syldb file: {}", combined_syldb_path.display()))?;
-            let combined_syldb_writer = BufWriter::new(combined_syldb_file);
-            
-            bincode::serialize_into(combined_syldb_writer, &all_syldb_entries)
-                .context("Failed to serialize combined syldb data")?;
+            if args.index_sequences {
+                index_syldb_sequences(&output_options, &combined_syldb_path, &mut all_syldb_entries, args.store_seq)?;
+            }
+            output_options.write_atomically(&combined_syldb_path, |writer| {
+                write_syldb(writer, enzyme.subsample_rate, DbGranularity::Contig, &all_syldb_entries)
+                    .context("Failed to serialize combined syldb data")
+            })
+            .context(format!("Failed to write combined syldb file: {}", combined_syldb_path.display()))?;
+        }
+        enzyme.report_filters();
+    }
+
+    // 处理基因组归档（tar.gz）
+    if let Some(archive) = &plan.genome_archive {
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Genomes)?;
+        progress.phase("genome archive", 1);
+        let genomes = process_genome_archive(Path::new(archive), &enzyme, store_seq)?;
+        progress.sample_done();
+        eprintln!("Extracted {} genomes from archive {}", genomes.len(), archive);
+
+        let mut all_syldb_entries = Vec::new();
+        for (_, mut entries) in genomes {
+            if let Some(max_tags) = args.subsample_tags {
+                subsample_tags_per_genome(&mut entries, max_tags);
+            }
+            all_syldb_entries.extend(entries);
+        }
+        if let Some(existing) = &args.append {
+            all_syldb_entries = append_to_existing_syldb(existing, all_syldb_entries, &enzyme)?;
+        }
+
+        if !all_syldb_entries.is_empty() {
+            let output_name = args.out_name.as_ref().map_or_else(|| "combined".to_string(), |s| s.clone());
+            let combined_syldb_path = Path::new(&args.sample_output_dir).join(format!("{}.syldb", output_name));
+            if args.index_sequences {
+                index_syldb_sequences(&output_options, &combined_syldb_path, &mut all_syldb_entries, args.store_seq)?;
+            }
+            output_options.write_atomically(&combined_syldb_path, |writer| {
+                write_syldb(writer, enzyme.subsample_rate, DbGranularity::Contig, &all_syldb_entries)
+                    .context("Failed to serialize combined syldb data")
+            })
+            .context(format!("Failed to write combined syldb file: {}", combined_syldb_path.display()))?;
         }
+        enzyme.report_filters();
     }
 
     // 处理基因组文件
-    if let Some(genome_files) = &args.genomes {
-        let enzyme = EnzymeSpec::new(&args.enzyme)?;
+    if args.genomes.is_some() {
+        let genome_files = &plan.genomes;
+        progress.phase("genomes", genome_files.len());
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Genomes)?;
         let mut all_syldb_entries = Vec::new();
         
         // 并行处理所有 FASTA 文件，添加内存监控
@@ -1175,64 +2545,67 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                 
                 let input_path = Path::new(file);
                 let output_base = Path::new(&args.sample_output_dir).join(input_path.file_stem().unwrap_or_default());
-                process_fasta_to_syldb(
+                let entries = process_fasta_to_syldb(
                     input_path,
                     &output_base,
                     &enzyme,
                     &args.format,
                     file.ends_with(".gz"),
-                )
+                    store_seq,
+                );
+                progress.sample_done();
+                entries
             })
             .collect();
 
         // 收集所有结果
-        for (file, result) in genome_files.iter().zip(results) {
-            match result {
-                Ok(mut entries) => {
-                    // 为每个条目添加基因组来源信息
-                    for entry in &mut entries {
-                        entry.genome_source = file.clone();
-                    }
-                    all_syldb_entries.extend(entries);
-                },
-                Err(e) => {
-                    eprintln!("Error processing FASTA file: {}", e);
-                }
+        for (file, mut entries) in collect_genome_results(genome_files, results, args.keep_going)? {
+            // 为每个条目添加基因组来源信息
+            for entry in &mut entries {
+                entry.genome_source = file.clone();
+            }
+            if let Some(max_tags) = args.subsample_tags {
+                subsample_tags_per_genome(&mut entries, max_tags);
             }
+            if keep_individual {
+                write_individual_syldb(&output_options, &individual_syldb_path(&args.sample_output_dir, &file), &enzyme, &entries)?;
+            }
+            all_syldb_entries.extend(entries);
+        }
+
+        if let Some(existing) = &args.append {
+            all_syldb_entries = append_to_existing_syldb(existing, all_syldb_entries, &enzyme)?;
         }
 
         // 生成合并的 .syldb 文件
         if !all_syldb_entries.is_empty() {
             let output_name = args.out_name.as_ref().map_or_else(|| "combined".to_string(), |s| s.clone());
             let combined_syldb_path = Path::new(&args.sample_output_dir).join(format!("{}.syldb", output_name));
-            let combined_syldb_file = File::create(&combined_syldb_path)
-                .context(format!("Failed to create combined syldb file: {}", combined_syldb_path.display()))?;
-            let combined_syldb_writer = BufWriter::new(combined_syldb_file);
-            
-            bincode::serialize_into(combined_syldb_writer, &all_syldb_entries)
-                .context("Failed to serialize combined syldb data")?;
+            if args.index_sequences {
+                index_syldb_sequences(&output_options, &combined_syldb_path, &mut all_syldb_entries, args.store_seq)?;
+            }
+            output_options.write_atomically(&combined_syldb_path, |writer| {
+                write_syldb(writer, enzyme.subsample_rate, DbGranularity::Contig, &all_syldb_entries)
+                    .context("Failed to serialize combined syldb data")
+            })
+            .context(format!("Failed to write combined syldb file: {}", combined_syldb_path.display()))?;
         }
+        enzyme.report_filters();
     }
 
     // 处理样本列表文件
-    if let Some(sample_list) = &args.sample_list {
+    if args.sample_list.is_some() {
         let mut all_sylsp_entries = Vec::new();
-        let enzyme = EnzymeSpec::new(&args.enzyme)?;
-        
-        // 读取样本列表文件
-        let file = File::open(sample_list)
-            .context(format!("Failed to open sample list file: {}", sample_list))?;
-        let reader = BufReader::new(file);
+        let enzyme = enzyme_spec_from_args(&args, ExtractInput::Reads(prefilter.as_ref()))?;
         
         // 并行处理所有样本文件
-        let sample_files: Vec<String> = reader.lines()
-            .filter_map(|line| line.ok())
-            .collect();
+        let sample_files = &plan.sample_list_reads;
+        progress.phase("sample list", sample_files.len());
             
         // 使用FxHashMap优化样本处理
         let sample_stats = Arc::new(Mutex::new(SampleStatsMap::default()));
         
-        let results: Vec<Result<(String, Vec<(String, TagHash)>, Vec<SylspEntry>)>> = sample_files.par_iter()
+        let results: Vec<Result<SampleExtraction>> = sample_files.par_iter()
             .map(|file| {
                 // 检查内存使用
                 if let Some(current_memory) = get_memory_usage() {
@@ -1257,11 +2630,17 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
 
                 for result in reader.records() {
                     let record = result.context("Failed to read FASTQ record")?;
+                    if !enzyme.passes_read_length(record.seq().len()) {
+                        continue;
+                    }
                     stats.total_sequences += 1;
                     stats.total_sequence_length += record.seq().len();
                     
                     let tags = extract_and_validate_tags(record.seq(), &enzyme)
                         .context(format!("Failed to process read: {}", record.id()))?;
+                    if !enzyme.passes_prefilter(&tags) {
+                        continue;
+                    }
                         
                     for (i, tag) in tags.iter().enumerate() {
                         let id = format!("{}_tag{}", record.id(), i + 1);
@@ -1272,9 +2651,10 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                         
                         let entry = SylspEntry {
                             sequence_id: id,
-                            tag: hash_bytes(tag),
+                            tag: enzyme.hash_tag(tag),
                             quality: Some(String::from_utf8_lossy(record.qual()).to_string()),
                             sample_source: file_stem.clone(), // 用文件名去除扩展名作为样本名
+                            sequence: enzyme.keeps_sequences(store_seq).then(|| String::from_utf8_lossy(tag).to_string()),
                         };
                         sylsp_entries.push(entry);
                     }
@@ -1287,6 +2667,9 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                 global_stats.insert(file_stem.clone(), stats.clone());
                 
                 log_stats(stats, &enzyme);
+                let corrections = enzyme.correct_tag_errors(&mut sylsp_entries, store_seq);
+                correct_fasta_tags(&mut fa_entries, &corrections);
+                progress.sample_done();
                 Ok((file_stem, fa_entries, sylsp_entries))
             })
             .collect();
@@ -1294,26 +2677,23 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
         // 处理每个样本的结果
         for result in results {
             match result {
-                Ok((_file_stem, _fa_entries, sylsp_entries)) => {
-                    // 注释掉为每个样本生成独立文件的代码
-                    // let fa_path = Path::new(&args.sample_output_dir)
-                    //     .join(format!("{}.fasta", file_stem));
-                    // let mut fa_writer = create_writer(&fa_path, false)?;
-                    // 
-                    // for (id, tag) in fa_entries {
-                    //     writeln!(fa_writer, ">{}\n{}", id, String::from_utf8_lossy(&tag))
-                    //         .context("Failed to write FASTA record")?;
-                    // }
-                    // 
-                    // // 为每个样本生成独立的 sylsp 文件
-                    // let sample_sylsp_path = Path::new(&args.sample_output_dir)
-                    //     .join(format!("{}.sylsp", file_stem));
-                    // let sample_sylsp_file = File::create(&sample_sylsp_path)
-                    //     .context(format!("Failed to create sylsp file: {}", sample_sylsp_path.display()))?;
-                    // let sample_sylsp_writer = BufWriter::new(sample_sylsp_file);
-                    // 
-                    // bincode::serialize_into(sample_sylsp_writer, &sylsp_entries)
-                    //     .context(format!("Failed to serialize sylsp data for sample: {}", file_stem))?;
+                Ok((file_stem, fa_entries, sylsp_entries)) => {
+                    // --keep-individual/--separate-samples：为每个样本生成独立的 FASTA 和 sylsp 文件
+                    if per_sample_files {
+                        let fa_path = Path::new(&args.sample_output_dir)
+                            .join(format!("{}.fasta", file_stem));
+                        let mut fa_writer = create_writer(&output_options, &fa_path, false)?;
+
+                        for (id, tag) in fa_entries {
+                            writeln!(fa_writer, ">{}\n{}", id, String::from_utf8_lossy(&tag))
+                                .context("Failed to write FASTA record")?;
+                        }
+
+                        let sample_sylsp_path = Path::new(&args.sample_output_dir)
+                            .join(format!("{}.sylsp", file_stem));
+                        write_individual_sylsp(&output_options, &sample_sylsp_path, &enzyme, &sylsp_entries)
+                            .context(format!("Failed to write sylsp data for sample: {}", file_stem))?;
+                    }
                     
                     // 收集所有 sylsp 条目用于合并
                     all_sylsp_entries.extend(sylsp_entries);
@@ -1321,18 +2701,164 @@ pub fn extract(args: ExtractArgs) -> Result<()> {
                 Err(e) => eprintln!("Error processing sample file: {}", e),
             }
         }
-        
-        // 生成合并的 .sylsp 文件
-        let output_name = args.out_name.as_ref().map_or_else(|| "combined".to_string(), |s| s.clone());
-        let sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", output_name));
-        let sylsp_file = File::create(&sylsp_path)
-            .context(format!("Failed to create combined sylsp file: {}", sylsp_path.display()))?;
-        let sylsp_writer = BufWriter::new(sylsp_file);
-        
-        bincode::serialize_into(sylsp_writer, &all_sylsp_entries)
-            .context("Failed to serialize combined sylsp data")?;
+        
+        // 生成合并的 .sylsp 文件
+        if !separate_samples {
+            let output_name = args.out_name.as_ref().map_or_else(|| "combined".to_string(), |s| s.clone());
+            let sylsp_path = Path::new(&args.sample_output_dir).join(format!("{}.sylsp", output_name));
+            if args.index_sequences {
+                index_sylsp_sequences(&output_options, &sylsp_path, &mut all_sylsp_entries, args.store_seq)?;
+            }
+            let depths = if args.depth_normalize { sample_tag_totals(&all_sylsp_entries) } else { Vec::new() };
+            output_options.write_atomically(&sylsp_path, |writer| {
+                write_sylsp_header_with_depths(writer, SylspKind::TagEntries, &enzyme.name, enzyme.subsample_rate, &depths)?;
+                bincode::serialize_into(writer, &all_sylsp_entries)
+                    .context("Failed to serialize combined sylsp data")
+            })
+            .context(format!("Failed to write combined sylsp file: {}", sylsp_path.display()))?;
+        }
+        enzyme.report_filters();
+    }
+
+    // 导入预提取的标签FASTA（--tag-fasta）
+    if !plan.tag_fastas.is_empty() {
+        import_tag_fastas(&output_options, &progress, &args, &plan.tag_fastas, store_seq)?;
+    }
+
+    if let Some(report_path) = &args.recognition_site_report {
+        recognition_site_report(&output_options, &args, &plan, report_path)?;
+    }
+    progress.finish();
+
+    Ok(())
+}
+
+// --tag-fasta的输出：<out-name>.syldb（genome）或.sylsp（sample），未指定--out-name时为tags
+fn tag_fasta_output_path(args: &ExtractArgs) -> PathBuf {
+    let output_name = args.out_name.clone().unwrap_or_else(|| "tags".to_string());
+    let suffix = if args.tag_fasta_type == "sample" { "sylsp" } else { "syldb" };
+    Path::new(&args.sample_output_dir).join(format!("{}.{}", output_name, suffix))
+}
+
+// 读取每条记录一个标签的FASTA，返回(记录ID, 定向后的标签)。
+// 长度与酶不符直接报错（多半是-e选错了）；含非ACGT碱基的记录跳过并计数
+fn read_tag_fasta<R: Read>(reader: R, enzyme: &EnzymeSpec, tag_length: usize) -> Result<Vec<(String, TagHash)>> {
+    let mut tags = Vec::new();
+    let mut skipped = 0usize;
+    for record in fasta::Reader::new(reader).records() {
+        let record = record.context("Failed to read tag FASTA record")?;
+        let tag = record.seq().to_ascii_uppercase();
+        if tag.len() != tag_length {
+            return Err(anyhow::anyhow!(
+                "Tag {} is {} bp but {} tags are {} bp; check -e", record.id(), tag.len(), enzyme.name, tag_length));
+        }
+        if !is_valid_dna_scalar(&tag) {
+            skipped += 1;
+            continue;
+        }
+        let oriented = enzyme.orient_tag(&tag);
+        if enzyme.passes_subsample(&oriented) {
+            tags.push((record.id().to_string(), oriented));
+        }
+    }
+    if skipped > 0 {
+        eprintln!("Skipped {} tags containing non-ACGT bases", skipped);
+    }
+    Ok(tags)
+}
+
+// 外部标签不经过酶切匹配，直接哈希；--tag-orientation forward 等同于 --strand-specific
+fn import_tag_fastas(output_options: &OutputOptions, progress: &Progress, args: &ExtractArgs, files: &[String], store_seq: bool) -> Result<()> {
+    let enzyme = EnzymeSpec::new(&args.enzyme)?
+        .with_strand_specific(args.tag_orientation == "forward")
+        .with_subsample_rate(args.subsample_rate).with_fnv_tag_hash(args.fnv_tag_hash);
+    let tag_length = ENZYME_TAG_LENGTHS
+        .iter()
+        .find(|(name, _)| *name == enzyme.name)
+        .map(|(_, len)| *len)
+        .ok_or_else(|| anyhow::anyhow!("Unknown enzyme: {}", enzyme.name))?;
+    let output_path = tag_fasta_output_path(args);
+    progress.phase("tag fasta", files.len());
+
+    if args.tag_fasta_type == "sample" {
+        let mut entries = Vec::new();
+        for file in files {
+            let tags = read_tag_fasta(create_reader(Path::new(file))?, &enzyme, tag_length)
+                .with_context(|| format!("Failed to import tag FASTA: {}", file))?;
+            let sample_source = sample_stem(file);
+            entries.extend(tags.into_iter().map(|(sequence_id, tag)| SylspEntry {
+                sequence_id,
+                tag: enzyme.hash_tag(&tag),
+                quality: None,
+                sample_source: sample_source.clone(),
+                sequence: store_seq.then(|| String::from_utf8_lossy(&tag).into_owned()),
+            }));
+            progress.sample_done();
+        }
+        let depths = if args.depth_normalize { sample_tag_totals(&entries) } else { Vec::new() };
+        output_options.write_atomically(&output_path, |writer| {
+            write_sylsp_header_with_depths(writer, SylspKind::TagEntries, &enzyme.name, enzyme.subsample_rate, &depths)?;
+            bincode::serialize_into(writer, &entries)
+                .context("Failed to serialize tag FASTA sylsp data")
+        })
+        .context(format!("Failed to write sylsp file: {}", output_path.display()))?;
+    } else {
+        let mut entries = Vec::new();
+        for file in files {
+            let mut tags = read_tag_fasta(create_reader(Path::new(file))?, &enzyme, tag_length)
+                .with_context(|| format!("Failed to import tag FASTA: {}", file))?;
+            // 同一基因组内重复的标签只保留一次
+            let mut seen = FxHashSet::default();
+            tags.retain(|(_, tag)| seen.insert(tag.clone()));
+            entries.push(SyldbEntry {
+                sequence_id: sample_stem(file),
+                tags: tags.iter().map(|(_, tag)| enzyme.hash_tag(tag)).collect(),
+                // 外部标签没有基因组坐标，按记录顺序编号
+                positions: (0..tags.len()).collect(),
+                genome_source: file.clone(),
+                tag_uniqueness: None,
+                gn_size: 0,
+                tag_sequences: store_seq.then(|| tags.iter().map(|(_, tag)| String::from_utf8_lossy(tag).into_owned()).collect()),
+                enzyme: enzyme.name.clone(),
+            });
+            progress.sample_done();
+        }
+        output_options.write_atomically(&output_path, |writer| {
+            write_syldb(writer, enzyme.subsample_rate, DbGranularity::Genome, &entries)
+                .context("Failed to serialize tag FASTA syldb data")
+        })
+        .context(format!("Failed to write syldb file: {}", output_path.display()))?;
+    }
+    enzyme.report_filters();
+    eprintln!("Imported {} tag FASTA files into {}", files.len(), output_path.display());
+    Ok(())
+}
+
+// 提取完成后重新扫描每个输入文件；用新的EnzymeSpec，避免过滤计数重复累加到提取的统计里
+fn recognition_site_report(output_options: &OutputOptions, args: &ExtractArgs, plan: &ExtractPlan, report_path: &str) -> Result<()> {
+    let enzyme = enzyme_spec_from_args(args, ExtractInput::Reads(None))?;
+    // 双端的两个mate分别统计；基因组不走--long-read
+    let genome_enzyme = enzyme_spec_from_args(args, ExtractInput::Genomes)?;
+    let mut inputs: Vec<(&str, &EnzymeSpec)> = Vec::new();
+    for (first, second) in plan.pairs.iter().chain(&plan.list_pairs) {
+        inputs.push((first, &enzyme));
+        inputs.push((second, &enzyme));
+    }
+    for file in plan.reads.iter().chain(&plan.sample_list_reads) {
+        inputs.push((file, &enzyme));
+    }
+    for file in plan.genome_list_genomes.iter().chain(&plan.genomes) {
+        inputs.push((file, &genome_enzyme));
+    }
+    if plan.genome_archive.is_some() {
+        eprintln!("Note: --genome-archive members are not included in the recognition site report");
     }
 
+    let rows = inputs.par_iter()
+        .map(|&(file, enzyme)| Ok((file.to_string(), site_report_for_file(file, enzyme)?)))
+        .collect::<Result<Vec<_>>>()?;
+    write_site_report(output_options, report_path, &enzyme, &rows)?;
+    eprintln!("Recognition site report for {} inputs written to {}", rows.len(), report_path);
     Ok(())
 }
 
@@ -1342,11 +2868,24 @@ fn process_fasta_to_syldb(
     enzyme: &EnzymeSpec,
     _format: &str,
     _compress: bool,
+    store_seq: bool,
 ) -> Result<Vec<SyldbEntry>> {
     // 注释掉生成单个.fa文件的代码
     // let fa_path = output_base.with_extension("fa");
     // let mut fa_writer = BufWriter::with_capacity(64 * 1024, File::create(&fa_path)?);
     
+    let reader = create_reader(input)?;
+    fasta_reader_to_syldb(reader, &input.to_string_lossy(), enzyme, store_seq)
+        .with_context(|| format!("Failed to process FASTA file: {}", input.display()))
+}
+
+// 从任意FASTA读取流提取标签，genome_source记为给定名称（文件路径或归档成员名）
+fn fasta_reader_to_syldb<R: Read>(
+    reader: R,
+    genome_source: &str,
+    enzyme: &EnzymeSpec,
+    store_seq: bool,
+) -> Result<Vec<SyldbEntry>> {
     let mut stats = ExtractionStats::new();
     // 预分配容量 - 估计每个序列平均产生50个标签
     let mut syldb_entries = Vec::with_capacity(100);
@@ -1354,46 +2893,44 @@ fn process_fasta_to_syldb(
     let mut tag_frequency = TagFrequencyMap::default();
 
     // 读取和处理 FASTA 记录
-    let reader = create_reader(input)?;
     for record in fasta::Reader::new(reader).records() {
         let record = record.context("Failed to read FASTA record")?;
         let seq_len = record.seq().len();
         stats.total_sequences += 1;
         stats.total_sequence_length += seq_len;
         
-        // 使用包含canonical处理的标签提取
-        let tags = extract_and_validate_tags(record.seq(), enzyme)
-            .context(format!("Failed to process sequence: {}", record.id()))?;
-            
-        // 预分配位置向量容量
-        let mut positions = Vec::with_capacity(tags.len());
-        for (i, tag) in tags.iter().enumerate() {
-            // 注释掉单个FASTA文件写入
-            // writeln!(fa_writer, ">{}_{}\n{}", 
-            //     record.id(), 
-            //     i + 1,
-            //     String::from_utf8_lossy(tag))
-            //     .context("Failed to write FASTA record")?;
-            
-            positions.push(i);
-            
+        // 使用包含canonical处理的标签提取；positions为标签在contig上的起始坐标
+        let (positions, tags): (Vec<usize>, Vec<TagHash>) = extract_positioned_tags(record.seq(), enzyme)
+            .context(format!("Failed to process sequence: {}", record.id()))?
+            .into_iter()
+            .unzip();
+
+        for tag in &tags {
             // 统计标签频率（现在使用canonical tags）
             *tag_frequency.entry(tag.clone()).or_insert(0) += 1;
         }
 
-        // 创建 syldb 条目 - 直接使用hash_bytes（现在处理canonical tags）
+        // 创建 syldb 条目 - 对canonical标签使用hash_tag
         let entry = SyldbEntry {
             sequence_id: record.id().to_string(),
-            tags: tags.iter().map(|t| hash_bytes(t)).collect(),
+            tags: tags.iter().map(|t| enzyme.hash_tag(t)).collect(),
             positions,
-            genome_source: input.to_string_lossy().to_string(),
+            genome_source: genome_source.to_string(),
             tag_uniqueness: None, // 初始时未标记，将由mark命令处理
+            gn_size: 0, // 读完全部contig后统一填入
+            tag_sequences: store_seq.then(|| tags.iter().map(|t| String::from_utf8_lossy(t).to_string()).collect()),
+            enzyme: enzyme.name.clone(),
         };
         syldb_entries.push(entry);
             
         stats.total_tags += tags.len();
     }
 
+    // 记录基因组总长度，供inspect估算期望标签数
+    for entry in &mut syldb_entries {
+        entry.gn_size = stats.total_sequence_length;
+    }
+
     // 注释掉生成单个.syldb文件的代码
     // let syldb_path = output_base.with_extension("syldb");
     // let syldb_file = File::create(&syldb_path)
@@ -1410,11 +2947,102 @@ fn process_fasta_to_syldb(
 
 
 
+// 基因组批处理（-g / --genome-list）结束时的汇总：成功、跳过（没有任何序列）和失败的输入及原因
+#[derive(Debug, Default)]
+struct GenomeBatchReport {
+    succeeded: Vec<String>,
+    skipped: Vec<(String, String)>,
+    failed: Vec<(String, String)>,
+}
+
+impl GenomeBatchReport {
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "Genome batch summary: {} succeeded, {} skipped, {} failed\n",
+            self.succeeded.len(), self.skipped.len(), self.failed.len()
+        );
+        for (file, reason) in &self.skipped {
+            summary.push_str(&format!("  skipped  {}: {}\n", file, reason));
+        }
+        for (file, reason) in &self.failed {
+            summary.push_str(&format!("  failed   {}: {}\n", file, reason));
+        }
+        summary
+    }
+}
+
+// 按输入顺序汇总各基因组的处理结果并打印报告，返回成功的(文件, 条目)。
+// 有失败的输入时，除非--keep-going，否则报错且不写出缺少基因组的数据库
+fn collect_genome_results(
+    genome_files: &[String],
+    results: Vec<Result<Vec<SyldbEntry>>>,
+    keep_going: bool,
+) -> Result<Vec<(String, Vec<SyldbEntry>)>> {
+    let mut report = GenomeBatchReport::default();
+    let mut genomes = Vec::new();
+    for (file, result) in genome_files.iter().zip(results) {
+        match result {
+            Ok(entries) if entries.is_empty() => {
+                report.skipped.push((file.clone(), "no FASTA records".to_string()));
+            }
+            Ok(entries) => {
+                report.succeeded.push(file.clone());
+                genomes.push((file.clone(), entries));
+            }
+            Err(e) => report.failed.push((file.clone(), format!("{:#}", e))),
+        }
+    }
+
+    eprint!("{}", report.summary());
+    if !report.failed.is_empty() && !keep_going {
+        return Err(anyhow::anyhow!(
+            "{} of {} genome files failed (first: {}); rerun with --keep-going to build the database from the rest",
+            report.failed.len(), genome_files.len(), report.failed[0].0
+        ));
+    }
+    Ok(genomes)
+}
+
+fn is_fasta_member(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    [".fa", ".fasta", ".fna", ".fas"].iter().any(|ext| name.ends_with(ext))
+}
+
+// --genome-archive：顺序读取.tar/.tar.gz中的FASTA成员（成员本身也可以是.gz），不解包到磁盘。
+// 每个成员是一个基因组，成员路径作为genome_source；非FASTA成员跳过
+fn process_genome_archive(archive: &Path, enzyme: &EnzymeSpec, store_seq: bool) -> Result<Vec<(String, Vec<SyldbEntry>)>> {
+    let reader = create_reader(archive)?;
+    let mut tar = tar::Archive::new(reader);
+    let mut genomes = Vec::new();
+
+    for member in tar.entries().context(format!("Failed to read archive: {}", archive.display()))? {
+        let member = member.context(format!("Failed to read archive member in {}", archive.display()))?;
+        if !member.header().entry_type().is_file() {
+            continue;
+        }
+        let name = member.path()?.to_string_lossy().to_string();
+        if !is_fasta_member(&name) {
+            continue;
+        }
+
+        let entries = if name.ends_with(".gz") {
+            fasta_reader_to_syldb(GzDecoder::new(member), &name, enzyme, store_seq)
+        } else {
+            fasta_reader_to_syldb(member, &name, enzyme, store_seq)
+        }
+        .context(format!("Failed to process archive member {} in {}", name, archive.display()))?;
+        genomes.push((name, entries));
+    }
+
+    Ok(genomes)
+}
+
 fn process_paired_fastq_to_sylsp(
     input1: &str,
     input2: &str,
     enzyme: &EnzymeSpec,
     sample_source: &str,
+    merge_pairs: bool,
 ) -> Result<Vec<(String, TagHash, String)>> {
     let reader1 = fastq::Reader::new(create_reader(Path::new(input1))?);
     let reader2 = fastq::Reader::new(create_reader(Path::new(input2))?);
@@ -1439,18 +3067,55 @@ fn process_paired_fastq_to_sylsp(
             None => break,
         };
 
-        let seq_len1 = record1.seq().len();
-        let seq_len2 = record2.seq().len();
+        // --min-read-length按mate分别检查；两个mate都太短时整对跳过
+        let keep1 = enzyme.passes_read_length(record1.seq().len());
+        let keep2 = enzyme.passes_read_length(record2.seq().len());
+        if !keep1 && !keep2 {
+            continue;
+        }
+
+        let seq_len1 = if keep1 { record1.seq().len() } else { 0 };
+        let seq_len2 = if keep2 { record2.seq().len() } else { 0 };
         stats.total_sequences += 1;
         stats.total_sequence_length += seq_len1 + seq_len2;
+
+        // --merge-pairs：重叠的双端先合并成一个片段，找回跨越接合处的酶切位点
+        if merge_pairs && keep1 && keep2 {
+            if let Some(merged) = merge_overlapping_pair(record1.seq(), record1.qual(), record2.seq(), record2.qual()) {
+                let tags = extract_and_validate_tags(&merged, enzyme)
+                    .context(format!("Failed to process merged read: {}", record1.id()))?;
+                if !enzyme.passes_prefilter(&tags) {
+                    continue;
+                }
+                stats.total_tags += tags.len();
+                for (i, tag) in tags.iter().enumerate() {
+                    let entry_key = (record1.id().to_string(), i, tag.clone());
+                    if seen_pairs.insert(entry_key) {
+                        fa_entries.push((format!("{}_{}", record1.id(), i + 1), tag.clone(), sample_source.to_string()));
+                    }
+                }
+                continue;
+            }
+        }
         
         // 处理第一条序列（使用canonical处理）
-        let tags1 = extract_and_validate_tags(record1.seq(), enzyme)
-            .context(format!("Failed to process read: {}", record1.id()))?;
+        let tags1 = if keep1 {
+            extract_and_validate_tags(record1.seq(), enzyme)
+                .context(format!("Failed to process read: {}", record1.id()))?
+        } else {
+            Vec::new()
+        };
             
         // 处理第二条序列（使用canonical处理）
-        let tags2 = extract_and_validate_tags(record2.seq(), enzyme)
-            .context(format!("Failed to process read: {}", record2.id()))?;
+        let tags2 = if keep2 {
+            extract_and_validate_tags(record2.seq(), enzyme)
+                .context(format!("Failed to process read: {}", record2.id()))?
+        } else {
+            Vec::new()
+        };
+        if !enzyme.passes_prefilter(tags1.iter().chain(&tags2)) {
+            continue;
+        }
             
         stats.total_tags += tags1.len() + tags2.len();
             
@@ -1474,6 +3139,43 @@ fn process_paired_fastq_to_sylsp(
     Ok(fa_entries)
 }
 
+const MIN_PAIR_OVERLAP: usize = 12;
+const MAX_OVERLAP_MISMATCH_RATE: f64 = 0.1;
+
+// 双端合并：R2取反向互补后与R1末端比对，在所有不短于MIN_PAIR_OVERLAP的重叠长度中
+// 选错配率最低的（相同时取更长的重叠），错配率超过MAX_OVERLAP_MISMATCH_RATE视为不重叠返回None。
+// 重叠区的不一致碱基取质量值更高的一方
+pub fn merge_overlapping_pair(seq1: &[u8], qual1: &[u8], seq2: &[u8], qual2: &[u8]) -> Option<Vec<u8>> {
+    let rc2 = reverse_complement(seq2);
+    let rq2: Vec<u8> = qual2.iter().rev().copied().collect();
+    let max_overlap = seq1.len().min(rc2.len());
+
+    let mut best: Option<(usize, f64)> = None;
+    for overlap in MIN_PAIR_OVERLAP..=max_overlap {
+        let start = seq1.len() - overlap;
+        let mismatches = seq1[start..].iter().zip(&rc2[..overlap]).filter(|(a, b)| a != b).count();
+        let rate = mismatches as f64 / overlap as f64;
+        if rate > MAX_OVERLAP_MISMATCH_RATE {
+            continue;
+        }
+        if best.is_none_or(|(_, best_rate)| rate <= best_rate) {
+            best = Some((overlap, rate));
+        }
+    }
+
+    let (overlap, _) = best?;
+    let start = seq1.len() - overlap;
+    let mut merged = seq1[..start].to_vec();
+    for i in 0..overlap {
+        let (b1, b2) = (seq1[start + i], rc2[i]);
+        let q1 = qual1.get(start + i).copied().unwrap_or(0);
+        let q2 = rq2.get(i).copied().unwrap_or(0);
+        merged.push(if b1 == b2 || q1 >= q2 { b1 } else { b2 });
+    }
+    merged.extend_from_slice(&rc2[overlap..]);
+    Some(merged)
+}
+
 fn calculate_tag_percentage(tag_count: usize, total_kmers: usize) -> f64 {
     if total_kmers == 0 {
         0.0
@@ -1489,7 +3191,7 @@ fn read_file_list(path: &str) -> Result<Vec<String>> {
         .context(format!("Failed to open file list: {}", path))?;
     let reader = BufReader::new(file);
     let files: Vec<String> = reader.lines()
-        .filter_map(|line| line.ok())
+        .map_while(Result::ok)
         .collect();
     Ok(files)
 }
@@ -1501,7 +3203,7 @@ fn read_file_list(path: &str) -> Result<Vec<String>> {
 
 
 // 添加反向互补序列计算函数
-fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
     let mut rc = Vec::with_capacity(seq.len());
     for &b in seq.iter().rev() {
         let complement = match b {
@@ -1517,7 +3219,7 @@ fn reverse_complement(seq: &[u8]) -> Vec<u8> {
 }
 
 // 获取 canonical 版本的序列（字典序较小的）
-fn get_canonical_sequence(seq: &[u8]) -> Vec<u8> {
+pub fn get_canonical_sequence(seq: &[u8]) -> Vec<u8> {
     let rc = reverse_complement(seq);
     
     // 比较正向和反向互补序列的字典序
@@ -1527,3 +3229,1027 @@ fn get_canonical_sequence(seq: &[u8]) -> Vec<u8> {
         rc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sketch::SequencesSketch;
+    use crate::test_support::{sylsp_entry, TempDir};
+    use std::io::Cursor;
+
+    fn write_with_header<T: Serialize>(kind: SylspKind, payload: &T) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_sylsp_header(&mut buf, kind, "", 1).unwrap();
+        bincode::serialize_into(&mut buf, payload).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_sylsp_header_classifies_file_shapes() {
+        let entries = vec![sylsp_entry("s1", hash_bytes(b"ACGT"))];
+        let sketch = SequencesSketch::new("s1.fq".to_string(), 200, 31, false, None, 150.0);
+
+        let mut reader = Cursor::new(write_with_header(SylspKind::TagEntries, &entries));
+        assert_eq!(read_sylsp_header(&mut reader).unwrap(), Some(SylspKind::TagEntries));
+        let decoded: Vec<SylspEntry> = bincode::deserialize_from(reader).unwrap();
+        assert_eq!(decoded.len(), 1);
+
+        let mut reader = Cursor::new(write_with_header(SylspKind::SingleSketch, &sketch));
+        assert_eq!(read_sylsp_header(&mut reader).unwrap(), Some(SylspKind::SingleSketch));
+        let decoded: SequencesSketch = bincode::deserialize_from(reader).unwrap();
+        assert_eq!(decoded.file_name, "s1.fq");
+
+        let mut reader = Cursor::new(write_with_header(SylspKind::SketchList, &vec![sketch.clone(), sketch]));
+        assert_eq!(read_sylsp_header(&mut reader).unwrap(), Some(SylspKind::SketchList));
+        let decoded: Vec<SequencesSketch> = bincode::deserialize_from(reader).unwrap();
+        assert_eq!(decoded.len(), 2);
+
+        // 没有格式头的旧文件不应被消耗任何字节
+        let mut reader = Cursor::new(bincode::serialize(&entries).unwrap());
+        assert_eq!(read_sylsp_header(&mut reader).unwrap(), None);
+        let decoded: Vec<SylspEntry> = bincode::deserialize_from(reader).unwrap();
+        assert_eq!(decoded.len(), 1);
+    }
+
+    #[test]
+    fn test_baseline_syldb_without_header_still_loads() {
+        // 基线版本写出的条目：没有gn_size、tag_sequences和enzyme，也没有格式头
+        #[derive(Serialize)]
+        struct BaselineSyldbEntry {
+            sequence_id: String,
+            tags: Vec<Hash>,
+            positions: Vec<usize>,
+            genome_source: String,
+            tag_uniqueness: Option<Vec<bool>>,
+        }
+        let baseline = vec![BaselineSyldbEntry {
+            sequence_id: "contig1".to_string(),
+            tags: vec![11, 22],
+            positions: vec![0, 40],
+            genome_source: "g1.fa".to_string(),
+            tag_uniqueness: Some(vec![true, false]),
+        }];
+        let tmp = TempDir::new("baseline_syldb");
+        let path = tmp.join("old.syldb");
+        bincode::serialize_into(File::create(&path).unwrap(), &baseline).unwrap();
+
+        let (header, entries) = read_syldb_with_header(BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!(header, None);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tags, vec![11, 22]);
+        assert_eq!(entries[0].tag_uniqueness, Some(vec![true, false]));
+        assert_eq!(entries[0].gn_size, 0);
+        assert!(entries[0].enzyme.is_empty());
+        // 旧库没有记录酶，不参与混合酶检查
+        assert_eq!(database_enzyme(&entries).unwrap(), None);
+
+        // 重新写出后带格式头，再读回内容不变
+        let mut data = Vec::new();
+        write_syldb(&mut data, 1, DbGranularity::Contig, &entries).unwrap();
+        let (header, reread) = read_syldb_with_header(Cursor::new(data)).unwrap();
+        assert_eq!(header, Some(SyldbHeader { version: SYLDB_FORMAT_VERSION, subsample_rate: 1, granularity: DbGranularity::Contig }));
+        assert_eq!(reread[0].sequence_id, "contig1");
+        assert_eq!(reread[0].positions, vec![0, 40]);
+    }
+
+    #[test]
+    fn test_baseline_sylsp_entries_without_sequence_still_load() {
+        // 基线版本的样本：没有格式头，条目没有sequence字段
+        #[derive(Serialize)]
+        struct BaselineSylspEntry {
+            sequence_id: String,
+            tag: Hash,
+            quality: Option<String>,
+            sample_source: String,
+        }
+        let baseline: Vec<BaselineSylspEntry> = (0..3u64)
+            .map(|i| BaselineSylspEntry {
+                sequence_id: format!("read{}", i),
+                tag: i + 100,
+                quality: None,
+                sample_source: "s1".to_string(),
+            })
+            .collect();
+
+        let mut reader = Cursor::new(bincode::serialize(&baseline).unwrap());
+        let header = read_sylsp_header_full(&mut reader).unwrap();
+        assert_eq!(header, None);
+        let entries = read_sylsp_entries(reader, header.as_ref()).unwrap();
+        assert_eq!(entries.iter().map(|e| e.tag).collect::<Vec<_>>(), vec![100, 101, 102]);
+        assert!(entries.iter().all(|e| e.sequence.is_none()));
+
+        // 版本1的格式头之后也是旧的条目布局
+        let mut data = SYLSP_MAGIC.to_vec();
+        data.push(1);
+        bincode::serialize_into(&mut data, &SylspKind::TagEntries).unwrap();
+        bincode::serialize_into(&mut data, &baseline).unwrap();
+        let mut reader = Cursor::new(data);
+        let header = read_sylsp_header_full(&mut reader).unwrap();
+        assert_eq!(header.as_ref().map(|h| h.version), Some(1));
+        let mut streamed = Vec::new();
+        let count = stream_sylsp_entries(reader, header.as_ref(), |entry| streamed.push(entry.sequence_id)).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(streamed, vec!["read0", "read1", "read2"]);
+    }
+
+    #[test]
+    fn test_long_read_tolerates_site_substitution() {
+        // BcgI 位点: 10N CGA 6N TGC 10N，把CGA中的G替换成T
+        let flank = b"ACGTTGCAAC";
+        let mut site = Vec::new();
+        site.extend_from_slice(flank);
+        site.extend_from_slice(b"CTA");
+        site.extend_from_slice(b"GATTCA");
+        site.extend_from_slice(b"TGC");
+        site.extend_from_slice(flank);
+
+        let exact = EnzymeSpec::new("BcgI").unwrap();
+        let fuzzy = EnzymeSpec::new("BcgI").unwrap().with_max_mismatches(1);
+
+        assert!(extract_and_validate_tags(&site, &exact).unwrap().is_empty());
+        let tags = extract_and_validate_tags(&site, &fuzzy).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0], get_canonical_sequence(&site));
+    }
+
+    #[test]
+    fn test_long_read_tolerates_site_indels() {
+        // BcgI 位点: 10N CGA 6N TGC 10N
+        let left = b"ACGTTGCAAC";
+        let right = b"GTCAAGCTTA";
+        let reference = [&left[..], b"CGA", b"GATTCA", b"TGC", &right[..]].concat();
+        let fuzzy = EnzymeSpec::new("BcgI").unwrap().with_max_mismatches(1);
+        let expected = get_canonical_sequence(&reference);
+
+        // 固定碱基缺失一个G：按位点补回，得到与参考相同的标签
+        let deletion = [&left[..], b"CA", b"GATTCA", b"TGC", &right[..]].concat();
+        assert_eq!(extract_and_validate_tags(&deletion, &fuzzy).unwrap(), vec![expected.clone()]);
+
+        // 固定碱基中插入一个G：插入的碱基被丢弃，侧翼仍按原长度截取
+        let insertion = [&left[..], b"CGGA", b"GATTCA", b"TGC", &right[..]].concat();
+        assert_eq!(extract_and_validate_tags(&insertion, &fuzzy).unwrap(), vec![expected.clone()]);
+
+        // 自由位置的插入无法确定丢弃哪个碱基，但仍得到一个标准长度的标签
+        let free_insertion = [&left[..], b"CGA", b"GATTTCA", b"TGC", &right[..]].concat();
+        let tags = extract_and_validate_tags(&free_insertion, &fuzzy).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].len(), reference.len());
+
+        // 精确位点不会被前移一位以插入的方式命中
+        let padded = [&b"T"[..], &reference[..], b"T"].concat();
+        assert_eq!(extract_and_validate_tags(&padded, &fuzzy).unwrap(), vec![expected]);
+
+        // 两处编辑超出了1的上限
+        let two_edits = [&left[..], b"CA", b"GATTTCA", b"TGC", &right[..]].concat();
+        assert!(extract_and_validate_tags(&two_edits, &fuzzy).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_zero_max_site_mismatches_matches_exactly() {
+        let site = [&b"ACGTTGCAAC"[..], b"CTA", b"GATTCA", b"TGC", b"ACGTTGCAAC"].concat();
+        let enzyme = read_enzyme_spec("BcgI", true, 0, false).unwrap();
+        assert_eq!(enzyme.max_mismatches, 0);
+        assert!(extract_and_validate_tags(&site, &enzyme).unwrap().is_empty());
+        assert_eq!(read_enzyme_spec("BcgI", true, 2, false).unwrap().max_mismatches, 2);
+    }
+
+    #[test]
+    fn test_genome_archive_members_become_genome_sources() {
+        let site = "ACGTTGCAACCGAGATTCATGCACGTTGCAA";
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, body) in [
+            ("refs/g1.fa", format!(">c1\nTTT{}TTT\n", site)),
+            ("refs/g2.fna", format!(">c1\nGGG{}GGG\n>c2\nAAAA\n", site)),
+            ("refs/README.txt", "not a genome\n".to_string()),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(body.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, body.as_bytes()).unwrap();
+        }
+        let tar_bytes = builder.into_inner().unwrap();
+
+        let tmp = TempDir::new("archive");
+
+        let path = tmp.join("archive.tar.gz");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&tar_bytes).unwrap();
+        encoder.finish().unwrap();
+
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        let genomes = process_genome_archive(&path, &enzyme, false).unwrap();
+
+        let names: Vec<&str> = genomes.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["refs/g1.fa", "refs/g2.fna"]);
+        for (name, entries) in &genomes {
+            assert!(!entries.is_empty());
+            assert!(entries.iter().all(|e| &e.genome_source == name));
+            assert_eq!(entries[0].tags.len(), 1);
+        }
+        assert_eq!(genomes[1].1.len(), 2);
+    }
+
+    #[test]
+    fn test_append_adds_genome_to_existing_database() {
+        let entry = |genome: &str| SyldbEntry {
+            sequence_id: format!("{}_contig1", genome),
+            tags: vec![1, 2],
+            positions: vec![0, 50],
+            genome_source: genome.to_string(),
+            tag_uniqueness: Some(vec![true, false]),
+            gn_size: 100,
+            tag_sequences: None,
+            enzyme: String::new(),
+        };
+        let tmp = TempDir::new("append");
+        let path = tmp.join("append.syldb");
+        let existing = vec![entry("g1.fa"), entry("g2.fa")];
+        write_syldb(&mut File::create(&path).unwrap(), 1, DbGranularity::Contig, &existing).unwrap();
+
+        let mut new_entry = entry("g3.fa");
+        new_entry.tag_uniqueness = None;
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        let combined = append_to_existing_syldb(path.to_str().unwrap(), vec![new_entry], &enzyme).unwrap();
+
+        let genomes: FxHashSet<&str> = combined.iter().map(|e| e.genome_source.as_str()).collect();
+        assert_eq!(genomes.len(), 3);
+        assert!(combined.iter().all(|e| e.tag_uniqueness.is_none()));
+    }
+
+    #[test]
+    fn test_merge_pairs_recovers_junction_spanning_tag() {
+        let site = "ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        let fragment = format!("GGTACCTTAGCAGTCAGGTC{}TTAGGCATCCAGTGACATGG", site);
+        let r1 = &fragment[..50];
+        let r2 = String::from_utf8(reverse_complement(&fragment.as_bytes()[22..])).unwrap();
+
+        let tmp = TempDir::new("merge");
+
+        let dir = tmp.path();
+        let (p1, p2) = (dir.join("s_1.fq"), dir.join("s_2.fq"));
+        std::fs::write(&p1, format!("@read1\n{}\n+\n{}\n", r1, "I".repeat(r1.len()))).unwrap();
+        std::fs::write(&p2, format!("@read1\n{}\n+\n{}\n", r2, "I".repeat(r2.len()))).unwrap();
+
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        let (p1, p2) = (p1.to_str().unwrap(), p2.to_str().unwrap());
+        let independent = process_paired_fastq_to_sylsp(p1, p2, &enzyme, "s", false).unwrap();
+        let merged = process_paired_fastq_to_sylsp(p1, p2, &enzyme, "s", true).unwrap();
+
+        assert!(independent.is_empty());
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].1, get_canonical_sequence(site.as_bytes()));
+    }
+
+    #[test]
+    fn test_low_complexity_tag_is_dropped() {
+        let enzyme = EnzymeSpec::new("BcgI").unwrap().with_min_complexity(1.0);
+        let homopolymer = vec![b'A'; 32];
+        let normal = b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+
+        assert_eq!(tag_entropy(&homopolymer), 0.0);
+        assert!(!enzyme.passes_complexity(&homopolymer));
+        assert!(enzyme.passes_complexity(normal));
+        assert_eq!(enzyme.low_complexity_filtered.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // 默认不过滤
+        assert!(EnzymeSpec::new("BcgI").unwrap().passes_complexity(&homopolymer));
+    }
+
+    #[test]
+    fn test_strand_specific_keeps_reverse_complement_distinct() {
+        let site = b"ACGTTGCAACCGAGATTCATGCACGTTGCAA";
+        let forward = b"TTT".iter().chain(site.iter()).chain(b"TTT".iter()).copied().collect::<Vec<u8>>();
+        let reverse = reverse_complement(&forward);
+
+        let canonical = EnzymeSpec::new("BcgI").unwrap();
+        let stranded = EnzymeSpec::new("BcgI").unwrap().with_strand_specific(true);
+
+        let fwd = extract_tags_scalar(&forward, &canonical).unwrap();
+        let rev = extract_tags_scalar(&reverse, &canonical).unwrap();
+        assert_eq!(fwd.len(), 1);
+        assert_eq!(fwd, rev);
+
+        let fwd = extract_tags_scalar(&forward, &stranded).unwrap();
+        let rev = extract_tags_scalar(&reverse, &stranded).unwrap();
+        assert_eq!(fwd.len(), 1);
+        assert_eq!(rev.len(), 1);
+        assert_ne!(fwd, rev);
+        assert_eq!(rev[0], reverse_complement(&fwd[0]));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_avx2_and_scalar_extractors_agree() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        // 简单的LCG生成可复现的随机序列，混入N和非UTF8字节
+        let mut state: u64 = 0x2b4d_2024;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        for enzyme_name in ["BcgI", "CspCI", "AloI"] {
+            let enzyme = EnzymeSpec::new(enzyme_name).unwrap();
+            for _ in 0..200 {
+                let len = 50 + next() % 400;
+                let seq: Vec<u8> = (0..len)
+                    .map(|_| match next() % 100 {
+                        0 => b'N',
+                        1 => 0xFF,
+                        r => b"ACGT"[r % 4],
+                    })
+                    .collect();
+                let mut scalar = extract_tags_scalar(&seq, &enzyme).unwrap();
+                let mut avx2 = unsafe { extract_tags_avx2(&seq, &enzyme).unwrap() };
+                scalar.sort();
+                avx2.sort();
+                assert_eq!(scalar, avx2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dry_run_plan_matches_processed_inputs() {
+        let tmp = TempDir::new("plan");
+        let dir = tmp.path();
+        let genome_list = dir.join("genomes.txt");
+        std::fs::write(&genome_list, "g1.fa\ng2.fna.gz\n").unwrap();
+        let l1 = dir.join("l1.txt");
+        let l2 = dir.join("l2.txt");
+        std::fs::write(&l1, "s1_R1.fq.gz\n").unwrap();
+        std::fs::write(&l2, "s1_R2.fq.gz\n").unwrap();
+
+        let args = ExtractArgs {
+            genome_list: Some(genome_list.to_str().unwrap().to_string()),
+            reads: Some(vec!["a.fq".to_string()]),
+            first_pair_list: Some(l1.to_str().unwrap().to_string()),
+            second_pair_list: Some(l2.to_str().unwrap().to_string()),
+            sample_output_dir: "out".to_string(),
+            ..Default::default()
+        };
+        let plan = plan_extract(&args).unwrap();
+
+        assert_eq!(plan.genome_list_genomes, vec!["g1.fa", "g2.fna.gz"]);
+        assert_eq!(plan.reads, vec!["a.fq"]);
+        assert_eq!(plan.list_pairs, vec![("s1_R1.fq.gz".to_string(), "s1_R2.fq.gz".to_string())]);
+        assert_eq!(sample_stem(&plan.list_pairs[0].0), "s1_R1");
+        assert_eq!(
+            plan.outputs,
+            vec![
+                PathBuf::from("out/combined.sylsp"),
+                PathBuf::from("out/reads.fasta"),
+                PathBuf::from("out/reads.sylsp"),
+                PathBuf::from("out/combined.syldb"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_individual_writes_per_genome_and_combined() {
+        let tmp = TempDir::new("indiv");
+        let dir = tmp.path();
+        let out_dir = dir.join("out");
+
+        // 每个基因组带一个BcgI位点
+        let site = "ACGTTGCAACCGAGATTCATGCACGTTGCAA";
+        let mut genomes = Vec::new();
+        for name in ["g1", "g2", "g3"] {
+            let path = dir.join(format!("{}.fa", name));
+            std::fs::write(&path, format!(">{}\nTTTT{}TTTT\n", name, site)).unwrap();
+            genomes.push(path.to_str().unwrap().to_string());
+        }
+
+        let args = ExtractArgs {
+            genomes: Some(genomes),
+            sample_output_dir: out_dir.to_str().unwrap().to_string(),
+            enzyme: "BcgI".to_string(),
+            threads: 1,
+            format: "fa".to_string(),
+            keep_individual: true,
+            ..Default::default()
+        };
+        extract(args).unwrap();
+
+        for name in ["g1", "g2", "g3", "combined"] {
+            assert!(out_dir.join(format!("{}.syldb", name)).exists(), "missing {}.syldb", name);
+        }
+        let combined: Vec<SyldbEntry> =
+            read_syldb(BufReader::new(File::open(out_dir.join("combined.syldb")).unwrap())).unwrap();
+        assert_eq!(combined.len(), 3);
+    }
+
+    #[test]
+    fn test_separate_samples_union_equals_combined() {
+        let tmp = TempDir::new("sep");
+        let dir = tmp.path();
+
+        let site = "ACGTTGCAACCGAGATTCATGCACGTTGCAA";
+        let mut reads = Vec::new();
+        for (name, prefix) in [("a", "GGGG"), ("b", "CCCC")] {
+            let path = dir.join(format!("{}.fq", name));
+            let seq = format!("{}{}T", prefix, site);
+            let qual = "I".repeat(seq.len());
+            std::fs::write(&path, format!("@{}1\n{}\n+\n{}\n", name, seq, qual)).unwrap();
+            reads.push(path.to_str().unwrap().to_string());
+        }
+
+        let read_entries = |path: PathBuf| -> Vec<(String, u64, String)> {
+            let mut reader = BufReader::new(File::open(path).unwrap());
+            read_sylsp_header(&mut reader).unwrap();
+            let entries: Vec<SylspEntry> = bincode::deserialize_from(reader).unwrap();
+            entries.into_iter().map(|e| (e.sequence_id, e.tag, e.sample_source)).collect()
+        };
+
+        let run = |out: &str, separate_samples: bool| {
+            extract(ExtractArgs {
+                reads: Some(reads.clone()),
+                sample_output_dir: dir.join(out).to_str().unwrap().to_string(),
+                enzyme: "BcgI".to_string(),
+                threads: 1,
+                format: "fa".to_string(),
+                separate_samples,
+                ..Default::default()
+            })
+            .unwrap();
+        };
+        run("separate", true);
+        run("combined", false);
+
+        assert!(!dir.join("separate/reads.sylsp").exists());
+        let mut union = read_entries(dir.join("separate/a.sylsp"));
+        union.extend(read_entries(dir.join("separate/b.sylsp")));
+        union.sort();
+        let mut combined = read_entries(dir.join("combined/reads.sylsp"));
+        combined.sort();
+        assert!(!combined.is_empty());
+        assert_eq!(union, combined);
+    }
+
+    #[test]
+    fn test_subsample_tags_caps_each_genome_deterministically() {
+        let entry = |genome: &str, tags: Vec<Hash>| SyldbEntry {
+            sequence_id: format!("{}_contig", genome),
+            positions: tags.iter().map(|t| *t as usize * 100).collect(),
+            tag_sequences: Some(tags.iter().map(|t| t.to_string()).collect()),
+            tags,
+            genome_source: genome.to_string(),
+            tag_uniqueness: None,
+            gn_size: 0,
+            enzyme: String::new(),
+        };
+        let build = || {
+            vec![
+                entry("g1", (0..50).collect()),
+                entry("g1", (50..80).collect()),
+                entry("g2", (100..105).collect()),
+            ]
+        };
+
+        let mut first = build();
+        subsample_tags_per_genome(&mut first, 10);
+        let g1: usize = first.iter().filter(|e| e.genome_source == "g1").map(|e| e.tags.len()).sum();
+        assert_eq!(g1, 10);
+        assert_eq!(first[2].tags.len(), 5);
+        for e in &first {
+            assert_eq!(e.positions.len(), e.tags.len());
+            for (tag, (pos, seq)) in e.tags.iter().zip(e.positions.iter().zip(e.tag_sequences.as_ref().unwrap())) {
+                // 位置与序列仍然与各自的标签对应
+                assert_eq!(seq, &tag.to_string());
+                assert_eq!(*pos, *tag as usize * 100);
+            }
+        }
+
+        let mut second = build();
+        subsample_tags_per_genome(&mut second, 10);
+        let tags = |v: &[SyldbEntry]| v.iter().map(|e| e.tags.clone()).collect::<Vec<_>>();
+        assert_eq!(tags(&first), tags(&second));
+    }
+
+    #[test]
+    fn test_sequence_index_recovers_tag_sequence() {
+        let tag = b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        let mut entries = vec![SylspEntry {
+            sequence: Some(String::from_utf8_lossy(tag).to_string()),
+            ..sylsp_entry("s", mm_hash_tag(tag))
+        }];
+
+        let tmp = TempDir::new("seqidx");
+
+        let path = tmp.join("seqidx.sylsp");
+        index_sylsp_sequences(&OutputOptions::default(), &path, &mut entries, false).unwrap();
+        let index = read_sequence_index(&path).unwrap().unwrap();
+
+        assert!(entries[0].sequence.is_none());
+        assert_eq!(index.get(&mm_hash_tag(tag)).map(String::as_str), Some("ACGTTGCAACCGAGATTCATGCACGTTGCAAT"));
+
+        let (_, collisions) = build_sequence_index(vec![(1, "AAA"), (1, "AAA"), (1, "CCC")].into_iter());
+        assert_eq!(collisions, 1);
+    }
+
+    #[test]
+    fn test_mm_tag_hash_is_stable_and_collision_free() {
+        // 固定标签的哈希值不能随版本变化，否则旧数据库与新样本无法比较
+        assert_eq!(mm_hash_tag(b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT"), 0xb3e2d99c78a76650);
+        assert_eq!(mm_hash_tag(b"ACGT"), 0x47da4d8e60326182);
+        assert_eq!(mm_hash_tag(b"acgt"), mm_hash_tag(b"ACGT"));
+        assert_ne!(mm_hash_tag(b"ACGT"), mm_hash_tag(b"AACGT"));
+
+        let mut state = 7u64;
+        let mut tags: FxHashSet<Vec<u8>> = FxHashSet::default();
+        while tags.len() < 200_000 {
+            let mut bits = crate::sketch::mm_hash64(state);
+            state += 1;
+            let tag: Vec<u8> = (0..32).map(|_| { let b = b"ACGT"[(bits & 3) as usize]; bits >>= 2; b }).collect();
+            tags.insert(tag);
+        }
+        // 同长度32bp标签：mm哈希是双射，碰撞数必为0（FNV只是概率上很少）
+        let mm: FxHashSet<Hash> = tags.iter().map(|t| mm_hash_tag(t)).collect();
+        assert_eq!(mm.len(), tags.len());
+    }
+
+    #[test]
+    fn test_fnv_tag_hash_is_per_enzyme_spec() {
+        let tag = b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        let fnv = EnzymeSpec::new("BcgI").unwrap().with_fnv_tag_hash(true);
+        let default = EnzymeSpec::new("BcgI").unwrap();
+        // 同一进程里两种设置互不影响
+        assert_eq!(fnv.hash_tag(tag), hash_bytes(tag));
+        assert_eq!(default.hash_tag(tag), mm_hash_tag(tag));
+    }
+
+    #[test]
+    fn test_doubling_subsample_rate_halves_tags() {
+        // splitmix64生成可复现的伪随机标签哈希
+        let mut state = 0x5eed_u64;
+        let hashes: Vec<Hash> = (0..40_000)
+            .map(|_| {
+                state = state.wrapping_add(0x9e3779b97f4a7c15);
+                let mut z = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                z ^ (z >> 31)
+            })
+            .collect();
+        let kept = |rate: u64| hashes.iter().filter(|&&h| subsample_keeps(h, rate)).count();
+
+        assert_eq!(kept(1), hashes.len());
+        let ratio = kept(4) as f64 / kept(8) as f64;
+        assert!((ratio - 2.0).abs() < 0.15, "ratio {}", ratio);
+        // 抽样是确定性的：rate 8保留的标签都在rate 4的集合中
+        assert!(hashes.iter().all(|&h| !subsample_keeps(h, 8) || subsample_keeps(h, 4)));
+
+        let enzyme = EnzymeSpec::new("BcgI").unwrap().with_subsample_rate(u64::MAX);
+        let site = b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        assert!(enzyme.passes_subsample(site) == subsample_keeps(mm_hash_tag(site), u64::MAX));
+    }
+
+    #[test]
+    fn test_adapter_tag_is_removed_and_clean_tag_kept() {
+        let enzyme = EnzymeSpec::new("BcgI").unwrap()
+            .with_adapters(&["truseq".to_string()], false)
+            .unwrap();
+        // 中间12bp来自TruSeq接头
+        let adapter_tag = b"CGAACCCCAAAGATCGGAAGAGCATTCGCTGC";
+        let clean_tag = b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        assert!(!enzyme.passes_adapter(adapter_tag));
+        assert!(enzyme.passes_adapter(clean_tag));
+        // 反向互补链上的接头同样识别
+        assert!(!enzyme.passes_adapter(&reverse_complement(adapter_tag)));
+        assert_eq!(enzyme.adapter_tags.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+        let flagging = EnzymeSpec::new("BcgI").unwrap()
+            .with_adapters(&["AGATCGGAAGAGC".to_string()], true)
+            .unwrap();
+        assert!(flagging.passes_adapter(adapter_tag));
+        assert_eq!(flagging.adapter_tags.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        assert!(EnzymeSpec::new("BcgI").unwrap().with_adapters(&["NNNN".to_string()], false).is_err());
+    }
+
+    #[test]
+    fn test_tag_offset_aligns_asymmetric_site() {
+        // 侧翼不对称（13 + 9）的34bp位点，标签长度仍是BcgI的32bp
+        let asymmetric = |enzyme: EnzymeSpec| EnzymeSpec {
+            patterns: vec![Regex::new(r"[ACGT]{13}CGA[ACGT]{6}TGC[ACGT]{9}").unwrap()],
+            strand_specific: true,
+            ..enzyme
+        };
+        let site = b"GGACGTTGCAACACGAGATTCATGCACGTTGCAA";
+        assert_eq!(site.len(), 34);
+
+        let centered = extract_tags_scalar(site, &asymmetric(EnzymeSpec::new("BcgI").unwrap())).unwrap();
+        assert_eq!(centered, vec![site[1..33].to_vec()]);
+
+        let offset = extract_tags_scalar(site, &asymmetric(EnzymeSpec::new("BcgI").unwrap().with_tag_offset(Some(2)))).unwrap();
+        assert_eq!(offset, vec![site[2..34].to_vec()]);
+
+        // 偏移超出范围时截到位点末端
+        let clamped = EnzymeSpec::new("BcgI").unwrap().with_tag_offset(Some(10));
+        assert_eq!(clamped.tag_start(34, 32), 2);
+    }
+
+    #[test]
+    fn test_corrupt_genome_is_reported_and_fails_without_keep_going() {
+        let tmp = TempDir::new("batch_report");
+        let dir = tmp.path();
+
+        let valid = dir.join("valid.fa");
+        std::fs::write(&valid, ">g1\nTTTTACGTTGCAACCGAGATTCATGCACGTTGCAATTTT\n").unwrap();
+        let corrupt = dir.join("corrupt.fa");
+        std::fs::write(&corrupt, "this is not a FASTA file\n").unwrap();
+        let empty = dir.join("empty.fa");
+        std::fs::write(&empty, "").unwrap();
+        let genomes: Vec<String> = [&valid, &corrupt, &empty].iter().map(|p| p.to_str().unwrap().to_string()).collect();
+
+        let args = |out: &str, keep_going: bool| ExtractArgs {
+            genomes: Some(genomes.clone()),
+            sample_output_dir: dir.join(out).to_str().unwrap().to_string(),
+            enzyme: "BcgI".to_string(),
+            threads: 1,
+            format: "fa".to_string(),
+            keep_going,
+            ..Default::default()
+        };
+
+        let err = extract(args("strict", false)).unwrap_err();
+        assert!(format!("{:#}", err).contains("corrupt.fa"), "{:#}", err);
+        assert!(!dir.join("strict/combined.syldb").exists());
+
+        extract(args("lenient", true)).unwrap();
+        let combined: Vec<SyldbEntry> =
+            read_syldb(BufReader::new(File::open(dir.join("lenient/combined.syldb")).unwrap())).unwrap();
+        assert_eq!(combined.len(), 1);
+        assert_eq!(combined[0].genome_source, genomes[0]);
+
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        let results = genomes.iter()
+            .map(|g| process_fasta_to_syldb(Path::new(g), Path::new(g), &enzyme, "fa", false, false))
+            .collect();
+        let report = collect_genome_results(&genomes, results, true).unwrap();
+        assert_eq!(report.len(), 1);
+    }
+
+    #[test]
+    fn test_recognition_site_report_counts_n_containing_site() {
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        let clean = b"TTTTACGTTGCAACCGAGATTCATGCACGTTGCAATTTT";
+        let with_n = b"TTTTACGTTNCAACCGAGATTCATGCACGTTGCAATTTT";
+
+        let mut report = SiteReport::default();
+        report.add_sequence(with_n, &enzyme).unwrap();
+        assert_eq!(report.sites, 1);
+        assert_eq!(report.valid_tags, 0);
+        assert_eq!(report.ambiguous_base, 1);
+        assert_eq!(report.efficiency(), 0.0);
+
+        // 有效标签与提取路径一致
+        let (tags, rejections) = extract_tags_with_rejections(clean, &enzyme).unwrap();
+        assert!(rejections.is_empty());
+        assert_eq!(tags, extract_and_validate_tags(clean, &enzyme).unwrap());
+
+        // 侧翼被序列端点截断的位点
+        let (tags, rejections) = extract_tags_with_rejections(&clean[9..], &enzyme).unwrap();
+        assert!(tags.is_empty());
+        assert_eq!(rejections, vec![SiteRejection::Truncated]);
+
+        let tmp = TempDir::new("site_report");
+
+        let dir = tmp.path();
+        let path = dir.join("sites.tsv");
+        report.add_sequence(clean, &enzyme).unwrap();
+        write_site_report(&OutputOptions::default(), path.to_str().unwrap(), &enzyme, &[("reads.fq".to_string(), report)]).unwrap();
+        let lines: Vec<String> = std::fs::read_to_string(&path).unwrap().lines().map(String::from).collect();
+        assert_eq!(lines[0], SITE_REPORT_HEADER);
+        assert_eq!(lines[1], "reads.fq\tBcgI\t2\t2\t1\t0.5000\t1\t0\t0\t0\t0\t0");
+    }
+
+    #[test]
+    fn test_enzyme_tag_lengths_match_pattern_widths() {
+        assert_eq!(ENZYME_DEFINITIONS.len(), ENZYME_TAG_LENGTHS.len());
+        for (name, patterns) in ENZYME_DEFINITIONS {
+            let tag_length = check_tag_length(name, patterns).unwrap();
+            for pattern in *patterns {
+                // 按掩码构造一条满足模式的位点，正则应整段匹配
+                let site: Vec<u8> = parse_site_masks(pattern).iter()
+                    .map(|&mask| b"ACGT"[mask.trailing_zeros() as usize])
+                    .collect();
+                let m = Regex::new(pattern).unwrap().find(&site).unwrap();
+                assert_eq!(m.as_bytes().len(), tag_length, "{} {}", name, pattern);
+            }
+            assert!(EnzymeSpec::new(name).is_ok());
+        }
+
+        let err = check_tag_length("BcgI", &["[ACGT]{10}CGA[ACGT]{6}TGC[ACGT]{9}"]).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+    }
+
+    #[test]
+    fn test_depth_normalize_records_sample_totals_in_header() {
+        let entries: Vec<SylspEntry> = (0..5).map(|t| sylsp_entry("deep", t)).chain((0..2).map(|t| sylsp_entry("shallow", t))).collect();
+        let depths = sample_tag_totals(&entries);
+        assert_eq!(depths, vec![("deep".to_string(), 5), ("shallow".to_string(), 2)]);
+
+        let mut bytes = Vec::new();
+        write_sylsp_header_with_depths(&mut bytes, SylspKind::TagEntries, "", 1, &depths).unwrap();
+        bincode::serialize_into(&mut bytes, &entries).unwrap();
+        let mut reader = std::io::BufReader::new(&bytes[..]);
+        let header = read_sylsp_header_full(&mut reader).unwrap().unwrap();
+        assert_eq!(header.kind, SylspKind::TagEntries);
+        assert_eq!(header.sample_depths, depths);
+        let read_back: Vec<SylspEntry> = bincode::deserialize_from(reader).unwrap();
+        assert_eq!(read_back.len(), 7);
+
+        // 未记录时为空，读取方仍能跳过格式头
+        let mut bytes = Vec::new();
+        write_sylsp_header(&mut bytes, SylspKind::TagEntries, "", 1).unwrap();
+        assert!(read_sylsp_header_full(&mut &bytes[..]).unwrap().unwrap().sample_depths.is_empty());
+    }
+
+    #[test]
+    fn test_database_enzyme_rejects_mixed_enzyme_database() {
+        let entry = |genome: &str, enzyme: &str| SyldbEntry {
+            sequence_id: format!("{}_contig1", genome),
+            tags: vec![1, 2],
+            positions: vec![0, 50],
+            genome_source: genome.to_string(),
+            tag_uniqueness: None,
+            gn_size: 100,
+            tag_sequences: None,
+            enzyme: enzyme.to_string(),
+        };
+        let single = vec![entry("g1.fa", "BcgI"), entry("g2.fa", "BcgI"), entry("legacy.fa", "")];
+        assert_eq!(database_enzyme(&single).unwrap(), Some("BcgI".to_string()));
+        assert_eq!(database_enzyme(&[entry("legacy.fa", "")]).unwrap(), None);
+
+        let mixed = vec![entry("g1.fa", "BcgI"), entry("g2.fa", "CjeI"), entry("g3.fa", "CjeI")];
+        let message = database_enzyme(&mixed).unwrap_err().to_string();
+        assert!(message.contains("BcgI (1 genomes)"));
+        assert!(message.contains("CjeI (2 genomes)"));
+    }
+
+    #[test]
+    fn test_genome_positions_are_increasing_sequence_offsets() {
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        // 三个BcgI位点（CGA N6 TGC）相隔不等的间距，位点之间填充不含位点的序列
+        let site = |n: u8| -> Vec<u8> {
+            let mut s = b"AAAAAAAAAACGA".to_vec();
+            s.extend((0..6).map(|i| b"ACGT"[((i + n) % 4) as usize]));
+            s.extend(b"TGCAAAAAAAAAAAA");
+            s
+        };
+        let spacer = |len: usize| vec![b'T'; len];
+        let mut seq = spacer(100);
+        let mut expected_sites = Vec::new();
+        for (n, gap) in [(0u8, 250usize), (1, 40), (2, 1_000)] {
+            expected_sites.push(seq.len());
+            seq.extend(site(n));
+            seq.extend(spacer(gap));
+        }
+
+        let fasta = format!(">contig1\n{}\n", String::from_utf8(seq.clone()).unwrap());
+        let entries = fasta_reader_to_syldb(fasta.as_bytes(), "genome.fa", &enzyme, false).unwrap();
+        let entry = &entries[0];
+        assert_eq!(entry.tags.len(), 3);
+        assert_eq!(entry.positions.len(), entry.tags.len());
+        assert!(entry.positions.windows(2).all(|w| w[0] < w[1]));
+        assert_ne!(entry.positions, vec![0, 1, 2]);
+        // 每个坐标落在对应位点内，坐标处切出的标签就是保存的标签
+        let positioned = extract_positioned_tags(&seq, &enzyme).unwrap();
+        for ((pos, tag), site_start) in positioned.iter().zip(&expected_sites) {
+            assert!(*pos >= *site_start && *pos < site_start + 34);
+            assert_eq!(enzyme.orient_tag(&seq[*pos..*pos + tag.len()]), *tag);
+        }
+        assert_eq!(positioned.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(), entry.positions);
+    }
+
+    #[test]
+    fn test_min_read_length_skips_short_reads() {
+        let site = "ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        let short = format!("GGT{}TTA", site);
+        let long = format!("GGTACCTTAGCAGTCAGGTC{}TTAGGCATCCAGTGACATGG", site);
+
+        let tmp = TempDir::new("min_read_length");
+
+        let dir = tmp.path();
+        let (p1, p2) = (dir.join("s_1.fq"), dir.join("s_2.fq"));
+        let fastq = |reads: &[(&str, &str)]| reads.iter()
+            .map(|(id, seq)| format!("@{}\n{}\n+\n{}\n", id, seq, "I".repeat(seq.len())))
+            .collect::<String>();
+        // 第一对两个mate都短；第二对只有mate2短
+        std::fs::write(&p1, fastq(&[("pair1", &short), ("pair2", &long)])).unwrap();
+        std::fs::write(&p2, fastq(&[("pair1/2", &short), ("pair2/2", &short)])).unwrap();
+        let (p1, p2) = (p1.to_str().unwrap(), p2.to_str().unwrap());
+
+        let all = process_paired_fastq_to_sylsp(p1, p2, &EnzymeSpec::new("BcgI").unwrap(), "s", false).unwrap();
+        let enzyme = EnzymeSpec::new("BcgI").unwrap().with_min_read_length(long.len() - 1);
+        let filtered = process_paired_fastq_to_sylsp(p1, p2, &enzyme, "s", false).unwrap();
+
+        assert_eq!(all.len(), 4);
+        assert_eq!(filtered.len(), 1);
+        assert!(filtered[0].0.starts_with("pair2"));
+        assert_eq!(filtered[0].1, get_canonical_sequence(site.as_bytes()));
+        assert_eq!(enzyme.short_reads_skipped.load(std::sync::atomic::Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_strict_length_rejects_truncated_match() {
+        // 内置酶的位点宽度在构造时已校验，这里用侧翼被截短的位点模拟比标签短的匹配
+        let truncated = |enzyme: EnzymeSpec| EnzymeSpec {
+            patterns: vec![Regex::new(r"[ACGT]{4}CGA[ACGT]{6}TGC[ACGT]{4}").unwrap()],
+            ..enzyme
+        };
+        let seq = b"GGGGACCGAGATTCATGCACGTGGGG";
+
+        let lenient = truncated(EnzymeSpec::new("BcgI").unwrap());
+        let tags = extract_and_validate_tags(seq, &lenient).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(lenient.length_mismatched_tags.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        let strict = truncated(EnzymeSpec::new("BcgI").unwrap().with_strict_length(true));
+        assert!(extract_and_validate_tags(seq, &strict).unwrap().is_empty());
+        assert_eq!(strict.length_mismatched_tags.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // 完整长度的位点不受影响
+        let full = EnzymeSpec::new("BcgI").unwrap().with_strict_length(true);
+        let site = b"ACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        assert_eq!(extract_and_validate_tags(site, &full).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_enzyme_spec_from_args_separates_read_only_options() {
+        let args = ExtractArgs {
+            enzyme: "BcgI".to_string(),
+            strict_length: true,
+            long_read: true,
+            max_site_mismatches: 2,
+            min_read_length: 50,
+            error_correct: true,
+            error_correct_ratio: 10.0,
+            ..ExtractArgs::default()
+        };
+        let panel = Arc::new(FxHashSet::default());
+
+        let genomes = enzyme_spec_from_args(&args, ExtractInput::Genomes).unwrap();
+        assert!(genomes.strict_length);
+        assert_eq!((genomes.max_mismatches, genomes.min_read_length), (0, 0));
+        assert!(genomes.error_correct_ratio.is_none() && genomes.prefilter_tags.is_none());
+
+        let reads = enzyme_spec_from_args(&args, ExtractInput::Reads(Some(&panel))).unwrap();
+        assert!(reads.strict_length);
+        assert_eq!((reads.max_mismatches, reads.min_read_length), (2, 50));
+        assert_eq!(reads.error_correct_ratio, Some(10.0));
+        assert!(reads.prefilter_tags.is_some());
+    }
+
+    #[test]
+    fn test_tag_fasta_import_matches_extracted_sample() {
+        let tmp = TempDir::new("tag_fasta");
+        let dir = tmp.path();
+        let out_dir = dir.join("out");
+        std::fs::create_dir_all(&out_dir).unwrap();
+
+        // 外部工具按基因组正链写出的标签；样本reads来自反链
+        let site = b"ACGTTGCAACCGAGATTCATGCACGTTGCAA";
+        let genome = b"TTT".iter().chain(site.iter()).chain(b"TTT".iter()).copied().collect::<Vec<u8>>();
+        let read = reverse_complement(&genome);
+        let forward_tags = extract_tags_scalar(&genome, &EnzymeSpec::new("BcgI").unwrap().with_strand_specific(true)).unwrap();
+        assert_eq!(forward_tags.len(), 1);
+        let tag_fasta = dir.join("g1.fa");
+        std::fs::write(&tag_fasta, format!(">t1\n{}\n", String::from_utf8_lossy(&forward_tags[0]))).unwrap();
+
+        let import = |orientation: &str| -> Vec<Hash> {
+            let args = ExtractArgs {
+                enzyme: "BcgI".to_string(),
+                tag_fasta: Some(vec![tag_fasta.to_str().unwrap().to_string()]),
+                tag_fasta_type: "genome".to_string(),
+                tag_orientation: orientation.to_string(),
+                sample_output_dir: out_dir.to_str().unwrap().to_string(),
+                out_name: Some(orientation.to_string()),
+                ..Default::default()
+            };
+            import_tag_fastas(&OutputOptions::overwriting(), &Progress::default(), &args, args.tag_fasta.as_ref().unwrap(), false).unwrap();
+            let entries = read_syldb(BufReader::new(File::open(tag_fasta_output_path(&args)).unwrap())).unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].sequence_id, "g1");
+            entries[0].tags.clone()
+        };
+
+        let sample: Vec<Hash> = extract_and_validate_tags(&read, &EnzymeSpec::new("BcgI").unwrap()).unwrap()
+            .iter().map(|tag| mm_hash_tag(tag)).collect();
+        assert_eq!(sample.len(), 1);
+        assert_eq!(import("canonical"), sample);
+
+        // forward：只与按同一链方向提取的数据可比
+        let forward = import("forward");
+        assert_eq!(forward, vec![mm_hash_tag(&forward_tags[0])]);
+        let stranded_sample = extract_tags_scalar(&read, &EnzymeSpec::new("BcgI").unwrap().with_strand_specific(true)).unwrap();
+        assert_ne!(forward, vec![mm_hash_tag(&stranded_sample[0])]);
+
+        // 长度不符的标签报错
+        std::fs::write(&tag_fasta, ">short\nACGT\n").unwrap();
+        let args = ExtractArgs {
+            enzyme: "BcgI".to_string(),
+            tag_fasta: Some(vec![tag_fasta.to_str().unwrap().to_string()]),
+            sample_output_dir: out_dir.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        assert!(import_tag_fastas(&OutputOptions::overwriting(), &Progress::default(), &args, args.tag_fasta.as_ref().unwrap(), false).is_err());
+    }
+
+    #[test]
+    fn test_error_correct_collapses_single_substitution_variant() {
+        let enzyme = EnzymeSpec::new("BcgI").unwrap().with_error_correction(Some(10.0));
+        let parent = get_canonical_sequence(b"ACGTTGCAACCGAGATTCATGCACGTTGCAAG");
+        let mut variant = parent.clone();
+        variant[5] = if variant[5] == b'A' { b'C' } else { b'A' };
+        let variant = get_canonical_sequence(&variant);
+        let unrelated = get_canonical_sequence(b"TTGACCATGACGAGATTCATGCATTGACCAGT");
+
+        let entry = |tag: &[u8]| SylspEntry {
+            sequence: enzyme.keeps_sequences(false).then(|| String::from_utf8_lossy(tag).to_string()),
+            ..sylsp_entry("s", mm_hash_tag(tag))
+        };
+        let mut entries: Vec<SylspEntry> = (0..20).map(|_| entry(&parent)).collect();
+        entries.push(entry(&variant));
+        entries.push(entry(&unrelated));
+
+        enzyme.correct_tag_errors(&mut entries, false);
+        assert_eq!(entries[20].tag, mm_hash_tag(&parent));
+        assert_eq!(entries[21].tag, mm_hash_tag(&unrelated));
+        assert!(entries.iter().all(|e| e.sequence.is_none()));
+        assert_eq!(enzyme.error_corrected_tags.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // 邻居丰度不足ratio倍时不改写
+        let corrections = tag_error_corrections([parent.as_slice(), variant.as_slice()], 10.0, get_canonical_sequence);
+        assert!(corrections.is_empty());
+    }
+
+    #[test]
+    fn test_error_correct_rewrites_reads_fasta() {
+        let tmp = TempDir::new("error_correct_fasta");
+        let dir = tmp.path();
+
+        // 20条read带真实位点，1条在标签侧翼有一个替换
+        let site = "GGGGACGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        let variant = "GGGGAAGTTGCAACCGAGATTCATGCACGTTGCAAT";
+        let mut fastq = String::new();
+        for (i, seq) in std::iter::repeat_n(site, 20).chain([variant]).enumerate() {
+            fastq.push_str(&format!("@r{}\n{}\n+\n{}\n", i, seq, "I".repeat(seq.len())));
+        }
+        let reads = dir.join("s1.fq");
+        std::fs::write(&reads, fastq).unwrap();
+
+        extract(ExtractArgs {
+            reads: Some(vec![reads.to_str().unwrap().to_string()]),
+            sample_output_dir: dir.to_str().unwrap().to_string(),
+            enzyme: "BcgI".to_string(),
+            threads: 1,
+            format: "fa".to_string(),
+            error_correct: true,
+            error_correct_ratio: 10.0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let enzyme = EnzymeSpec::new("BcgI").unwrap();
+        let parent = extract_and_validate_tags(site.as_bytes(), &enzyme).unwrap();
+        let fasta = std::fs::read_to_string(dir.join("reads.fasta")).unwrap();
+        let tags: Vec<&str> = fasta.lines().filter(|line| !line.starts_with('>')).collect();
+        assert_eq!(tags.len(), 21);
+        assert!(tags.iter().all(|tag| tag.as_bytes() == parent[0].as_slice()));
+    }
+
+    #[test]
+    fn test_prefilter_keeps_only_reads_hitting_panel() {
+        let tmp = TempDir::new("prefilter");
+        let dir = tmp.path();
+
+        let on_target = "TTTACGTTGCAACCGAGATTCATGCACGTTGCAATTT";
+        let off_target = "TTTGGCATTACAGCGATTGACCTGCAATCGGTTACTTT";
+        let panel_tags = extract_and_validate_tags(on_target.as_bytes(), &EnzymeSpec::new("BcgI").unwrap()).unwrap();
+        assert!(!panel_tags.is_empty());
+        let panel: FxHashSet<Hash> = panel_tags.iter().map(|tag| mm_hash_tag(tag)).collect();
+
+        let fastq = |reads: &[(&str, &str)]| -> String {
+            reads.iter().map(|(id, seq)| format!("@{}\n{}\n+\n{}\n", id, seq, "I".repeat(seq.len()))).collect()
+        };
+        let mate = "T".repeat(40);
+        let r1 = dir.join("s_R1.fq");
+        let r2 = dir.join("s_R2.fq");
+        std::fs::write(&r1, fastq(&[("on", on_target), ("off", off_target)])).unwrap();
+        std::fs::write(&r2, fastq(&[("on", &mate), ("off", &mate)])).unwrap();
+
+        let enzyme = EnzymeSpec::new("BcgI").unwrap().with_prefilter(Some(Arc::new(panel)));
+        let entries = process_paired_fastq_to_sylsp(r1.to_str().unwrap(), r2.to_str().unwrap(), &enzyme, "s", false).unwrap();
+
+        assert_eq!(entries.len(), panel_tags.len());
+        assert!(entries.iter().all(|(id, tag, _)| id.starts_with("on_") && panel_tags.contains(tag)));
+        assert_eq!(enzyme.prefilter_reads_dropped.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // 没有面板时不过滤
+        let unfiltered = EnzymeSpec::new("BcgI").unwrap();
+        assert!(unfiltered.passes_prefilter(&extract_and_validate_tags(off_target.as_bytes(), &unfiltered).unwrap()));
+    }
+}
@@ -2,13 +2,13 @@
 
 use crate::cmdline::InspectArgs;
 use anyhow::{Context, Result};
-use bincode;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
 // use regex::Regex;
-use crate::constants::Hash;
+use crate::constants::{Hash, natural_cmp, check_output_dir, OutputOptions};
+use crate::extract::{read_sequence_index, stream_sylsp_entries, SequenceIndex, SylspEntry};
 use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,7 +26,6 @@ struct InspectResult {
     sample_sources: Option<Vec<SampleStats>>,
     // 每个样本对应的 tag -> 计数
     per_sample_tag_counts: Option<std::collections::HashMap<String, std::collections::HashMap<Hash, usize>>>,
-    tag_lengths: Vec<usize>,
     tag_length_distribution: Vec<(usize, usize, f64)>,
     patterns: Vec<String>,
     genome_stats: Option<Vec<GenomeStats>>,
@@ -39,6 +38,47 @@ struct GenomeStats {
     total_tags: usize,
     unique_tags: usize,
     tag_length_distribution: Vec<(usize, usize, f64)>,
+    genome_size: usize,
+    expected_tags: Option<f64>,
+    // 每Mb基因组的标签数，用于不同大小基因组之间的比较
+    tags_per_mb: Option<f64>,
+}
+
+impl InspectResult {
+    // --min-count：去掉合计计数低于min_count的tag，总数和唯一数也只统计保留下来的tag，
+    // 与频率表和矩阵一致。返回去掉的tag数
+    fn retain_min_count(&mut self, min_count: usize) -> usize {
+        if min_count <= 1 {
+            return 0;
+        }
+        let before = self.tag_frequency_stats.len();
+        self.tag_frequency_stats.retain(|(_, count)| *count >= min_count);
+        self.total_tags = self.tag_frequency_stats.iter().map(|(_, count)| count).sum();
+        self.unique_tags = self.tag_frequency_stats.len();
+        before - self.tag_frequency_stats.len()
+    }
+}
+
+// 观测标签数低于期望值的这个比例时，提示可能是不完整的组装
+const LOW_COMPLETENESS_RATIO: f64 = 0.6;
+
+fn is_low_completeness(observed_tags: usize, expected_tags: f64) -> bool {
+    expected_tags > 0.0 && (observed_tags as f64) < expected_tags * LOW_COMPLETENESS_RATIO
+}
+
+fn tags_per_mb(total_tags: usize, genome_size: usize) -> Option<f64> {
+    if genome_size == 0 {
+        return None;
+    }
+    Some(total_tags as f64 / (genome_size as f64 / 1_000_000.0))
+}
+
+// 至少命中一个标签才能检出，基因组中标签越多可检出的比例越小
+fn min_detectable_fraction(total_tags: usize) -> Option<f64> {
+    if total_tags == 0 {
+        return None;
+    }
+    Some(1.0 / total_tags as f64)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -56,9 +96,30 @@ struct TagMatrix {
     matrix: HashMap<(String, Hash), usize>,
 }
 
+impl TagMatrix {
+    // 去掉所有样本合计计数低于min_count的tag（多为测序噪声）
+    fn retain_min_count(&mut self, min_count: usize) {
+        if min_count <= 1 {
+            return;
+        }
+        let mut totals: HashMap<Hash, usize> = HashMap::new();
+        for ((_, tag), count) in &self.matrix {
+            *totals.entry(*tag).or_insert(0) += count;
+        }
+        let keep = |tag: &Hash| totals.get(tag).copied().unwrap_or(0) >= min_count;
+        self.tags.retain(|tag| keep(tag));
+        self.matrix.retain(|(_, tag), _| keep(tag));
+    }
+}
+
 pub fn inspect(args: InspectArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, args.temp_dir.as_deref());
+    // 矩阵在最后才写出，先检查目录可写
+    if let Some(log_path) = &args.log_path {
+        check_output_dir(log_path)?;
+    }
     let mut writer = match args.out_file_name {
-        Some(path) => Box::new(BufWriter::new(File::create(path)?)) as Box<dyn Write>,
+        Some(path) => Box::new(BufWriter::new(output.create_file(path)?)) as Box<dyn Write>,
         None => Box::new(BufWriter::new(std::io::stdout())) as Box<dyn Write>,
     };
 
@@ -69,9 +130,18 @@ pub fn inspect(args: InspectArgs) -> Result<()> {
         matrix: HashMap::new(),
     };
 
+    // extract --index-sequences 写出的旁路索引，合并后用于把hash还原成序列
+    let mut sequence_index = SequenceIndex::default();
+
     for file in &args.files {
-        match inspect_file(file) {
-            Ok(result) => {
+        match read_sequence_index(Path::new(file)) {
+            Ok(Some(index)) => sequence_index.extend(index),
+            Ok(None) => {}
+            Err(e) => eprintln!("Warning: ignoring sequence index for {}: {}", file, e),
+        }
+        match inspect_file(file, &args.enzyme, args.log_path.is_some()) {
+            Ok(mut result) => {
+                let hidden = result.retain_min_count(args.min_count);
                 // 输出文件信息
                 writeln!(writer, "File Information:")?;
                 writeln!(writer, "----------------")?;
@@ -95,17 +165,20 @@ pub fn inspect(args: InspectArgs) -> Result<()> {
                 
                 writeln!(writer, "\nTag Frequency Statistics:")?;
                 writeln!(writer, "------------------------")?;
-                writeln!(writer, "{:<20} {:<10}", "Tag Hash", "Count")?;
+                writeln!(writer, "{:<20} {:<10}", "Tag", "Count")?;
                 writeln!(writer, "{:-<30}", "")?;
                 
-                // 显示前20个最常见的tag
+                // 显示前20个最常见的tag（低于--min-count的已去掉）
                 let display_count = std::cmp::min(20, result.tag_frequency_stats.len());
                 for (tag_hash, count) in result.tag_frequency_stats.iter().take(display_count) {
-                    writeln!(writer, "{:<20} {:<10}", format!("{:016x}", tag_hash), count)?;
+                    writeln!(writer, "{:<20} {:<10}", tag_label(*tag_hash, &sequence_index), count)?;
                 }
                 if result.tag_frequency_stats.len() > display_count {
                     writeln!(writer, "... and {} more unique tags", result.tag_frequency_stats.len() - display_count)?;
                 }
+                if args.min_count > 1 {
+                    writeln!(writer, "({} tags below --min-count {} excluded from all statistics)", hidden, args.min_count)?;
+                }
                 
                 writeln!(writer, "\nDetected Enzyme Information:")?;
                 writeln!(writer, "-------------------------")?;
@@ -151,6 +224,20 @@ pub fn inspect(args: InspectArgs) -> Result<()> {
                         writeln!(writer, "  Records: {}", genome.num_records)?;
                         writeln!(writer, "  Total tags: {}", genome.total_tags)?;
                         writeln!(writer, "  Unique tags: {}", genome.unique_tags)?;
+                        if genome.genome_size > 0 {
+                            writeln!(writer, "  Genome size: {} bp", genome.genome_size)?;
+                        }
+                        if let Some(density) = genome.tags_per_mb {
+                            writeln!(writer, "  Tags per Mb: {:.2}", density)?;
+                        }
+                        if let Some(fraction) = min_detectable_fraction(genome.total_tags) {
+                            writeln!(writer, "  Smallest detectable genome fraction: {:.4}%", fraction * 100.0)?;
+                        }
+                        if let Some(expected) = genome.expected_tags {
+                            writeln!(writer, "  Expected tags ({}): {:.0} (observed/expected: {:.2}){}",
+                                args.enzyme, expected, genome.total_tags as f64 / expected,
+                                if is_low_completeness(genome.total_tags, expected) { "  [LOW: possibly incomplete assembly]" } else { "" })?;
+                        }
                         writeln!(writer, "  Tag length distribution:")?;
                         for (length, count, _) in &genome.tag_length_distribution {
                             writeln!(writer, "    Length {}: {} tags", length, count)?;
@@ -168,28 +255,76 @@ pub fn inspect(args: InspectArgs) -> Result<()> {
         }
     }
 
+    if args.validate {
+        writeln!(writer, "Canonicalization Check:")?;
+        writeln!(writer, "----------------------")?;
+        for file in &args.files {
+            match read_stored_sequences(file) {
+                Ok(sequences) if sequences.is_empty() => {
+                    writeln!(writer, "{}: no stored tag sequences (re-run extract with --store-seq)", file)?;
+                }
+                Ok(sequences) => {
+                    let report = check_canonical(sequences.iter().map(|s| s.as_str()));
+                    writeln!(writer, "{}: {} tags checked, {} non-canonical, {} reverse-complement pairs",
+                             file, report.checked, report.non_canonical.len(), report.rc_pairs.len())?;
+                    for seq in report.non_canonical.iter().take(20) {
+                        writeln!(writer, "  non-canonical: {}", seq)?;
+                    }
+                    for (a, b) in report.rc_pairs.iter().take(20) {
+                        writeln!(writer, "  rc pair: {} / {}", a, b)?;
+                    }
+                }
+                Err(e) => eprintln!("Failed to validate {}: {}", file, e),
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    if args.gc_report {
+        writeln!(writer, "Tag Composition:")?;
+        writeln!(writer, "---------------")?;
+        for file in &args.files {
+            match read_grouped_sequences(file) {
+                Ok(groups) if groups.is_empty() => {
+                    writeln!(writer, "{}: no stored tag sequences (re-run extract with --store-seq)", file)?;
+                }
+                Ok(groups) => {
+                    writeln!(writer, "{}:", file)?;
+                    for (source, sequences) in &groups {
+                        let report = CompositionReport::from_sequences(sequences.iter().map(|s| s.as_str()));
+                        write_composition_report(&mut writer, source, &report)?;
+                    }
+                }
+                Err(e) => eprintln!("Failed to report composition for {}: {}", file, e),
+            }
+        }
+        writeln!(writer)?;
+    }
+
     // 如果指定了输出路径，生成TSV矩阵
     if let Some(log_path) = &args.log_path {
-        generate_tsv_matrix(&tag_matrix, log_path, &args.tsv_name)?;
+        tag_matrix.retain_min_count(args.min_count);
+        generate_tsv_matrix(&output, &tag_matrix, &sequence_index, log_path, &args.tsv_name)?;
     }
 
     Ok(())
 }
 
-fn inspect_file(file_path: &str) -> Result<InspectResult> {
+// per_sample：是否累积每个样本的tag计数，只有写标签矩阵(--log-path)时才需要
+fn inspect_file(file_path: &str, enzyme: &str, per_sample: bool) -> Result<InspectResult> {
     let path = Path::new(file_path);
     let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     match path.extension().and_then(|s| s.to_str()) {
-        Some("syldb") => inspect_syldb(reader, file_path),
-        Some("sylsp") => inspect_sylsp(reader, file_path),
+        Some("syldb") => inspect_syldb(reader, file_path, enzyme),
+        Some("sylsp") => inspect_sylsp(reader, file_path, per_sample),
         _ => Err(anyhow::anyhow!("Unknown file extension, expected .syldb or .sylsp")),
     }
 }
 
-fn inspect_syldb(reader: BufReader<File>, file_path: &str) -> Result<InspectResult> {
-    let entries: Vec<crate::extract::SyldbEntry> = bincode::deserialize_from(reader)
+fn inspect_syldb(reader: BufReader<File>, file_path: &str, enzyme_name: &str) -> Result<InspectResult> {
+    let entries = crate::extract::read_syldb(reader)
         .context("Failed to deserialize .syldb file")?;
 
     let mut tag_lengths = Vec::new();
@@ -211,10 +346,14 @@ fn inspect_syldb(reader: BufReader<File>, file_path: &str) -> Result<InspectResu
             total_tags: 0,
             unique_tags: 0,
             tag_length_distribution: Vec::new(),
+            genome_size: 0,
+            expected_tags: None,
+            tags_per_mb: None,
         });
         
         stats.num_records += 1;
         stats.total_tags += entry.tags.len();
+        stats.genome_size = stats.genome_size.max(entry.gn_size);
         
         // 处理tags和uniqueness信息
         for (i, tag) in entry.tags.iter().enumerate() {
@@ -242,6 +381,13 @@ fn inspect_syldb(reader: BufReader<File>, file_path: &str) -> Result<InspectResu
             }
         }
         stats.tag_length_distribution = calculate_tag_distribution(&lengths);
+
+        // 根据基因组大小和酶切位点概率估算期望标签数
+        if stats.genome_size > 0 {
+            stats.expected_tags = crate::extract::expected_tags_per_bp(enzyme_name)
+                .map(|per_bp| per_bp * stats.genome_size as f64);
+        }
+        stats.tags_per_mb = tags_per_mb(stats.total_tags, stats.genome_size);
     }
 
     let distribution = calculate_tag_distribution(&tag_lengths);
@@ -256,7 +402,7 @@ fn inspect_syldb(reader: BufReader<File>, file_path: &str) -> Result<InspectResu
     };
     
     let mut tag_frequency_stats: Vec<(Hash, usize)> = tag_frequency.into_iter().collect();
-    tag_frequency_stats.sort_by(|a, b| b.1.cmp(&a.1)); // 按频率降序排序
+    tag_frequency_stats.sort_by_key(|&(_, count)| std::cmp::Reverse(count)); // 按频率降序排序
 
     println!("Total tags: {}", tag_lengths.len());
     if has_unique_marks {
@@ -288,33 +434,43 @@ fn inspect_syldb(reader: BufReader<File>, file_path: &str) -> Result<InspectResu
         },
         sample_sources: None,
         per_sample_tag_counts: None,
-        tag_lengths,
         tag_length_distribution: distribution,
         patterns,
         genome_stats: Some(genome_stats.into_values().collect()),
     })
 }
 
-fn inspect_sylsp(reader: BufReader<File>, file_path: &str) -> Result<InspectResult> {
-    let entries: Vec<crate::extract::SylspEntry> = bincode::deserialize_from(reader)
-        .context("Failed to deserialize .sylsp file")?;
+fn inspect_sylsp<R: BufRead>(mut reader: R, file_path: &str, per_sample: bool) -> Result<InspectResult> {
+    let header = crate::extract::read_sylsp_header_full(&mut reader)?;
+    if let Some(header) = &header {
+        if header.kind != crate::extract::SylspKind::TagEntries {
+            return Err(anyhow::anyhow!("{} is a k-mer sketch file; use `meta2bseek view` instead", file_path));
+        }
+        if header.subsample_rate > 1 {
+            println!("Tags subsampled at rate 1/{} (--subsample-rate)", header.subsample_rate);
+        }
+    }
 
-    let mut tag_lengths = Vec::new();
-    let mut sample_stats = std::collections::HashMap::new();
-    let mut tag_frequency = std::collections::HashMap::new();
-    let mut per_sample_tag_counts: std::collections::HashMap<String, std::collections::HashMap<Hash, usize>> = std::collections::HashMap::new();
+    // 边读边累积统计，不把整个Vec<SylspEntry>读入内存；内存只随不同tag数增长。
+    // 每个样本的tag计数随样本数×tag数增长，不写矩阵时不收集
+    let mut sample_stats: HashMap<String, SampleStats> = HashMap::new();
+    let mut tag_frequency: HashMap<Hash, usize> = HashMap::new();
+    let mut per_sample_tag_counts: HashMap<String, HashMap<Hash, usize>> = HashMap::new();
+    let mut first_contig_name = None;
 
-    for entry in &entries {
-        tag_lengths.push(8); // Hash is always 8 bytes (u64)
+    let num_records = stream_sylsp_entries(reader, header.as_ref(), |entry: SylspEntry| {
         *tag_frequency.entry(entry.tag).or_insert(0) += 1;
 
         // 累积每个样本的 tag 计数
-        let sample_entry = per_sample_tag_counts
-            .entry(entry.sample_source.clone())
-            .or_insert_with(std::collections::HashMap::new);
-        *sample_entry.entry(entry.tag).or_insert(0) += 1;
+        if per_sample {
+            *per_sample_tag_counts
+                .entry(entry.sample_source.clone())
+                .or_default()
+                .entry(entry.tag)
+                .or_insert(0) += 1;
+        }
 
-        let stats = sample_stats.entry(entry.sample_source.clone()).or_insert(SampleStats {
+        let stats = sample_stats.entry(entry.sample_source.clone()).or_insert_with(|| SampleStats {
             source: entry.sample_source.clone(),
             num_records: 0,
             total_tags: 0,
@@ -322,40 +478,38 @@ fn inspect_sylsp(reader: BufReader<File>, file_path: &str) -> Result<InspectResu
         });
         stats.num_records += 1;
         stats.total_tags += 1;
-    }
 
-    for stats in sample_stats.values_mut() {
-        let mut sample_lengths = Vec::new();
-        for entry in &entries {
-            if entry.sample_source == stats.source {
-                sample_lengths.push(8); // Hash is always 8 bytes (u64)
-            }
+        if first_contig_name.is_none() {
+            first_contig_name = Some(entry.sequence_id);
         }
-        stats.tag_length_distribution = calculate_tag_distribution(&sample_lengths);
+    })
+    .context("Failed to deserialize .sylsp file")?;
+
+    for stats in sample_stats.values_mut() {
+        stats.tag_length_distribution = hash_length_distribution(stats.total_tags);
     }
 
-    let distribution = calculate_tag_distribution(&tag_lengths);
+    let distribution = hash_length_distribution(num_records);
     let (enzyme, patterns, _matched_count, _matched_ratio) = ("unknown".to_string(), Vec::new(), 0, 0.0);
 
     // 计算tag统计信息
     let unique_tags = tag_frequency.len();
     let mut tag_frequency_stats: Vec<(Hash, usize)> = tag_frequency.into_iter().collect();
-    tag_frequency_stats.sort_by(|a, b| b.1.cmp(&a.1)); // 按频率降序排序
+    tag_frequency_stats.sort_by_key(|&(_, count)| std::cmp::Reverse(count)); // 按频率降序排序
 
     Ok(InspectResult {
         file_type: "SampleProfile".to_string(),
         file_name: file_path.to_string(),
         enzyme,
-        num_records: entries.len(),
-        total_tags: tag_lengths.len(),
+        num_records,
+        total_tags: num_records,
         unique_tags,
         tag_frequency_stats,
         mean_read_length: None,
-        first_contig_name: entries.first().map(|e| e.sequence_id.clone()),
+        first_contig_name,
         genome_sources: None,
         sample_sources: Some(sample_stats.into_values().collect()),
-        per_sample_tag_counts: Some(per_sample_tag_counts),
-        tag_lengths,
+        per_sample_tag_counts: per_sample.then_some(per_sample_tag_counts),
         tag_length_distribution: distribution,
         patterns,
         genome_stats: None,
@@ -389,6 +543,15 @@ fn inspect_sylsp(reader: BufReader<File>, file_path: &str) -> Result<InspectResu
 //     (best_enzyme, best_patterns, best_count, best_ratio)
 // }
 
+// 样本tag都以8字节哈希(u64)存储，长度分布只有一个取值，按计数直接构造
+fn hash_length_distribution(count: usize) -> Vec<(usize, usize, f64)> {
+    if count == 0 {
+        Vec::new()
+    } else {
+        vec![(8, count, 100.0)]
+    }
+}
+
 fn calculate_tag_distribution(tag_lengths: &[usize]) -> Vec<(usize, usize, f64)> {
     use std::collections::HashMap;
     
@@ -455,37 +618,320 @@ fn collect_tag_matrix_data(result: &InspectResult, tag_matrix: &mut TagMatrix) {
     }
 }
 
-fn generate_tsv_matrix(tag_matrix: &TagMatrix, log_path: &str, tsv_name: &str) -> Result<()> {
+// 有序列索引时显示标签序列，否则显示16进制hash
+fn tag_label(tag: Hash, sequence_index: &SequenceIndex) -> String {
+    sequence_index.get(&tag).cloned().unwrap_or_else(|| format!("{:016x}", tag))
+}
+
+fn generate_tsv_matrix(output: &OutputOptions, tag_matrix: &TagMatrix, sequence_index: &SequenceIndex, log_path: &str, tsv_name: &str) -> Result<()> {
     // 确保输出目录存在
     std::fs::create_dir_all(log_path)?;
     
     // 构建完整的文件路径
     let tsv_path = Path::new(log_path).join(tsv_name);
-    let mut tsv_writer = BufWriter::new(File::create(&tsv_path)?);
-    
-    // 排序样本和标签以确保输出的一致性
-    let mut sorted_samples = tag_matrix.samples.clone();
-    sorted_samples.sort();
-    let mut sorted_tags = tag_matrix.tags.clone();
-    sorted_tags.sort();
-    
-    // 写入表头
-    write!(tsv_writer, "Tag")?; // 目前保存的是哈希，无法还原原始序列，这里用16进制hash展示
-    for sample in &sorted_samples {
-        write!(tsv_writer, "\t{}", sample)?;
-    }
-    writeln!(tsv_writer)?;
+    output.write_atomically(&tsv_path, |tsv_writer| {
+        // 排序样本和标签以确保输出的一致性
+        let mut sorted_samples = tag_matrix.samples.clone();
+        sorted_samples.sort_by(|a, b| natural_cmp(a, b));
+        let mut sorted_tags = tag_matrix.tags.clone();
+        sorted_tags.sort();
     
-    // 写入数据行
-    for tag in &sorted_tags {
-        write!(tsv_writer, "{:016x}", tag)?;
+        // 写入表头
+        write!(tsv_writer, "Tag")?; // 有--index-sequences索引时为序列，否则为16进制hash
         for sample in &sorted_samples {
-            let count = tag_matrix.matrix.get(&(sample.clone(), *tag)).unwrap_or(&0);
-            write!(tsv_writer, "\t{}", count)?;
+            write!(tsv_writer, "\t{}", sample)?;
         }
         writeln!(tsv_writer)?;
-    }
     
+        // 写入数据行
+        for tag in &sorted_tags {
+            write!(tsv_writer, "{}", tag_label(*tag, sequence_index))?;
+            for sample in &sorted_samples {
+                let count = tag_matrix.matrix.get(&(sample.clone(), *tag)).unwrap_or(&0);
+                write!(tsv_writer, "\t{}", count)?;
+            }
+            writeln!(tsv_writer)?;
+        }
+        Ok(())
+    })?;
+
     println!("Tag count matrix saved to: {}", tsv_path.display());
     Ok(())
 }
+
+#[derive(Debug, Default)]
+struct CanonicalReport {
+    checked: usize,
+    non_canonical: Vec<String>,
+    rc_pairs: Vec<(String, String)>,
+}
+
+// 读取文件中保存的标签序列（extract --store-seq），没有保存时返回空
+fn read_stored_sequences(file_path: &str) -> Result<Vec<String>> {
+    Ok(read_grouped_sequences(file_path)?.into_iter().flat_map(|(_, sequences)| sequences).collect())
+}
+
+// 按基因组(.syldb)或样本(.sylsp)分组的标签序列，组名按自然顺序排列
+fn read_grouped_sequences(file_path: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let path = Path::new(file_path);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("syldb") => {
+            let entries = crate::extract::read_syldb(reader)
+                .context("Failed to deserialize .syldb file")?;
+            for entry in entries {
+                if let Some(sequences) = entry.tag_sequences {
+                    groups.entry(entry.genome_source).or_default().extend(sequences);
+                }
+            }
+        }
+        Some("sylsp") => {
+            let header = crate::extract::read_sylsp_header_full(&mut reader)?;
+            let entries = crate::extract::read_sylsp_entries(reader, header.as_ref())
+                .context("Failed to deserialize .sylsp file")?;
+            for entry in entries {
+                if let Some(sequence) = entry.sequence {
+                    groups.entry(entry.sample_source).or_default().push(sequence);
+                }
+            }
+        }
+        _ => return Err(anyhow::anyhow!("Unknown file extension, expected .syldb or .sylsp")),
+    }
+    let mut groups: Vec<(String, Vec<String>)> = groups.into_iter().collect();
+    groups.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+    Ok(groups)
+}
+
+// GC直方图的分箱数，每箱10%
+const GC_BINS: usize = 10;
+
+// 标签序列的碱基组成：bases按A、C、G、T、其他计数；
+// 每个标签的GC含量只按ACGT碱基计算，全为N的标签不计入GC统计
+#[derive(Debug, Default)]
+struct CompositionReport {
+    tags: usize,
+    bases: [usize; 5],
+    gc_sum: f64,
+    gc_tags: usize,
+    gc_histogram: [usize; GC_BINS],
+}
+
+impl CompositionReport {
+    fn from_sequences<'a>(sequences: impl Iterator<Item = &'a str>) -> Self {
+        let mut report = CompositionReport::default();
+        for seq in sequences {
+            report.tags += 1;
+            let mut counts = [0usize; 5];
+            for base in seq.bytes() {
+                let i = match base.to_ascii_uppercase() {
+                    b'A' => 0,
+                    b'C' => 1,
+                    b'G' => 2,
+                    b'T' => 3,
+                    _ => 4,
+                };
+                counts[i] += 1;
+            }
+            for (total, count) in report.bases.iter_mut().zip(counts) {
+                *total += count;
+            }
+            let acgt = counts[0] + counts[1] + counts[2] + counts[3];
+            if acgt > 0 {
+                let gc = (counts[1] + counts[2]) as f64 / acgt as f64;
+                report.gc_sum += gc;
+                report.gc_tags += 1;
+                report.gc_histogram[((gc * GC_BINS as f64) as usize).min(GC_BINS - 1)] += 1;
+            }
+        }
+        report
+    }
+
+    fn base_fraction(&self, i: usize) -> f64 {
+        let total: usize = self.bases.iter().sum();
+        if total == 0 { 0.0 } else { self.bases[i] as f64 / total as f64 }
+    }
+
+    fn mean_gc(&self) -> Option<f64> {
+        (self.gc_tags > 0).then(|| self.gc_sum / self.gc_tags as f64)
+    }
+}
+
+fn write_composition_report(writer: &mut dyn Write, source: &str, report: &CompositionReport) -> Result<()> {
+    writeln!(writer, "  {}: {} tags", source, report.tags)?;
+    writeln!(writer, "    Base composition: A {:.2}%  C {:.2}%  G {:.2}%  T {:.2}%  other {:.2}%",
+             report.base_fraction(0) * 100.0, report.base_fraction(1) * 100.0, report.base_fraction(2) * 100.0,
+             report.base_fraction(3) * 100.0, report.base_fraction(4) * 100.0)?;
+    match report.mean_gc() {
+        Some(gc) => writeln!(writer, "    Mean GC: {:.2}%", gc * 100.0)?,
+        None => writeln!(writer, "    Mean GC: NA")?,
+    }
+    writeln!(writer, "    GC distribution:")?;
+    for (i, count) in report.gc_histogram.iter().enumerate() {
+        let lower = i * 100 / GC_BINS;
+        let upper = (i + 1) * 100 / GC_BINS;
+        let close = if i == GC_BINS - 1 { "]" } else { ")" };
+        writeln!(writer, "      [{}%, {}%{}: {}", lower, upper, close, count)?;
+    }
+    Ok(())
+}
+
+// 重新做一次canonical化：标量与AVX2两条路径都应只产出canonical标签，
+// 且同一标签的正反链不应同时出现
+fn check_canonical<'a>(sequences: impl Iterator<Item = &'a str>) -> CanonicalReport {
+    use crate::extract::{get_canonical_sequence, reverse_complement};
+
+    let mut report = CanonicalReport::default();
+    let mut seen: std::collections::HashSet<&'a str> = std::collections::HashSet::new();
+
+    for seq in sequences {
+        report.checked += 1;
+        if get_canonical_sequence(seq.as_bytes()) != seq.as_bytes() {
+            report.non_canonical.push(seq.to_string());
+        }
+        let rc = String::from_utf8_lossy(&reverse_complement(seq.as_bytes())).to_string();
+        // 回文序列的反向互补就是自身，不算成对
+        if rc != seq && seen.contains(rc.as_str()) {
+            report.rc_pairs.push((rc, seq.to_string()));
+        }
+        seen.insert(seq);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::expected_tags_per_bp;
+    use crate::test_support::sylsp_entry;
+
+    #[test]
+    fn test_half_expected_tags_is_flagged() {
+        let per_bp = expected_tags_per_bp("BcgI").unwrap();
+        // BcgI: 两个方向各6个固定碱基
+        assert!((per_bp - 2.0 / 4096.0).abs() < 1e-12);
+
+        let expected = per_bp * 4_096_000.0;
+        assert!(is_low_completeness((expected / 2.0) as usize, expected));
+        assert!(!is_low_completeness(expected as usize, expected));
+    }
+
+    #[test]
+    fn test_tags_per_mb_is_tags_over_genome_megabases() {
+        assert_eq!(tags_per_mb(1_000, 2_500_000), Some(400.0));
+        assert!((tags_per_mb(7, 1_500).unwrap() - 7.0 / 0.0015).abs() < 1e-9);
+        assert_eq!(tags_per_mb(10, 0), None);
+    }
+
+    #[test]
+    fn test_non_canonical_tag_is_flagged() {
+        let sequences = ["AACG", "TTTG", "CAAA"];
+        let report = check_canonical(sequences.iter().copied());
+
+        assert_eq!(report.checked, 3);
+        // TTTG的反向互补CAAA更小，所以TTTG不是canonical
+        assert_eq!(report.non_canonical, vec!["TTTG".to_string()]);
+        assert_eq!(report.rc_pairs.len(), 1);
+    }
+
+    // 记录底层reader已读出的字节数
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes_read.set(self.bytes_read.get() + n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_streaming_sylsp_stats_match_full_deserialization() {
+        let entries: Vec<SylspEntry> = (0..200_000u64)
+            .map(|i| sylsp_entry(&format!("sample{}", i % 3), i % 1_000))
+            .collect();
+        let mut header = Vec::new();
+        crate::extract::write_sylsp_header(&mut header, crate::extract::SylspKind::TagEntries, "", 1).unwrap();
+        let mut data = header.clone();
+        bincode::serialize_into(&mut data, &entries).unwrap();
+
+        let result = inspect_sylsp(std::io::Cursor::new(&data), "large.sylsp", true).unwrap();
+
+        // 非流式的参考统计
+        let mut expected_per_sample: HashMap<String, HashMap<Hash, usize>> = HashMap::new();
+        for entry in &entries {
+            *expected_per_sample.entry(entry.sample_source.clone()).or_default().entry(entry.tag).or_insert(0) += 1;
+        }
+        assert_eq!(result.num_records, entries.len());
+        assert_eq!(result.total_tags, entries.len());
+        assert_eq!(result.unique_tags, 1_000);
+        assert_eq!(result.tag_frequency_stats[0].1, 200);
+        assert_eq!(result.first_contig_name.as_deref(), Some("sample0_read0"));
+        assert_eq!(result.per_sample_tag_counts.unwrap(), expected_per_sample);
+        let samples = result.sample_sources.unwrap();
+        assert_eq!(samples.iter().map(|s| s.total_tags).sum::<usize>(), entries.len());
+        assert_eq!(result.tag_length_distribution, vec![(8, entries.len(), 100.0)]);
+
+        // 读到第一条记录时只消耗了文件开头的几十个字节，而不是整个向量
+        let bytes_read = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut bytes_at_first = None;
+        let reader = CountingReader { inner: &data[header.len()..], bytes_read: bytes_read.clone() };
+        let count = crate::extract::stream_bincode_vec(reader, |_: SylspEntry| {
+            bytes_at_first.get_or_insert(bytes_read.get());
+        })
+        .unwrap();
+        assert_eq!(count, entries.len());
+        assert!(bytes_at_first.unwrap() < 256);
+        assert!(data.len() > 1_000_000);
+    }
+
+    #[test]
+    fn test_min_count_filters_total_and_unique_tags() {
+        // tag 1出现3次，tag 2出现2次，tag 3只出现1次
+        let entries: Vec<SylspEntry> = [1u64, 1, 1, 2, 2, 3].iter()
+            .map(|&tag| sylsp_entry("s1", tag))
+            .collect();
+        let mut data = Vec::new();
+        crate::extract::write_sylsp_header(&mut data, crate::extract::SylspKind::TagEntries, "", 1).unwrap();
+        bincode::serialize_into(&mut data, &entries).unwrap();
+
+        let mut result = inspect_sylsp(std::io::Cursor::new(&data), "s1.sylsp", false).unwrap();
+        // 不写矩阵时不收集每个样本的tag计数
+        assert!(result.per_sample_tag_counts.is_none());
+        assert_eq!(result.retain_min_count(1), 0);
+        assert_eq!((result.total_tags, result.unique_tags), (6, 3));
+
+        assert_eq!(result.retain_min_count(2), 1);
+        assert_eq!((result.total_tags, result.unique_tags), (5, 2));
+        assert!(result.tag_frequency_stats.iter().all(|(tag, _)| *tag != 3));
+    }
+
+    #[test]
+    fn test_composition_report_matches_known_gc() {
+        // GC分别为0、50%、100%、50%（N不计入）
+        let sequences = ["AATT", "ACGT", "GGCC", "ANNC"];
+        let report = CompositionReport::from_sequences(sequences.iter().copied());
+        assert_eq!(report.tags, 4);
+        assert_eq!(report.bases, [4, 4, 3, 3, 2]);
+        assert!((report.mean_gc().unwrap() - 0.5).abs() < 1e-12);
+        assert!((report.base_fraction(4) - 2.0 / 16.0).abs() < 1e-12);
+        assert_eq!(report.gc_histogram[0], 1);
+        assert_eq!(report.gc_histogram[5], 2);
+        assert_eq!(report.gc_histogram[GC_BINS - 1], 1);
+
+        let empty = CompositionReport::from_sequences(["NNNN"].iter().copied());
+        assert_eq!(empty.mean_gc(), None);
+        assert_eq!(empty.gc_histogram.iter().sum::<usize>(), 0);
+
+        let mut out = Vec::new();
+        write_composition_report(&mut out, "genome_a", &report).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Mean GC: 50.00%"));
+        assert!(text.contains("[90%, 100%]: 1"));
+    }
+}
@@ -1,13 +1,20 @@
 pub mod cmdline;
 pub mod constants;
-pub mod query;
 pub mod extract;
+pub mod sketch;
 pub mod inspect;
 pub mod contain;
+pub mod distance;
+pub mod mark;
+pub mod sylph;
+#[cfg(test)]
+mod test_support;
 
 
 pub use cmdline::Cli;
 pub use constants::*;
+// 库调用入口：返回结构化结果而不是写文件
+pub use contain::{query_to_results, QueryResult};
 
 #[cfg(target_arch = "x86_64")]
 pub mod avx2_seeding;
@@ -19,6 +19,16 @@ mod constants;
 mod inspect;
 mod view;
 mod mark;
+mod report;
+mod distance;
+mod validate;
+mod run;
+mod summary;
+mod export;
+mod compare;
+mod sylph;
+#[cfg(test)]
+mod test_support;
 
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc; //use std::panic::set_hook;
@@ -26,13 +36,5 @@ static GLOBAL: Jemalloc = Jemalloc; //use std::panic::set_hook;
 fn main() -> Result<()> {
     let cli = cmdline::Cli::parse();
 
-    match cli.mode {
-        cmdline::Mode::Extract(extract_args) => extract::extract(extract_args),
-        cmdline::Mode::Sketch(sketch_args) => sketch::sketch(sketch_args),
-        cmdline::Mode::Inspect(inspect_args) => inspect::inspect(inspect_args),
-        cmdline::Mode::View(view_args) => view::view(view_args),
-        cmdline::Mode::Query(contain_args) => contain::query(contain_args),
-        cmdline::Mode::Profile(profile_args) => contain::profile(profile_args),
-        cmdline::Mode::Mark(mark_args) => mark::mark(mark_args)
-    }
+    run::dispatch(cli.mode)
 }
\ No newline at end of file
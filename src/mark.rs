@@ -1,15 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use fxhash::{FxHashMap, FxHashSet};
 use serde::{Serialize, Deserialize};
 use std::{
     fs::File,
-    io::{BufReader, BufWriter},
+    io::BufReader,
     path::Path,
 };
 
 use crate::cmdline::MarkArgs;
-use crate::extract::SyldbEntry;
-use crate::constants::Hash;
+use crate::extract::{get_canonical_sequence, read_syldb_with_header, syldb_granularity, write_syldb, DbGranularity, SyldbEntry};
+use crate::constants::{Hash, check_output_dir, hash_bytes, OutputOptions};
 
 /// 包含unique标记统计信息的结构体
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -25,57 +25,99 @@ pub fn mark(args: MarkArgs) -> Result<()> {
     
     // 读取.syldb文件
     let input_path = Path::new(&args.input_file);
-    let syldb_entries = read_syldb_file(input_path)?;
+    // 标记完成后才写出，先确认输出目录可写
+    let output_dir = args.output_file.as_deref().map(Path::new).unwrap_or(input_path).parent();
+    check_output_dir(output_dir.unwrap_or_else(|| Path::new("")))?;
+    let (subsample_rate, granularity, syldb_entries) = read_syldb_file(input_path)?;
     
     println!("已读取 {} 个syldb条目", syldb_entries.len());
     
     // 分析并标记unique tags
-    let marked_entries = mark_unique_tags(syldb_entries)?;
+    let marked_entries = mark_unique_tags(syldb_entries, args.collapse_rc)?;
     
     // 生成统计信息
     let stats = generate_statistics(&marked_entries);
     print_statistics(&stats);
     
     // 写回文件
+    // 未指定-o时按设计原地覆盖输入文件，不受--force限制
+    let output = OutputOptions::new(args.force || args.output_file.is_none(), args.temp_dir.as_deref());
     let output_path = if let Some(output) = args.output_file {
         Path::new(&output).to_path_buf()
     } else {
         input_path.to_path_buf()
     };
     
-    write_syldb_file(&output_path, &marked_entries)?;
+    write_syldb_file(&output_path, subsample_rate, granularity, &marked_entries, &output)?;
     
     println!("标记完成，已写入文件: {}", output_path.display());
     
     Ok(())
 }
 
-/// 读取.syldb文件
-fn read_syldb_file(path: &Path) -> Result<Vec<SyldbEntry>> {
+/// 读取.syldb文件，同时返回格式头记录的标签抽样率和条目粒度，写回时保留
+fn read_syldb_file(path: &Path) -> Result<(u64, DbGranularity, Vec<SyldbEntry>)> {
     let file = File::open(path)
         .context(format!("无法打开文件: {}", path.display()))?;
     let reader = BufReader::new(file);
     
-    let entries: Vec<SyldbEntry> = bincode::deserialize_from(reader)
+    let (header, entries) = read_syldb_with_header(reader)
         .context("无法反序列化syldb文件")?;
     
-    Ok(entries)
+    let granularity = syldb_granularity(header.as_ref());
+    Ok((header.map_or(1, |header| header.subsample_rate), granularity, entries))
 }
 
-/// 标记unique tags的核心逻辑
-fn mark_unique_tags(mut entries: Vec<SyldbEntry>) -> Result<Vec<SyldbEntry>> {
-    // 构建tag到基因组源的映射
+/// 构建tag到基因组源的映射（profile的EM丰度估计也使用）
+pub(crate) fn build_tag_to_genomes(entries: &[SyldbEntry]) -> FxHashMap<Hash, FxHashSet<String>> {
     let mut tag_to_genomes: FxHashMap<Hash, FxHashSet<String>> = FxHashMap::default();
-    
-    // 第一次遍历：收集所有tag和它们出现的基因组
-    for entry in &entries {
+    for entry in entries {
         for tag in &entry.tags {
             tag_to_genomes
-                .entry(tag.clone())
-                .or_insert_with(FxHashSet::default)
+                .entry(*tag)
+                .or_default()
                 .insert(entry.genome_source.clone());
         }
     }
+    tag_to_genomes
+}
+
+/// 判断uniqueness所用的tag键：默认即存储的哈希；collapse_rc时按存储的序列取canonical方向后重新哈希，
+/// 使--strand-specific数据库中互为反向互补的tag计为同一个
+fn uniqueness_keys(entries: &[SyldbEntry], collapse_rc: bool) -> Result<Vec<Vec<Hash>>> {
+    entries
+        .iter()
+        .map(|entry| {
+            if !collapse_rc {
+                return Ok(entry.tags.clone());
+            }
+            let sequences = entry.tag_sequences.as_ref().ok_or_else(|| anyhow!(
+                "--collapse-rc needs tag sequences, but {} has none; re-extract with --store-seq",
+                entry.sequence_id
+            ))?;
+            Ok(sequences
+                .iter()
+                .map(|seq| hash_bytes(&get_canonical_sequence(seq.as_bytes())))
+                .collect())
+        })
+        .collect()
+}
+
+/// 标记unique tags的核心逻辑
+fn mark_unique_tags(mut entries: Vec<SyldbEntry>, collapse_rc: bool) -> Result<Vec<SyldbEntry>> {
+    // 第一次遍历：收集所有tag和它们出现的基因组
+    let keys = uniqueness_keys(&entries, collapse_rc)?;
+    let tag_to_genomes = if collapse_rc {
+        let mut tag_to_genomes: FxHashMap<Hash, FxHashSet<String>> = FxHashMap::default();
+        for (entry, entry_keys) in entries.iter().zip(&keys) {
+            for key in entry_keys {
+                tag_to_genomes.entry(*key).or_default().insert(entry.genome_source.clone());
+            }
+        }
+        tag_to_genomes
+    } else {
+        build_tag_to_genomes(&entries)
+    };
     
     println!("总共找到 {} 个唯一tags", tag_to_genomes.len());
     
@@ -87,13 +129,13 @@ fn mark_unique_tags(mut entries: Vec<SyldbEntry>) -> Result<Vec<SyldbEntry>> {
     
     println!("其中 {} 个tags是unique的 ({:.2}%)", 
         unique_tag_count, 
-        unique_tag_count as f64 / tag_to_genomes.len() as f64 * 100.0);
+        percentage(unique_tag_count, tag_to_genomes.len()));
     
     // 第二次遍历：为每个entry标记其tags的uniqueness
-    for entry in &mut entries {
+    for (entry, entry_keys) in entries.iter_mut().zip(&keys) {
         let mut tag_uniqueness = Vec::with_capacity(entry.tags.len());
         
-        for tag in &entry.tags {
+        for tag in entry_keys {
             let is_unique = tag_to_genomes
                 .get(tag)
                 .map(|genomes| genomes.len() == 1)
@@ -127,11 +169,20 @@ fn generate_statistics(entries: &[SyldbEntry]) -> Vec<GenomeStats> {
     }
     
     let mut stats: Vec<GenomeStats> = genome_stats.into_values().collect();
-    stats.sort_by(|a, b| b.total_tags.cmp(&a.total_tags)); // 按total_tags降序排列
+    stats.sort_by_key(|s| std::cmp::Reverse(s.total_tags)); // 按total_tags降序排列
     
     stats
 }
 
+/// 百分比，空数据库（total为0）时为0而不是NaN
+fn percentage(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
 /// 打印统计信息
 fn print_statistics(stats: &[GenomeStats]) {
     let total_tags: usize = stats.iter().map(|s| s.total_tags).sum();
@@ -141,17 +192,13 @@ fn print_statistics(stats: &[GenomeStats]) {
     println!("Total tags: {}", total_tags);
     println!("Unique tags: {} ({:.2}%)", 
         total_unique_tags, 
-        if total_tags > 0 { total_unique_tags as f64 / total_tags as f64 * 100.0 } else { 0.0 });
+        percentage(total_unique_tags, total_tags));
     
     println!("\nGenome-specific statistics:");
     println!("------------------------");
     
     for stat in stats {
-        let unique_percentage = if stat.total_tags > 0 {
-            stat.unique_tags as f64 / stat.total_tags as f64 * 100.0
-        } else {
-            0.0
-        };
+        let unique_percentage = percentage(stat.unique_tags, stat.total_tags);
         
         // 提取基因组名称（去掉路径）
         let genome_name = Path::new(&stat.genome_source)
@@ -166,21 +213,17 @@ fn print_statistics(stats: &[GenomeStats]) {
 }
 
 /// 写入.syldb文件
-fn write_syldb_file(path: &Path, entries: &[SyldbEntry]) -> Result<()> {
-    let file = File::create(path)
-        .context(format!("无法创建文件: {}", path.display()))?;
-    let writer = BufWriter::new(file);
-    
-    bincode::serialize_into(writer, entries)
-        .context("无法序列化syldb数据")?;
-    
-    Ok(())
+fn write_syldb_file(path: &Path, subsample_rate: u64, granularity: DbGranularity, entries: &[SyldbEntry], output: &OutputOptions) -> Result<()> {
+    output.write_atomically(path, |writer| {
+        write_syldb(writer, subsample_rate, granularity, entries)
+            .context("无法序列化syldb数据")
+    })
+    .context(format!("无法写入文件: {}", path.display()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::constants::hash_bytes;
     
     #[test]
     fn test_mark_unique_tags() {
@@ -191,6 +234,9 @@ mod tests {
                 positions: vec![0, 1],
                 genome_source: "genome_a.fa".to_string(),
                 tag_uniqueness: None,
+                gn_size: 0,
+                tag_sequences: None,
+                enzyme: String::new(),
             },
             SyldbEntry {
                 sequence_id: "seq2".to_string(),
@@ -198,17 +244,57 @@ mod tests {
                 positions: vec![0, 1],
                 genome_source: "genome_b.fa".to_string(),
                 tag_uniqueness: None,
+                gn_size: 0,
+                tag_sequences: None,
+                enzyme: String::new(),
             },
         ];
         
-        let marked_entries = mark_unique_tags(entries).unwrap();
+        let marked_entries = mark_unique_tags(entries, false).unwrap();
         
         // ATGC在两个基因组中都出现，应该不是unique
         // CGTA只在genome_a中出现，应该是unique
         // TTTT只在genome_b中出现，应该是unique
-        assert_eq!(marked_entries[0].tag_uniqueness.as_ref().unwrap()[0], false); // ATGC
-        assert_eq!(marked_entries[0].tag_uniqueness.as_ref().unwrap()[1], true);  // CGTA
-        assert_eq!(marked_entries[1].tag_uniqueness.as_ref().unwrap()[0], false); // ATGC
-        assert_eq!(marked_entries[1].tag_uniqueness.as_ref().unwrap()[1], true);  // TTTT
+        assert!(!marked_entries[0].tag_uniqueness.as_ref().unwrap()[0]); // ATGC
+        assert!(marked_entries[0].tag_uniqueness.as_ref().unwrap()[1]); // CGTA
+        assert!(!marked_entries[1].tag_uniqueness.as_ref().unwrap()[0]); // ATGC
+        assert!(marked_entries[1].tag_uniqueness.as_ref().unwrap()[1]); // TTTT
+    }
+
+    #[test]
+    fn test_mark_empty_database_has_no_nan() {
+        let marked = mark_unique_tags(Vec::new(), false).unwrap();
+        assert!(marked.is_empty());
+        assert_eq!(percentage(0, 0), 0.0);
+        assert_eq!(percentage(1, 4), 25.0);
+    }
+
+    #[test]
+    fn test_collapse_rc_merges_reverse_complement_tags() {
+        // --strand-specific数据库：两个基因组各存了同一位点的一条链
+        let entry = |genome: &str, seqs: &[&str]| SyldbEntry {
+            sequence_id: format!("{}_c1", genome),
+            tags: seqs.iter().map(|s| hash_bytes(s.as_bytes())).collect(),
+            positions: (0..seqs.len()).collect(),
+            genome_source: genome.to_string(),
+            tag_uniqueness: None,
+            gn_size: 0,
+            tag_sequences: Some(seqs.iter().map(|s| s.to_string()).collect()),
+            enzyme: String::new(),
+        };
+        let entries = vec![entry("genome_a.fa", &["AACGTG", "GGGGCC"]), entry("genome_b.fa", &["CACGTT"])];
+
+        let stranded = mark_unique_tags(entries.clone(), false).unwrap();
+        assert_eq!(stranded[0].tag_uniqueness.as_deref(), Some(&[true, true][..]));
+        assert_eq!(stranded[1].tag_uniqueness.as_deref(), Some(&[true][..]));
+
+        let collapsed = mark_unique_tags(entries.clone(), true).unwrap();
+        assert_eq!(collapsed[0].tag_uniqueness.as_deref(), Some(&[false, true][..]));
+        assert_eq!(collapsed[1].tag_uniqueness.as_deref(), Some(&[false][..]));
+
+        // 没有存储序列时无法判断反向互补
+        let mut no_seq = entries;
+        no_seq[1].tag_sequences = None;
+        assert!(mark_unique_tags(no_seq, true).is_err());
     }
 }
@@ -0,0 +1,261 @@
+use crate::cmdline::ReportArgs;
+use crate::constants::OutputOptions;
+use crate::contain::{read_taxonomy_file, DEFAULT_TAXONOMY_MAX_SKIPPED};
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+// 图表中除top taxa之外的部分合并为一类
+const OTHER_LABEL: &str = "Other";
+
+// 堆叠柱状图的调色板，超过数量后循环使用
+const PALETTE: [&str; 12] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948",
+    "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac", "#86bcb6", "#d37295",
+];
+const OTHER_COLOR: &str = "#d9d9d9";
+
+const BAR_WIDTH: usize = 40;
+const BAR_GAP: usize = 20;
+const CHART_HEIGHT: usize = 300;
+const CHART_MARGIN: usize = 40;
+
+// profile 输出的丰度矩阵：基因组矩阵(Genome\tS1\tS2...)或物种矩阵(#Kingdom...Species\tS1...)
+#[derive(Debug, Default)]
+pub(crate) struct AbundanceTable {
+    pub samples: Vec<String>,
+    pub taxa: Vec<(String, Vec<f64>)>,
+}
+
+pub fn report(args: ReportArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, None);
+    output.check_file(&args.output)?;
+
+    let file = File::open(&args.matrix)
+        .with_context(|| format!("Failed to open abundance matrix: {}", args.matrix))?;
+    let mut table = parse_abundance_matrix(BufReader::new(file))?;
+
+    // 基因组矩阵可以借助taxonomy文件显示物种名
+    if let Some(taxonomy_file) = &args.taxonomy_file {
+        let taxonomy = read_taxonomy_file(taxonomy_file, &args.taxonomy_format, &args.taxonomy_has_header, DEFAULT_TAXONOMY_MAX_SKIPPED)?;
+        for (name, _) in table.taxa.iter_mut() {
+            if let Some(info) = taxonomy.get(name.as_str()) {
+                if !info.species.is_empty() {
+                    *name = format!("{} ({})", info.species, name);
+                }
+            }
+        }
+    }
+
+    let top = top_taxa(&table, args.top);
+    let html = render_html(&table, &top, &args.matrix);
+
+    output.write_atomically(&args.output, |writer| {
+        writer.write_all(html.as_bytes())?;
+        Ok(())
+    })
+    .with_context(|| format!("Failed to write HTML report: {}", args.output))?;
+
+    eprintln!("Report written to {} ({} samples, {} taxa)", args.output, table.samples.len(), table.taxa.len());
+    Ok(())
+}
+
+pub(crate) fn parse_abundance_matrix<R: BufRead>(reader: R) -> Result<AbundanceTable> {
+    let mut lines = reader.lines();
+    let header = lines
+        .next()
+        .context("Abundance matrix is empty")?
+        .context("Failed to read abundance matrix header")?;
+    let header_fields: Vec<&str> = header.split('\t').collect();
+
+    // 物种矩阵前7列是分类学信息
+    let label_columns = if header.starts_with("#Kingdom") { 7 } else { 1 };
+    if header_fields.len() <= label_columns {
+        return Err(anyhow::anyhow!("Abundance matrix header has no sample columns"));
+    }
+
+    let mut table = AbundanceTable {
+        samples: header_fields[label_columns..].iter().map(|s| s.to_string()).collect(),
+        taxa: Vec::new(),
+    };
+
+    for (line_no, line) in lines.enumerate() {
+        let line = line.context("Failed to read abundance matrix line")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != label_columns + table.samples.len() {
+            return Err(anyhow::anyhow!(
+                "Line {} has {} columns, expected {}",
+                line_no + 2,
+                fields.len(),
+                label_columns + table.samples.len()
+            ));
+        }
+
+        let label = if label_columns == 7 {
+            // 优先用种名，缺失时回退到更高的分类级别
+            fields[..7]
+                .iter()
+                .rev()
+                .find(|f| !f.is_empty())
+                .map(|f| f.to_string())
+                .unwrap_or_else(|| "Unclassified".to_string())
+        } else {
+            fields[0].to_string()
+        };
+        let values = fields[label_columns..]
+            .iter()
+            .map(|v| v.parse::<f64>().with_context(|| format!("Invalid abundance value '{}' on line {}", v, line_no + 2)))
+            .collect::<Result<Vec<_>>>()?;
+        table.taxa.push((label, values));
+    }
+
+    Ok(table)
+}
+
+// 按样本平均丰度取前n个taxa，其余合并为Other
+fn top_taxa(table: &AbundanceTable, n: usize) -> Vec<(String, Vec<f64>)> {
+    let mut ranked: Vec<&(String, Vec<f64>)> = table.taxa.iter().collect();
+    ranked.sort_by(|a, b| {
+        // 样本数相同，按总和排序等价于按平均值排序
+        let total_a: f64 = a.1.iter().sum();
+        let total_b: f64 = b.1.iter().sum();
+        total_b.partial_cmp(&total_a).unwrap_or(std::cmp::Ordering::Equal).then_with(|| a.0.cmp(&b.0))
+    });
+
+    let mut top: Vec<(String, Vec<f64>)> = ranked.iter().take(n).map(|t| (*t).clone()).collect();
+    if ranked.len() > n {
+        let mut other = vec![0.0; table.samples.len()];
+        for (_, values) in &ranked[n..] {
+            for (o, v) in other.iter_mut().zip(values) {
+                *o += v;
+            }
+        }
+        top.push((OTHER_LABEL.to_string(), other));
+    }
+    top
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn color_for(index: usize, label: &str) -> &'static str {
+    if label == OTHER_LABEL {
+        OTHER_COLOR
+    } else {
+        PALETTE[index % PALETTE.len()]
+    }
+}
+
+fn render_html(table: &AbundanceTable, top: &[(String, Vec<f64>)], source: &str) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>meta2bseek profile report</title>\n");
+    html.push_str("<style>\nbody{font-family:sans-serif;margin:2em;}\ntable{border-collapse:collapse;}\nth,td{border:1px solid #ccc;padding:4px 8px;text-align:right;}\nth{cursor:pointer;background:#f4f4f4;}\ntd:first-child,th:first-child{text-align:left;}\n</style>\n");
+    html.push_str("</head>\n<body>\n");
+    html.push_str(&format!("<h1>meta2bseek profile report</h1>\n<p>Source: {} &mdash; {} samples, {} taxa</p>\n",
+        html_escape(source), table.samples.len(), table.taxa.len()));
+
+    html.push_str(&render_chart(table, top));
+
+    // 可排序表格：点击表头按该列排序
+    html.push_str("<h2>Top taxa</h2>\n<table id=\"abundance\">\n<thead><tr><th>Taxon</th>");
+    for sample in &table.samples {
+        html.push_str(&format!("<th>{}</th>", html_escape(sample)));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for (label, values) in top.iter().filter(|(label, _)| label != OTHER_LABEL) {
+        html.push_str(&format!("<tr class=\"taxon\"><td>{}</td>", html_escape(label)));
+        for v in values {
+            html.push_str(&format!("<td>{:.4}</td>", v));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+    html.push_str("<script>\ndocument.querySelectorAll('#abundance th').forEach(function(th, col) {\n  th.addEventListener('click', function() {\n    var body = document.querySelector('#abundance tbody');\n    var rows = Array.from(body.rows);\n    var asc = th.dataset.asc !== 'true';\n    th.dataset.asc = asc;\n    rows.sort(function(a, b) {\n      var x = a.cells[col].textContent, y = b.cells[col].textContent;\n      var nx = parseFloat(x), ny = parseFloat(y);\n      var c = (isNaN(nx) || isNaN(ny)) ? x.localeCompare(y) : nx - ny;\n      return asc ? c : -c;\n    });\n    rows.forEach(function(r) { body.appendChild(r); });\n  });\n});\n</script>\n");
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// 每个样本一组堆叠柱，按样本内总丰度归一化到100%
+fn render_chart(table: &AbundanceTable, top: &[(String, Vec<f64>)]) -> String {
+    let width = CHART_MARGIN * 2 + table.samples.len() * (BAR_WIDTH + BAR_GAP);
+    let height = CHART_HEIGHT + CHART_MARGIN * 2;
+    let mut svg = format!(
+        "<h2>Relative abundance of top taxa</h2>\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        width, height
+    );
+
+    for (s, sample) in table.samples.iter().enumerate() {
+        let x = CHART_MARGIN + s * (BAR_WIDTH + BAR_GAP);
+        let total: f64 = top.iter().map(|(_, values)| values[s]).sum();
+        svg.push_str(&format!("<g class=\"sample-bar\" data-sample=\"{}\">\n", html_escape(sample)));
+
+        let mut y = (CHART_MARGIN + CHART_HEIGHT) as f64;
+        if total > 0.0 {
+            for (t, (label, values)) in top.iter().enumerate() {
+                let h = values[s] / total * CHART_HEIGHT as f64;
+                if h <= 0.0 {
+                    continue;
+                }
+                y -= h;
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{:.2}\" width=\"{}\" height=\"{:.2}\" fill=\"{}\"><title>{}: {:.2}%</title></rect>\n",
+                    x, y, BAR_WIDTH, h, color_for(t, label), html_escape(label), values[s] / total * 100.0
+                ));
+            }
+        }
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"10\" text-anchor=\"middle\">{}</text>\n</g>\n",
+            x + BAR_WIDTH / 2,
+            CHART_MARGIN + CHART_HEIGHT + 15,
+            html_escape(sample)
+        ));
+    }
+    svg.push_str("</svg>\n");
+
+    // 图例
+    svg.push_str("<p>");
+    for (t, (label, _)) in top.iter().enumerate() {
+        svg.push_str(&format!(
+            "<span style=\"display:inline-block;margin-right:1em\"><span style=\"display:inline-block;width:10px;height:10px;background:{}\"></span> {}</span>",
+            color_for(t, label),
+            html_escape(label)
+        ));
+    }
+    svg.push_str("</p>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_has_row_per_top_taxon_and_bar_per_sample() {
+        let tsv = "#Kingdom\tPhylum\tClass\tOrder\tFamily\tGenus\tSpecies\tS1\tS2\tS3\n\
+            Bacteria\tP1\tC1\tO1\tF1\tG1\tSpecies A\t50.0\t10.0\t0.0\n\
+            Bacteria\tP1\tC1\tO1\tF1\tG2\tSpecies B\t30.0\t60.0\t20.0\n\
+            Bacteria\tP2\tC2\tO2\tF2\tG3\t\t15.0\t5.0\t70.0\n\
+            Bacteria\tP2\tC2\tO2\tF2\tG4\tSpecies D\t5.0\t25.0\t10.0\n";
+        let table = parse_abundance_matrix(tsv.as_bytes()).unwrap();
+        assert_eq!(table.samples, vec!["S1", "S2", "S3"]);
+        // 缺少种名时回退到属名
+        assert_eq!(table.taxa[2].0, "G3");
+
+        let top = top_taxa(&table, 3);
+        assert_eq!(top.len(), 4);
+        assert_eq!(top[3].0, OTHER_LABEL);
+
+        let html = render_html(&table, &top, "species_matrix.tsv");
+        assert_eq!(html.matches("<tr class=\"taxon\">").count(), 3);
+        assert_eq!(html.matches("<g class=\"sample-bar\"").count(), 3);
+        assert!(html.contains("Species B"));
+        assert!(!html.contains("Species D</td>"));
+    }
+}
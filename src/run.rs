@@ -0,0 +1,207 @@
+use crate::cmdline::{Cli, Mode, RunArgs};
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use std::path::Path;
+
+// 清单中的一个作业：子命令名加上与命令行完全相同的参数，
+// 由clap解析成对应的参数结构体，因此清单支持的选项与命令行始终一致
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Job {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    jobs: Vec<Job>,
+}
+
+impl Job {
+    fn label(&self, index: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("job{}:{}", index + 1, self.command))
+    }
+}
+
+// 把解析好的子命令分派给对应模块
+pub fn dispatch(mode: Mode) -> Result<()> {
+    match mode {
+        Mode::Extract(extract_args) => crate::extract::extract(extract_args),
+        Mode::Sketch(sketch_args) => crate::sketch::sketch(sketch_args),
+        Mode::Inspect(inspect_args) => crate::inspect::inspect(inspect_args),
+        Mode::View(view_args) => crate::view::view(view_args),
+        Mode::Query(contain_args) => crate::contain::query(contain_args),
+        Mode::Profile(profile_args) => crate::contain::profile(profile_args),
+        Mode::Mark(mark_args) => crate::mark::mark(mark_args),
+        Mode::Report(report_args) => crate::report::report(report_args),
+        Mode::Validate(validate_args) => crate::validate::validate(validate_args),
+        Mode::Summary(summary_args) => crate::summary::summary(summary_args),
+        Mode::Export(export_args) => crate::export::export(export_args),
+        Mode::ExportFasta(export_args) => crate::export::export_fasta(export_args),
+        Mode::CompareProfiles(compare_args) => crate::compare::compare_profiles(compare_args),
+        Mode::Run(run_args) => run(run_args),
+    }
+}
+
+pub fn run(args: RunArgs) -> Result<()> {
+    let jobs = read_manifest(&args.manifest)?;
+    eprintln!("Running {} jobs from {}", jobs.len(), args.manifest);
+
+    // (作业名, 错误信息)；None表示成功
+    let mut outcomes: Vec<(String, Option<String>)> = Vec::new();
+    for (index, job) in jobs.iter().enumerate() {
+        let label = job.label(index);
+        eprintln!("\n=== [{}/{}] {}: meta2bseek {} {} ===", index + 1, jobs.len(), label, job.command, job.args.join(" "));
+        let outcome = run_job(job).err().map(|e| format!("{:#}", e));
+        let failed = outcome.is_some();
+        outcomes.push((label, outcome));
+        if failed && args.fail_fast {
+            break;
+        }
+    }
+
+    println!("\nJob\tStatus\tError");
+    for (label, outcome) in &outcomes {
+        match outcome {
+            None => println!("{}\tOK\t", label),
+            Some(error) => println!("{}\tFAILED\t{}", label, error.replace('\n', " ")),
+        }
+    }
+
+    let failed = outcomes.iter().filter(|(_, outcome)| outcome.is_some()).count();
+    let skipped = jobs.len() - outcomes.len();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} jobs failed{}", failed, jobs.len(),
+            if skipped > 0 { format!(", {} not run (--fail-fast)", skipped) } else { String::new() }));
+    }
+    Ok(())
+}
+
+fn run_job(job: &Job) -> Result<()> {
+    let argv = std::iter::once("meta2bseek".to_string())
+        .chain(std::iter::once(job.command.clone()))
+        .chain(job.args.iter().cloned());
+    let cli = Cli::try_parse_from(argv).map_err(|e| anyhow!("Invalid arguments: {}", e))?;
+    if matches!(cli.mode, Mode::Run(_)) {
+        return Err(anyhow!("Manifests cannot run other manifests"));
+    }
+    dispatch(cli.mode)
+}
+
+// .yaml/.yml按YAML解析（顶层jobs列表），其他按TSV：作业名<TAB>子命令<TAB>空白分隔的参数
+pub fn read_manifest(path: &str) -> Result<Vec<Job>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest: {}", path))?;
+    let is_yaml = matches!(
+        Path::new(path).extension().and_then(|s| s.to_str()),
+        Some("yaml") | Some("yml")
+    );
+
+    let jobs = if is_yaml {
+        let manifest: Manifest = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse YAML manifest: {}", path))?;
+        manifest.jobs
+    } else {
+        parse_tsv_manifest(&content).with_context(|| format!("Failed to parse manifest: {}", path))?
+    };
+
+    if jobs.is_empty() {
+        return Err(anyhow!("Manifest {} contains no jobs", path));
+    }
+    Ok(jobs)
+}
+
+fn parse_tsv_manifest(content: &str) -> Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        // 跳过空行和注释行
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, '\t');
+        let name = fields.next().unwrap_or_default().trim();
+        let command = fields.next()
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .ok_or_else(|| anyhow!("Line {}: expected name<TAB>command<TAB>arguments", line_number + 1))?;
+        let args = fields.next()
+            .map(|a| a.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        jobs.push(Job {
+            name: (!name.is_empty()).then(|| name.to_string()),
+            command: command.to_string(),
+            args,
+        });
+    }
+    Ok(jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::io::Write;
+
+    // splitmix64生成可复现的随机碱基
+    fn random_genome(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state.wrapping_add(0x9e3779b97f4a7c15);
+                let mut z = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                b"ACGT"[((z ^ (z >> 31)) & 3) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_two_job_manifest_runs_extract_then_profile() {
+        let dir = TempDir::new("run_manifest");
+
+        let genome = random_genome(400_000, 42);
+        let genome_path = dir.join("genome.fa");
+        let mut fasta = std::fs::File::create(&genome_path).unwrap();
+        writeln!(fasta, ">contig1\n{}", String::from_utf8_lossy(&genome)).unwrap();
+
+        // 按50bp步长平铺150bp的reads，覆盖整个基因组
+        let reads_path = dir.join("reads.fq");
+        let mut fastq = std::fs::File::create(&reads_path).unwrap();
+        for (i, start) in (0..genome.len() - 150).step_by(50).enumerate() {
+            let read = String::from_utf8_lossy(&genome[start..start + 150]);
+            writeln!(fastq, "@read{}\n{}\n+\n{}", i, read, "I".repeat(150)).unwrap();
+        }
+
+        let out = dir.join("out");
+        let manifest_path = dir.join("jobs.tsv");
+        std::fs::write(&manifest_path, format!(
+            "# name\tcommand\targuments\nbuild\textract\t-g {} -r {} -d {}\nprofile\tprofile\t--sample-file {} --db-file {} --log-path {} --tsv-name profile.tsv\n",
+            genome_path.display(), reads_path.display(), out.display(),
+            out.join("reads.sylsp").display(), out.join("combined.syldb").display(), out.display(),
+        )).unwrap();
+
+        let jobs = read_manifest(manifest_path.to_str().unwrap()).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].command, "extract");
+
+        run(RunArgs { manifest: manifest_path.to_str().unwrap().to_string(), fail_fast: true }).unwrap();
+        assert!(out.join("combined.syldb").exists());
+        assert!(out.join("reads.sylsp").exists());
+        assert!(out.join("profile.tsv").exists());
+    }
+
+    #[test]
+    fn test_yaml_and_tsv_manifests_parse_to_same_jobs() {
+        let yaml = "jobs:\n  - name: db\n    command: extract\n    args: [\"-g\", \"a.fa\", \"-d\", \"out\"]\n";
+        let manifest: Manifest = serde_yaml::from_str(yaml).unwrap();
+        let tsv = parse_tsv_manifest("db\textract\t-g a.fa  -d out\n").unwrap();
+        assert_eq!(manifest.jobs, tsv);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::cmdline::SketchArgs;
+use crate::constants::{init_thread_pool, OutputOptions, Progress};
 use crate::extract::{
-    GenomeSketch, get_memory_usage,
+    GenomeSketch, get_memory_usage, read_sylsp_header, write_sylsp_header, SylspKind,
 };
 use anyhow::{Result, Context, anyhow};
 use fxhash::{FxHashMap, FxHashSet, FxHasher};
@@ -8,14 +9,13 @@ use log::{info, warn, debug};
 use rayon::prelude::*;
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{BufWriter, BufReader, BufRead};
+use std::io::{BufReader, BufRead};
 use std::path::Path;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 use needletail::parse_fastx_file;
 use scalable_cuckoo_filter::ScalableCuckooFilterBuilder;
-use bincode;
 
 pub type Hash = u64;
 pub type Kmer = u64;
@@ -43,6 +43,7 @@ const BYTE_TO_SEQ: [u8; 256] = [
 
 const SAMPLE_FILE_SUFFIX: &str = ".sylsp";
 const QUERY_FILE_SUFFIX: &str = ".syldb";
+// 单端reads默认的去重上限，可用--max-dedup-count覆盖
 const MAX_DEDUP_COUNT: u32 = 10000;
 
 // 文件格式检查函数
@@ -103,6 +104,19 @@ pub fn mm_hash64(kmer: u64) -> u64 {
     key
 }
 
+// 标签哈希：与k-mer相同的2-bit编码后用mm_hash64。标签长度不固定，先以长度的哈希为种子，
+// 超过32bp的标签按32bp分块依次混入。mm_hash64是u64上的双射，所以同长度、不超过32bp的
+// 不同标签（BcgI等2bRAD标签）哈希必然不同；旧的FNV-1a对此没有保证，雪崩性也更差
+pub fn mm_hash_tag(seq: &[u8]) -> Hash {
+    let mut hash = mm_hash64(seq.len() as u64);
+    for chunk in seq.chunks(32) {
+        let packed = chunk.iter()
+            .fold(0u64, |acc, &b| (acc << 2) | (BYTE_TO_SEQ[b as usize] & 3) as u64);
+        hash = mm_hash64(hash ^ packed);
+    }
+    hash
+}
+
 // K-mer提取函数
 pub fn extract_kmers(string: &[u8], kmer_vec: &mut Vec<u64>, c: usize, k: usize) {
     if string.len() < k {
@@ -132,8 +146,8 @@ pub fn extract_kmers(string: &[u8], kmer_vec: &mut Vec<u64>, c: usize, k: usize)
     }
 
     // 滑动窗口提取k-mers
-    for i in k - 1..len {
-        let nuc_byte = string[i] as usize;
+    for &byte in &string[k - 1..len] {
+        let nuc_byte = byte as usize;
         let nuc_f = BYTE_TO_SEQ[nuc_byte] as u64;
         if nuc_f >= 4 {
             continue; // 跳过无效核苷酸
@@ -199,8 +213,8 @@ pub fn extract_kmers_positions(
     }
 
     // 滑动窗口提取k-mers
-    for i in k - 1..len {
-        let nuc_byte = string[i] as usize;
+    for (i, &byte) in string.iter().enumerate().take(len).skip(k - 1) {
+        let nuc_byte = byte as usize;
         let nuc_f = BYTE_TO_SEQ[nuc_byte] as u64;
         if nuc_f >= 4 {
             continue; // 跳过无效核苷酸
@@ -241,6 +255,9 @@ pub struct SequencesSketch {
     pub paired: bool,
     pub sample_name: Option<String>,
     pub mean_read_length: f64,
+    // 去重去掉的k-mer计数占比；旧版本写出的sketch没有这一项
+    #[serde(default)]
+    pub duplication_rate: Option<f64>,
 }
 
 impl SequencesSketch {
@@ -260,10 +277,21 @@ impl SequencesSketch {
             paired,
             sample_name,
             mean_read_length,
+            duplication_rate: None,
         }
     }
 }
 
+// 去重去掉的k-mer计数占全部k-mer计数的比例
+fn duplication_rate(num_dup_removed: usize, kmer_counts: &FxHashMap<Kmer, u32>) -> f64 {
+    let total = kmer_counts.values().map(|&c| c as f64).sum::<f64>() + num_dup_removed as f64;
+    if total == 0.0 {
+        0.0
+    } else {
+        num_dup_removed as f64 / total
+    }
+}
+
 // 重复去除函数
 fn dup_removal_exact(
     kmer_counts: &mut FxHashMap<Kmer, u32>,
@@ -311,7 +339,7 @@ fn dup_removal_exact(
 fn pair_kmer_single(s1: &[u8]) -> Option<([Marker; 2], [Marker; 2])> {
     let k = std::mem::size_of::<Marker>() * 4;
     if s1.len() < 4 * k + 2 {
-        return None;
+        None
     } else {
         let mut kmer_f = 0;
         let mut kmer_g = 0;
@@ -337,7 +365,7 @@ fn pair_kmer_single(s1: &[u8]) -> Option<([Marker; 2], [Marker; 2])> {
             kmer_t <<= 2;
             kmer_t |= nuc_4;
         }
-        return Some(([kmer_f, kmer_r], [kmer_g, kmer_t]));
+        Some(([kmer_f, kmer_r], [kmer_g, kmer_t]))
     }
 }
 
@@ -346,7 +374,7 @@ fn pair_kmer_single(s1: &[u8]) -> Option<([Marker; 2], [Marker; 2])> {
 fn pair_kmer(s1: &[u8], s2: &[u8]) -> Option<([Marker; 2], [Marker; 2])> {
     let k = std::mem::size_of::<Marker>() * 4;
     if s1.len() < 2 * k + 1 || s2.len() < 2 * k + 1 {
-        return None;
+        None
     } else {
         let mut kmer_f = 0;
         let mut kmer_g = 0;
@@ -371,7 +399,7 @@ fn pair_kmer(s1: &[u8], s2: &[u8]) -> Option<([Marker; 2], [Marker; 2])> {
             kmer_t <<= 2;
             kmer_t |= nuc_4;
         }
-        return Some(([kmer_f, kmer_r], [kmer_g, kmer_t]));
+        Some(([kmer_f, kmer_r], [kmer_g, kmer_t]))
     }
 }
 
@@ -385,10 +413,7 @@ fn check_args_valid(args: &SketchArgs) -> Result<()> {
         log::LevelFilter::Info
     };
 
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(args.threads)
-        .build_global()
-        .with_context(|| "Failed to initialize thread pool")?;
+    init_thread_pool(args.threads);
 
     simple_logger::SimpleLogger::new()
         .with_level(level)
@@ -542,6 +567,34 @@ fn parse_sample_names(args: &SketchArgs) -> Result<Option<Vec<String>>> {
     Ok(Some(sample_names))
 }
 
+// 样本名按顺序先分配给配对reads，再分配给单端reads，数量必须完全对应
+fn validate_sample_names(sample_names: &Option<Vec<String>>, num_pairs: usize, num_reads: usize) -> Result<()> {
+    if let Some(names) = sample_names {
+        if names.len() != num_pairs + num_reads {
+            return Err(anyhow!(
+                "Got {} sample names but {} read inputs ({} paired, {} single-end). \
+                Sample names are assigned to paired inputs first, then to single-end reads",
+                names.len(),
+                num_pairs + num_reads,
+                num_pairs,
+                num_reads
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn sample_name_at(sample_names: &Option<Vec<String>>, index: usize) -> Result<Option<String>> {
+    match sample_names {
+        Some(names) => names
+            .get(index)
+            .cloned()
+            .map(Some)
+            .with_context(|| format!("No sample name for read input #{} ({} sample names given)", index + 1, names.len())),
+        None => Ok(None),
+    }
+}
+
 // sketch单个序列文件
 pub fn sketch_sequences_needle(
     read_file: &str,
@@ -549,6 +602,7 @@ pub fn sketch_sequences_needle(
     k: usize,
     sample_name: Option<String>,
     no_dedup: bool,
+    max_dedup_count: u32,
 ) -> Result<SequencesSketch> {
     let mut kmer_map = HashMap::default();
     let reader = parse_fastx_file(read_file)
@@ -581,7 +635,7 @@ pub fn sketch_sequences_needle(
                 kmer_pair,
                 &mut num_dup_removed,
                 no_dedup,
-                Some(MAX_DEDUP_COUNT),
+                Some(max_dedup_count),
             );
         }
         
@@ -590,11 +644,10 @@ pub fn sketch_sequences_needle(
         mean_read_length = mean_read_length + ((seq.len() as f64) - mean_read_length) / counter;
     }
 
-    let percent = (num_dup_removed as f64) / 
-        ((kmer_map.values().sum::<u32>() as f64) + num_dup_removed as f64) * 100.;
+    let rate = duplication_rate(num_dup_removed, &kmer_map);
     debug!(
         "Number of sketched k-mers removed due to read duplication for {}: {}. Percentage: {:.2}%",
-        read_file, num_dup_removed, percent
+        read_file, num_dup_removed, rate * 100.
     );
 
     Ok(SequencesSketch {
@@ -605,10 +658,12 @@ pub fn sketch_sequences_needle(
         paired: false,
         sample_name,
         mean_read_length,
+        duplication_rate: Some(rate),
     })
 }
 
 // sketch配对reads
+#[allow(clippy::too_many_arguments)]
 pub fn sketch_pair_sequences(
     read_file1: &str,
     read_file2: &str,
@@ -617,6 +672,7 @@ pub fn sketch_pair_sequences(
     sample_name: Option<String>,
     no_dedup: bool,
     dedup_fpr: f64,
+    max_dedup_count: Option<u32>,
 ) -> Result<SequencesSketch> {
     let r1o = parse_fastx_file(read_file1)
         .with_context(|| format!("Failed to parse first pair file: {}", read_file1))?;
@@ -679,7 +735,7 @@ pub fn sketch_pair_sequences(
                     kmer_pair,
                     &mut num_dup_removed,
                     no_dedup,
-                    None,
+                    max_dedup_count,
                 );
             }
             
@@ -694,7 +750,7 @@ pub fn sketch_pair_sequences(
                     kmer_pair,
                     &mut num_dup_removed,
                     no_dedup,
-                    None,
+                    max_dedup_count,
                 );
             }
         } else {
@@ -702,14 +758,14 @@ pub fn sketch_pair_sequences(
         }
     }
     
-    let percent = (num_dup_removed as f64) / 
-        ((read_sketch.kmer_counts.values().sum::<u32>() as f64) + num_dup_removed as f64) * 100.;
+    let rate = duplication_rate(num_dup_removed, &read_sketch.kmer_counts);
     debug!(
         "Number of sketched k-mers removed due to read duplication for {}: {}. Percentage: {:.2}%",
-        read_sketch.file_name, num_dup_removed, percent
+        read_sketch.file_name, num_dup_removed, rate * 100.
     );
     
     read_sketch.mean_read_length = mean_read_length;
+    read_sketch.duplication_rate = Some(rate);
     Ok(read_sketch)
 }
 
@@ -852,6 +908,7 @@ pub fn sketch_genome_individual(
 
 // 生成合并的样本文件
 fn generate_merged_sample_file(
+    output: &OutputOptions,
     args: &SketchArgs,
     read_inputs: &[String],
     first_pairs: &[String],
@@ -861,16 +918,9 @@ fn generate_merged_sample_file(
     
     // 读取所有单端reads的sketch文件
     for (i, read_file) in read_inputs.iter().enumerate() {
-        let mut sample_name = None;
-        if let Some(names) = sample_names {
-            sample_name = Some(names[i + first_pairs.len()].clone());
-        }
+        let sample_name = sample_name_at(sample_names, i + first_pairs.len())?;
         
-        let sketch_name = if sample_name.is_some() {
-            sample_name.as_ref().unwrap()
-        } else {
-            read_file
-        };
+        let sketch_name = sample_name.as_ref().unwrap_or(read_file);
         
         let read_file_path = Path::new(sketch_name).file_name().unwrap();
         let file_path = Path::new(&args.sample_output_dir).join(read_file_path);
@@ -879,7 +929,8 @@ fn generate_merged_sample_file(
         if Path::new(&file_path_str).exists() {
             let file = File::open(&file_path_str)
                 .with_context(|| format!("Failed to open sketch file: {}", file_path_str))?;
-            let reader = BufReader::new(file);
+            let mut reader = BufReader::new(file);
+            read_sylsp_header(&mut reader)?;
             let sketch: SequencesSketch = bincode::deserialize_from(reader)
                 .with_context(|| format!("Failed to deserialize sketch from: {}", file_path_str))?;
             all_sketches.push(sketch);
@@ -888,16 +939,9 @@ fn generate_merged_sample_file(
     
     // 读取所有配对reads的sketch文件
     for (i, read_file1) in first_pairs.iter().enumerate() {
-        let mut sample_name = None;
-        if let Some(names) = sample_names {
-            sample_name = Some(names[i].clone());
-        }
+        let sample_name = sample_name_at(sample_names, i)?;
         
-        let sketch_name = if sample_name.is_some() {
-            sample_name.as_ref().unwrap()
-        } else {
-            read_file1
-        };
+        let sketch_name = sample_name.as_ref().unwrap_or(read_file1);
         
         let read_file_path = Path::new(sketch_name).file_name().unwrap();
         let file_path = Path::new(&args.sample_output_dir).join(read_file_path);
@@ -906,7 +950,8 @@ fn generate_merged_sample_file(
         if Path::new(&file_path_str).exists() {
             let file = File::open(&file_path_str)
                 .with_context(|| format!("Failed to open paired sketch file: {}", file_path_str))?;
-            let reader = BufReader::new(file);
+            let mut reader = BufReader::new(file);
+            read_sylsp_header(&mut reader)?;
             let sketch: SequencesSketch = bincode::deserialize_from(reader)
                 .with_context(|| format!("Failed to deserialize paired sketch from: {}", file_path_str))?;
             all_sketches.push(sketch);
@@ -919,12 +964,12 @@ fn generate_merged_sample_file(
         let merged_file_path = Path::new(&args.sample_output_dir)
             .join(format!("{}{}", merged_name, SAMPLE_FILE_SUFFIX));
         
-        let merged_file = File::create(&merged_file_path)
-            .with_context(|| format!("Failed to create merged sample file: {}", merged_file_path.display()))?;
-        let mut writer = BufWriter::new(merged_file);
-        
-        bincode::serialize_into(&mut writer, &all_sketches)
-            .with_context(|| "Failed to serialize merged sample sketches")?;
+        output.write_atomically(&merged_file_path, |writer| {
+            write_sylsp_header(writer, SylspKind::SketchList, "", 1)?;
+            bincode::serialize_into(writer, &all_sketches)
+                .with_context(|| "Failed to serialize merged sample sketches")
+        })
+        .with_context(|| format!("Failed to write merged sample file: {}", merged_file_path.display()))?;
         
         info!("Merged sample file created: {}", merged_file_path.display());
     }
@@ -934,6 +979,7 @@ fn generate_merged_sample_file(
 
 // 生成合并的基因组数据库文件
 fn generate_merged_genome_file(
+    output: &OutputOptions,
     args: &SketchArgs,
     genome_inputs: &[String],
 ) -> Result<()> {
@@ -984,12 +1030,11 @@ fn generate_merged_genome_file(
         let merged_file_path = Path::new(&args.output_dir)
             .join(format!("{}{}", merged_name, QUERY_FILE_SUFFIX));
         
-        let merged_file = File::create(&merged_file_path)
-            .with_context(|| format!("Failed to create merged genome database file: {}", merged_file_path.display()))?;
-        let mut writer = BufWriter::new(merged_file);
-        
-        bincode::serialize_into(&mut writer, &all_sketches)
-            .with_context(|| "Failed to serialize merged genome sketches")?;
+        output.write_atomically(&merged_file_path, |writer| {
+            bincode::serialize_into(writer, &all_sketches)
+                .with_context(|| "Failed to serialize merged genome sketches")
+        })
+        .with_context(|| format!("Failed to write merged genome database file: {}", merged_file_path.display()))?;
         
         info!("Merged genome database file created: {}", merged_file_path.display());
     }
@@ -998,7 +1043,75 @@ fn generate_merged_genome_file(
 }
 
 // 主sketch函数
+// 单个样本sketch文件的路径：样本名（或第一个reads文件名）去掉目录后加后缀，配对reads额外带.paired
+fn sample_sketch_path(sample_output_dir: &str, sketch_name: &str, paired: bool) -> String {
+    let read_file_path = Path::new(sketch_name).file_name().unwrap();
+    let file_path = Path::new(sample_output_dir).join(read_file_path);
+    if paired {
+        format!("{}.paired{}", file_path.to_str().unwrap(), SAMPLE_FILE_SUFFIX)
+    } else {
+        format!("{}{}", file_path.to_str().unwrap(), SAMPLE_FILE_SUFFIX)
+    }
+}
+
+// --dry-run：按与正式运行相同的解析结果列出输入、样本名和输出路径
+fn print_sketch_plan(
+    args: &SketchArgs,
+    read_inputs: &[String],
+    genome_inputs: &[String],
+    first_pairs: &[String],
+    second_pairs: &[String],
+    sample_names: &Option<Vec<String>>,
+) {
+    let name_at = |i: usize, file: &str| -> String {
+        sample_names
+            .as_ref()
+            .and_then(|names| names.get(i).cloned())
+            .unwrap_or_else(|| file.to_string())
+    };
+
+    println!("Dry run: no files will be sketched or written.");
+    if !first_pairs.is_empty() {
+        println!("\nPaired reads:");
+        for (i, (first, second)) in first_pairs.iter().zip(second_pairs).enumerate() {
+            let name = name_at(i, first);
+            println!("  {} + {}  [sample: {}] -> {}", first, second, name, sample_sketch_path(&args.sample_output_dir, &name, true));
+        }
+    }
+    if !read_inputs.is_empty() {
+        println!("\nReads:");
+        for (i, file) in read_inputs.iter().enumerate() {
+            let name = name_at(i + first_pairs.len(), file);
+            println!("  {}  [sample: {}] -> {}", file, name, sample_sketch_path(&args.sample_output_dir, &name, false));
+        }
+    }
+    if !genome_inputs.is_empty() {
+        println!("\nGenomes:");
+        for file in genome_inputs {
+            let file_stem = Path::new(file).file_stem().unwrap().to_str().unwrap();
+            let output = if args.individual {
+                Path::new(&args.output_dir).join(format!("{}_<contig>{}", file_stem, QUERY_FILE_SUFFIX))
+            } else {
+                Path::new(&args.output_dir).join(format!("{}{}", file_stem, QUERY_FILE_SUFFIX))
+            };
+            println!("  {} -> {}", file, output.display());
+        }
+    }
+
+    println!("\nMerged outputs:");
+    if !read_inputs.is_empty() || !first_pairs.is_empty() {
+        let merged_name = args.out_name.as_deref().unwrap_or("merged_samples");
+        println!("  {}", Path::new(&args.sample_output_dir).join(format!("{}{}", merged_name, SAMPLE_FILE_SUFFIX)).display());
+    }
+    if !genome_inputs.is_empty() {
+        let merged_name = args.out_name.as_deref().unwrap_or("merged_database");
+        println!("  {}", Path::new(&args.output_dir).join(format!("{}{}", merged_name, QUERY_FILE_SUFFIX)).display());
+    }
+}
+
 pub fn sketch(args: SketchArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, args.temp_dir.as_deref());
+    let progress = Progress::new(args.progress_json.as_deref());
     let mut read_inputs = vec![];
     let mut genome_inputs = vec![];
     let mut first_pairs = vec![];
@@ -1010,12 +1123,11 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
     parse_paired_end_reads(&args, &mut first_pairs, &mut second_pairs)?;
 
     let sample_names = parse_sample_names(&args)?;
-    if let Some(names) = &sample_names {
-        if names.len() != first_pairs.len() + read_inputs.len() {
-            return Err(anyhow!(
-                "Sample name length is not equal to the number of reads"
-            ));
-        }
+    validate_sample_names(&sample_names, first_pairs.len(), read_inputs.len())?;
+
+    if args.dry_run {
+        print_sketch_plan(&args, &read_inputs, &genome_inputs, &first_pairs, &second_pairs, &sample_names);
+        return Ok(());
     }
 
     let mut max_ram = usize::MAX;
@@ -1033,16 +1145,14 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
     // 处理配对reads - 只生成单个子文件，不合并
     if !first_pairs.is_empty() && !second_pairs.is_empty() {
         info!("Sketching paired sequences...");
+        progress.phase("paired reads", first_pairs.len());
         let iter_vec: Vec<usize> = (0..first_pairs.len()).collect();
         
         iter_vec.into_par_iter().try_for_each(|i| -> Result<()> {
             let read_file1 = &first_pairs[i];
             let read_file2 = &second_pairs[i];
 
-            let mut sample_name = None;
-            if let Some(names) = &sample_names {
-                sample_name = Some(names[i].clone());
-            }
+            let sample_name = sample_name_at(&sample_names, i)?;
             
             let read_sketch = sketch_pair_sequences(
                 read_file1,
@@ -1052,13 +1162,13 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
                 sample_name.clone(),
                 args.no_dedup,
                 args.fpr,
+                args.max_dedup_count,
             )?;
 
             // 创建输出目录
             fs::create_dir_all(&args.sample_output_dir)
                 .with_context(|| format!("Could not create directory at {}", args.sample_output_dir))?;
             
-            let pref = Path::new(&args.sample_output_dir);
             let sketch_name = if sample_name.is_some() {
                 read_sketch.sample_name.as_ref().unwrap()
             } else {
@@ -1066,18 +1176,16 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
             };
 
             // 生成单个配对文件的子文件
-            let read_file_path = Path::new(sketch_name).file_name().unwrap();
-            let file_path = pref.join(read_file_path);
-            let file_path_str = format!("{}.paired{}", file_path.to_str().unwrap(), SAMPLE_FILE_SUFFIX);
-
-            let mut read_sk_file = BufWriter::new(
-                File::create(&file_path_str)
-                    .with_context(|| format!("Failed to create file: {}", file_path_str))?
-            );
-
-            bincode::serialize_into(&mut read_sk_file, &read_sketch)
-                .with_context(|| "Failed to serialize paired read sketch")?;
+            let file_path_str = sample_sketch_path(&args.sample_output_dir, sketch_name, true);
+
+            output.write_atomically(&file_path_str, |writer| {
+                write_sylsp_header(writer, SylspKind::SingleSketch, "", 1)?;
+                bincode::serialize_into(writer, &read_sketch)
+                    .with_context(|| "Failed to serialize paired read sketch")
+            })
+            .with_context(|| format!("Failed to write file: {}", file_path_str))?;
             info!("Individual sketching {} complete.", file_path_str);
+            progress.sample_done();
             
             Ok(())
         })?;
@@ -1086,6 +1194,7 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
     // 处理单端reads - 只生成单个子文件，不合并
     if !read_inputs.is_empty() {
         info!("Sketching non-paired sequences...");
+        progress.phase("reads", read_inputs.len());
         let iter_vec: Vec<usize> = (0..read_inputs.len()).collect();
         
         iter_vec.into_par_iter().try_for_each(|i| -> Result<()> {
@@ -1096,10 +1205,7 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
             let read_file = &read_inputs[i];
             check_vram_and_block(max_ram, read_file);
             
-            let mut sample_name = None;
-            if let Some(names) = &sample_names {
-                sample_name = Some(names[i + first_pairs.len()].clone());
-            }
+            let sample_name = sample_name_at(&sample_names, i + first_pairs.len())?;
 
             let read_sketch = sketch_sequences_needle(
                 read_file,
@@ -1107,6 +1213,7 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
                 args.k,
                 sample_name.clone(),
                 args.no_dedup,
+                args.max_dedup_count.unwrap_or(MAX_DEDUP_COUNT),
             )?;
 
             let sketch_name = if sample_name.is_some() {
@@ -1116,18 +1223,16 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
             };
             
             // 生成单个文件的子文件
-            let read_file_path = Path::new(sketch_name).file_name().unwrap();
-            let file_path = pref.join(read_file_path);
-            let file_path_str = format!("{}{}", file_path.to_str().unwrap(), SAMPLE_FILE_SUFFIX);
-
-            let mut read_sk_file = BufWriter::new(
-                File::create(&file_path_str)
-                    .with_context(|| format!("Failed to create file: {}", file_path_str))?
-            );
-
-            bincode::serialize_into(&mut read_sk_file, &read_sketch)
-                .with_context(|| "Failed to serialize read sketch")?;
+            let file_path_str = sample_sketch_path(&args.sample_output_dir, sketch_name, false);
+
+            output.write_atomically(&file_path_str, |writer| {
+                write_sylsp_header(writer, SylspKind::SingleSketch, "", 1)?;
+                bincode::serialize_into(writer, &read_sketch)
+                    .with_context(|| "Failed to serialize read sketch")
+            })
+            .with_context(|| format!("Failed to write file: {}", file_path_str))?;
             info!("Individual sketching {} complete.", file_path_str);
+            progress.sample_done();
             
             Ok(())
         })?;
@@ -1136,6 +1241,7 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
     // 处理基因组文件 - 只生成单个子文件，不合并
     if !genome_inputs.is_empty() {
         info!("Sketching genomes...");
+        progress.phase("genomes", genome_inputs.len());
         let iter_vec: Vec<usize> = (0..genome_inputs.len()).collect();
         let counter: Mutex<usize> = Mutex::new(0);
 
@@ -1162,12 +1268,11 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
                     let file_stem = genome_path.file_stem().unwrap().to_str().unwrap();
                     let individual_path = output_dir.join(format!("{}_{}{}", file_stem, j, QUERY_FILE_SUFFIX));
                     
-                    let mut individual_file = BufWriter::new(
-                        File::create(&individual_path)
-                            .with_context(|| format!("Failed to create individual genome file: {}", individual_path.display()))?
-                    );
-                    bincode::serialize_into(&mut individual_file, &vec![sketch.clone()])
-                        .with_context(|| "Failed to serialize individual genome sketch")?;
+                    output.write_atomically(&individual_path, |writer| {
+                        bincode::serialize_into(writer, &vec![sketch.clone()])
+                            .with_context(|| "Failed to serialize individual genome sketch")
+                    })
+                    .with_context(|| format!("Failed to write individual genome file: {}", individual_path.display()))?;
                     info!("Individual genome sketch {} complete.", individual_path.display());
                 }
             } else {
@@ -1184,18 +1289,18 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
                 let file_stem = genome_path.file_stem().unwrap().to_str().unwrap();
                 let individual_path = output_dir.join(format!("{}{}", file_stem, QUERY_FILE_SUFFIX));
                 
-                let mut individual_file = BufWriter::new(
-                    File::create(&individual_path)
-                        .with_context(|| format!("Failed to create individual genome file: {}", individual_path.display()))?
-                );
-                bincode::serialize_into(&mut individual_file, &vec![genome_sketch.clone()])
-                    .with_context(|| "Failed to serialize individual genome sketch")?;
+                output.write_atomically(&individual_path, |writer| {
+                    bincode::serialize_into(writer, &vec![genome_sketch.clone()])
+                        .with_context(|| "Failed to serialize individual genome sketch")
+                })
+                .with_context(|| format!("Failed to write individual genome file: {}", individual_path.display()))?;
                 info!("Individual genome sketch {} complete.", individual_path.display());
             }
             
+            progress.sample_done();
             let mut c = counter.lock().unwrap();
             *c += 1;
-            if *c % 100 == 0 && *c != 0 {
+            if (*c).is_multiple_of(100) && *c != 0 {
                 info!("{} genomes processed.", *c);
             }
             
@@ -1206,15 +1311,98 @@ pub fn sketch(args: SketchArgs) -> Result<()> {
     // 生成合并的样本文件
     if !read_inputs.is_empty() || !first_pairs.is_empty() {
         info!("Generating merged sample file...");
-        generate_merged_sample_file(&args, &read_inputs, &first_pairs, &sample_names)?;
+        generate_merged_sample_file(&output, &args, &read_inputs, &first_pairs, &sample_names)?;
     }
 
     // 生成合并的基因组数据库文件  
     if !genome_inputs.is_empty() {
         info!("Generating merged genome database file...");
-        generate_merged_genome_file(&args, &genome_inputs)?;
+        generate_merged_genome_file(&output, &args, &genome_inputs)?;
     }
 
+    progress.finish();
     info!("Finished.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_short_sample_names_list_is_a_friendly_error() {
+        let names = Some(vec!["s1".to_string(), "s2".to_string()]);
+
+        let err = validate_sample_names(&names, 2, 1).unwrap_err().to_string();
+        assert!(err.contains("Got 2 sample names but 3 read inputs (2 paired, 1 single-end)"));
+        assert!(sample_name_at(&names, 2).is_err());
+
+        assert!(validate_sample_names(&names, 1, 1).is_ok());
+        assert_eq!(sample_name_at(&names, 1).unwrap(), Some("s2".to_string()));
+        assert_eq!(sample_name_at(&None, 5).unwrap(), None);
+    }
+
+    #[test]
+    fn test_max_dedup_count_limits_which_kmers_are_deduplicated() {
+        // 同一k-mer先由5个不同的read pair计数，再重复出现5次第一个pair
+        let run = |cap: u32| {
+            let mut counts = FxHashMap::default();
+            let mut pairs = FxHashSet::default();
+            let mut removed = 0;
+            let km: Kmer = 42;
+            let pair = |i: Marker| Some(([i, i], [i + 100, i + 100]));
+            for i in 0..5 {
+                dup_removal_exact(&mut counts, &mut pairs, &km, pair(i), &mut removed, false, Some(cap));
+            }
+            for _ in 0..5 {
+                dup_removal_exact(&mut counts, &mut pairs, &km, pair(0), &mut removed, false, Some(cap));
+            }
+            (counts[&km], removed)
+        };
+
+        // 计数已达上限，之后的重复不再去除
+        assert_eq!(run(3), (10, 0));
+        // 提高上限后同样的重复被识别并去除
+        assert_eq!(run(MAX_DEDUP_COUNT), (5, 5));
+    }
+
+    #[test]
+    fn test_paired_sketch_stores_duplication_rate() {
+        // 确定性的伪随机序列，避免read内部出现重复k-mer
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut random_read = |len: usize| -> String {
+            (0..len).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                b"ACGT"[(state % 4) as usize] as char
+            }).collect()
+        };
+        let (a1, a2, b1, b2) = (random_read(150), random_read(150), random_read(150), random_read(150));
+
+        // 第一对reads完全重复一次，第二对唯一
+        let dir = TempDir::new("dup_rate");
+        let write_fastq = |name: &str, reads: &[&String]| {
+            let path = dir.join(name);
+            let body: String = reads.iter().enumerate()
+                .map(|(i, r)| format!("@r{}\n{}\n+\n{}\n", i, r, "I".repeat(r.len())))
+                .collect();
+            fs::write(&path, body).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let r1 = write_fastq("r1.fq", &[&a1, &a1, &b1]);
+        let r2 = write_fastq("r2.fq", &[&a2, &a2, &b2]);
+
+        let sketch = sketch_pair_sequences(&r1, &r2, 1, 31, None, false, 0.001, None).unwrap();
+        let no_dedup = sketch_pair_sequences(&r1, &r2, 1, 31, None, true, 0.001, None).unwrap();
+
+        // 重复的那对reads贡献的k-mer全部被去除，占总计数的三分之一
+        let rate = sketch.duplication_rate.unwrap();
+        assert!((rate - 1.0 / 3.0).abs() < 1e-9);
+        let kept: u32 = sketch.kmer_counts.values().sum();
+        let total: u32 = no_dedup.kmer_counts.values().sum();
+        assert!((rate - duplication_rate((total - kept) as usize, &sketch.kmer_counts)).abs() < 1e-12);
+        assert_eq!(no_dedup.duplication_rate, Some(0.0));
+    }
+}
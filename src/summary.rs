@@ -0,0 +1,131 @@
+use crate::cmdline::SummaryArgs;
+use crate::constants::Hash;
+use crate::extract::{read_sylsp_header_full, stream_sylsp_entries, SylspEntry, SylspKind};
+use anyhow::{anyhow, Context, Result};
+use fxhash::{FxHashMap, FxHashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// 合并.sylsp中各样本之间的标签共享情况
+#[derive(Debug)]
+struct SharingSummary {
+    // (样本, 不同标签数, 仅在该样本中出现的标签数)，按样本名排序
+    samples: Vec<(String, usize, usize)>,
+    // (样本A, 样本B, 共享标签数, Jaccard)，A < B
+    pairs: Vec<(String, String, usize, f64)>,
+    // 所有样本都有的标签数
+    core_tags: usize,
+    // 至少一个样本有的标签数
+    pan_tags: usize,
+}
+
+pub fn summary(args: SummaryArgs) -> Result<()> {
+    let file = File::open(&args.file).with_context(|| format!("Failed to open {}", args.file))?;
+    let summary = summarize_sharing(BufReader::new(file), &args.file)?;
+
+    println!("Sample\tTags\tUnique_tags");
+    for (sample, tags, unique) in &summary.samples {
+        println!("{}\t{}\t{}", sample, tags, unique);
+    }
+
+    println!("\nSample_A\tSample_B\tShared_tags\tJaccard");
+    for (a, b, shared, jaccard) in &summary.pairs {
+        println!("{}\t{}\t{}\t{:.4}", a, b, shared, jaccard);
+    }
+
+    println!("\nCore tags (in all {} samples): {}", summary.samples.len(), summary.core_tags);
+    println!("Pan tags (in any sample): {}", summary.pan_tags);
+    Ok(())
+}
+
+fn summarize_sharing<R: BufRead>(mut reader: R, file_path: &str) -> Result<SharingSummary> {
+    let header = read_sylsp_header_full(&mut reader)?;
+    if let Some(kind) = header.as_ref().map(|header| header.kind) {
+        if kind != SylspKind::TagEntries {
+            return Err(anyhow!("{} is a k-mer sketch file; summary needs extracted 2bRAD tags", file_path));
+        }
+    }
+
+    // 按sample_source分组，每个样本只记不同的标签
+    let mut sample_tags: FxHashMap<String, FxHashSet<Hash>> = FxHashMap::default();
+    stream_sylsp_entries(reader, header.as_ref(), |entry: SylspEntry| {
+        sample_tags.entry(entry.sample_source).or_default().insert(entry.tag);
+    })
+    .with_context(|| format!("Failed to deserialize {}", file_path))?;
+
+    if sample_tags.is_empty() {
+        return Err(anyhow!("{} contains no tags", file_path));
+    }
+
+    let mut names: Vec<&String> = sample_tags.keys().collect();
+    names.sort();
+
+    // 每个标签出现在多少个样本中
+    let mut tag_samples: FxHashMap<Hash, usize> = FxHashMap::default();
+    for tags in sample_tags.values() {
+        for tag in tags {
+            *tag_samples.entry(*tag).or_insert(0) += 1;
+        }
+    }
+
+    let samples = names.iter()
+        .map(|name| {
+            let tags = &sample_tags[*name];
+            let unique = tags.iter().filter(|tag| tag_samples[*tag] == 1).count();
+            (name.to_string(), tags.len(), unique)
+        })
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (i, a) in names.iter().enumerate() {
+        for b in &names[i + 1..] {
+            let (tags_a, tags_b) = (&sample_tags[*a], &sample_tags[*b]);
+            let shared = tags_a.intersection(tags_b).count();
+            let union = tags_a.len() + tags_b.len() - shared;
+            let jaccard = if union > 0 { shared as f64 / union as f64 } else { 0.0 };
+            pairs.push((a.to_string(), b.to_string(), shared, jaccard));
+        }
+    }
+
+    Ok(SharingSummary {
+        samples,
+        pairs,
+        core_tags: tag_samples.values().filter(|&&n| n == names.len()).count(),
+        pan_tags: tag_samples.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::write_sylsp_header;
+    use crate::test_support::sylsp_entry;
+
+    #[test]
+    fn test_three_sample_pairwise_sharing() {
+        // A: 1-6, B: 4-9, C: 1,2,5,10（重复观测不重复计数）
+        let mut entries: Vec<SylspEntry> = (1..=6).map(|t| sylsp_entry("A", t)).collect();
+        entries.extend((4..=9).map(|t| sylsp_entry("B", t)));
+        entries.extend([1, 2, 5, 10, 10].iter().map(|&t| sylsp_entry("C", t)));
+
+        let mut data = Vec::new();
+        write_sylsp_header(&mut data, SylspKind::TagEntries, "", 1).unwrap();
+        bincode::serialize_into(&mut data, &entries).unwrap();
+        let summary = summarize_sharing(std::io::Cursor::new(data), "three.sylsp").unwrap();
+
+        let pair = |a: &str, b: &str| summary.pairs.iter().find(|p| p.0 == a && p.1 == b).unwrap().2;
+        assert_eq!(summary.pairs.len(), 3);
+        assert_eq!(pair("A", "B"), 3); // 4,5,6
+        assert_eq!(pair("A", "C"), 3); // 1,2,5
+        assert_eq!(pair("B", "C"), 1); // 5
+        assert!((summary.pairs[0].3 - 3.0 / 9.0).abs() < 1e-12);
+
+        assert_eq!(summary.samples, vec![
+            ("A".to_string(), 6, 1), // 3
+            ("B".to_string(), 6, 3), // 7,8,9
+            ("C".to_string(), 4, 1), // 10
+        ]);
+        assert_eq!(summary.core_tags, 1);
+        assert_eq!(summary.pan_tags, 10);
+    }
+}
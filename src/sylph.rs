@@ -0,0 +1,137 @@
+use crate::extract::GenomeSketch;
+use crate::sketch::SequencesSketch;
+use anyhow::{anyhow, Context, Result};
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+// sylph原生的.syldb/.sylsp：与sketch输出一样是bincode，但字段顺序不同，且没有格式头。
+// 读入后转换成本仓库的GenomeSketch/SequencesSketch，之后走sketch路径的比较。
+// sylph的k-mer哈希同样是2bit编码k-mer的mm_hash64，c值不同时由reconcile_and_intersect协调
+
+// sylph的GenomeSketch；.syldb是Vec<SylphGenomeSketch>
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct SylphGenomeSketch {
+    pub genome_kmers: Vec<u64>,
+    pub pseudotax_tracked_nonused_kmers: Option<Vec<u64>>,
+    pub file_name: String,
+    pub first_contig_name: String,
+    pub c: usize,
+    pub k: usize,
+    pub gn_size: usize,
+    pub min_spacing: usize,
+}
+
+// sylph的SequencesSketch；.sylsp是单个SylphSequencesSketch。
+// sylph把kmer_counts按(k-mer, 计数)序列编码，bincode下与map的编码字节相同
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct SylphSequencesSketch {
+    pub kmer_counts: FxHashMap<u64, u32>,
+    pub c: usize,
+    pub k: usize,
+    pub file_name: String,
+    pub sample_name: Option<String>,
+    pub paired: bool,
+    pub mean_read_length: f64,
+}
+
+impl From<SylphGenomeSketch> for GenomeSketch {
+    fn from(sketch: SylphGenomeSketch) -> Self {
+        GenomeSketch {
+            file_name: sketch.file_name,
+            first_contig_name: sketch.first_contig_name,
+            gn_size: sketch.gn_size,
+            c: sketch.c,
+            k: sketch.k,
+            min_spacing: sketch.min_spacing,
+            genome_kmers: sketch.genome_kmers,
+        }
+    }
+}
+
+impl From<SylphSequencesSketch> for SequencesSketch {
+    fn from(sketch: SylphSequencesSketch) -> Self {
+        SequencesSketch {
+            kmer_counts: sketch.kmer_counts,
+            file_name: sketch.file_name,
+            c: sketch.c,
+            k: sketch.k,
+            paired: sketch.paired,
+            sample_name: sketch.sample_name,
+            mean_read_length: sketch.mean_read_length,
+            duplication_rate: None,
+        }
+    }
+}
+
+// 没有格式头，反序列化成功不代表真是sylph文件；c和k不合理时视为格式不符
+fn check_parameters(c: usize, k: usize) -> Result<()> {
+    if c == 0 || k == 0 || k > 32 {
+        return Err(anyhow!("Not a sylph sketch (c = {}, k = {})", c, k));
+    }
+    Ok(())
+}
+
+pub fn read_sylph_genome_sketches<R: Read>(reader: R) -> Result<Vec<GenomeSketch>> {
+    let sketches: Vec<SylphGenomeSketch> = bincode::deserialize_from(reader)
+        .context("Failed to deserialize sylph database")?;
+    for sketch in &sketches {
+        check_parameters(sketch.c, sketch.k)?;
+    }
+    Ok(sketches.into_iter().map(GenomeSketch::from).collect())
+}
+
+pub fn read_sylph_sequences_sketch<R: Read>(reader: R) -> Result<SequencesSketch> {
+    let sketch: SylphSequencesSketch = bincode::deserialize_from(reader)
+        .context("Failed to deserialize sylph sample sketch")?;
+    check_parameters(sketch.c, sketch.k)?;
+    Ok(sketch.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sylph_fixtures_deserialize_into_crate_sketches() {
+        let genome = SylphGenomeSketch {
+            genome_kmers: vec![12, 700, 9_000],
+            pseudotax_tracked_nonused_kmers: Some(vec![55]),
+            file_name: "GCF_000005845.fna".to_string(),
+            first_contig_name: "NC_000913.3".to_string(),
+            c: 200,
+            k: 31,
+            gn_size: 4_641_652,
+            min_spacing: 30,
+        };
+        let db = bincode::serialize(&vec![genome.clone()]).unwrap();
+        let genomes = read_sylph_genome_sketches(db.as_slice()).unwrap();
+        assert_eq!(genomes.len(), 1);
+        assert_eq!(genomes[0].genome_kmers, genome.genome_kmers);
+        assert_eq!((genomes[0].c, genomes[0].k, genomes[0].gn_size), (200, 31, 4_641_652));
+        assert_eq!(genomes[0].first_contig_name, "NC_000913.3");
+
+        let mut kmer_counts = FxHashMap::default();
+        kmer_counts.insert(12, 3);
+        kmer_counts.insert(9_000, 1);
+        let sample = SylphSequencesSketch {
+            kmer_counts: kmer_counts.clone(),
+            c: 200,
+            k: 31,
+            file_name: "reads_1.fq.gz".to_string(),
+            sample_name: Some("s1".to_string()),
+            paired: true,
+            mean_read_length: 150.0,
+        };
+        let sylsp = bincode::serialize(&sample).unwrap();
+        let sketch = read_sylph_sequences_sketch(sylsp.as_slice()).unwrap();
+        assert_eq!(sketch.kmer_counts, kmer_counts);
+        assert_eq!(sketch.sample_name.as_deref(), Some("s1"));
+        assert!(sketch.paired);
+        assert_eq!(sketch.duplication_rate, None);
+
+        // 本仓库自己的sketch不会被当成sylph文件
+        let native = bincode::serialize(&SequencesSketch::new("reads.fq".to_string(), 200, 31, false, None, 150.0)).unwrap();
+        assert!(read_sylph_sequences_sketch(native.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::contain::GenomeProfileResult;
+use crate::extract::SylspEntry;
+use crate::sketch::Hash;
+
+static NEXT_TEMP_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// 测试用临时目录：路径含进程号和序号，并行测试之间互不冲突；
+// 离开作用域时连同内容一起删除，断言失败导致panic时也不会残留
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let id = NEXT_TEMP_DIR.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("m2b_{}_{}_{}", name, std::process::id(), id));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+
+    // 以字符串形式返回目录下的路径，便于构造命令行参数
+    pub fn join_str<P: AsRef<Path>>(&self, name: P) -> String {
+        self.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// 测试用的样本标签条目：read名由样本和标签派生，不带质量和标签序列
+pub fn sylsp_entry(sample: &str, tag: Hash) -> SylspEntry {
+    SylspEntry {
+        sequence_id: format!("{}_read{}", sample, tag),
+        tag,
+        quality: None,
+        sample_source: sample.to_string(),
+        sequence: None,
+    }
+}
+
+// 测试用的基因组profile结果：默认是样本中ANI 99%、1000个标签共享100个、丰度为0的一条检出，
+// 各测试只改关心的字段
+pub struct GenomeResultBuilder {
+    result: GenomeProfileResult,
+}
+
+pub fn genome_result(genome_id: &str, sample_id: &str) -> GenomeResultBuilder {
+    GenomeResultBuilder {
+        result: GenomeProfileResult {
+            genome_id: genome_id.to_string(),
+            sample_id: sample_id.to_string(),
+            adjusted_ani: 99.0,
+            taxonomic_abundance: 0.0,
+            sequence_abundance: 0.0,
+            common_tags: 100,
+            total_tags: 1000,
+            eff_cov: 0.1,
+            breadth_bins: (0, 0),
+            shared_span: 0,
+        },
+    }
+}
+
+impl GenomeResultBuilder {
+    pub fn ani(mut self, adjusted_ani: f64) -> Self {
+        self.result.adjusted_ani = adjusted_ani;
+        self
+    }
+
+    // 分类丰度和序列丰度取同一个值
+    pub fn abundance(mut self, abundance: f64) -> Self {
+        self.result.taxonomic_abundance = abundance;
+        self.result.sequence_abundance = abundance;
+        self
+    }
+
+    pub fn tags(mut self, common_tags: usize, total_tags: usize) -> Self {
+        self.result.common_tags = common_tags;
+        self.result.total_tags = total_tags;
+        self
+    }
+
+    pub fn eff_cov(mut self, eff_cov: f64) -> Self {
+        self.result.eff_cov = eff_cov;
+        self
+    }
+
+    pub fn build(self) -> GenomeProfileResult {
+        self.result
+    }
+}
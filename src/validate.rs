@@ -0,0 +1,171 @@
+use crate::cmdline::ValidateArgs;
+use crate::extract::{read_syldb, read_sylsp_entries, read_sylsp_header_full, GenomeSketch, SyldbEntry, SylspEntry, SylspKind};
+use crate::sketch::SequencesSketch;
+use anyhow::{anyhow, Context, Result};
+use fxhash::FxHashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+// 每个文件最多列出的问题数，其余只计数
+const MAX_REPORTED_VIOLATIONS: usize = 20;
+
+pub fn validate(args: ValidateArgs) -> Result<()> {
+    let mut failed = 0;
+    for path in &args.files {
+        let (kind, entries, violations) = validate_file(path)?;
+        if violations.is_empty() {
+            println!("OK\t{}\t{}\t{} entries", path, kind, entries);
+            continue;
+        }
+
+        failed += 1;
+        println!("INVALID\t{}\t{}\t{} entries, {} problems", path, kind, entries, violations.len());
+        for violation in violations.iter().take(MAX_REPORTED_VIOLATIONS) {
+            println!("  - {}", violation);
+        }
+        if violations.len() > MAX_REPORTED_VIOLATIONS {
+            println!("  ... and {} more", violations.len() - MAX_REPORTED_VIOLATIONS);
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("{} of {} files failed validation", failed, args.files.len()));
+    }
+    Ok(())
+}
+
+// 返回(文件类型描述, 条目数, 问题列表)。无法反序列化的文件直接报错
+fn validate_file(path: &str) -> Result<(&'static str, usize, Vec<String>)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut reader = BufReader::new(file);
+
+    if path.ends_with(".syldb") {
+        // 标签数据库和sketch数据库共用扩展名，按顺序尝试
+        let entries = read_syldb(&mut reader);
+        if let Ok(entries) = entries {
+            return Ok(("tag database", entries.len(), check_syldb_entries(&entries)));
+        }
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+        let sketches: Vec<GenomeSketch> = bincode::deserialize_from(BufReader::new(file))
+            .with_context(|| format!("{} is neither a tag nor a sketch database (corrupted?)", path))?;
+        return Ok(("sketch database", sketches.len(), Vec::new()));
+    }
+
+    if path.ends_with(".sylsp") {
+        let header = read_sylsp_header_full(&mut reader)?;
+        return match header.as_ref().map(|header| header.kind) {
+            Some(SylspKind::TagEntries) | None => {
+                let entries = read_sylsp_entries(reader, header.as_ref())
+                    .with_context(|| format!("Failed to deserialize tag entries from {} (corrupted?)", path))?;
+                Ok(("tag sample", entries.len(), check_sylsp_entries(&entries)))
+            }
+            Some(SylspKind::SingleSketch) => {
+                let _: SequencesSketch = bincode::deserialize_from(reader)
+                    .with_context(|| format!("Failed to deserialize sketch from {} (corrupted?)", path))?;
+                Ok(("sketch sample", 1, Vec::new()))
+            }
+            Some(SylspKind::SketchList) => {
+                let sketches: Vec<SequencesSketch> = bincode::deserialize_from(reader)
+                    .with_context(|| format!("Failed to deserialize sketches from {} (corrupted?)", path))?;
+                Ok(("sketch sample list", sketches.len(), Vec::new()))
+            }
+        };
+    }
+
+    Err(anyhow!("{} does not have a .syldb or .sylsp extension", path))
+}
+
+fn check_syldb_entries(entries: &[SyldbEntry]) -> Vec<String> {
+    let mut violations = Vec::new();
+    // 同一基因组的所有条目应记录相同的基因组长度
+    let mut genome_sizes: FxHashMap<&str, usize> = FxHashMap::default();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let name = if entry.sequence_id.is_empty() {
+            violations.push(format!("entry {}: empty sequence_id", i));
+            format!("entry {}", i)
+        } else {
+            format!("entry {} ({})", i, entry.sequence_id)
+        };
+
+        if entry.positions.len() != entry.tags.len() {
+            violations.push(format!("{}: {} positions for {} tags", name, entry.positions.len(), entry.tags.len()));
+        }
+        if let Some(uniqueness) = &entry.tag_uniqueness {
+            if uniqueness.len() != entry.tags.len() {
+                violations.push(format!("{}: {} uniqueness marks for {} tags", name, uniqueness.len(), entry.tags.len()));
+            }
+        }
+        if let Some(sequences) = &entry.tag_sequences {
+            if sequences.len() != entry.tags.len() {
+                violations.push(format!("{}: {} tag sequences for {} tags", name, sequences.len(), entry.tags.len()));
+            }
+        }
+        if entry.genome_source.is_empty() {
+            violations.push(format!("{}: empty genome_source", name));
+        } else if let Some(&size) = genome_sizes.get(entry.genome_source.as_str()) {
+            if size != entry.gn_size {
+                violations.push(format!("{}: gn_size {} differs from {} for genome {}", name, entry.gn_size, size, entry.genome_source));
+            }
+        } else {
+            genome_sizes.insert(&entry.genome_source, entry.gn_size);
+        }
+    }
+    violations
+}
+
+fn check_sylsp_entries(entries: &[SylspEntry]) -> Vec<String> {
+    let mut violations = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.sequence_id.is_empty() {
+            violations.push(format!("entry {}: empty sequence_id", i));
+        }
+        if entry.sample_source.is_empty() {
+            violations.push(format!("entry {}: empty sample_source", i));
+        }
+        if let (Some(quality), Some(sequence)) = (&entry.quality, &entry.sequence) {
+            if quality.len() != sequence.len() {
+                violations.push(format!("entry {}: quality length {} does not match sequence length {}", i, quality.len(), sequence.len()));
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn entry(sequence_id: &str) -> SyldbEntry {
+        SyldbEntry {
+            sequence_id: sequence_id.to_string(),
+            tags: vec![1, 2, 3],
+            positions: vec![10, 20, 30],
+            genome_source: "genome.fa".to_string(),
+            tag_uniqueness: Some(vec![true, false, true]),
+            gn_size: 1000,
+            tag_sequences: None,
+            enzyme: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_validator_catches_mismatched_positions() {
+        let mut entries = vec![entry("contig1"), entry("contig2")];
+        assert!(check_syldb_entries(&entries).is_empty());
+
+        entries[1].positions.pop();
+        let dir = TempDir::new("validate");
+        let path = dir.join("db.syldb");
+        crate::extract::write_syldb(&mut std::fs::File::create(&path).unwrap(), 1, crate::extract::DbGranularity::Contig, &entries).unwrap();
+
+        let (kind, count, violations) = validate_file(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(kind, "tag database");
+        assert_eq!(count, 2);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].contains("contig2"));
+        assert!(violations[0].contains("2 positions for 3 tags"));
+    }
+}
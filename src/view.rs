@@ -2,15 +2,15 @@
 
 use crate::cmdline::ViewArgs;
 use crate::sketch::SequencesSketch;
-use crate::extract::GenomeSketch;
+use crate::sylph::{read_sylph_genome_sketches, read_sylph_sequences_sketch};
+use crate::extract::{GenomeSketch, read_sylsp_header, stream_bincode_vec, SylspKind};
 use anyhow::{Context, Result};
-use bincode;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 use std::collections::HashMap;
-use crate::constants::Hash;
+use crate::constants::{Hash, natural_cmp, check_output_dir, OutputOptions};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct ViewResult {
@@ -28,10 +28,44 @@ struct ViewResult {
     sample_sources: Option<Vec<SampleStats>>,
     // 每个样本对应的 kmer -> 计数
     per_sample_kmer_counts: Option<std::collections::HashMap<String, std::collections::HashMap<Hash, u32>>>,
-    kmer_lengths: Vec<usize>,
     kmer_length_distribution: Vec<(usize, usize, f64)>,
     min_spacing: Option<usize>,
     genome_stats: Option<Vec<GenomeStats>>,
+    effective_resolution: Option<EffectiveResolution>,
+}
+
+// 由c和k推算的检测分辨率：每Mb基因组期望抽中的k-mer数，以及平均大小的基因组
+// 至少抽中一个k-mer时对应的最小可检出基因组比例
+impl ViewResult {
+    // --min-count：去掉合计计数低于min_count的k-mer，总数和唯一数也只统计保留下来的k-mer，
+    // 与频率表和矩阵一致。返回去掉的k-mer数
+    fn retain_min_count(&mut self, min_count: usize) -> usize {
+        if min_count <= 1 {
+            return 0;
+        }
+        let before = self.kmer_frequency_stats.len();
+        self.kmer_frequency_stats.retain(|(_, count)| *count as usize >= min_count);
+        self.total_kmers = self.kmer_frequency_stats.iter().map(|(_, count)| *count as usize).sum();
+        self.unique_kmers = self.kmer_frequency_stats.len();
+        before - self.kmer_frequency_stats.len()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct EffectiveResolution {
+    kmers_per_mb: f64,
+    min_detectable_fraction: Option<f64>,
+}
+
+impl EffectiveResolution {
+    fn new(c: usize, k: usize, mean_genome_size: f64) -> Self {
+        let sampled_kmers = |length: f64| (length - k as f64 + 1.0).max(0.0) / c.max(1) as f64;
+        let genome_kmers = sampled_kmers(mean_genome_size);
+        EffectiveResolution {
+            kmers_per_mb: sampled_kmers(1_000_000.0),
+            min_detectable_fraction: if genome_kmers > 0.0 { Some((1.0 / genome_kmers).min(1.0)) } else { None },
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -47,9 +81,13 @@ struct SampleStats {
     source: String,
     num_records: usize,
     total_kmers: usize,
+    duplication_rate: Option<f64>,
     kmer_length_distribution: Vec<(usize, usize, f64)>,
 }
 
+// 稠密矩阵超过这个k-mer数时提示使用--long-format
+const DENSE_MATRIX_WARN_KMERS: usize = 1_000_000;
+
 #[derive(Debug)]
 struct KmerMatrix {
     samples: Vec<String>,
@@ -57,9 +95,30 @@ struct KmerMatrix {
     matrix: HashMap<(String, Hash), u32>,
 }
 
+impl KmerMatrix {
+    // 去掉所有样本合计计数低于min_count的k-mer（多为测序噪声）
+    fn retain_min_count(&mut self, min_count: usize) {
+        if min_count <= 1 {
+            return;
+        }
+        let mut totals: HashMap<Hash, usize> = HashMap::new();
+        for ((_, kmer), count) in &self.matrix {
+            *totals.entry(*kmer).or_insert(0) += *count as usize;
+        }
+        let keep = |kmer: &Hash| totals.get(kmer).copied().unwrap_or(0) >= min_count;
+        self.kmers.retain(|kmer| keep(kmer));
+        self.matrix.retain(|(_, kmer), _| keep(kmer));
+    }
+}
+
 pub fn view(args: ViewArgs) -> Result<()> {
+    let output = OutputOptions::new(args.force, args.temp_dir.as_deref());
+    // 矩阵在最后才写出，先检查目录可写
+    if let Some(log_path) = &args.log_path {
+        check_output_dir(log_path)?;
+    }
     let mut writer = match args.out_file_name {
-        Some(path) => Box::new(BufWriter::new(File::create(path)?)) as Box<dyn Write>,
+        Some(path) => Box::new(BufWriter::new(output.create_file(path)?)) as Box<dyn Write>,
         None => Box::new(BufWriter::new(std::io::stdout())) as Box<dyn Write>,
     };
 
@@ -71,8 +130,9 @@ pub fn view(args: ViewArgs) -> Result<()> {
     };
 
     for file in &args.files {
-        match view_file(file) {
-            Ok(result) => {
+        match view_file(file, args.log_path.is_some()) {
+            Ok(mut result) => {
+                let hidden = result.retain_min_count(args.min_count);
                 // 输出文件信息
                 writeln!(writer, "File Information:")?;
                 writeln!(writer, "----------------")?;
@@ -87,7 +147,10 @@ pub fn view(args: ViewArgs) -> Result<()> {
                         writeln!(writer, "Samples: yes ({} samples)", samples.len())?;
                         writeln!(writer, "Per-sample total k-mers:")?;
                         for s in samples {
-                            writeln!(writer, "  {}: {}", s.source, s.total_kmers)?;
+                            match s.duplication_rate {
+                                Some(rate) => writeln!(writer, "  {}: {} (duplication rate {:.2}%)", s.source, s.total_kmers, rate * 100.0)?,
+                                None => writeln!(writer, "  {}: {}", s.source, s.total_kmers)?,
+                            }
                         }
                     }
                     _ => {
@@ -100,13 +163,19 @@ pub fn view(args: ViewArgs) -> Result<()> {
                 if let Some(mean_length) = result.mean_read_length {
                     writeln!(writer, "Mean read length: {:.2}", mean_length)?;
                 }
+                if let Some(resolution) = &result.effective_resolution {
+                    writeln!(writer, "Effective resolution: {:.1} sampled k-mers per Mb", resolution.kmers_per_mb)?;
+                    if let Some(fraction) = resolution.min_detectable_fraction {
+                        writeln!(writer, "Smallest detectable genome fraction: {:.4}%", fraction * 100.0)?;
+                    }
+                }
                 
                 writeln!(writer, "\nK-mer Frequency Statistics:")?;
                 writeln!(writer, "---------------------------")?;
                 writeln!(writer, "{:<20} {:<10}", "K-mer Hash", "Count")?;
                 writeln!(writer, "{:-<30}", "")?;
                 
-                // 显示前20个最常见的k-mer
+                // 显示前20个最常见的k-mer（低于--min-count的已去掉）
                 let display_count = std::cmp::min(20, result.kmer_frequency_stats.len());
                 for (kmer_hash, count) in result.kmer_frequency_stats.iter().take(display_count) {
                     writeln!(writer, "{:<20} {:<10}", format!("{:016x}", kmer_hash), count)?;
@@ -114,6 +183,9 @@ pub fn view(args: ViewArgs) -> Result<()> {
                 if result.kmer_frequency_stats.len() > display_count {
                     writeln!(writer, "... and {} more unique k-mers", result.kmer_frequency_stats.len() - display_count)?;
                 }
+                if args.min_count > 1 {
+                    writeln!(writer, "({} k-mers below --min-count {} excluded from all statistics)", hidden, args.min_count)?;
+                }
                 
                 writeln!(writer, "\nK-mer Length Distribution:")?;
                 writeln!(writer, "-------------------------")?;
@@ -164,20 +236,30 @@ pub fn view(args: ViewArgs) -> Result<()> {
 
     // 如果指定了输出路径，生成TSV矩阵
     if let Some(log_path) = &args.log_path {
-        generate_tsv_matrix(&kmer_matrix, log_path, &args.tsv_name)?;
+        kmer_matrix.retain_min_count(args.min_count);
+        if args.long_format {
+            generate_long_tsv_matrix(&output, &kmer_matrix, log_path, &args.tsv_name)?;
+        } else {
+            if kmer_matrix.kmers.len() > DENSE_MATRIX_WARN_KMERS {
+                eprintln!("Warning: {} k-mers make the dense matrix very large and mostly zero; consider --long-format",
+                         kmer_matrix.kmers.len());
+            }
+            generate_tsv_matrix(&output, &kmer_matrix, log_path, &args.tsv_name)?;
+        }
     }
 
     Ok(())
 }
 
-fn view_file(file_path: &str) -> Result<ViewResult> {
+// per_sample：是否累积每个样本的k-mer计数，只有写k-mer矩阵(--log-path)时才需要
+fn view_file(file_path: &str, per_sample: bool) -> Result<ViewResult> {
     let path = Path::new(file_path);
     let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     match path.extension().and_then(|s| s.to_str()) {
         Some("syldb") => view_syldb(reader, file_path),
-        Some("sylsp") => view_sylsp(reader, file_path),
+        Some("sylsp") => view_sylsp(reader, file_path, per_sample),
         _ => Err(anyhow::anyhow!("Unknown file extension, expected .syldb or .sylsp")),
     }
 }
@@ -185,8 +267,15 @@ fn view_file(file_path: &str) -> Result<ViewResult> {
 fn view_syldb(reader: BufReader<File>, file_path: &str) -> Result<ViewResult> {
     println!("Attempting to deserialize {} as genome sketches...", file_path);
     
-    let entries: Vec<GenomeSketch> = bincode::deserialize_from(reader)
-        .with_context(|| format!("Failed to deserialize .syldb file: {}", file_path))?;
+    let entries: Vec<GenomeSketch> = match bincode::deserialize_from(reader) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Attempting to deserialize {} as a sylph database...", file_path);
+            read_sylph_genome_sketches(BufReader::new(File::open(file_path)?))
+                .map_err(|_| e)
+                .with_context(|| format!("Failed to deserialize .syldb file: {}", file_path))?
+        }
+    };
 
     if entries.is_empty() {
         return Err(anyhow::anyhow!("Empty .syldb file"));
@@ -204,6 +293,7 @@ fn view_syldb(reader: BufReader<File>, file_path: &str) -> Result<ViewResult> {
     let c = first_entry.c;
     let k = first_entry.k;
     let min_spacing = first_entry.min_spacing;
+    let mean_genome_size = entries.iter().map(|e| e.gn_size as f64).sum::<f64>() / entries.len() as f64;
 
     for (i, entry) in entries.iter().enumerate() {
         if i % 100 == 0 {
@@ -245,7 +335,7 @@ fn view_syldb(reader: BufReader<File>, file_path: &str) -> Result<ViewResult> {
     // 计算k-mer统计信息
     let unique_kmers = kmer_frequency.len();
     let mut kmer_frequency_stats: Vec<(Hash, u32)> = kmer_frequency.into_iter().collect();
-    kmer_frequency_stats.sort_by(|a, b| b.1.cmp(&a.1)); // 按频率降序排序
+    kmer_frequency_stats.sort_by_key(|&(_, count)| std::cmp::Reverse(count)); // 按频率降序排序
 
     Ok(ViewResult {
         file_type: "GenomeSketch".to_string(),
@@ -265,20 +355,36 @@ fn view_syldb(reader: BufReader<File>, file_path: &str) -> Result<ViewResult> {
         },
         sample_sources: None,
         per_sample_kmer_counts: None,
-        kmer_lengths,
         kmer_length_distribution: distribution,
         min_spacing: Some(min_spacing),
         genome_stats: Some(genome_stats.into_values().collect()),
+        effective_resolution: Some(EffectiveResolution::new(c, k, mean_genome_size)),
     })
 }
 
-fn view_sylsp(reader: BufReader<File>, file_path: &str) -> Result<ViewResult> {
-    // 尝试反序列化为单个SequencesSketch
+fn view_sylsp(mut reader: BufReader<File>, file_path: &str, per_sample: bool) -> Result<ViewResult> {
+    // 有格式头的文件直接按记录的形态分派
+    if let Some(kind) = read_sylsp_header(&mut reader)? {
+        return match kind {
+            SylspKind::SingleSketch => {
+                let sketch: SequencesSketch = bincode::deserialize_from(reader)
+                    .with_context(|| format!("Failed to deserialize .sylsp file: {}", file_path))?;
+                view_single_sylsp(sketch, file_path, per_sample)
+            }
+            SylspKind::SketchList => view_sketch_stream(reader, file_path, per_sample),
+            SylspKind::TagEntries => Err(anyhow::anyhow!(
+                "{} contains extracted 2bRAD tags, not k-mer sketches; use `meta2bseek inspect` instead",
+                file_path
+            )),
+        };
+    }
+
+    // 旧文件没有格式头：尝试反序列化为单个SequencesSketch
     let single_sketch: Result<SequencesSketch, _> = bincode::deserialize_from(reader);
     
     if let Ok(sketch) = single_sketch {
         // 单个样本文件
-        return view_single_sylsp(sketch, file_path);
+        return view_single_sylsp(sketch, file_path, per_sample);
     }
     
     // 如果单个反序列化失败，尝试作为多个sketch的列表
@@ -288,138 +394,122 @@ fn view_sylsp(reader: BufReader<File>, file_path: &str) -> Result<ViewResult> {
     // 添加错误处理和日志
     println!("Attempting to deserialize {} as multiple sketches...", file_path);
     
-    let sketches: Result<Vec<SequencesSketch>, _> = bincode::deserialize_from(reader);
+    if let Ok(result) = view_sketch_stream(reader, file_path, per_sample) {
+        return Ok(result);
+    }
     
-    if let Ok(sketches) = sketches {
-        if sketches.is_empty() {
-            return Err(anyhow::anyhow!("Empty .sylsp file"));
-        }
-
-        println!("Successfully deserialized {} sketches from {}", sketches.len(), file_path);
-
-        let mut kmer_lengths = Vec::new();
-        let mut sample_stats = std::collections::HashMap::new();
-        let mut kmer_frequency = std::collections::HashMap::new();
-        let mut per_sample_kmer_counts: std::collections::HashMap<String, std::collections::HashMap<Hash, u32>> = std::collections::HashMap::new();
-
-        // 获取第一个条目的参数作为参考
-        let first_sketch = &sketches[0];
-        let c = first_sketch.c;
-        let k = first_sketch.k;
+    // 如果Meta2bseek格式失败，尝试sylph格式
+    println!("Meta2bseek format failed, attempting sylph format...");
+    match read_sylph_sequences_sketch(BufReader::new(File::open(file_path)?)) {
+        Ok(sketch) => view_single_sylsp(sketch, file_path, per_sample),
+        Err(_) => Err(anyhow::anyhow!("File format not recognized. This file is neither a meta2bseek nor a sylph sketch, or it is corrupted.")),
+    }
+}
 
-        for (i, sketch) in sketches.iter().enumerate() {
-            if i % 100 == 0 {
-                println!("Processing sketch {}/{}", i + 1, sketches.len());
-            }
-            
-            let sample_name = sketch.sample_name.as_ref().unwrap_or(&sketch.file_name);
-            
+// 逐个读取sketch并累积统计，合并文件中的全部sketch不会同时驻留内存
+fn view_sketch_stream<R: Read>(reader: R, file_path: &str, per_sample: bool) -> Result<ViewResult> {
+    let mut sample_stats: HashMap<String, SampleStats> = HashMap::new();
+    let mut kmer_frequency: HashMap<Hash, u32> = HashMap::new();
+    let mut per_sample_kmer_counts: HashMap<String, HashMap<Hash, u32>> = HashMap::new();
+    let mut params: Option<(usize, usize)> = None;
+    let mut total_kmers = 0usize;
+    let mut read_length_sum = 0.0;
+
+    let num_sketches = stream_bincode_vec(reader, |sketch: SequencesSketch| {
+        // 以第一个sketch的参数作为参考
+        params.get_or_insert((sketch.c, sketch.k));
+
+        let sample_name = sketch.sample_name.clone().unwrap_or_else(|| sketch.file_name.clone());
+        let mut sketch_kmers = 0usize;
+        for (kmer, count) in &sketch.kmer_counts {
+            *kmer_frequency.entry(*kmer).or_insert(0) += count;
+            sketch_kmers += *count as usize;
+        }
+        // 累积每个样本的 k-mer 计数
+        if per_sample {
+            let sample_entry = per_sample_kmer_counts.entry(sample_name.clone()).or_default();
             for (kmer, count) in &sketch.kmer_counts {
-                for _ in 0..*count {
-                    kmer_lengths.push(k); // K-mer size is always k
-                }
-                *kmer_frequency.entry(*kmer).or_insert(0) += count;
-
-                // 累积每个样本的 k-mer 计数
-                let sample_entry = per_sample_kmer_counts
-                    .entry(sample_name.clone())
-                    .or_insert_with(std::collections::HashMap::new);
                 *sample_entry.entry(*kmer).or_insert(0) += count;
             }
-
-            let stats = sample_stats.entry(sample_name.clone()).or_insert(SampleStats {
-                source: sample_name.clone(),
-                num_records: 1, // Each sketch represents one sample
-                total_kmers: 0,
-                kmer_length_distribution: Vec::new(),
-            });
-            stats.total_kmers += sketch.kmer_counts.values().sum::<u32>() as usize;
         }
 
-        for stats in sample_stats.values_mut() {
-            let mut sample_lengths = Vec::new();
-            for sketch in &sketches {
-                let sample_name = sketch.sample_name.as_ref().unwrap_or(&sketch.file_name);
-                if sample_name == &stats.source {
-                    for (_, count) in &sketch.kmer_counts {
-                        for _ in 0..*count {
-                            sample_lengths.push(k); // K-mer size is always k
-                        }
-                    }
-                }
-            }
-            stats.kmer_length_distribution = calculate_kmer_distribution(&sample_lengths);
-        }
+        let stats = sample_stats.entry(sample_name.clone()).or_insert_with(|| SampleStats {
+            source: sample_name,
+            num_records: 1, // Each sketch represents one sample
+            total_kmers: 0,
+            duplication_rate: sketch.duplication_rate,
+            kmer_length_distribution: Vec::new(),
+        });
+        stats.total_kmers += sketch_kmers;
+        total_kmers += sketch_kmers;
+        read_length_sum += sketch.mean_read_length;
+    })
+    .with_context(|| format!("Failed to deserialize .sylsp file: {}", file_path))?;
 
-        let distribution = calculate_kmer_distribution(&kmer_lengths);
+    let Some((c, k)) = params else {
+        return Err(anyhow::anyhow!("Empty .sylsp file"));
+    };
+    println!("Successfully read {} sketches from {}", num_sketches, file_path);
 
-        // 计算k-mer统计信息
-        let unique_kmers = kmer_frequency.len();
-        let mut kmer_frequency_stats: Vec<(Hash, u32)> = kmer_frequency.into_iter().collect();
-        kmer_frequency_stats.sort_by(|a, b| b.1.cmp(&a.1)); // 按频率降序排序
+    for stats in sample_stats.values_mut() {
+        stats.kmer_length_distribution = uniform_kmer_distribution(k, stats.total_kmers);
+    }
 
+    // 计算k-mer统计信息
+    let unique_kmers = kmer_frequency.len();
+    let mut kmer_frequency_stats: Vec<(Hash, u32)> = kmer_frequency.into_iter().collect();
+    kmer_frequency_stats.sort_by_key(|&(_, count)| std::cmp::Reverse(count)); // 按频率降序排序
+
+    Ok(ViewResult {
+        file_type: "SampleSketch".to_string(),
+        file_name: file_path.to_string(),
+        c,
+        k,
+        num_records: num_sketches,
+        total_kmers,
+        unique_kmers,
+        kmer_frequency_stats,
         // 计算平均read长度
-        let total_mean_length: f64 = sketches.iter()
-            .map(|s| s.mean_read_length)
-            .sum::<f64>() / sketches.len() as f64;
-
-        return Ok(ViewResult {
-            file_type: "SampleSketch".to_string(),
-            file_name: file_path.to_string(),
-            c,
-            k,
-            num_records: sketches.len(),
-            total_kmers: kmer_lengths.len(),
-            unique_kmers,
-            kmer_frequency_stats,
-            mean_read_length: Some(total_mean_length),
-            first_contig_name: None,
-            genome_sources: None,
-            sample_sources: Some(sample_stats.into_values().collect()),
-            per_sample_kmer_counts: Some(per_sample_kmer_counts),
-            kmer_lengths,
-            kmer_length_distribution: distribution,
-            min_spacing: None,
-            genome_stats: None,
-        });
-    }
-    
-    // 如果Meta2bseek格式失败，尝试sylph格式
-    println!("Meta2bseek format failed, attempting sylph format...");
-    return Err(anyhow::anyhow!("File format not recognized. This file may be in sylph format or corrupted."));
+        mean_read_length: Some(read_length_sum / num_sketches as f64),
+        first_contig_name: None,
+        genome_sources: None,
+        sample_sources: Some(sample_stats.into_values().collect()),
+        per_sample_kmer_counts: per_sample.then_some(per_sample_kmer_counts),
+        kmer_length_distribution: uniform_kmer_distribution(k, total_kmers),
+        min_spacing: None,
+        genome_stats: None,
+        effective_resolution: None,
+    })
 }
 
-fn view_single_sylsp(sketch: SequencesSketch, file_path: &str) -> Result<ViewResult> {
-    let mut kmer_lengths = Vec::new();
+fn view_single_sylsp(sketch: SequencesSketch, file_path: &str, per_sample: bool) -> Result<ViewResult> {
     let mut kmer_frequency = std::collections::HashMap::new();
-    let mut per_sample_kmer_counts: std::collections::HashMap<String, std::collections::HashMap<Hash, u32>> = std::collections::HashMap::new();
 
     let sample_name = sketch.sample_name.as_ref().unwrap_or(&sketch.file_name);
+    let total_kmers: usize = sketch.kmer_counts.values().map(|&count| count as usize).sum();
     
     for (kmer, count) in &sketch.kmer_counts {
-        for _ in 0..*count {
-            kmer_lengths.push(sketch.k); // K-mer size is always k
-        }
         *kmer_frequency.entry(*kmer).or_insert(0) += count;
-
-        // 累积样本的 k-mer 计数
-        let sample_entry = per_sample_kmer_counts
-            .entry(sample_name.clone())
-            .or_insert_with(std::collections::HashMap::new);
-        *sample_entry.entry(*kmer).or_insert(0) += count;
     }
+    // 单个样本的 k-mer 计数就是sketch本身的计数
+    let per_sample_kmer_counts = per_sample.then(|| {
+        let mut counts: HashMap<String, HashMap<Hash, u32>> = HashMap::new();
+        counts.insert(sample_name.clone(), sketch.kmer_counts.iter().map(|(kmer, count)| (*kmer, *count)).collect());
+        counts
+    });
 
-    let distribution = calculate_kmer_distribution(&kmer_lengths);
+    let distribution = uniform_kmer_distribution(sketch.k, total_kmers);
 
     // 计算k-mer统计信息
     let unique_kmers = kmer_frequency.len();
     let mut kmer_frequency_stats: Vec<(Hash, u32)> = kmer_frequency.into_iter().collect();
-    kmer_frequency_stats.sort_by(|a, b| b.1.cmp(&a.1)); // 按频率降序排序
+    kmer_frequency_stats.sort_by_key(|&(_, count)| std::cmp::Reverse(count)); // 按频率降序排序
 
     let sample_stats = vec![SampleStats {
         source: sample_name.clone(),
         num_records: 1,
-        total_kmers: kmer_lengths.len(),
+        total_kmers,
+        duplication_rate: sketch.duplication_rate,
         kmer_length_distribution: distribution.clone(),
     }];
 
@@ -429,21 +519,30 @@ fn view_single_sylsp(sketch: SequencesSketch, file_path: &str) -> Result<ViewRes
         c: sketch.c,
         k: sketch.k,
         num_records: 1,
-        total_kmers: kmer_lengths.len(),
+        total_kmers,
         unique_kmers,
         kmer_frequency_stats,
         mean_read_length: Some(sketch.mean_read_length),
         first_contig_name: None,
         genome_sources: None,
         sample_sources: Some(sample_stats),
-        per_sample_kmer_counts: Some(per_sample_kmer_counts),
-        kmer_lengths,
+        per_sample_kmer_counts,
         kmer_length_distribution: distribution,
         min_spacing: None,
         genome_stats: None,
+        effective_resolution: None,
     })
 }
 
+// 样本sketch中所有k-mer长度都是k，分布只有一个取值，按计数直接构造
+fn uniform_kmer_distribution(k: usize, count: usize) -> Vec<(usize, usize, f64)> {
+    if count == 0 {
+        Vec::new()
+    } else {
+        vec![(k, count, 100.0)]
+    }
+}
+
 fn calculate_kmer_distribution(kmer_lengths: &[usize]) -> Vec<(usize, usize, f64)> {
     use std::collections::HashMap;
     
@@ -503,44 +602,133 @@ fn collect_kmer_matrix_data(result: &ViewResult, kmer_matrix: &mut KmerMatrix) {
                 }
                 let key = (sample_name.clone(), *kmer_hash);
                 let entry = kmer_matrix.matrix.entry(key).or_insert(0);
-                *entry += *count as u32;
+                *entry += *count;
             }
         }
         _ => {}
     }
 }
 
-fn generate_tsv_matrix(kmer_matrix: &KmerMatrix, log_path: &str, tsv_name: &str) -> Result<()> {
+fn generate_tsv_matrix(output: &OutputOptions, kmer_matrix: &KmerMatrix, log_path: &str, tsv_name: &str) -> Result<()> {
     // 确保输出目录存在
     std::fs::create_dir_all(log_path)?;
     
     // 构建完整的文件路径
     let tsv_path = Path::new(log_path).join(tsv_name);
-    let mut tsv_writer = BufWriter::new(File::create(&tsv_path)?);
-    
-    // 排序样本和k-mer以确保输出的一致性
-    let mut sorted_samples = kmer_matrix.samples.clone();
-    sorted_samples.sort();
-    let mut sorted_kmers = kmer_matrix.kmers.clone();
-    sorted_kmers.sort();
+    output.write_atomically(&tsv_path, |tsv_writer| {
+        // 排序样本和k-mer以确保输出的一致性
+        let mut sorted_samples = kmer_matrix.samples.clone();
+        sorted_samples.sort_by(|a, b| natural_cmp(a, b));
+        let mut sorted_kmers = kmer_matrix.kmers.clone();
+        sorted_kmers.sort();
     
-    // 写入表头
-    write!(tsv_writer, "K-mer")?; // 目前保存的是哈希，无法还原原始序列，这里用16进制hash展示
-    for sample in &sorted_samples {
-        write!(tsv_writer, "\t{}", sample)?;
-    }
-    writeln!(tsv_writer)?;
-    
-    // 写入数据行
-    for kmer in &sorted_kmers {
-        write!(tsv_writer, "{:016x}", kmer)?;
+        // 写入表头
+        write!(tsv_writer, "K-mer")?; // 目前保存的是哈希，无法还原原始序列，这里用16进制hash展示
         for sample in &sorted_samples {
-            let count = kmer_matrix.matrix.get(&(sample.clone(), *kmer)).unwrap_or(&0);
-            write!(tsv_writer, "\t{}", count)?;
+            write!(tsv_writer, "\t{}", sample)?;
         }
         writeln!(tsv_writer)?;
-    }
     
+        // 写入数据行
+        for kmer in &sorted_kmers {
+            write!(tsv_writer, "{:016x}", kmer)?;
+            for sample in &sorted_samples {
+                let count = kmer_matrix.matrix.get(&(sample.clone(), *kmer)).unwrap_or(&0);
+                write!(tsv_writer, "\t{}", count)?;
+            }
+            writeln!(tsv_writer)?;
+        }
+        Ok(())
+    })?;
+
     println!("K-mer count matrix saved to: {}", tsv_path.display());
     Ok(())
 }
+
+fn generate_long_tsv_matrix(output: &OutputOptions, kmer_matrix: &KmerMatrix, log_path: &str, tsv_name: &str) -> Result<()> {
+    std::fs::create_dir_all(log_path)?;
+
+    let tsv_path = Path::new(log_path).join(tsv_name);
+    let mut written = 0;
+    output.write_atomically(&tsv_path, |tsv_writer| {
+        written = write_long_matrix(kmer_matrix, tsv_writer)?;
+        Ok(())
+    })?;
+
+    println!("K-mer count triples ({} non-zero) saved to: {}", written, tsv_path.display());
+    Ok(())
+}
+
+// 稀疏三元组格式：每行一个非零的(kmer, sample, count)，可直接载入scipy/pandas稀疏结构
+fn write_long_matrix<W: Write>(kmer_matrix: &KmerMatrix, writer: &mut W) -> Result<usize> {
+    let mut triples: Vec<(&Hash, &String, &u32)> = kmer_matrix.matrix.iter()
+        .filter(|(_, count)| **count > 0)
+        .map(|((sample, kmer), count)| (kmer, sample, count))
+        .collect();
+    triples.sort();
+
+    writeln!(writer, "K-mer\tSample\tCount")?;
+    for (kmer, sample, count) in &triples {
+        writeln!(writer, "{:016x}\t{}\t{}", kmer, sample, count)?;
+    }
+
+    Ok(triples.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_matrix_has_one_row_per_nonzero_pair() {
+        let mut kmer_matrix = KmerMatrix {
+            samples: vec!["s1".to_string(), "s2".to_string()],
+            kmers: vec![1, 2, 3],
+            matrix: HashMap::new(),
+        };
+        kmer_matrix.matrix.insert(("s1".to_string(), 1), 4);
+        kmer_matrix.matrix.insert(("s1".to_string(), 3), 1);
+        kmer_matrix.matrix.insert(("s2".to_string(), 2), 7);
+        kmer_matrix.matrix.insert(("s2".to_string(), 3), 0);
+
+        let mut buf = Vec::new();
+        let written = write_long_matrix(&kmer_matrix, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+
+        assert_eq!(written, 3);
+        assert_eq!(text.lines().count(), 1 + 3);
+        assert!(text.contains("0000000000000002\ts2\t7"));
+    }
+
+    #[test]
+    fn test_min_count_excludes_singletons() {
+        let mut kmer_matrix = KmerMatrix {
+            samples: vec!["s1".to_string(), "s2".to_string()],
+            kmers: vec![1, 2, 3],
+            matrix: HashMap::new(),
+        };
+        kmer_matrix.matrix.insert(("s1".to_string(), 1), 1);
+        kmer_matrix.matrix.insert(("s2".to_string(), 1), 1);
+        kmer_matrix.matrix.insert(("s1".to_string(), 2), 1);
+        kmer_matrix.matrix.insert(("s2".to_string(), 3), 5);
+
+        kmer_matrix.retain_min_count(2);
+
+        assert_eq!(kmer_matrix.kmers, vec![1, 3]);
+        assert!(!kmer_matrix.matrix.contains_key(&("s1".to_string(), 2)));
+    }
+
+    #[test]
+    fn test_halving_c_doubles_effective_resolution() {
+        let coarse = EffectiveResolution::new(200, 31, 2_000_000.0);
+        let fine = EffectiveResolution::new(100, 31, 2_000_000.0);
+
+        assert!((fine.kmers_per_mb / coarse.kmers_per_mb - 2.0).abs() < 1e-12);
+        let (coarse_min, fine_min) = (coarse.min_detectable_fraction.unwrap(), fine.min_detectable_fraction.unwrap());
+        assert!((coarse_min / fine_min - 2.0).abs() < 1e-12);
+        assert!((coarse_min - 200.0 / (2_000_000.0 - 30.0)).abs() < 1e-15);
+
+        // 基因组短于k时一个k-mer也抽不到
+        assert_eq!(EffectiveResolution::new(200, 31, 20.0).min_detectable_fraction, None);
+    }
+}